@@ -0,0 +1,396 @@
+use crate::api::auth::with_principal;
+use crate::api::range::{http_date, if_range_matches, parse_range, ByteRange};
+use crate::api::reply::{json, parse_object_id, reject, reject_with};
+use crate::api::{with_config, with_storage, with_store};
+use crate::config::Config;
+use crate::controller::access::Principal;
+use crate::controller::file_system::{
+    copy_asset, delete_asset, get_asset, move_asset, save_asset_stream,
+};
+use crate::controller::integrity::{record_integrity, verifying_stream};
+use crate::data_models::asset::{Asset, Integrity};
+use crate::storage::{asset_key, SharedStorage};
+use crate::store::SharedStore;
+use bytes::Buf;
+use futures::{Stream, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use std::io::Error;
+use std::path::Path;
+use std::sync::Arc;
+use warp::http::{header, Response, StatusCode};
+use warp::hyper::Body;
+use warp::{Filter, Rejection, Reply};
+
+/**
+ * ____________________________________________________________________________________________
+ * AssetView, the JSON representation of an Asset
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug, Serialize)]
+pub struct AssetView {
+    pub id: Option<String>,
+    pub folder_id: String,
+    pub uuid: String,
+    pub tag: String,
+    pub path: String,
+    pub size: i64,
+    pub checksum: String,
+    pub integrity: Integrity,
+    pub verified_at: Option<i64>,
+    pub timestamp: String,
+    pub timestamp_readable: String,
+}
+
+impl From<&Asset> for AssetView {
+    fn from(asset: &Asset) -> AssetView {
+        AssetView {
+            id: asset.id.as_ref().map(|id| id.to_hex()),
+            folder_id: asset.folder_id.to_hex(),
+            uuid: asset.uuid.clone(),
+            tag: asset.tag.clone(),
+            path: asset.path.clone(),
+            size: asset.size,
+            checksum: asset.checksum.clone(),
+            integrity: asset.integrity,
+            verified_at: asset.verified_at,
+            timestamp: asset.timestamp.clone(),
+            timestamp_readable: asset.timestamp_readable.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UploadQuery {
+    pub tag: String,
+    pub extension: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MoveAssetBody {
+    pub folder: Option<String>,
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CopyAssetBody {
+    pub folder: String,
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadReply {
+    pub id: String,
+    pub size: u64,
+    pub checksum: String,
+}
+/**
+ * Routes under /assets
+ */
+pub fn routes(
+    store: SharedStore,
+    storage: SharedStorage,
+    config: Arc<Config>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let upload = warp::path!("folders" / String / "assets")
+        .and(warp::post())
+        .and(with_store(store.clone()))
+        .and(with_storage(storage.clone()))
+        .and(with_config(config.clone()))
+        .and(with_principal(store.clone()))
+        .and(warp::query::<UploadQuery>())
+        .and(warp::body::stream())
+        .and_then(upload_handler);
+
+    let download = warp::path!("assets" / String)
+        .and(warp::get())
+        .and(with_store(store.clone()))
+        .and(with_storage(storage.clone()))
+        .and(with_config(config.clone()))
+        .and(with_principal(store.clone()))
+        .and(warp::header::optional::<String>("range"))
+        .and(warp::header::optional::<String>("if-range"))
+        .and_then(download_handler);
+
+    let move_asset = warp::path!("assets" / String)
+        .and(warp::patch())
+        .and(with_store(store.clone()))
+        .and(with_storage(storage.clone()))
+        .and(with_config(config.clone()))
+        .and(with_principal(store.clone()))
+        .and(warp::body::json())
+        .and_then(move_handler);
+
+    let copy = warp::path!("assets" / String / "copies")
+        .and(warp::post())
+        .and(with_store(store.clone()))
+        .and(with_storage(storage.clone()))
+        .and(with_config(config.clone()))
+        .and(with_principal(store.clone()))
+        .and(warp::body::json())
+        .and_then(copy_handler);
+
+    let delete = warp::path!("assets" / String)
+        .and(warp::delete())
+        .and(with_store(store.clone()))
+        .and(with_storage(storage))
+        .and(with_config(config))
+        .and(with_principal(store))
+        .and_then(delete_handler);
+
+    upload.or(download).or(move_asset).or(copy).or(delete)
+}
+/**
+ * POST /folders/{id}/assets?tag=..&extension=.. with the raw file as the request body, with or
+ * without a Content-Length, the body is streamed to storage chunk by chunk
+ */
+async fn upload_handler(
+    id: String,
+    store: SharedStore,
+    storage: SharedStorage,
+    config: Arc<Config>,
+    principal: Principal,
+    query: UploadQuery,
+    body: impl Stream<Item = Result<impl Buf, warp::Error>> + Send + 'static,
+) -> Result<impl Reply, Rejection> {
+    let folder = parse_object_id(&id)?;
+    let file_stream = Box::pin(
+        body.map_ok(|mut chunk| chunk.to_bytes())
+            .map_err(Error::other),
+    );
+
+    let stored_asset = save_asset_stream(
+        store.as_ref(),
+        storage.as_ref(),
+        &config,
+        &principal,
+        file_stream,
+        &query.tag,
+        &folder,
+        &query.extension,
+    )
+    .await
+    .map_err(reject)?;
+
+    Ok(json(
+        &UploadReply {
+            id: stored_asset.id.to_hex(),
+            size: stored_asset.size,
+            checksum: stored_asset.checksum,
+        },
+        StatusCode::CREATED,
+    ))
+}
+/**
+ * GET /assets/{id or uuid}, streams the file and honours Range / If-Range with 206 responses, full
+ * downloads are checked against the checksum of the asset unless verify_downloads is off
+ */
+async fn download_handler(
+    id_or_uuid: String,
+    store: SharedStore,
+    storage: SharedStorage,
+    config: Arc<Config>,
+    principal: Principal,
+    range: Option<String>,
+    if_range: Option<String>,
+) -> Result<Response<Body>, Rejection> {
+    let asset_doc = get_asset(store.as_ref(), &principal, &id_or_uuid)
+        .await
+        .map_err(reject)?
+        .ok_or_else(|| {
+            reject_with(
+                StatusCode::NOT_FOUND,
+                &format!("No asset found for {}", id_or_uuid),
+            )
+        })?;
+
+    let key = asset_key(&config.storage_root, &asset_doc).map_err(reject)?;
+    let object_meta = match storage.stat(&key).await.map_err(reject)? {
+        Some(object_meta) => object_meta,
+        None => {
+            if asset_doc.integrity != Integrity::Missing {
+                let _ = record_integrity(store.as_ref(), &asset_doc, Integrity::Missing).await;
+            }
+
+            return Err(reject_with(
+                StatusCode::NOT_FOUND,
+                &format!("The bytes of asset {} are missing in storage", id_or_uuid),
+            ));
+        }
+    };
+    let len = object_meta.size;
+    let last_modified = object_meta.modified;
+
+    // An asset's bytes never change for a given uuid, so it makes a strong validator
+    let etag = format!("\"{}\"", asset_doc.uuid);
+
+    let byte_range = match &range {
+        Some(range) if if_range_matches(if_range.as_deref(), &etag, last_modified) => {
+            parse_range(range, len)
+        }
+        _ => ByteRange::Full,
+    };
+
+    // Assets are served from the API origin, so browsers may neither guess their type nor render
+    // the scriptable ones inline, where they could act with the credentials of whoever opens them
+    let mut response = Response::builder()
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CONTENT_TYPE, content_type(&asset_doc.path))
+        .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+        .header(header::ETAG, &etag)
+        .header(header::LAST_MODIFIED, http_date(last_modified));
+    if is_scriptable(&asset_doc.path) {
+        response = response.header(header::CONTENT_DISPOSITION, "attachment");
+    }
+
+    let response = match byte_range {
+        ByteRange::Full => {
+            let mut bytes = storage.get(&key).await.map_err(reject)?;
+            if config.verify_downloads && !asset_doc.checksum.is_empty() {
+                bytes = verifying_stream(bytes, asset_doc.clone(), store.clone());
+            }
+
+            response
+                .status(StatusCode::OK)
+                .header(header::CONTENT_LENGTH, len)
+                .body(Body::wrap_stream(bytes))
+        }
+        ByteRange::Partial(start, end) => {
+            let bytes = storage.get_range(&key, start, end).await.map_err(reject)?;
+            let part_len = end - start + 1;
+
+            response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_LENGTH, part_len)
+                .header(
+                    header::CONTENT_RANGE,
+                    format!("bytes {}-{}/{}", start, end, len),
+                )
+                .body(Body::wrap_stream(bytes))
+        }
+        ByteRange::Unsatisfiable => response
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+            .body(Body::empty()),
+    };
+
+    response.map_err(|err| reject_with(StatusCode::INTERNAL_SERVER_ERROR, &err.to_string()))
+}
+/**
+ * PATCH /assets/{id or uuid} { folder?, tag? }, move the asset to another folder and/or retag it
+ */
+async fn move_handler(
+    id_or_uuid: String,
+    store: SharedStore,
+    storage: SharedStorage,
+    config: Arc<Config>,
+    principal: Principal,
+    body: MoveAssetBody,
+) -> Result<impl Reply, Rejection> {
+    let folder = match &body.folder {
+        Some(id) => Some(parse_object_id(id)?),
+        None => None,
+    };
+
+    let asset_doc = move_asset(
+        store.as_ref(),
+        storage.as_ref(),
+        &config,
+        &principal,
+        &id_or_uuid,
+        folder.as_ref(),
+        body.tag.as_deref(),
+    )
+    .await
+    .map_err(reject)?;
+
+    Ok(json(&AssetView::from(&asset_doc), StatusCode::OK))
+}
+/**
+ * POST /assets/{id or uuid}/copies { folder, tag? }, copy the asset into a folder as a new asset
+ */
+async fn copy_handler(
+    id_or_uuid: String,
+    store: SharedStore,
+    storage: SharedStorage,
+    config: Arc<Config>,
+    principal: Principal,
+    body: CopyAssetBody,
+) -> Result<impl Reply, Rejection> {
+    let folder = parse_object_id(&body.folder)?;
+    let asset_doc = copy_asset(
+        store.as_ref(),
+        storage.as_ref(),
+        &config,
+        &principal,
+        &id_or_uuid,
+        &folder,
+        body.tag.as_deref(),
+    )
+    .await
+    .map_err(reject)?;
+
+    Ok(json(&AssetView::from(&asset_doc), StatusCode::CREATED))
+}
+/**
+ * DELETE /assets/{id or uuid}
+ */
+async fn delete_handler(
+    id_or_uuid: String,
+    store: SharedStore,
+    storage: SharedStorage,
+    config: Arc<Config>,
+    principal: Principal,
+) -> Result<impl Reply, Rejection> {
+    delete_asset(
+        store.as_ref(),
+        storage.as_ref(),
+        &config,
+        &principal,
+        &id_or_uuid,
+    )
+    .await
+    .map_err(reject)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+/**
+ * Guess the Content-Type of an asset from its extension
+ */
+fn content_type(path: &str) -> &'static str {
+    match lowercase_extension(path).as_str() {
+        "mp4" | "m4v" => "video/mp4",
+        "webm" => "video/webm",
+        "mov" => "video/quicktime",
+        "mkv" => "video/x-matroska",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "ogg" => "audio/ogg",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "pdf" => "application/pdf",
+        "json" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "html" => "text/html; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+/**
+ * Whether a browser would run scripts in an asset with this extension when showing it inline
+ */
+fn is_scriptable(path: &str) -> bool {
+    matches!(
+        lowercase_extension(path).as_str(),
+        "html" | "htm" | "xhtml" | "svg" | "xml" | "js"
+    )
+}
+
+fn lowercase_extension(path: &str) -> String {
+    Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("")
+        .to_lowercase()
+}
@@ -0,0 +1,81 @@
+use crate::api::reply::{reject, reject_with};
+use crate::controller::access::Principal;
+use crate::controller::key::resolve_key;
+use crate::controller::session::resolve_session;
+use crate::store::{MetaStore, SharedStore};
+use warp::http::StatusCode;
+use warp::{Filter, Rejection};
+
+/**
+ * Filter resolving the caller of a request from its Authorization header, requests without one
+ * are anonymous and requests with an unknown, expired or revoked token or key are rejected with 401
+ */
+pub fn with_principal(
+    store: SharedStore,
+) -> impl Filter<Extract = (Principal,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization").and_then(move |authorization| {
+        let store = store.clone();
+        async move { resolve_principal(store.as_ref(), authorization).await }
+    })
+}
+/**
+ * Filter extracting the bearer token of a request, rejecting with 401 if there is none
+ */
+pub fn bearer_token() -> impl Filter<Extract = (String,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization").and_then(
+        |authorization: Option<String>| async move {
+            authorization
+                .as_deref()
+                .and_then(parse_bearer)
+                .map(|token| token.to_string())
+                .ok_or_else(unauthorized)
+        },
+    )
+}
+
+async fn resolve_principal(
+    store: &dyn MetaStore,
+    authorization: Option<String>,
+) -> Result<Principal, Rejection> {
+    let authorization = match authorization {
+        Some(authorization) => authorization,
+        None => return Ok(Principal::Anonymous),
+    };
+
+    let token = parse_bearer(&authorization).ok_or_else(unauthorized)?;
+
+    // API keys look like <uuid>.<secret>, session tokens are plain hex
+    if token.contains('.') {
+        let key_doc = resolve_key(store, token).await.map_err(reject)?;
+
+        return match key_doc {
+            Some(key_doc) => Ok(Principal::Key(Box::new(key_doc))),
+            None => Err(unauthorized()),
+        };
+    }
+
+    let user_doc = resolve_session(store, token).await.map_err(reject)?;
+
+    match user_doc {
+        Some(user_doc) => Ok(Principal::User(Box::new(user_doc))),
+        None => Err(unauthorized()),
+    }
+}
+/**
+ * Parse "Bearer <token>" into its token
+ */
+fn parse_bearer(authorization: &str) -> Option<&str> {
+    let token = authorization.trim().strip_prefix("Bearer ")?.trim();
+    if token.is_empty() {
+        return None;
+    }
+
+    Some(token)
+}
+
+fn unauthorized() -> Rejection {
+    reject_with(
+        StatusCode::UNAUTHORIZED,
+        "Missing, invalid or expired bearer token",
+    )
+}
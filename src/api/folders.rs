@@ -0,0 +1,356 @@
+use crate::api::assets::AssetView;
+use crate::api::auth::with_principal;
+use crate::api::keys::UserBody;
+use crate::api::reply::{json, parse_object_id, reject};
+use crate::api::users::UserView;
+use crate::api::{with_config, with_storage, with_store};
+use crate::config::Config;
+use crate::controller::access::Principal;
+use crate::controller::file_system::{
+    add_folder_admin, copy_folder, create_folder, create_sub_folder, delete_folder, get_folder,
+    list_folder, move_folder, remove_folder_admin, FolderListing,
+};
+use crate::data_models::folder::Folder;
+use crate::storage::SharedStorage;
+use crate::store::SharedStore;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+/**
+ * ____________________________________________________________________________________________
+ * FolderView, the JSON representation of a Folder
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug, Serialize)]
+pub struct FolderView {
+    pub id: Option<String>,
+    pub tag: String,
+    pub path: String,
+    pub files: Vec<String>,
+    pub is_public: bool,
+    pub access_groups: Vec<String>,
+    pub timestamp: String,
+    pub timestamp_readable: String,
+}
+
+impl From<&Folder> for FolderView {
+    fn from(folder: &Folder) -> FolderView {
+        FolderView {
+            id: folder.id.as_ref().map(|id| id.to_hex()),
+            tag: folder.tag.clone(),
+            path: folder.path.clone(),
+            files: folder.files.iter().map(|id| id.to_hex()).collect(),
+            is_public: folder.is_public,
+            access_groups: folder.access_groups.iter().map(|id| id.to_hex()).collect(),
+            timestamp: folder.timestamp.clone(),
+            timestamp_readable: folder.timestamp_readable.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct FolderListingView {
+    pub folder: FolderView,
+    pub assets: Vec<AssetView>,
+    pub sub_folders: Vec<FolderView>,
+}
+
+impl From<&FolderListing> for FolderListingView {
+    fn from(listing: &FolderListing) -> FolderListingView {
+        FolderListingView {
+            folder: FolderView::from(&listing.folder),
+            assets: listing.assets.iter().map(AssetView::from).collect(),
+            sub_folders: listing.sub_folders.iter().map(FolderView::from).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateFolderBody {
+    pub name: String,
+    pub parent_path: Option<String>,
+    pub access_group: Option<String>,
+}
+#[derive(Debug, Deserialize)]
+pub struct MoveFolderBody {
+    pub name: Option<String>,
+    pub parent_path: Option<String>,
+}
+#[derive(Debug, Deserialize)]
+pub struct CopyFolderBody {
+    pub name: String,
+    pub parent_path: Option<String>,
+    #[serde(default)]
+    pub keep_access: bool,
+}
+/**
+ * Routes under /folders
+ */
+pub fn routes(
+    store: SharedStore,
+    storage: SharedStorage,
+    config: Arc<Config>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let create = warp::path!("folders")
+        .and(warp::post())
+        .and(with_store(store.clone()))
+        .and(with_storage(storage.clone()))
+        .and(with_config(config.clone()))
+        .and(with_principal(store.clone()))
+        .and(warp::body::json())
+        .and_then(create_folder_handler);
+
+    let list = warp::path!("folders" / String)
+        .and(warp::get())
+        .and(with_store(store.clone()))
+        .and(with_principal(store.clone()))
+        .and_then(list_folder_handler);
+
+    let move_folder = warp::path!("folders" / String)
+        .and(warp::patch())
+        .and(with_store(store.clone()))
+        .and(with_storage(storage.clone()))
+        .and(with_config(config.clone()))
+        .and(with_principal(store.clone()))
+        .and(warp::body::json())
+        .and_then(move_folder_handler);
+
+    let copy = warp::path!("folders" / String / "copies")
+        .and(warp::post())
+        .and(with_store(store.clone()))
+        .and(with_storage(storage.clone()))
+        .and(with_config(config.clone()))
+        .and(with_principal(store.clone()))
+        .and(warp::body::json())
+        .and_then(copy_folder_handler);
+
+    let delete = warp::path!("folders" / String)
+        .and(warp::delete())
+        .and(with_store(store.clone()))
+        .and(with_storage(storage))
+        .and(with_config(config))
+        .and(with_principal(store.clone()))
+        .and_then(delete_folder_handler);
+
+    let add_admin = warp::path!("folders" / String / "admins")
+        .and(warp::post())
+        .and(with_store(store.clone()))
+        .and(with_principal(store.clone()))
+        .and(warp::body::json())
+        .and_then(add_admin_handler);
+
+    let remove_admin = warp::path!("folders" / String / "admins" / String)
+        .and(warp::delete())
+        .and(with_store(store.clone()))
+        .and(with_principal(store))
+        .and_then(remove_admin_handler);
+
+    create
+        .or(list)
+        .or(move_folder)
+        .or(copy)
+        .or(delete)
+        .or(add_admin)
+        .or(remove_admin)
+}
+/**
+ * POST /folders { name, parent_path?, access_group? }
+ */
+async fn create_folder_handler(
+    store: SharedStore,
+    storage: SharedStorage,
+    config: Arc<Config>,
+    principal: Principal,
+    body: CreateFolderBody,
+) -> Result<impl Reply, Rejection> {
+    let access_group = match &body.access_group {
+        Some(id) => Some(parse_object_id(id)?),
+        None => None,
+    };
+
+    let folder_doc = match &body.parent_path {
+        Some(parent_path) => {
+            create_sub_folder(
+                store.as_ref(),
+                storage.as_ref(),
+                &config,
+                &principal,
+                parent_path,
+                &body.name,
+                access_group,
+            )
+            .await
+        }
+        None => {
+            create_folder(
+                store.as_ref(),
+                storage.as_ref(),
+                &config,
+                &principal,
+                &body.name,
+                access_group,
+                None,
+            )
+            .await
+        }
+    }
+    .map_err(reject)?;
+
+    Ok(json(&FolderView::from(&folder_doc), StatusCode::CREATED))
+}
+/**
+ * GET /folders/{id}, the folder with its assets and sub folders
+ */
+async fn list_folder_handler(
+    id: String,
+    store: SharedStore,
+    principal: Principal,
+) -> Result<impl Reply, Rejection> {
+    let folder = parse_object_id(&id)?;
+    let listing = list_folder(store.as_ref(), &principal, &folder)
+        .await
+        .map_err(reject)?;
+
+    Ok(json(&FolderListingView::from(&listing), StatusCode::OK))
+}
+/**
+ * PATCH /folders/{id} { name?, parent_path? }, rename the folder and/or move it below another one,
+ * with everything below it. Left out they stay as they are, the storage root as parent_path makes it
+ * a top level folder
+ */
+async fn move_folder_handler(
+    id: String,
+    store: SharedStore,
+    storage: SharedStorage,
+    config: Arc<Config>,
+    principal: Principal,
+    body: MoveFolderBody,
+) -> Result<impl Reply, Rejection> {
+    let folder = parse_object_id(&id)?;
+    let folder_doc = get_folder(store.as_ref(), &folder).await.map_err(reject)?;
+
+    let name = body.name.unwrap_or_else(|| folder_doc.tag.clone());
+    let parent_path = body
+        .parent_path
+        .unwrap_or_else(|| parent_path_of(&config, &folder_doc));
+
+    let folder_doc = move_folder(
+        store.as_ref(),
+        storage.as_ref(),
+        &config,
+        &principal,
+        &folder,
+        &parent_path,
+        &name,
+    )
+    .await
+    .map_err(reject)?;
+
+    Ok(json(&FolderView::from(&folder_doc), StatusCode::OK))
+}
+/**
+ * POST /folders/{id}/copies { name, parent_path?, keep_access? }, copy the folder with everything
+ * below it, next to the original unless parent_path is given
+ */
+async fn copy_folder_handler(
+    id: String,
+    store: SharedStore,
+    storage: SharedStorage,
+    config: Arc<Config>,
+    principal: Principal,
+    body: CopyFolderBody,
+) -> Result<impl Reply, Rejection> {
+    let folder = parse_object_id(&id)?;
+    let parent_path = match body.parent_path {
+        Some(parent_path) => parent_path,
+        None => {
+            let folder_doc = get_folder(store.as_ref(), &folder).await.map_err(reject)?;
+            parent_path_of(&config, &folder_doc)
+        }
+    };
+
+    let folder_doc = copy_folder(
+        store.as_ref(),
+        storage.as_ref(),
+        &config,
+        &principal,
+        &folder,
+        &parent_path,
+        &body.name,
+        body.keep_access,
+    )
+    .await
+    .map_err(reject)?;
+
+    Ok(json(&FolderView::from(&folder_doc), StatusCode::CREATED))
+}
+/**
+ * The path of the folder above folder, the storage root for a top level folder
+ */
+fn parent_path_of(config: &Config, folder: &Folder) -> String {
+    Path::new(&folder.path)
+        .parent()
+        .and_then(|parent| parent.to_str())
+        .unwrap_or(&config.storage_root)
+        .to_string()
+}
+/**
+ * DELETE /folders/{id}, deletes every sub folder and asset below it as well
+ */
+async fn delete_folder_handler(
+    id: String,
+    store: SharedStore,
+    storage: SharedStorage,
+    config: Arc<Config>,
+    principal: Principal,
+) -> Result<impl Reply, Rejection> {
+    let folder = parse_object_id(&id)?;
+    delete_folder(
+        store.as_ref(),
+        storage.as_ref(),
+        &config,
+        &principal,
+        &folder,
+    )
+    .await
+    .map_err(reject)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+/**
+ * POST /folders/{id}/admins { user }, make a user an admin of the folder
+ */
+async fn add_admin_handler(
+    id: String,
+    store: SharedStore,
+    principal: Principal,
+    body: UserBody,
+) -> Result<impl Reply, Rejection> {
+    let folder = parse_object_id(&id)?;
+    let user = parse_object_id(&body.user)?;
+    let user_doc = add_folder_admin(store.as_ref(), &principal, &folder, &user)
+        .await
+        .map_err(reject)?;
+
+    Ok(json(&UserView::from(&user_doc), StatusCode::OK))
+}
+/**
+ * DELETE /folders/{id}/admins/{user}, take the folder away from one of its admins
+ */
+async fn remove_admin_handler(
+    id: String,
+    user: String,
+    store: SharedStore,
+    principal: Principal,
+) -> Result<impl Reply, Rejection> {
+    let folder = parse_object_id(&id)?;
+    let user = parse_object_id(&user)?;
+    let user_doc = remove_folder_admin(store.as_ref(), &principal, &folder, &user)
+        .await
+        .map_err(reject)?;
+
+    Ok(json(&UserView::from(&user_doc), StatusCode::OK))
+}
@@ -0,0 +1,185 @@
+use crate::api::auth::with_principal;
+use crate::api::reply::{json, parse_object_id, reject};
+use crate::api::with_store;
+use crate::controller::access::Principal;
+use crate::controller::auth::{
+    add_key_to_access_group, delete_access_group, register_new_access_group,
+    remove_key_from_access_group, rename_access_group,
+};
+use crate::data_models::access_group::AccessGroup;
+use crate::store::SharedStore;
+use serde::{Deserialize, Serialize};
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+/**
+ * ____________________________________________________________________________________________
+ * AccessGroupView, the JSON representation of an AccessGroup
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug, Serialize)]
+pub struct AccessGroupView {
+    pub id: Option<String>,
+    pub tag: String,
+    pub allowed_keys: Vec<String>,
+    pub timestamp: String,
+    pub timestamp_readable: String,
+}
+
+impl From<&AccessGroup> for AccessGroupView {
+    fn from(access_group: &AccessGroup) -> AccessGroupView {
+        AccessGroupView {
+            id: access_group.id.as_ref().map(|id| id.to_hex()),
+            tag: access_group.tag.clone(),
+            allowed_keys: access_group
+                .allowed_keys
+                .iter()
+                .map(|id| id.to_hex())
+                .collect(),
+            timestamp: access_group.timestamp.clone(),
+            timestamp_readable: access_group.timestamp_readable.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TagBody {
+    pub tag: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KeyBody {
+    pub key: String,
+}
+/**
+ * Routes under /groups
+ */
+pub fn routes(store: SharedStore) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let create = warp::path!("groups")
+        .and(warp::post())
+        .and(with_store(store.clone()))
+        .and(with_principal(store.clone()))
+        .and(warp::body::json())
+        .and_then(create_group_handler);
+
+    let rename = warp::path!("groups" / String)
+        .and(warp::patch())
+        .and(with_store(store.clone()))
+        .and(with_principal(store.clone()))
+        .and(warp::body::json())
+        .and_then(rename_group_handler);
+
+    let delete = warp::path!("groups" / String)
+        .and(warp::delete())
+        .and(with_store(store.clone()))
+        .and(with_principal(store.clone()))
+        .and_then(delete_group_handler);
+
+    let add_key = warp::path!("groups" / String / "keys")
+        .and(warp::post())
+        .and(with_store(store.clone()))
+        .and(with_principal(store.clone()))
+        .and(warp::body::json())
+        .and_then(add_key_handler);
+
+    let remove_key = warp::path!("groups" / String / "keys" / String)
+        .and(warp::delete())
+        .and(with_store(store.clone()))
+        .and(with_principal(store))
+        .and_then(remove_key_handler);
+
+    create.or(rename).or(delete).or(add_key).or(remove_key)
+}
+/**
+ * POST /groups { tag }
+ */
+async fn create_group_handler(
+    store: SharedStore,
+    principal: Principal,
+    body: TagBody,
+) -> Result<impl Reply, Rejection> {
+    let access_group_doc = register_new_access_group(store.as_ref(), &principal, &body.tag)
+        .await
+        .map_err(reject)?;
+
+    Ok(json(
+        &AccessGroupView::from(&access_group_doc),
+        StatusCode::CREATED,
+    ))
+}
+/**
+ * PATCH /groups/{id} { tag }
+ */
+async fn rename_group_handler(
+    id: String,
+    store: SharedStore,
+    principal: Principal,
+    body: TagBody,
+) -> Result<impl Reply, Rejection> {
+    let access_group = parse_object_id(&id)?;
+    let access_group_doc =
+        rename_access_group(store.as_ref(), &principal, &access_group, &body.tag)
+            .await
+            .map_err(reject)?;
+
+    Ok(json(
+        &AccessGroupView::from(&access_group_doc),
+        StatusCode::OK,
+    ))
+}
+/**
+ * DELETE /groups/{id}
+ */
+async fn delete_group_handler(
+    id: String,
+    store: SharedStore,
+    principal: Principal,
+) -> Result<impl Reply, Rejection> {
+    let access_group = parse_object_id(&id)?;
+    delete_access_group(store.as_ref(), &principal, &access_group)
+        .await
+        .map_err(reject)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+/**
+ * POST /groups/{id}/keys { key }
+ */
+async fn add_key_handler(
+    id: String,
+    store: SharedStore,
+    principal: Principal,
+    body: KeyBody,
+) -> Result<impl Reply, Rejection> {
+    let access_group = parse_object_id(&id)?;
+    let key = parse_object_id(&body.key)?;
+    let access_group_doc = add_key_to_access_group(store.as_ref(), &principal, &access_group, &key)
+        .await
+        .map_err(reject)?;
+
+    Ok(json(
+        &AccessGroupView::from(&access_group_doc),
+        StatusCode::OK,
+    ))
+}
+/**
+ * DELETE /groups/{id}/keys/{key}
+ */
+async fn remove_key_handler(
+    id: String,
+    key: String,
+    store: SharedStore,
+    principal: Principal,
+) -> Result<impl Reply, Rejection> {
+    let access_group = parse_object_id(&id)?;
+    let key = parse_object_id(&key)?;
+    let access_group_doc =
+        remove_key_from_access_group(store.as_ref(), &principal, &access_group, &key)
+            .await
+            .map_err(reject)?;
+
+    Ok(json(
+        &AccessGroupView::from(&access_group_doc),
+        StatusCode::OK,
+    ))
+}
@@ -0,0 +1,175 @@
+use crate::api::auth::with_principal;
+use crate::api::reply::{json, parse_object_id, reject};
+use crate::api::users::UserView;
+use crate::api::with_store;
+use crate::controller::access::Principal;
+use crate::controller::key::{assign_key, create_key, delete_key, set_key_active, unassign_key};
+use crate::data_models::key::Key;
+use crate::store::SharedStore;
+use serde::{Deserialize, Serialize};
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+/**
+ * ____________________________________________________________________________________________
+ * KeyView, the JSON representation of a Key (never includes the secret hash)
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug, Serialize)]
+pub struct KeyView {
+    pub id: Option<String>,
+    pub uuid: String,
+    pub active: bool,
+    pub timestamp: String,
+    pub timestamp_readable: String,
+}
+
+impl From<&Key> for KeyView {
+    fn from(key: &Key) -> KeyView {
+        KeyView {
+            id: key.id.as_ref().map(|id| id.to_hex()),
+            uuid: key.uuid.clone(),
+            active: key.active,
+            timestamp: key.timestamp.clone(),
+            timestamp_readable: key.timestamp_readable.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateKeyReply {
+    pub token: String,
+    pub key: KeyView,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ActiveBody {
+    pub active: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserBody {
+    pub user: String,
+}
+/**
+ * Routes under /keys
+ */
+pub fn routes(store: SharedStore) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let create = warp::path!("keys")
+        .and(warp::post())
+        .and(with_store(store.clone()))
+        .and(with_principal(store.clone()))
+        .and_then(create_key_handler);
+
+    let set_active = warp::path!("keys" / String)
+        .and(warp::patch())
+        .and(with_store(store.clone()))
+        .and(with_principal(store.clone()))
+        .and(warp::body::json())
+        .and_then(set_active_handler);
+
+    let delete = warp::path!("keys" / String)
+        .and(warp::delete())
+        .and(with_store(store.clone()))
+        .and(with_principal(store.clone()))
+        .and_then(delete_key_handler);
+
+    let assign = warp::path!("keys" / String / "users")
+        .and(warp::post())
+        .and(with_store(store.clone()))
+        .and(with_principal(store.clone()))
+        .and(warp::body::json())
+        .and_then(assign_handler);
+
+    let unassign = warp::path!("keys" / String / "users" / String)
+        .and(warp::delete())
+        .and(with_store(store.clone()))
+        .and(with_principal(store))
+        .and_then(unassign_handler);
+
+    create.or(set_active).or(delete).or(assign).or(unassign)
+}
+/**
+ * POST /keys, answers with the keys bearer token which is never shown again
+ */
+async fn create_key_handler(
+    store: SharedStore,
+    principal: Principal,
+) -> Result<impl Reply, Rejection> {
+    let issued_key = create_key(store.as_ref(), &principal)
+        .await
+        .map_err(reject)?;
+
+    Ok(json(
+        &CreateKeyReply {
+            key: KeyView::from(&issued_key.key),
+            token: issued_key.token,
+        },
+        StatusCode::CREATED,
+    ))
+}
+/**
+ * PATCH /keys/{id} { active }, revoke or reactivate a key
+ */
+async fn set_active_handler(
+    id: String,
+    store: SharedStore,
+    principal: Principal,
+    body: ActiveBody,
+) -> Result<impl Reply, Rejection> {
+    let key = parse_object_id(&id)?;
+    let key_doc = set_key_active(store.as_ref(), &principal, &key, body.active)
+        .await
+        .map_err(reject)?;
+
+    Ok(json(&KeyView::from(&key_doc), StatusCode::OK))
+}
+/**
+ * DELETE /keys/{id}, removes the key from every user and access group
+ */
+async fn delete_key_handler(
+    id: String,
+    store: SharedStore,
+    principal: Principal,
+) -> Result<impl Reply, Rejection> {
+    let key = parse_object_id(&id)?;
+    delete_key(store.as_ref(), &principal, &key)
+        .await
+        .map_err(reject)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+/**
+ * POST /keys/{id}/users { user }, hand the key to a user
+ */
+async fn assign_handler(
+    id: String,
+    store: SharedStore,
+    principal: Principal,
+    body: UserBody,
+) -> Result<impl Reply, Rejection> {
+    let key = parse_object_id(&id)?;
+    let user = parse_object_id(&body.user)?;
+    let user_doc = assign_key(store.as_ref(), &principal, &key, &user)
+        .await
+        .map_err(reject)?;
+
+    Ok(json(&UserView::from(&user_doc), StatusCode::OK))
+}
+/**
+ * DELETE /keys/{id}/users/{user}, take the key away from a user
+ */
+async fn unassign_handler(
+    id: String,
+    user: String,
+    store: SharedStore,
+    principal: Principal,
+) -> Result<impl Reply, Rejection> {
+    let key = parse_object_id(&id)?;
+    let user = parse_object_id(&user)?;
+    let user_doc = unassign_key(store.as_ref(), &principal, &key, &user)
+        .await
+        .map_err(reject)?;
+
+    Ok(json(&UserView::from(&user_doc), StatusCode::OK))
+}
@@ -0,0 +1,73 @@
+pub mod assets;
+pub mod auth;
+pub mod folders;
+pub mod groups;
+pub mod keys;
+pub mod range;
+pub mod reply;
+pub mod sessions;
+pub mod users;
+
+use crate::config::Config;
+use crate::storage::SharedStorage;
+use crate::store::SharedStore;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use warp::{Filter, Reply};
+
+/**
+ * All routes of the HTTP API, with rejections turned into JSON error replies
+ */
+pub fn routes(
+    store: SharedStore,
+    storage: SharedStorage,
+    config: Arc<Config>,
+) -> impl Filter<Extract = impl Reply, Error = Infallible> + Clone {
+    users::routes(store.clone(), config.clone())
+        .or(sessions::routes(store.clone(), config.clone()))
+        .or(folders::routes(
+            store.clone(),
+            storage.clone(),
+            config.clone(),
+        ))
+        .or(groups::routes(store.clone()))
+        .or(keys::routes(store.clone()))
+        .or(assets::routes(store, storage, config))
+        .recover(reply::handle_rejection)
+}
+/**
+ * Serve the HTTP API on the given address until the process is stopped
+ */
+pub async fn serve(
+    store: SharedStore,
+    storage: SharedStorage,
+    config: Arc<Config>,
+    addr: SocketAddr,
+) {
+    warp::serve(routes(store, storage, config)).run(addr).await;
+}
+/**
+ * Filter to hand the shared metadata store to a route handler
+ */
+pub fn with_store(
+    store: SharedStore,
+) -> impl Filter<Extract = (SharedStore,), Error = Infallible> + Clone {
+    warp::any().map(move || store.clone())
+}
+/**
+ * Filter to hand the shared asset storage to a route handler
+ */
+pub fn with_storage(
+    storage: SharedStorage,
+) -> impl Filter<Extract = (SharedStorage,), Error = Infallible> + Clone {
+    warp::any().map(move || storage.clone())
+}
+/**
+ * Filter to hand the shared Config to a route handler
+ */
+pub fn with_config(
+    config: Arc<Config>,
+) -> impl Filter<Extract = (Arc<Config>,), Error = Infallible> + Clone {
+    warp::any().map(move || config.clone())
+}
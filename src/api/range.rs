@@ -0,0 +1,101 @@
+use chrono::{DateTime, Utc};
+
+/**
+ * ____________________________________________________________________________________________
+ * ByteRange, outcome of resolving a Range header against a resource of a known length
+ * ____________________________________________________________________________________________
+ * Full: Serve the whole resource (no Range header, an ignored If-Range, an invalid or a multi
+ *       range request)
+ * Partial: Serve the inclusive byte range start..=end
+ * Unsatisfiable: The range lies outside the resource, answer 416
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug, PartialEq, Eq)]
+pub enum ByteRange {
+    Full,
+    Partial(u64, u64),
+    Unsatisfiable,
+}
+/**
+ * Resolve a Range header value ("bytes=0-99", "bytes=100-", "bytes=-100") against a length. A range
+ * that can't be parsed or ends before it starts is invalid and ignored, only a valid one starting
+ * past the end of the resource is unsatisfiable
+ */
+pub fn parse_range(header: &str, len: u64) -> ByteRange {
+    let spec = match header.trim().strip_prefix("bytes=") {
+        Some(spec) => spec.trim(),
+        None => return ByteRange::Full,
+    };
+
+    // Only single ranges are served as 206, it is valid to ignore multi range requests
+    if spec.contains(',') {
+        return ByteRange::Full;
+    }
+
+    let (start, end) = match spec.split_once('-') {
+        Some(bounds) => bounds,
+        None => return ByteRange::Full,
+    };
+
+    let (start, end) = (start.trim(), end.trim());
+    if start.is_empty() {
+        // Suffix range, the last n bytes
+        let suffix_len = match end.parse::<u64>() {
+            Ok(suffix_len) => suffix_len,
+            Err(_) => return ByteRange::Full,
+        };
+
+        if suffix_len == 0 || len == 0 {
+            return ByteRange::Unsatisfiable;
+        }
+
+        return ByteRange::Partial(len.saturating_sub(suffix_len), len - 1);
+    }
+
+    let start = match start.parse::<u64>() {
+        Ok(start) => start,
+        Err(_) => return ByteRange::Full,
+    };
+
+    let end = if end.is_empty() {
+        None
+    } else {
+        match end.parse::<u64>() {
+            Ok(end) if end < start => return ByteRange::Full,
+            Ok(end) => Some(end),
+            Err(_) => return ByteRange::Full,
+        }
+    };
+
+    if start >= len {
+        return ByteRange::Unsatisfiable;
+    }
+
+    ByteRange::Partial(start, end.unwrap_or(len - 1).min(len - 1))
+}
+/**
+ * Decide if a Range header may be honoured given the If-Range header, the current ETag and
+ * the last modification time of the resource. A date has to be the exact Last-Modified date
+ */
+pub fn if_range_matches(if_range: Option<&str>, etag: &str, last_modified: DateTime<Utc>) -> bool {
+    let if_range = match if_range {
+        Some(if_range) => if_range.trim(),
+        None => return true,
+    };
+
+    // Weak validators can never be used with If-Range
+    if if_range.starts_with('"') || if_range.starts_with("W/") {
+        return if_range == etag;
+    }
+
+    match DateTime::parse_from_rfc2822(if_range) {
+        Ok(date) => date.timestamp() == last_modified.timestamp(),
+        Err(_) => false,
+    }
+}
+/**
+ * Format a timestamp as an HTTP date (RFC 7231 IMF-fixdate)
+ */
+pub fn http_date(date: DateTime<Utc>) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
@@ -0,0 +1,136 @@
+use crate::controller::error::ControllerError;
+use serde::Serialize;
+use std::convert::Infallible;
+use warp::http::StatusCode;
+use warp::{reject::Reject, Rejection, Reply};
+use wither::mongodb::bson::oid::ObjectId;
+
+/**
+ * ____________________________________________________________________________________________
+ * ApiError
+ * ____________________________________________________________________________________________
+ * status: HTTP status code to answer with
+ * code: Stable machine readable error code, clients can branch on it
+ * message: Human readable description of what went wrong
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl Reject for ApiError {}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: &str) -> ApiError {
+        ApiError {
+            status,
+            code: status_code_name(status),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<ControllerError> for ApiError {
+    fn from(err: ControllerError) -> ApiError {
+        ApiError {
+            status: StatusCode::from_u16(err.status_code())
+                .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            code: err.code(),
+            message: err.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+    code: &'static str,
+}
+/**
+ * Error code of a rejection that didn't come from a controller, based on its status code
+ */
+fn status_code_name(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::BAD_REQUEST => "invalid_input",
+        StatusCode::UNAUTHORIZED => "unauthorized",
+        StatusCode::FORBIDDEN => "forbidden",
+        StatusCode::NOT_FOUND => "not_found",
+        StatusCode::METHOD_NOT_ALLOWED => "method_not_allowed",
+        StatusCode::CONFLICT => "already_exists",
+        StatusCode::PAYLOAD_TOO_LARGE => "payload_too_large",
+        StatusCode::RANGE_NOT_SATISFIABLE => "range_not_satisfiable",
+        _ => "internal_error",
+    }
+}
+/**
+ * Turn a ControllerError into a warp Rejection
+ */
+pub fn reject(err: ControllerError) -> Rejection {
+    warp::reject::custom(ApiError::from(err))
+}
+/**
+ * Reject with a specific status code and message
+ */
+pub fn reject_with(status: StatusCode, message: &str) -> Rejection {
+    warp::reject::custom(ApiError::new(status, message))
+}
+/**
+ * Parse a hex string ObjectId, rejecting with 400 if it is malformed
+ */
+pub fn parse_object_id(id: &str) -> Result<ObjectId, Rejection> {
+    ObjectId::with_string(id).map_err(|_| {
+        reject_with(
+            StatusCode::BAD_REQUEST,
+            &format!("{} is not a valid ObjectId", id),
+        )
+    })
+}
+/**
+ * Build a JSON reply with a status code
+ */
+pub fn json<T: Serialize>(
+    body: &T,
+    status: StatusCode,
+) -> warp::reply::WithStatus<warp::reply::Json> {
+    warp::reply::with_status(warp::reply::json(body), status)
+}
+/**
+ * Map every rejection to a JSON body of the form { "error": "...", "code": "..." }
+ */
+pub async fn handle_rejection(rejection: Rejection) -> Result<impl Reply, Infallible> {
+    if let Some(api_error) = rejection.find::<ApiError>() {
+        let body = ErrorBody {
+            error: api_error.message.clone(),
+            code: api_error.code,
+        };
+        return Ok(json(&body, api_error.status));
+    }
+
+    let (status, message) = if rejection.is_not_found() {
+        (StatusCode::NOT_FOUND, "Not found".to_string())
+    } else if let Some(body_error) = rejection.find::<warp::filters::body::BodyDeserializeError>() {
+        (StatusCode::BAD_REQUEST, body_error.to_string())
+    } else if rejection.find::<warp::reject::PayloadTooLarge>().is_some() {
+        (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "Payload too large".to_string(),
+        )
+    } else if rejection.find::<warp::reject::MethodNotAllowed>().is_some() {
+        (
+            StatusCode::METHOD_NOT_ALLOWED,
+            "Method not allowed".to_string(),
+        )
+    } else {
+        (StatusCode::BAD_REQUEST, format!("{:?}", rejection))
+    };
+
+    let body = ErrorBody {
+        error: message,
+        code: status_code_name(status),
+    };
+
+    Ok(json(&body, status))
+}
@@ -0,0 +1,146 @@
+use crate::api::auth::{bearer_token, with_principal};
+use crate::api::reply::{json, parse_object_id, reject, reject_with};
+use crate::api::users::{Credentials, UserView};
+use crate::api::{with_config, with_store};
+use crate::config::Config;
+use crate::controller::access::Principal;
+use crate::controller::auth::login_user;
+use crate::controller::session::{list_sessions, logout_session, revoke_session};
+use crate::data_models::session::Session;
+use crate::store::SharedStore;
+use serde::Serialize;
+use std::sync::Arc;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+/**
+ * ____________________________________________________________________________________________
+ * SessionView, the JSON representation of a Session (never includes the token hash)
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug, Serialize)]
+pub struct SessionView {
+    pub id: Option<String>,
+    pub user: String,
+    pub expires_at: i64,
+    pub expires_at_readable: String,
+    pub timestamp: String,
+    pub timestamp_readable: String,
+}
+
+impl From<&Session> for SessionView {
+    fn from(session: &Session) -> SessionView {
+        SessionView {
+            id: session.id.as_ref().map(|id| id.to_hex()),
+            user: session.user.to_hex(),
+            expires_at: session.expires_at,
+            expires_at_readable: session.expires_at_readable.clone(),
+            timestamp: session.timestamp.clone(),
+            timestamp_readable: session.timestamp_readable.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct LoginReply {
+    pub token: String,
+    pub session: SessionView,
+    pub user: UserView,
+}
+/**
+ * Routes under /sessions
+ */
+pub fn routes(
+    store: SharedStore,
+    config: Arc<Config>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let login = warp::path!("sessions")
+        .and(warp::post())
+        .and(with_store(store.clone()))
+        .and(with_config(config))
+        .and(warp::body::json())
+        .and_then(login_handler);
+
+    let list = warp::path!("sessions")
+        .and(warp::get())
+        .and(with_store(store.clone()))
+        .and(with_principal(store.clone()))
+        .and_then(list_handler);
+
+    let logout = warp::path!("sessions")
+        .and(warp::delete())
+        .and(with_store(store.clone()))
+        .and(bearer_token())
+        .and_then(logout_handler);
+
+    let revoke = warp::path!("sessions" / String)
+        .and(warp::delete())
+        .and(with_store(store.clone()))
+        .and(with_principal(store))
+        .and_then(revoke_handler);
+
+    login.or(list).or(logout).or(revoke)
+}
+/**
+ * POST /sessions { user, pass }
+ */
+async fn login_handler(
+    store: SharedStore,
+    config: Arc<Config>,
+    body: Credentials,
+) -> Result<impl Reply, Rejection> {
+    let issued_session = login_user(store.as_ref(), &config, &body.user, &body.pass)
+        .await
+        .map_err(reject)?;
+
+    match issued_session {
+        Some(issued_session) => Ok(json(
+            &LoginReply {
+                session: SessionView::from(&issued_session.session),
+                user: UserView::from(&issued_session.user),
+                token: issued_session.token,
+            },
+            StatusCode::CREATED,
+        )),
+        None => Err(reject_with(
+            StatusCode::UNAUTHORIZED,
+            "Invalid username or password",
+        )),
+    }
+}
+/**
+ * GET /sessions, the callers active sessions
+ */
+async fn list_handler(store: SharedStore, principal: Principal) -> Result<impl Reply, Rejection> {
+    let sessions = list_sessions(store.as_ref(), &principal)
+        .await
+        .map_err(reject)?;
+    let session_views: Vec<SessionView> = sessions.iter().map(SessionView::from).collect();
+
+    Ok(json(&session_views, StatusCode::OK))
+}
+/**
+ * DELETE /sessions, logout of the session of the presented token
+ */
+async fn logout_handler(store: SharedStore, token: String) -> Result<impl Reply, Rejection> {
+    logout_session(store.as_ref(), &token)
+        .await
+        .map_err(reject)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+/**
+ * DELETE /sessions/{id}, revoke another one of the callers sessions
+ */
+async fn revoke_handler(
+    id: String,
+    store: SharedStore,
+    principal: Principal,
+) -> Result<impl Reply, Rejection> {
+    let session = parse_object_id(&id)?;
+    revoke_session(store.as_ref(), &principal, &session)
+        .await
+        .map_err(reject)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,111 @@
+use crate::api::auth::with_principal;
+use crate::api::reply::{json, parse_object_id, reject};
+use crate::api::{with_config, with_store};
+use crate::config::Config;
+use crate::controller::access::Principal;
+use crate::controller::file_system::{delete_user, register_user};
+use crate::data_models::user::User;
+use crate::store::SharedStore;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+/**
+ * ____________________________________________________________________________________________
+ * UserView, the public representation of a User (never includes the password hash)
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug, Serialize)]
+pub struct UserView {
+    pub id: Option<String>,
+    pub user: String,
+    pub keys: Vec<String>,
+    pub key_admins: Vec<String>,
+    pub user_admins: Vec<String>,
+    pub folder_admins: Vec<String>,
+    pub access_group_admins: Vec<String>,
+    pub superuser: bool,
+    pub timestamp: String,
+    pub timestamp_readable: String,
+}
+
+impl From<&User> for UserView {
+    fn from(user: &User) -> UserView {
+        UserView {
+            id: user.id.as_ref().map(|id| id.to_hex()),
+            user: user.user.clone(),
+            keys: user.keys.iter().map(|id| id.to_hex()).collect(),
+            key_admins: user.key_admins.iter().map(|id| id.to_hex()).collect(),
+            user_admins: user.user_admins.iter().map(|id| id.to_hex()).collect(),
+            folder_admins: user.folder_admins.iter().map(|id| id.to_hex()).collect(),
+            access_group_admins: user
+                .access_group_admins
+                .iter()
+                .map(|id| id.to_hex())
+                .collect(),
+            superuser: user.superuser,
+            timestamp: user.timestamp.clone(),
+            timestamp_readable: user.timestamp_readable.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Credentials {
+    pub user: String,
+    pub pass: String,
+}
+/**
+ * Routes under /users
+ */
+pub fn routes(
+    store: SharedStore,
+    config: Arc<Config>,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let create = warp::path!("users")
+        .and(warp::post())
+        .and(with_store(store.clone()))
+        .and(with_config(config))
+        .and(with_principal(store.clone()))
+        .and(warp::body::json())
+        .and_then(create_user_handler);
+
+    let delete = warp::path!("users" / String)
+        .and(warp::delete())
+        .and(with_store(store.clone()))
+        .and(with_principal(store))
+        .and_then(delete_user_handler);
+
+    create.or(delete)
+}
+/**
+ * POST /users { user, pass }, open to anyone only with open_registration
+ */
+async fn create_user_handler(
+    store: SharedStore,
+    config: Arc<Config>,
+    principal: Principal,
+    body: Credentials,
+) -> Result<impl Reply, Rejection> {
+    let user_doc = register_user(store.as_ref(), &config, &principal, &body.user, &body.pass)
+        .await
+        .map_err(reject)?;
+
+    Ok(json(&UserView::from(&user_doc), StatusCode::CREATED))
+}
+/**
+ * DELETE /users/{id}
+ */
+async fn delete_user_handler(
+    id: String,
+    store: SharedStore,
+    principal: Principal,
+) -> Result<impl Reply, Rejection> {
+    let user = parse_object_id(&id)?;
+    delete_user(store.as_ref(), &principal, &user)
+        .await
+        .map_err(reject)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,740 @@
+use clap::{Parser, Subcommand};
+use file_server::config::Config;
+use file_server::controller::{
+    access::Principal,
+    auth::{add_key_to_access_group, register_new_access_group},
+    error::ControllerError,
+    file_system::{
+        add_folder_admin, copy_asset, copy_folder, create_folder, create_sub_folder,
+        create_superuser, create_user, delete_asset, delete_folder, delete_user, get_asset,
+        get_folder, get_folder_by_path, get_user_by_name, list_folder, list_folder_tree,
+        list_users, move_asset, move_folder, remove_folder_admin, save_asset_stream,
+        set_user_password,
+    },
+    key::{create_key, set_key_active},
+};
+use file_server::data_models::folder::Folder;
+use file_server::fsck::fsck;
+use file_server::import::{import_tree, ImportEvent};
+use file_server::scrub::{print_report, scrub};
+use file_server::storage::{asset_key, open_storage, Storage};
+use file_server::store::{open_store, MetaStore};
+use file_server::util::{get_file_stream, get_random_token};
+use futures::stream::StreamExt;
+use std::path::Path;
+use std::process::exit;
+use tokio::io::AsyncWriteExt;
+use wither::mongodb::bson::oid::ObjectId;
+
+/**
+ * Operator tool working directly on the metadata store and storage of a file-server, every command
+ * runs as the user given by --as (the configured admin_user by default) and goes through the same
+ * controllers, and so the same access checks, as the HTTP API
+ */
+#[derive(Parser)]
+#[clap(
+    name = "file-server-admin",
+    version,
+    about = "Manage the users, folders, keys, access groups and assets of a file-server",
+    long_about = None
+)]
+struct Cli {
+    /// Username to act as, defaults to admin_user of the config
+    #[clap(long = "as", global = true)]
+    as_user: Option<String>,
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Manage users
+    #[clap(subcommand)]
+    User(UserCommand),
+    /// Manage folders, given by ObjectId or path
+    #[clap(subcommand)]
+    Folder(FolderCommand),
+    /// Manage API keys
+    #[clap(subcommand)]
+    Key(KeyCommand),
+    /// Manage access groups
+    #[clap(subcommand)]
+    Group(GroupCommand),
+    /// Manage assets
+    #[clap(subcommand)]
+    Asset(AssetCommand),
+    /// Re-read every asset and flag the corrupt and missing ones, superusers only
+    Scrub,
+    /// Turn a directory and everything below it into folders and assets, tagged with their names
+    Import {
+        directory: String,
+        /// Folder to import into, a top level folder is created without one
+        #[clap(long)]
+        parent: Option<String>,
+        /// User that becomes an admin of the imported folder
+        #[clap(long)]
+        admin: Option<String>,
+    },
+    /// Report where storage and the metadata disagree, superusers only
+    Fsck {
+        /// Undo what was found: delete broken docs and orphaned bytes, fix lists and blob references
+        #[clap(long)]
+        repair: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum UserCommand {
+    /// Create a user, a password is generated if none is given
+    Create {
+        name: String,
+        #[clap(long)]
+        pass: Option<String>,
+        #[clap(long)]
+        superuser: bool,
+    },
+    /// List the users visible to the acting user
+    List,
+    /// Delete a user
+    Delete { name: String },
+    /// Change the password of a user, a password is generated if none is given
+    Passwd {
+        name: String,
+        #[clap(long)]
+        pass: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum FolderCommand {
+    /// Create a top level folder, or a sub folder of --parent
+    Create {
+        name: String,
+        #[clap(long)]
+        parent: Option<String>,
+        #[clap(long)]
+        group: Option<String>,
+    },
+    /// List the assets and sub folders of a folder
+    List { folder: String },
+    /// Show a folder and every folder below it
+    Tree { folder: String },
+    /// Move a folder below --parent (the storage root for the top level) and/or rename it
+    Mv {
+        folder: String,
+        #[clap(long)]
+        parent: Option<String>,
+        #[clap(long)]
+        name: Option<String>,
+    },
+    /// Copy a folder with everything below it as name, next to it or below --parent
+    Cp {
+        folder: String,
+        name: String,
+        #[clap(long)]
+        parent: Option<String>,
+        /// Keep the access groups and visibility of the originals instead of making the copies private
+        #[clap(long)]
+        keep_access: bool,
+    },
+    /// Delete a folder with everything below it
+    Delete { folder: String },
+    /// Make a user an admin of a folder
+    AddAdmin { folder: String, user: String },
+    /// Take a folder away from one of its admins
+    RemoveAdmin { folder: String, user: String },
+}
+
+#[derive(Subcommand)]
+enum KeyCommand {
+    /// Create a key, its token is shown once
+    Create,
+    /// Revoke a key
+    Revoke { key: String },
+}
+
+#[derive(Subcommand)]
+enum GroupCommand {
+    /// Create an access group
+    Create { tag: String },
+    /// Allow a key in an access group
+    AddKey { group: String, key: String },
+}
+
+#[derive(Subcommand)]
+enum AssetCommand {
+    /// Upload a file into a folder, the tag defaults to the file name
+    Put {
+        folder: String,
+        file: String,
+        #[clap(long)]
+        tag: Option<String>,
+    },
+    /// Download an asset, by ObjectId or uuid, to a file
+    Get { asset: String, file: String },
+    /// Move an asset, by ObjectId or uuid, to another folder and/or retag it
+    Mv {
+        asset: String,
+        #[clap(long)]
+        folder: Option<String>,
+        #[clap(long)]
+        tag: Option<String>,
+    },
+    /// Copy an asset, by ObjectId or uuid, into a folder as a new asset
+    Cp {
+        asset: String,
+        folder: String,
+        #[clap(long)]
+        tag: Option<String>,
+    },
+    /// Delete an asset, by ObjectId or uuid
+    Rm { asset: String },
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+
+    let config = Config::load().unwrap_or_else(|err| fail(&err.to_string()));
+    let store = match open_store(&config).await {
+        Ok(store) => store,
+        Err(err) => fail(&format!("Could not open the metadata store: {}", err)),
+    };
+    let storage = match open_storage(&config).await {
+        Ok(storage) => storage,
+        Err(err) => fail(&format!("Could not open the asset storage: {}", err)),
+    };
+
+    if let Err(err) = run(store.as_ref(), storage.as_ref(), &config, cli).await {
+        fail(&format!("{} ({})", err, err.code()));
+    }
+}
+
+async fn run(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    cli: Cli,
+) -> Result<(), ControllerError> {
+    let as_user = cli.as_user.as_deref().unwrap_or(&config.admin_user);
+    let principal = Principal::User(Box::new(get_user_by_name(store, as_user).await?));
+
+    match cli.command {
+        Command::User(command) => run_user(store, config, &principal, command).await,
+        Command::Folder(command) => run_folder(store, storage, config, &principal, command).await,
+        Command::Key(command) => run_key(store, &principal, command).await,
+        Command::Group(command) => run_group(store, &principal, command).await,
+        Command::Asset(command) => run_asset(store, storage, config, &principal, command).await,
+        Command::Scrub => run_scrub(store, storage, config, &principal).await,
+        Command::Import {
+            directory,
+            parent,
+            admin,
+        } => {
+            run_import(
+                store, storage, config, &principal, &directory, parent, admin,
+            )
+            .await
+        }
+        Command::Fsck { repair } => run_fsck(store, storage, config, &principal, repair).await,
+    }
+}
+
+async fn run_scrub(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    principal: &Principal,
+) -> Result<(), ControllerError> {
+    // The scrub reads every asset of every user
+    if !principal.require_user()?.superuser {
+        return Err(ControllerError::Forbidden(
+            "Only superusers can scrub assets".to_string(),
+        ));
+    }
+
+    let report = scrub(store, storage, config).await?;
+    print_report(&report);
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run_import(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    principal: &Principal,
+    directory: &str,
+    parent: Option<String>,
+    admin: Option<String>,
+) -> Result<(), ControllerError> {
+    let parent = match parent {
+        Some(parent) => Some(find_folder(store, &parent).await?.path),
+        None => None,
+    };
+    let admin = match admin {
+        Some(admin) => Some(require_id(&get_user_by_name(store, &admin).await?.id)?),
+        None => None,
+    };
+
+    let report = import_tree(
+        store,
+        storage,
+        config,
+        principal,
+        Path::new(directory),
+        parent.as_deref(),
+        admin.as_ref(),
+        &print_import_event,
+    )
+    .await?;
+    println!("{}", report.summary());
+
+    Ok(())
+}
+
+fn print_import_event(event: ImportEvent) {
+    match event {
+        ImportEvent::Folder(path, folder, true) => {
+            println!("Created folder {} for {}", folder.path, path.display())
+        }
+        ImportEvent::Folder(path, folder, false) => {
+            println!("Using folder {} for {}", folder.path, path.display())
+        }
+        ImportEvent::Asset(path, stored_asset) => println!(
+            "Stored asset {} ({} bytes) for {}",
+            stored_asset.id,
+            stored_asset.size,
+            path.display()
+        ),
+        ImportEvent::Unchanged(path) => println!("Already an asset: {}", path.display()),
+        ImportEvent::Conflict(path, reason) => {
+            println!("CONFLICT: Left out {}: {}", path.display(), reason)
+        }
+        ImportEvent::Failed(path, err) => {
+            println!("ERROR: Could not import {}: {}", path.display(), err)
+        }
+    }
+}
+
+async fn run_fsck(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    principal: &Principal,
+    repair: bool,
+) -> Result<(), ControllerError> {
+    // The check looks at, and the repair changes, everything of every user
+    if !principal.require_user()?.superuser {
+        return Err(ControllerError::Forbidden(
+            "Only superusers can check storage and metadata".to_string(),
+        ));
+    }
+
+    let report = fsck(store, storage, config, repair).await?;
+    file_server::fsck::print_report(&report);
+
+    Ok(())
+}
+
+async fn run_user(
+    store: &dyn MetaStore,
+    config: &Config,
+    principal: &Principal,
+    command: UserCommand,
+) -> Result<(), ControllerError> {
+    match command {
+        UserCommand::Create {
+            name,
+            pass,
+            superuser,
+        } => {
+            let (pass, generated) = pass_or_generated(pass);
+            let user = if superuser {
+                // Only superusers can make more of them
+                if !principal.require_user()?.superuser {
+                    return Err(ControllerError::Forbidden(
+                        "Only superusers can create superusers".to_string(),
+                    ));
+                }
+                create_superuser(store, config, &name, &pass).await?
+            } else {
+                create_user(store, config, &name, &pass).await?
+            };
+            println!("Created user {} {}", user.user, hex(&user.id));
+            print_generated(generated, &pass);
+        }
+        UserCommand::List => {
+            for user in list_users(store, principal).await? {
+                let role = if user.superuser { " (superuser)" } else { "" };
+                println!("{} {}{}", hex(&user.id), user.user, role);
+            }
+        }
+        UserCommand::Delete { name } => {
+            let user = get_user_by_name(store, &name).await?;
+            delete_user(store, principal, &require_id(&user.id)?).await?;
+            println!("Deleted user {}", name);
+        }
+        UserCommand::Passwd { name, pass } => {
+            let (pass, generated) = pass_or_generated(pass);
+            let user = get_user_by_name(store, &name).await?;
+            set_user_password(store, config, principal, &require_id(&user.id)?, &pass).await?;
+            println!("Changed the password of {}, its sessions are revoked", name);
+            print_generated(generated, &pass);
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_folder(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    principal: &Principal,
+    command: FolderCommand,
+) -> Result<(), ControllerError> {
+    match command {
+        FolderCommand::Create {
+            name,
+            parent,
+            group,
+        } => {
+            let group = group.as_deref().map(parse_id).transpose()?;
+            let folder = match parent {
+                Some(parent) => {
+                    let parent = find_folder(store, &parent).await?;
+                    create_sub_folder(
+                        store,
+                        storage,
+                        config,
+                        principal,
+                        &parent.path,
+                        &name,
+                        group,
+                    )
+                    .await?
+                }
+                None => {
+                    create_folder(store, storage, config, principal, &name, group, None).await?
+                }
+            };
+            println!("Created folder {} {}", folder.path, hex(&folder.id));
+        }
+        FolderCommand::List { folder } => {
+            let folder = find_folder(store, &folder).await?;
+            let listing = list_folder(store, principal, &require_id(&folder.id)?).await?;
+            for sub_folder in &listing.sub_folders {
+                println!("{} {}/", hex(&sub_folder.id), sub_folder.tag);
+            }
+            for asset in &listing.assets {
+                println!("{} {} {}", hex(&asset.id), asset.uuid, asset.tag);
+            }
+        }
+        FolderCommand::Tree { folder } => {
+            let folder = find_folder(store, &folder).await?;
+            let depth = folder.path.matches('/').count();
+            for node in list_folder_tree(store, principal, &require_id(&folder.id)?).await? {
+                let indent = "  ".repeat(node.path.matches('/').count() - depth);
+                println!(
+                    "{}{}/ ({} assets) {}",
+                    indent,
+                    node.tag,
+                    node.files.len(),
+                    hex(&node.id)
+                );
+            }
+        }
+        FolderCommand::Mv {
+            folder,
+            parent,
+            name,
+        } => {
+            let folder = find_folder(store, &folder).await?;
+            let parent_path = parent_path(store, config, &folder, parent).await?;
+            let name = name.unwrap_or_else(|| folder.tag.clone());
+            let moved = move_folder(
+                store,
+                storage,
+                config,
+                principal,
+                &require_id(&folder.id)?,
+                &parent_path,
+                &name,
+            )
+            .await?;
+            println!("Moved folder {} to {}", folder.path, moved.path);
+        }
+        FolderCommand::Cp {
+            folder,
+            name,
+            parent,
+            keep_access,
+        } => {
+            let folder = find_folder(store, &folder).await?;
+            let parent_path = parent_path(store, config, &folder, parent).await?;
+            let copy = copy_folder(
+                store,
+                storage,
+                config,
+                principal,
+                &require_id(&folder.id)?,
+                &parent_path,
+                &name,
+                keep_access,
+            )
+            .await?;
+            println!(
+                "Copied folder {} to {} {}",
+                folder.path,
+                copy.path,
+                hex(&copy.id)
+            );
+        }
+        FolderCommand::Delete { folder } => {
+            let folder = find_folder(store, &folder).await?;
+            delete_folder(store, storage, config, principal, &require_id(&folder.id)?).await?;
+            println!("Deleted folder {}", folder.path);
+        }
+        FolderCommand::AddAdmin { folder, user } => {
+            let folder = find_folder(store, &folder).await?;
+            let user_doc = get_user_by_name(store, &user).await?;
+            add_folder_admin(
+                store,
+                principal,
+                &require_id(&folder.id)?,
+                &require_id(&user_doc.id)?,
+            )
+            .await?;
+            println!("Made {} an admin of folder {}", user_doc.user, folder.path);
+        }
+        FolderCommand::RemoveAdmin { folder, user } => {
+            let folder = find_folder(store, &folder).await?;
+            let user_doc = get_user_by_name(store, &user).await?;
+            remove_folder_admin(
+                store,
+                principal,
+                &require_id(&folder.id)?,
+                &require_id(&user_doc.id)?,
+            )
+            .await?;
+            println!("Took folder {} away from {}", folder.path, user_doc.user);
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_key(
+    store: &dyn MetaStore,
+    principal: &Principal,
+    command: KeyCommand,
+) -> Result<(), ControllerError> {
+    match command {
+        KeyCommand::Create => {
+            let issued_key = create_key(store, principal).await?;
+            println!("Created key {}", hex(&issued_key.key.id));
+            println!(
+                "Its token is {} , it won't be shown again",
+                issued_key.token
+            );
+        }
+        KeyCommand::Revoke { key } => {
+            let key_doc = set_key_active(store, principal, &parse_id(&key)?, false).await?;
+            println!("Revoked key {}", hex(&key_doc.id));
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_group(
+    store: &dyn MetaStore,
+    principal: &Principal,
+    command: GroupCommand,
+) -> Result<(), ControllerError> {
+    match command {
+        GroupCommand::Create { tag } => {
+            let access_group = register_new_access_group(store, principal, &tag).await?;
+            println!(
+                "Created access group {} {}",
+                access_group.tag,
+                hex(&access_group.id)
+            );
+        }
+        GroupCommand::AddKey { group, key } => {
+            let access_group =
+                add_key_to_access_group(store, principal, &parse_id(&group)?, &parse_id(&key)?)
+                    .await?;
+            println!("Added key {} to access group {}", key, access_group.tag);
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_asset(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    principal: &Principal,
+    command: AssetCommand,
+) -> Result<(), ControllerError> {
+    match command {
+        AssetCommand::Put { folder, file, tag } => {
+            let folder = find_folder(store, &folder).await?;
+            let file_path = Path::new(&file);
+            let extension = file_path
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .unwrap_or_default();
+            let tag = match tag {
+                Some(tag) => tag,
+                None => file_path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .unwrap_or_default()
+                    .to_string(),
+            };
+
+            let file_stream = get_file_stream(&file)
+                .await
+                .ok_or_else(|| ControllerError::NotFound(format!("Could not open {}", file)))?;
+            let stored_asset = save_asset_stream(
+                store,
+                storage,
+                config,
+                principal,
+                file_stream,
+                &tag,
+                &require_id(&folder.id)?,
+                extension,
+            )
+            .await?;
+            println!(
+                "Stored asset {} ({} bytes, sha256 {})",
+                stored_asset.id, stored_asset.size, stored_asset.checksum
+            );
+        }
+        AssetCommand::Get { asset, file } => {
+            let asset_doc = get_asset(store, principal, &asset).await?.ok_or_else(|| {
+                ControllerError::NotFound(format!("No asset found for {}", asset))
+            })?;
+            let mut bytes = storage
+                .get(&asset_key(&config.storage_root, &asset_doc)?)
+                .await?;
+            let mut out = tokio::fs::File::create(&file).await?;
+            let mut size: u64 = 0;
+            while let Some(chunk) = bytes.next().await {
+                let chunk = chunk?;
+                size += chunk.len() as u64;
+                out.write_all(&chunk).await?;
+            }
+            out.flush().await?;
+            println!("Wrote {} bytes to {}", size, file);
+        }
+        AssetCommand::Mv { asset, folder, tag } => {
+            let folder = match folder {
+                Some(folder) => Some(require_id(&find_folder(store, &folder).await?.id)?),
+                None => None,
+            };
+            let moved = move_asset(
+                store,
+                storage,
+                config,
+                principal,
+                &asset,
+                folder.as_ref(),
+                tag.as_deref(),
+            )
+            .await?;
+            println!("Moved asset {} to {} ({})", asset, moved.path, moved.tag);
+        }
+        AssetCommand::Cp { asset, folder, tag } => {
+            let folder = require_id(&find_folder(store, &folder).await?.id)?;
+            let copy = copy_asset(
+                store,
+                storage,
+                config,
+                principal,
+                &asset,
+                &folder,
+                tag.as_deref(),
+            )
+            .await?;
+            println!("Copied asset {} to {} {}", asset, copy.path, hex(&copy.id));
+        }
+        AssetCommand::Rm { asset } => {
+            delete_asset(store, storage, config, principal, &asset).await?;
+            println!("Deleted asset {}", asset);
+        }
+    }
+
+    Ok(())
+}
+/**
+ * Find a folder by its ObjectId (hex) or its path
+ */
+async fn find_folder(store: &dyn MetaStore, folder: &str) -> Result<Folder, ControllerError> {
+    match ObjectId::with_string(folder) {
+        Ok(id) => get_folder(store, &id).await,
+        Err(_) => get_folder_by_path(store, folder).await,
+    }
+}
+
+/**
+ * The path of the folder given by parent (the storage root itself for the top level), or of the
+ * folder above folder without one
+ */
+async fn parent_path(
+    store: &dyn MetaStore,
+    config: &Config,
+    folder: &Folder,
+    parent: Option<String>,
+) -> Result<String, ControllerError> {
+    match parent {
+        Some(parent) if parent == config.storage_root => Ok(parent),
+        Some(parent) => Ok(find_folder(store, &parent).await?.path),
+        None => Ok(Path::new(&folder.path)
+            .parent()
+            .and_then(|parent| parent.to_str())
+            .unwrap_or(&config.storage_root)
+            .to_string()),
+    }
+}
+
+fn parse_id(id: &str) -> Result<ObjectId, ControllerError> {
+    ObjectId::with_string(id)
+        .map_err(|_| ControllerError::InvalidInput(format!("{} is not a valid ObjectId", id)))
+}
+
+fn require_id(id: &Option<ObjectId>) -> Result<ObjectId, ControllerError> {
+    id.clone()
+        .ok_or_else(|| ControllerError::Internal("Doc has no ObjectId".to_string()))
+}
+
+fn hex(id: &Option<ObjectId>) -> String {
+    id.as_ref().map(|id| id.to_hex()).unwrap_or_default()
+}
+
+fn pass_or_generated(pass: Option<String>) -> (String, bool) {
+    match pass {
+        Some(pass) => (pass, false),
+        None => (get_random_token(), true),
+    }
+}
+
+fn print_generated(generated: bool, pass: &str) {
+    if generated {
+        println!(
+            "Its generated password is {} , it won't be shown again",
+            pass
+        );
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("ERROR: {}", message);
+    exit(1)
+}
@@ -0,0 +1,55 @@
+use crate::config::Config;
+use crate::controller::error::ControllerError;
+use crate::controller::file_system::create_superuser;
+use crate::data_models::user::User;
+use crate::store::MetaStore;
+use crate::util::get_random_token;
+
+/**
+ * ____________________________________________________________________________________________
+ * InitialSuperuser, the superuser created by the bootstrap of an empty DB
+ * ____________________________________________________________________________________________
+ * user: The stored User doc
+ * generated_pass: The password if it was generated, it is never stored or shown again
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug)]
+pub struct InitialSuperuser {
+    pub user: User,
+    pub generated_pass: Option<String>,
+}
+/**
+ * Prepare the DB for use, this never deletes anything and is safe to run on every start:
+ * prepares the metadata store (indexes, tables) and creates the initial superuser, but only while
+ * no users exist at all, asset storage prepares itself when it is opened
+ */
+pub async fn bootstrap(
+    store: &dyn MetaStore,
+    config: &Config,
+) -> Result<Option<InitialSuperuser>, ControllerError> {
+    store.prepare().await?;
+
+    if store.count_users().await? > 0 {
+        return Ok(None);
+    }
+
+    let (pass, generated_pass) = match &config.admin_pass {
+        Some(pass) => (pass.clone(), None),
+        None => {
+            let pass = get_random_token();
+            (pass.clone(), Some(pass))
+        }
+    };
+
+    // The unique index on the username keeps two instances starting at once from both creating one
+    let user = match create_superuser(store, config, &config.admin_user, &pass).await {
+        Ok(user) => user,
+        Err(ControllerError::AlreadyExists(_)) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    Ok(Some(InitialSuperuser {
+        user,
+        generated_pass,
+    }))
+}
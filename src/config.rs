@@ -0,0 +1,420 @@
+use crate::constants::*;
+use crate::controller::paths::{validate_password, validate_user_name};
+use serde::Deserialize;
+use std::env;
+use std::fmt;
+use std::fs::read_to_string;
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+
+const MIN_BCRYPT_COST: u32 = 4;
+const MAX_BCRYPT_COST: u32 = 31;
+// Every part of an S3 multipart upload but the last has to be at least 5 MiB
+const MIN_S3_PART_SIZE: u64 = 5 * 1024 * 1024;
+const MAX_S3_PART_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+
+/**
+ * ____________________________________________________________________________________________
+ * Config, everything that differs between a dev, staging and prod deployment
+ * ____________________________________________________________________________________________
+ * storage_root: Directory every folder and asset is stored below
+ * storage_backend: Where the bytes of assets are kept
+ * content_addressed: Store the bytes of new uploads once by their checksum, shared between assets
+ * metadata_backend: Where users, folders, assets, keys and access groups are kept
+ * mongo_uri: Connection string of the MongoDB server
+ * db_name: Name of the MongoDB database
+ * sqlite_path: File of the SQLite database, created if missing
+ * s3_endpoint: URL of the S3 compatible server, AWS in s3_region if left out
+ * s3_region: Region requests to S3 are signed for
+ * s3_bucket: Bucket the bytes of assets are kept in, it has to exist
+ * s3_access_key: Access key id to sign requests to S3 with
+ * s3_secret_key: Secret access key to sign requests to S3 with
+ * s3_part_size: Size of the parts large uploads are sent to S3 in, in bytes
+ * bcrypt_cost: Cost factor used to hash passwords
+ * listen_addr: Address the HTTP API listens on
+ * max_upload_size: Largest accepted upload, in bytes
+ * open_registration: Let anyone create a user through the API, only superusers can otherwise
+ * session_ttl_secs: Lifetime of a session, in seconds
+ * verify_downloads: Check the bytes of full downloads against the checksum of their asset
+ * scrub_interval_secs: Time between two checks of every asset by the server, 0 turns it off
+ * admin_user: Name of the superuser created when the DB has no users yet
+ * admin_pass: Password of that superuser, one is generated and printed once if left out
+ * demo_pass: Password the seed command gives the demo user, one is generated and printed once if left out
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub storage_root: String,
+    pub storage_backend: StorageBackend,
+    pub content_addressed: bool,
+    pub metadata_backend: MetadataBackend,
+    pub mongo_uri: String,
+    pub db_name: String,
+    pub sqlite_path: String,
+    pub s3_endpoint: Option<String>,
+    pub s3_region: String,
+    pub s3_bucket: String,
+    pub s3_access_key: String,
+    pub s3_secret_key: String,
+    pub s3_part_size: u64,
+    pub bcrypt_cost: u32,
+    pub listen_addr: String,
+    pub max_upload_size: u64,
+    pub open_registration: bool,
+    pub session_ttl_secs: u64,
+    pub verify_downloads: bool,
+    pub scrub_interval_secs: u64,
+    pub admin_user: String,
+    pub admin_pass: Option<String>,
+    pub demo_pass: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            storage_root: DEFAULT_STORAGE_ROOT.to_string(),
+            storage_backend: StorageBackend::Local,
+            content_addressed: false,
+            metadata_backend: MetadataBackend::Mongo,
+            mongo_uri: DEFAULT_MONGO_URI.to_string(),
+            db_name: DEFAULT_DB_NAME.to_string(),
+            sqlite_path: DEFAULT_SQLITE_PATH.to_string(),
+            s3_endpoint: None,
+            s3_region: DEFAULT_S3_REGION.to_string(),
+            s3_bucket: DEFAULT_S3_BUCKET.to_string(),
+            s3_access_key: String::new(),
+            s3_secret_key: String::new(),
+            s3_part_size: DEFAULT_S3_PART_SIZE,
+            bcrypt_cost: DEFAULT_BCRYPT_COST,
+            listen_addr: DEFAULT_LISTEN_ADDR.to_string(),
+            max_upload_size: DEFAULT_MAX_UPLOAD_SIZE,
+            open_registration: false,
+            session_ttl_secs: DEFAULT_SESSION_TTL_SECS,
+            verify_downloads: true,
+            scrub_interval_secs: DEFAULT_SCRUB_INTERVAL_SECS,
+            admin_user: DEFAULT_ADMIN_USER.to_string(),
+            admin_pass: None,
+            demo_pass: None,
+        }
+    }
+}
+
+/**
+ * ____________________________________________________________________________________________
+ * MetadataBackend, the store metadata is kept in
+ * ____________________________________________________________________________________________
+ * Mongo: MongoDB at mongo_uri, in the database db_name
+ * Sqlite: SQLite in the file sqlite_path, for single node deployments
+ * Memory: In memory, gone when the process exits, for tests and demos
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetadataBackend {
+    Mongo,
+    Sqlite,
+    Memory,
+}
+
+impl FromStr for MetadataBackend {
+    type Err = ConfigError;
+
+    fn from_str(value: &str) -> Result<MetadataBackend, ConfigError> {
+        match value {
+            "mongo" => Ok(MetadataBackend::Mongo),
+            "sqlite" => Ok(MetadataBackend::Sqlite),
+            "memory" => Ok(MetadataBackend::Memory),
+            _ => Err(ConfigError::Invalid(format!(
+                "metadata_backend must be mongo, sqlite or memory, got {}",
+                value
+            ))),
+        }
+    }
+}
+
+/**
+ * ____________________________________________________________________________________________
+ * StorageBackend, the storage the bytes of assets are kept in
+ * ____________________________________________________________________________________________
+ * Local: Files below storage_root
+ * Memory: In memory, gone when the process exits, for tests and demos
+ * S3: Objects in s3_bucket of an S3 compatible server, like AWS S3 or MinIO
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    Local,
+    Memory,
+    S3,
+}
+
+impl FromStr for StorageBackend {
+    type Err = ConfigError;
+
+    fn from_str(value: &str) -> Result<StorageBackend, ConfigError> {
+        match value {
+            "local" => Ok(StorageBackend::Local),
+            "memory" => Ok(StorageBackend::Memory),
+            "s3" => Ok(StorageBackend::S3),
+            _ => Err(ConfigError::Invalid(format!(
+                "storage_backend must be local, memory or s3, got {}",
+                value
+            ))),
+        }
+    }
+}
+
+/**
+ * ____________________________________________________________________________________________
+ * ConfigError, every way loading the config can fail
+ * ____________________________________________________________________________________________
+ * Io: The config file exists but couldn't be read
+ * Parse: The config file isn't valid TOML, or has unknown or mistyped keys
+ * Invalid: A value, from the file or the environment, is out of range
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(String, std::io::Error),
+    Parse(String, toml::de::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => write!(f, "Could not read config {}: {}", path, err),
+            ConfigError::Parse(path, err) => write!(f, "Could not parse config {}: {}", path, err),
+            ConfigError::Invalid(message) => write!(f, "Invalid config: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io(_, err) => Some(err),
+            ConfigError::Parse(_, err) => Some(err),
+            ConfigError::Invalid(_) => None,
+        }
+    }
+}
+
+impl Config {
+    /**
+     * Load the config file named by FILE_SERVER_CONFIG (or ./file-server.toml), then apply the
+     * FILE_SERVER_* environment overrides and validate the result, a missing default file is fine
+     */
+    pub fn load() -> Result<Config, ConfigError> {
+        let (path, explicit) = match env::var(CONFIG_PATH_ENV) {
+            Ok(path) => (path, true),
+            Err(_) => (DEFAULT_CONFIG_PATH.to_string(), false),
+        };
+
+        let mut config = match read_to_string(Path::new(&path)) {
+            Ok(contents) => Config::from_toml(&path, &contents)?,
+            Err(err) if err.kind() == ErrorKind::NotFound && !explicit => Config::default(),
+            Err(err) => return Err(ConfigError::Io(path, err)),
+        };
+
+        config.apply_env()?;
+        config.validate()?;
+
+        Ok(config)
+    }
+    /**
+     * Parse a config from TOML, keys that are left out keep their default
+     */
+    pub fn from_toml(path: &str, contents: &str) -> Result<Config, ConfigError> {
+        toml::from_str(contents).map_err(|err| ConfigError::Parse(path.to_string(), err))
+    }
+    /**
+     * Override values with the FILE_SERVER_* environment variables that are set
+     */
+    pub fn apply_env(&mut self) -> Result<(), ConfigError> {
+        override_string(&mut self.storage_root, "FILE_SERVER_STORAGE_ROOT");
+        if let Ok(env_value) = env::var("FILE_SERVER_STORAGE_BACKEND") {
+            self.storage_backend = env_value.trim().parse()?;
+        }
+        if let Ok(env_value) = env::var("FILE_SERVER_METADATA_BACKEND") {
+            self.metadata_backend = env_value.trim().parse()?;
+        }
+        override_string(&mut self.mongo_uri, "FILE_SERVER_MONGO_URI");
+        override_string(&mut self.db_name, "FILE_SERVER_DB_NAME");
+        override_string(&mut self.sqlite_path, "FILE_SERVER_SQLITE_PATH");
+        if let Ok(env_value) = env::var("FILE_SERVER_S3_ENDPOINT") {
+            self.s3_endpoint = Some(env_value);
+        }
+        override_string(&mut self.s3_region, "FILE_SERVER_S3_REGION");
+        override_string(&mut self.s3_bucket, "FILE_SERVER_S3_BUCKET");
+        override_string(&mut self.s3_access_key, "FILE_SERVER_S3_ACCESS_KEY");
+        override_string(&mut self.s3_secret_key, "FILE_SERVER_S3_SECRET_KEY");
+        override_number(&mut self.s3_part_size, "FILE_SERVER_S3_PART_SIZE")?;
+        override_bool(&mut self.content_addressed, "FILE_SERVER_CONTENT_ADDRESSED")?;
+        override_string(&mut self.listen_addr, "FILE_SERVER_LISTEN_ADDR");
+        override_string(&mut self.admin_user, "FILE_SERVER_ADMIN_USER");
+        if let Ok(env_value) = env::var("FILE_SERVER_ADMIN_PASS") {
+            self.admin_pass = Some(env_value);
+        }
+        if let Ok(env_value) = env::var("FILE_SERVER_DEMO_PASS") {
+            self.demo_pass = Some(env_value);
+        }
+        override_number(&mut self.bcrypt_cost, "FILE_SERVER_BCRYPT_COST")?;
+        override_number(&mut self.max_upload_size, "FILE_SERVER_MAX_UPLOAD_SIZE")?;
+        override_bool(&mut self.open_registration, "FILE_SERVER_OPEN_REGISTRATION")?;
+        override_number(&mut self.session_ttl_secs, "FILE_SERVER_SESSION_TTL_SECS")?;
+        override_bool(&mut self.verify_downloads, "FILE_SERVER_VERIFY_DOWNLOADS")?;
+        override_number(
+            &mut self.scrub_interval_secs,
+            "FILE_SERVER_SCRUB_INTERVAL_SECS",
+        )?;
+
+        Ok(())
+    }
+    /**
+     * Make sure every value is usable before anything is started with it
+     */
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.storage_root.trim().is_empty() {
+            return Err(invalid("storage_root can't be empty"));
+        }
+        match self.metadata_backend {
+            MetadataBackend::Mongo => self.validate_mongo()?,
+            MetadataBackend::Sqlite if self.sqlite_path.trim().is_empty() => {
+                return Err(invalid("sqlite_path can't be empty"));
+            }
+            MetadataBackend::Sqlite | MetadataBackend::Memory => {}
+        }
+        if self.storage_backend == StorageBackend::S3 {
+            self.validate_s3()?;
+        }
+        if !(MIN_BCRYPT_COST..=MAX_BCRYPT_COST).contains(&self.bcrypt_cost) {
+            return Err(ConfigError::Invalid(format!(
+                "bcrypt_cost must be between {} and {}, got {}",
+                MIN_BCRYPT_COST, MAX_BCRYPT_COST, self.bcrypt_cost
+            )));
+        }
+        if self.max_upload_size == 0 {
+            return Err(invalid("max_upload_size must be larger than 0"));
+        }
+        if self.session_ttl_secs == 0 {
+            return Err(invalid("session_ttl_secs must be larger than 0"));
+        }
+        // The superuser and the demo user are created with these, so they follow the same rules
+        // as any other user
+        if let Err(err) = validate_user_name(&self.admin_user) {
+            return Err(ConfigError::Invalid(format!("admin_user: {}", err)));
+        }
+        for (name, pass) in [
+            ("admin_pass", &self.admin_pass),
+            ("demo_pass", &self.demo_pass),
+        ] {
+            if let Some(Err(err)) = pass.as_deref().map(validate_password) {
+                return Err(ConfigError::Invalid(format!(
+                    "{}: {}, leave it out to generate one",
+                    name, err
+                )));
+            }
+        }
+        self.socket_addr()?;
+
+        Ok(())
+    }
+    /**
+     * The MongoDB settings are only checked when the Mongo metadata backend is picked
+     */
+    fn validate_mongo(&self) -> Result<(), ConfigError> {
+        if self.mongo_uri.trim().is_empty() {
+            return Err(invalid("mongo_uri can't be empty"));
+        }
+        if self.db_name.trim().is_empty() {
+            return Err(invalid("db_name can't be empty"));
+        }
+
+        Ok(())
+    }
+    /**
+     * The S3 settings are only checked when the S3 storage backend is picked
+     */
+    fn validate_s3(&self) -> Result<(), ConfigError> {
+        if let Some(s3_endpoint) = &self.s3_endpoint {
+            if !s3_endpoint.starts_with("http://") && !s3_endpoint.starts_with("https://") {
+                return Err(ConfigError::Invalid(format!(
+                    "s3_endpoint must be an http:// or https:// URL, got {}",
+                    s3_endpoint
+                )));
+            }
+        }
+        if self.s3_region.trim().is_empty() {
+            return Err(invalid("s3_region can't be empty"));
+        }
+        if self.s3_bucket.trim().is_empty() {
+            return Err(invalid("s3_bucket can't be empty"));
+        }
+        if self.s3_access_key.is_empty() || self.s3_secret_key.is_empty() {
+            return Err(invalid(
+                "s3_access_key and s3_secret_key are needed by the s3 storage backend",
+            ));
+        }
+        if !(MIN_S3_PART_SIZE..=MAX_S3_PART_SIZE).contains(&self.s3_part_size) {
+            return Err(ConfigError::Invalid(format!(
+                "s3_part_size must be between {} and {}, got {}",
+                MIN_S3_PART_SIZE, MAX_S3_PART_SIZE, self.s3_part_size
+            )));
+        }
+
+        Ok(())
+    }
+    /**
+     * The listen address as a SocketAddr
+     */
+    pub fn socket_addr(&self) -> Result<SocketAddr, ConfigError> {
+        self.listen_addr.parse().map_err(|_| {
+            ConfigError::Invalid(format!(
+                "listen_addr {} is not a valid socket address",
+                self.listen_addr
+            ))
+        })
+    }
+}
+
+fn override_string(value: &mut String, name: &str) {
+    if let Ok(env_value) = env::var(name) {
+        *value = env_value;
+    }
+}
+
+fn override_number<T: std::str::FromStr>(value: &mut T, name: &str) -> Result<(), ConfigError> {
+    if let Ok(env_value) = env::var(name) {
+        *value = env_value.trim().parse().map_err(|_| {
+            ConfigError::Invalid(format!("{} must be a number, got {}", name, env_value))
+        })?;
+    }
+
+    Ok(())
+}
+
+fn override_bool(value: &mut bool, name: &str) -> Result<(), ConfigError> {
+    if let Ok(env_value) = env::var(name) {
+        *value = match env_value.trim() {
+            "true" | "1" => true,
+            "false" | "0" => false,
+            _ => {
+                return Err(ConfigError::Invalid(format!(
+                    "{} must be true or false, got {}",
+                    name, env_value
+                )))
+            }
+        };
+    }
+
+    Ok(())
+}
+
+fn invalid(message: &str) -> ConfigError {
+    ConfigError::Invalid(message.to_string())
+}
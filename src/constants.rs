@@ -1,3 +1,22 @@
-pub const ASSET_MAIN_PATH: &str = "./assets";
-pub const DB_NAME: &str = "file-server-dev";
-pub const MONGO_URI: &str = "mongodb://localhost:27017/";
+// Defaults of the Config, every one of them can be overridden by the config file or env
+pub const DEFAULT_CONFIG_PATH: &str = "./file-server.toml";
+pub const DEFAULT_STORAGE_ROOT: &str = "./assets";
+pub const DEFAULT_DB_NAME: &str = "file-server-dev";
+pub const DEFAULT_MONGO_URI: &str = "mongodb://localhost:27017/";
+pub const DEFAULT_SQLITE_PATH: &str = "./file-server.db";
+pub const DEFAULT_LISTEN_ADDR: &str = "127.0.0.1:8080";
+pub const DEFAULT_BCRYPT_COST: u32 = 13;
+pub const DEFAULT_MAX_UPLOAD_SIZE: u64 = 1024 * 1024 * 1024;
+pub const DEFAULT_SESSION_TTL_SECS: u64 = 60 * 60 * 24;
+pub const DEFAULT_SCRUB_INTERVAL_SECS: u64 = 60 * 60 * 24;
+pub const DEFAULT_ADMIN_USER: &str = "admin";
+pub const DEFAULT_S3_REGION: &str = "us-east-1";
+pub const DEFAULT_S3_BUCKET: &str = "file-server";
+pub const DEFAULT_S3_PART_SIZE: u64 = 8 * 1024 * 1024;
+
+pub const CONFIG_PATH_ENV: &str = "FILE_SERVER_CONFIG";
+pub const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+pub const SCRUB_PAGE_SIZE: usize = 100;
+pub const FSCK_GRACE_SECS: i64 = 60 * 60;
+pub const FALLBACK_EXTENSION: &str = "bin";
+pub const MIN_PASS_LEN: usize = 8;
@@ -0,0 +1,224 @@
+use crate::controller::error::ControllerError;
+use crate::data_models::{access_group::AccessGroup, folder::Folder, key::Key, user::User};
+use crate::store::MetaStore;
+use std::path::Path;
+use wither::mongodb::bson::oid::ObjectId;
+
+/**
+ * ____________________________________________________________________________________________
+ * Principal, who is calling a controller
+ * ____________________________________________________________________________________________
+ * Anonymous: A caller without credentials, can only list and read public folders
+ * User: A logged in user, rights are resolved from its keys and admin lists
+ * Key: A machine client authenticated with an active key, rights are those of the key alone
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug)]
+pub enum Principal {
+    Anonymous,
+    User(Box<User>),
+    Key(Box<Key>),
+}
+
+impl Principal {
+    /**
+     * The ObjectId of the calling user, if any
+     */
+    pub fn user_id(&self) -> Option<&ObjectId> {
+        match self {
+            Principal::User(user) => user.id.as_ref(),
+            Principal::Anonymous | Principal::Key(_) => None,
+        }
+    }
+    /**
+     * The calling user, or an error for callers that are not logged in
+     */
+    pub fn require_user(&self) -> Result<&User, ControllerError> {
+        match self {
+            Principal::User(user) => Ok(user),
+            Principal::Anonymous | Principal::Key(_) => Err(ControllerError::Forbidden(
+                "This operation requires a logged in user".to_string(),
+            )),
+        }
+    }
+    /**
+     * The ObjectId of the calling user, or an error for callers that are not logged in
+     */
+    pub fn require_user_id(&self) -> Result<ObjectId, ControllerError> {
+        let user = self.require_user()?;
+        match &user.id {
+            Some(id) => Ok(id.clone()),
+            None => Err(ControllerError::Internal(format!(
+                "User {} has no ObjectId",
+                user.user
+            ))),
+        }
+    }
+}
+/**
+ * The doc of the calling user as the store has it now. A principal is loaded once per request, so
+ * the admin lists and keys it holds miss what changed since, like a folder it just created
+ */
+pub async fn current_user(
+    store: &dyn MetaStore,
+    principal: &Principal,
+) -> Result<User, ControllerError> {
+    let user = principal.require_user()?;
+    let id = principal.require_user_id()?;
+
+    store
+        .find_user(&id)
+        .await?
+        .ok_or_else(|| ControllerError::Forbidden(format!("The user {} was deleted", user.user)))
+}
+/**
+ * ____________________________________________________________________________________________
+ * Permission, what a principal wants to do with a folder
+ * ____________________________________________________________________________________________
+ * List: See the folder and what is inside of it
+ * Read: Download assets of the folder
+ * Write: Upload assets and create sub folders in the folder
+ * Admin: Edit or delete the folder itself
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Permission {
+    List,
+    Read,
+    Write,
+    Admin,
+}
+/**
+ * Decide if principal may act on folder with permission, returning a Forbidden error if not
+ *
+ * Superusers and admins of the folder (or of any folder above it) can do anything, members of one
+ * of the folders access groups (User.keys -> active Key -> AccessGroup.allowed_keys) can list, read
+ * and write, as can a key principal allowed by one of them, and anyone can list and read a public
+ * folder
+ */
+pub async fn authorize_folder(
+    store: &dyn MetaStore,
+    principal: &Principal,
+    folder: &Folder,
+    permission: Permission,
+) -> Result<(), ControllerError> {
+    let public_access = folder.is_public && permission <= Permission::Read;
+    if public_access {
+        return Ok(());
+    }
+
+    let keys = match principal {
+        Principal::User(_) => {
+            let user = current_user(store, principal).await?;
+            if is_folder_admin(store, &user, folder).await? {
+                return Ok(());
+            }
+
+            user.keys.clone()
+        }
+        Principal::Key(key) => key.id.iter().cloned().collect(),
+        Principal::Anonymous => return Err(forbidden(folder, permission)),
+    };
+
+    if permission < Permission::Admin && is_folder_member(store, &keys, folder).await? {
+        return Ok(());
+    }
+
+    Err(forbidden(folder, permission))
+}
+/**
+ * Decide if principal is an admin of the access group, returning the access group doc if so
+ */
+pub async fn authorize_access_group(
+    store: &dyn MetaStore,
+    principal: &Principal,
+    access_group: &ObjectId,
+) -> Result<AccessGroup, ControllerError> {
+    let user = current_user(store, principal).await?;
+    if !user.superuser && !user.access_group_admins.contains(access_group) {
+        return Err(ControllerError::Forbidden(format!(
+            "User {} is not an admin of the access group {}",
+            user.user, access_group
+        )));
+    }
+
+    // Attempt to find the access group doc
+    store.find_access_group(access_group).await?.ok_or_else(|| {
+        ControllerError::NotFound(format!(
+            "Was unable to find an access group by the ObjectId {}",
+            access_group
+        ))
+    })
+}
+/**
+ * Check if user administrates folder, directly, through one of the folders above it or as superuser
+ */
+async fn is_folder_admin(
+    store: &dyn MetaStore,
+    user: &User,
+    folder: &Folder,
+) -> Result<bool, ControllerError> {
+    if user.superuser {
+        return Ok(true);
+    }
+
+    if let Some(folder_id) = &folder.id {
+        if user.folder_admins.contains(folder_id) {
+            return Ok(true);
+        }
+    }
+
+    if user.folder_admins.is_empty() {
+        return Ok(false);
+    }
+
+    // Collect the paths of every folder above this one
+    let ancestor_paths: Vec<String> = Path::new(&folder.path)
+        .ancestors()
+        .skip(1)
+        .filter_map(|ancestor| ancestor.to_str())
+        .map(|ancestor| ancestor.to_string())
+        .collect();
+
+    let ancestor_docs = store.find_folders_by_paths(&ancestor_paths).await?;
+
+    Ok(ancestor_docs.iter().any(|ancestor| match &ancestor.id {
+        Some(ancestor_id) => user.folder_admins.contains(ancestor_id),
+        None => false,
+    }))
+}
+/**
+ * Check if one of the active keys out of keys is allowed by one of the folders access groups
+ */
+async fn is_folder_member(
+    store: &dyn MetaStore,
+    keys: &[ObjectId],
+    folder: &Folder,
+) -> Result<bool, ControllerError> {
+    if keys.is_empty() || folder.access_groups.is_empty() {
+        return Ok(false);
+    }
+
+    // Resolve the keys to the ones that are still active
+    let active_key_docs = store.find_active_keys(keys).await?;
+
+    let active_keys: Vec<ObjectId> = active_key_docs
+        .iter()
+        .filter_map(|key| key.id.clone())
+        .collect();
+    if active_keys.is_empty() {
+        return Ok(false);
+    }
+
+    // Any of the folders access groups allowing any of those keys grants access
+    store
+        .any_access_group_allows(&folder.access_groups, &active_keys)
+        .await
+}
+
+fn forbidden(folder: &Folder, permission: Permission) -> ControllerError {
+    ControllerError::Forbidden(format!(
+        "No {:?} permission on the folder {}",
+        permission, folder.path
+    ))
+}
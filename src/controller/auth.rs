@@ -1,70 +1,191 @@
+use crate::config::Config;
+use crate::controller::access::{authorize_access_group, Principal};
 use crate::controller::error::ControllerError;
-use crate::data_models::{
-    access_group::AccessGroup, asset::Asset, folder::Folder, key::Key, user::User,
-};
-use crate::util::{get_time_meta, get_uuid};
+use crate::controller::session::{issue_session, IssuedSession};
+use crate::data_models::access_group::AccessGroup;
+use crate::store::{FolderList, MetaStore, UserList};
+use crate::util::get_time_meta;
 use bcrypt::verify;
-use wither::{
-    mongodb::{
-        bson::{doc, oid::ObjectId},
-        Database,
-    },
-    Model,
-};
+use wither::mongodb::bson::oid::ObjectId;
 
 /**
- * Attempt to login user with username and unhashed password
+ * Attempt to login user with username and unhashed password, issuing a session on success
  */
 pub async fn login_user(
-    db_ref: &Database,
+    store: &dyn MetaStore,
+    config: &Config,
     user: &str,
     pass: &str,
-) -> Result<Option<User>, ControllerError> {
-    // Attempt to find user doc
-    let user_doc_result = User::find_one(db_ref, doc! { "user": user }, None).await;
-    if user_doc_result.is_err() {
-        return Err(ControllerError {
-            io: None,
-            wither: user_doc_result.err(),
-            bcrypt: None,
-            operation: None,
-        });
-    }
+) -> Result<Option<IssuedSession>, ControllerError> {
+    // Attempt to find user doc, and check if it exists
+    let user = match store.find_user_by_name(user).await? {
+        Some(user) => user,
+        None => return Ok(None),
+    };
 
-    // Check if user exist
-    let user_doc = user_doc_result.unwrap();
-    if user_doc.is_none() {
+    // Verify the password with bcrypt
+    if !verify(pass, &user.pass)? {
         return Ok(None);
     }
 
-    let user = user_doc.unwrap();
-
-    // Try to get verify password result from bcrypt
-    let verify_pass_result = verify(pass, &user.pass);
-    if verify_pass_result.is_err() {
-        return Err(ControllerError {
-            io: None,
-            wither: None,
-            bcrypt: verify_pass_result.err(),
-            operation: None,
-        });
+    // If all goes well issue a session, its token identifies the user on later requests
+    let issued_session = issue_session(store, config, user).await?;
+
+    Ok(Some(issued_session))
+}
+/**
+ * Attempt to register a new access group, the admin becomes its first access group admin
+ */
+pub async fn register_new_access_group(
+    store: &dyn MetaStore,
+    principal: &Principal,
+    tag: &str,
+) -> Result<AccessGroup, ControllerError> {
+    // Only logged in users can create access groups
+    let admin = principal.require_user_id()?;
+
+    // Check if an access group with this tag already exists
+    ensure_access_group_tag_free(store, tag).await?;
+
+    // Get meta data for access group Doc
+    let (timestamp, timestamp_readable) = get_time_meta();
+
+    let access_group_doc = AccessGroup {
+        id: None,
+        tag: tag.to_string(),
+        allowed_keys: vec![],
+        timestamp,
+        timestamp_readable,
+    };
+
+    // Attempt to save access group doc and get its ObjectId
+    let access_group_doc = store.insert_access_group(access_group_doc).await?;
+    let _id = access_group_doc.id.clone().ok_or_else(|| {
+        ControllerError::Internal("Was unable to get access group docs _id field".to_string())
+    })?;
+
+    // Attempt to add this access groups _id to the admins access group admin list
+    let user_doc = store
+        .add_to_user_list(&admin, UserList::AccessGroupAdmins, &_id)
+        .await?;
+
+    // Verify a user exist and was updated, otherwise this access group has no admin
+    if user_doc.is_none() {
+        // We need to clear this access group as it has no admin
+        store.delete_access_group(&_id).await?;
+
+        return Err(ControllerError::NotFound(format!(
+            "Was unable to find a user by the ObjectId {}, an access group needs to have a admin to be created!",
+            admin
+        )));
     }
 
-    if !verify_pass_result.unwrap() {
-        return Ok(None);
+    Ok(access_group_doc)
+}
+/**
+ * Attempt to add a key to an access group, giving holders of this key access to its folders
+ */
+pub async fn add_key_to_access_group(
+    store: &dyn MetaStore,
+    principal: &Principal,
+    access_group: &ObjectId,
+    key: &ObjectId,
+) -> Result<AccessGroup, ControllerError> {
+    authorize_access_group(store, principal, access_group).await?;
+
+    // Make sure the key exists before referencing it
+    if store.find_key(key).await?.is_none() {
+        return Err(ControllerError::NotFound(format!(
+            "Was unable to find a key by the ObjectId {}",
+            key
+        )));
     }
 
-    // If all goes well return current user to store in memory
-    Ok(Some(user))
+    let access_group_doc = store.add_allowed_key(access_group, key).await?;
+    found_access_group(access_group_doc, access_group)
 }
 /**
- * Attempt to register a new access group
+ * Attempt to remove a key from an access group
  */
-pub async fn register_new_access_group(
-    db_ref: &Database,
-    admin: &ObjectId,
+pub async fn remove_key_from_access_group(
+    store: &dyn MetaStore,
+    principal: &Principal,
+    access_group: &ObjectId,
+    key: &ObjectId,
+) -> Result<AccessGroup, ControllerError> {
+    authorize_access_group(store, principal, access_group).await?;
+
+    let access_group_doc = store.remove_allowed_key(access_group, key).await?;
+    found_access_group(access_group_doc, access_group)
+}
+/**
+ * Attempt to rename an access group, tags stay unique
+ */
+pub async fn rename_access_group(
+    store: &dyn MetaStore,
+    principal: &Principal,
+    access_group: &ObjectId,
+    tag: &str,
+) -> Result<AccessGroup, ControllerError> {
+    authorize_access_group(store, principal, access_group).await?;
+    ensure_access_group_tag_free(store, tag).await?;
+
+    let access_group_doc = store.set_access_group_tag(access_group, tag).await?;
+    found_access_group(access_group_doc, access_group)
+}
+/**
+ * Attempt to delete an access group, detaching it from every folder and admin
+ */
+pub async fn delete_access_group(
+    store: &dyn MetaStore,
+    principal: &Principal,
+    access_group: &ObjectId,
+) -> Result<(), ControllerError> {
+    authorize_access_group(store, principal, access_group).await?;
+    let access_group_ids = std::slice::from_ref(access_group);
+
+    // Detach it from every folder first, so no folder references a missing group
+    store
+        .remove_from_all_folder_lists(FolderList::AccessGroups, access_group_ids)
+        .await?;
+
+    // Then from every user administrating it
+    store
+        .remove_from_all_user_lists(UserList::AccessGroupAdmins, access_group_ids)
+        .await?;
+
+    // Attempt to delete the access group doc itself
+    store.delete_access_group(access_group).await?;
+
+    Ok(())
+}
+/**
+ * Make sure no access group already uses tag
+ */
+async fn ensure_access_group_tag_free(
+    store: &dyn MetaStore,
     tag: &str,
 ) -> Result<(), ControllerError> {
+    if store.find_access_group_by_tag(tag).await?.is_some() {
+        return Err(ControllerError::AlreadyExists(format!(
+            "Cannot create an access group with the tag {}, it already exists!",
+            tag
+        )));
+    }
 
     Ok(())
 }
+/**
+ * The access group doc as it is after an update, a missing one is an error
+ */
+fn found_access_group(
+    access_group_doc: Option<AccessGroup>,
+    access_group: &ObjectId,
+) -> Result<AccessGroup, ControllerError> {
+    access_group_doc.ok_or_else(|| {
+        ControllerError::NotFound(format!(
+            "Was unable to find an access group by the ObjectId {}",
+            access_group
+        ))
+    })
+}
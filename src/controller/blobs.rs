@@ -0,0 +1,96 @@
+use crate::config::Config;
+use crate::controller::error::ControllerError;
+use crate::data_models::{asset::Asset, blob::Blob};
+use crate::storage::{asset_key, blob_key, Storage};
+use crate::store::MetaStore;
+use crate::util::{get_time_meta, get_timestamp};
+
+/**
+ * Add a reference to the blob checksum, creating it with one reference if it doesn't exist yet,
+ * returning whether it already existed
+ */
+pub async fn acquire_blob(
+    store: &dyn MetaStore,
+    checksum: &str,
+    size: u64,
+) -> Result<bool, ControllerError> {
+    let (timestamp, timestamp_readable) = get_time_meta();
+    let blob_doc = Blob {
+        id: None,
+        checksum: checksum.to_string(),
+        size: size as i64,
+        refs: 1,
+        touched_at: get_timestamp() as i64,
+        timestamp,
+        timestamp_readable,
+    };
+
+    store.acquire_blob(blob_doc).await
+}
+/**
+ * Turn the bytes staged under staged_key into a reference to the blob checksum. Bytes that are
+ * already stored are dropped, otherwise the staged ones become the blob
+ */
+pub async fn commit_staged_blob(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    staged_key: &str,
+    checksum: &str,
+    size: u64,
+) -> Result<(), ControllerError> {
+    let existed = match acquire_blob(store, checksum, size).await {
+        Ok(existed) => existed,
+        Err(err) => {
+            let _ = storage.delete(staged_key).await;
+            return Err(err);
+        }
+    };
+
+    // A blob whose bytes went missing is healed by the staged copy, they are the same bytes
+    let key = blob_key(checksum);
+    let stored = match existed {
+        true => storage.exists(&key).await,
+        false => Ok(false),
+    };
+    let committed = match stored {
+        Ok(true) => storage.delete(staged_key).await,
+        Ok(false) => storage.rename(staged_key, &key).await,
+        Err(err) => Err(err),
+    };
+
+    if let Err(err) = committed {
+        let _ = storage.delete(staged_key).await;
+        let _ = release_blob(store, checksum).await;
+        return Err(err);
+    }
+
+    Ok(())
+}
+/**
+ * Drop a reference to the blob checksum. The bytes of a blob that lost its last reference are left
+ * to fsck --repair, an upload of the same bytes may be storing them again right now and would lose
+ * them to a delete
+ */
+pub async fn release_blob(store: &dyn MetaStore, checksum: &str) -> Result<(), ControllerError> {
+    store.release_blob(checksum, get_timestamp() as i64).await?;
+
+    Ok(())
+}
+/**
+ * Let go of the bytes of asset, releasing its blob or removing the bytes stored under its path
+ */
+pub async fn release_asset_bytes(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    asset: &Asset,
+) -> Result<(), ControllerError> {
+    match &asset.blob {
+        Some(checksum) => release_blob(store, checksum).await,
+        None => {
+            storage
+                .delete(&asset_key(&config.storage_root, asset)?)
+                .await
+        }
+    }
+}
@@ -1,7 +1,175 @@
+use std::fmt;
+use wither::mongodb::error::{ErrorKind, WriteFailure};
+use wither::WitherError;
+
+/**
+ * ____________________________________________________________________________________________
+ * ControllerError, every way a controller can fail
+ * ____________________________________________________________________________________________
+ * NotFound: A doc or file that was asked for doesn't exist
+ * AlreadyExists: Something with the same unique name, tag or path already exists
+ * Forbidden: The principal isn't allowed to do this
+ * InvalidInput: The input was rejected before anything was touched
+ * PayloadTooLarge: An upload went past max_upload_size, nothing of it was kept
+ * Storage: Reading or writing asset storage failed
+ * Database: Talking to the metadata store failed
+ * Crypto: Hashing or verifying a password failed
+ * Internal: Something that should never happen, happened
+ * ____________________________________________________________________________________________
+ */
 #[derive(Debug)]
-pub struct ControllerError {
-    pub io: Option<std::io::Error>,
-    pub wither: Option<wither::WitherError>,
-    pub bcrypt: Option<bcrypt::BcryptError>,
-    pub operation: Option<String>,
+pub enum ControllerError {
+    NotFound(String),
+    AlreadyExists(String),
+    Forbidden(String),
+    InvalidInput(String),
+    PayloadTooLarge(String),
+    Storage(std::io::Error),
+    Database(Box<dyn std::error::Error + Send + Sync>),
+    Crypto(bcrypt::BcryptError),
+    Internal(String),
+}
+
+impl ControllerError {
+    /**
+     * Stable machine readable code of the error kind, clients can branch on it
+     */
+    pub fn code(&self) -> &'static str {
+        match self {
+            ControllerError::NotFound(_) => "not_found",
+            ControllerError::AlreadyExists(_) => "already_exists",
+            ControllerError::Forbidden(_) => "forbidden",
+            ControllerError::InvalidInput(_) => "invalid_input",
+            ControllerError::PayloadTooLarge(_) => "payload_too_large",
+            ControllerError::Storage(_) => "storage_error",
+            ControllerError::Database(_) => "database_error",
+            ControllerError::Crypto(_) => "crypto_error",
+            ControllerError::Internal(_) => "internal_error",
+        }
+    }
+    /**
+     * HTTP status code matching the error kind
+     */
+    pub fn status_code(&self) -> u16 {
+        match self {
+            ControllerError::NotFound(_) => 404,
+            ControllerError::AlreadyExists(_) => 409,
+            ControllerError::Forbidden(_) => 403,
+            ControllerError::InvalidInput(_) => 400,
+            ControllerError::PayloadTooLarge(_) => 413,
+            ControllerError::Storage(_)
+            | ControllerError::Database(_)
+            | ControllerError::Crypto(_)
+            | ControllerError::Internal(_) => 500,
+        }
+    }
+}
+
+impl fmt::Display for ControllerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControllerError::NotFound(message)
+            | ControllerError::AlreadyExists(message)
+            | ControllerError::Forbidden(message)
+            | ControllerError::InvalidInput(message)
+            | ControllerError::PayloadTooLarge(message)
+            | ControllerError::Internal(message) => write!(f, "{}", message),
+            ControllerError::Storage(err) => write!(f, "Storage error: {}", err),
+            ControllerError::Database(err) => write!(f, "Database error: {}", err),
+            ControllerError::Crypto(err) => write!(f, "Crypto error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ControllerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ControllerError::Storage(err) => Some(err),
+            ControllerError::Database(err) => {
+                Some(err.as_ref() as &(dyn std::error::Error + 'static))
+            }
+            ControllerError::Crypto(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ControllerError {
+    fn from(err: std::io::Error) -> ControllerError {
+        ControllerError::Storage(err)
+    }
+}
+
+impl From<WitherError> for ControllerError {
+    fn from(err: WitherError) -> ControllerError {
+        // Unique index violations are a conflict, not a broken database
+        if is_duplicate_key(&err) {
+            return ControllerError::AlreadyExists(format!(
+                "A doc with the same unique value already exists: {}",
+                err
+            ));
+        }
+
+        ControllerError::Database(Box::new(err))
+    }
+}
+
+impl From<wither::mongodb::error::Error> for ControllerError {
+    fn from(err: wither::mongodb::error::Error) -> ControllerError {
+        ControllerError::from(WitherError::from(err))
+    }
+}
+
+impl From<rusqlite::Error> for ControllerError {
+    fn from(err: rusqlite::Error) -> ControllerError {
+        // Unique constraint violations are a conflict, same as duplicate keys in MongoDB
+        if let rusqlite::Error::SqliteFailure(sqlite_err, _) = &err {
+            if sqlite_err.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE
+                || sqlite_err.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_PRIMARYKEY
+            {
+                return ControllerError::AlreadyExists(format!(
+                    "A row with the same unique value already exists: {}",
+                    err
+                ));
+            }
+        }
+
+        ControllerError::Database(Box::new(err))
+    }
+}
+
+impl From<reqwest::Error> for ControllerError {
+    fn from(err: reqwest::Error) -> ControllerError {
+        ControllerError::Storage(std::io::Error::other(err))
+    }
+}
+
+impl From<bcrypt::BcryptError> for ControllerError {
+    fn from(err: bcrypt::BcryptError) -> ControllerError {
+        ControllerError::Crypto(err)
+    }
+}
+/**
+ * Check if a MongoDB error is a duplicate key (E11000) error
+ */
+fn is_duplicate_key(err: &WitherError) -> bool {
+    const DUPLICATE_KEY: i32 = 11000;
+
+    let mongo_err = match err {
+        WitherError::Mongo(mongo_err) => mongo_err,
+        _ => return false,
+    };
+
+    match mongo_err.kind.as_ref() {
+        ErrorKind::WriteError(WriteFailure::WriteError(write_err)) => {
+            write_err.code == DUPLICATE_KEY
+        }
+        ErrorKind::CommandError(command_err) => command_err.code == DUPLICATE_KEY,
+        ErrorKind::BulkWriteError(bulk_err) => bulk_err
+            .write_errors
+            .iter()
+            .flatten()
+            .any(|write_err| write_err.code == DUPLICATE_KEY),
+        _ => false,
+    }
 }
@@ -1,73 +1,114 @@
-use crate::constants::ASSET_MAIN_PATH;
+use crate::config::Config;
+use crate::controller::access::{authorize_folder, current_user, Permission, Principal};
+use crate::controller::blobs::{
+    acquire_blob, commit_staged_blob, release_asset_bytes, release_blob,
+};
 use crate::controller::error::ControllerError;
+use crate::controller::paths::{
+    usable_extension, validate_extension, validate_name, validate_password, validate_user_name,
+};
 use crate::data_models::{
-    access_group::AccessGroup, asset::Asset, folder::Folder, key::Key, user::User,
+    asset::{Asset, Integrity},
+    folder::Folder,
+    user::User,
 };
-use crate::util::{get_time_meta, get_uuid};
-use bcrypt::{hash, DEFAULT_COST};
-use std::fs::*;
+use crate::storage::{blob_key, staging_key, storage_key, Storage};
+use crate::store::{FolderList, MetaStore, UserList};
+use crate::util::{get_time_meta, get_timestamp, get_uuid};
+use bcrypt::hash;
+use bytes::Bytes;
+use futures::stream::{self, Stream, StreamExt};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::path::Path;
-use wither::{
-    mongodb::{
-        bson::{doc, oid::ObjectId},
-        Database,
-    },
-    prelude::*,
-    Model,
-};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use wither::mongodb::bson::oid::ObjectId;
+/**
+ * Controller to register a user through the API, anyone can while open_registration is on and
+ * only superusers can otherwise
+ */
+pub async fn register_user(
+    store: &dyn MetaStore,
+    config: &Config,
+    principal: &Principal,
+    user: &str,
+    pass: &str,
+) -> Result<User, ControllerError> {
+    if !config.open_registration {
+        let is_superuser = match principal {
+            Principal::User(caller) => caller.superuser,
+            Principal::Anonymous | Principal::Key(_) => false,
+        };
+        if !is_superuser {
+            return Err(ControllerError::Forbidden(
+                "Registration is closed, only superusers can create users".to_string(),
+            ));
+        }
+    }
+
+    create_user(store, config, user, pass).await
+}
 /**
  * Controller to create a user for the file system
  */
 pub async fn create_user(
-    db_ref: &Database,
+    store: &dyn MetaStore,
+    config: &Config,
     user: &str,
     pass: &str,
 ) -> Result<User, ControllerError> {
+    insert_user(store, config, user, pass, false).await
+}
+/**
+ * Controller to create a superuser, only meant for bootstrapping and operators
+ */
+pub async fn create_superuser(
+    store: &dyn MetaStore,
+    config: &Config,
+    user: &str,
+    pass: &str,
+) -> Result<User, ControllerError> {
+    insert_user(store, config, user, pass, true).await
+}
+
+async fn insert_user(
+    store: &dyn MetaStore,
+    config: &Config,
+    user: &str,
+    pass: &str,
+    superuser: bool,
+) -> Result<User, ControllerError> {
+    // Make sure the name is safe and the password isn't trivially short
+    validate_user_name(user)?;
+    validate_password(pass)?;
+
     // Attempt to hash pass via bcrypt
-    let hashed_pass = hash(pass, DEFAULT_COST + 1);
-    if hashed_pass.is_err() {
-        return Err(ControllerError {
-            io: None,
-            wither: None,
-            bcrypt: hashed_pass.err(),
-            operation: None,
-        });
-    }
+    let hashed_pass = hash(pass, config.bcrypt_cost)?;
 
     let (timestamp, timestamp_readable) = get_time_meta();
-    let mut user_doc = User {
+    let user_doc = User {
         id: None,
         user: user.to_string(),
-        pass: hashed_pass.unwrap(),
+        pass: hashed_pass,
         keys: vec![],
         key_admins: vec![],
         user_admins: vec![],
         folder_admins: vec![],
         access_group_admins: vec![],
+        superuser,
         timestamp,
         timestamp_readable,
     };
 
-    // Attempt to save folder doc
-    let save_result = user_doc.save(db_ref, None).await;
-    if save_result.is_err() {
-        return Err(ControllerError {
-            io: None,
-            wither: save_result.err(),
-            bcrypt: None,
-            operation: None,
-        });
-    }
+    // Attempt to save user doc
+    let user_doc = store.insert_user(user_doc).await?;
 
     // Attempt to get ObjectId from saved doc
-    let doc_id = user_doc.id();
-    if doc_id.is_none() {
-        return Err(ControllerError {
-            io: None,
-            wither: None,
-            bcrypt: None,
-            operation: Some("FATAL: Was unable to get folder docs _id field after saving in MongoDB succesfully..".to_string())
-          });
+    if user_doc.id.is_none() {
+        return Err(ControllerError::Internal(
+            "Was unable to get user docs _id field".to_string(),
+        ));
     }
 
     Ok(user_doc)
@@ -76,48 +117,71 @@ pub async fn create_user(
  * Controller to create folder and associated DB operations
  */
 pub async fn create_folder(
-    db_ref: &Database,
-    admin: &ObjectId,
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    principal: &Principal,
     folder_name: &str,
     start_access_group: Option<ObjectId>,
     static_folder_path: Option<&str>,
 ) -> Result<Folder, ControllerError> {
-    let folder_path;
+    // Only logged in users can create folders, they become the admin of the folder
+    let admin = principal.require_user_id()?;
+
+    // Make sure the name is safe and the folder stays inside of the asset root
+    validate_name(folder_name)?;
+    let folder_path = match static_folder_path {
+        Some(static_path) => static_path.to_string(),
+        None => format!("{}/{}", config.storage_root, folder_name),
+    };
+    let folder_key = storage_key(&config.storage_root, &folder_path)?.to_string();
 
+    // Creating a folder inside of another one requires write access to the parent folder
+    let mut parent_folder = None;
     if static_folder_path.is_some() {
-        folder_path = static_folder_path.unwrap().to_string();
-    } else {
-        folder_path = format!("{}/{}", ASSET_MAIN_PATH, folder_name);
+        let parent_path = Path::new(&folder_path)
+            .parent()
+            .and_then(|parent| parent.to_str())
+            .unwrap_or(&config.storage_root);
+
+        let parent_folder_doc = get_folder_by_path(store, parent_path).await?;
+        authorize_folder(store, principal, &parent_folder_doc, Permission::Write).await?;
+        parent_folder = Some(parent_folder_doc);
     }
 
     // Check if a folder with this name already exists
-    if Path::new(&folder_path).exists() {
-        return Err(ControllerError {
-        io: None,
-        wither: None,
-        bcrypt: None,
-        operation: Some("Cannot create a folder that already exists, use another name or delete the other folder!".to_string())
-      });
-    }
-
-    // Attempt to create the directory on disk
-    let create_dir_result = create_dir_all(&folder_path);
-    if create_dir_result.is_err() {
-        return Err(ControllerError {
-            io: create_dir_result.err(),
-            wither: None,
-            bcrypt: None,
-            operation: None,
-        });
+    if store.find_folder_by_path(&folder_path).await?.is_some() {
+        return Err(ControllerError::AlreadyExists(
+            "Cannot create a folder that already exists, use another name or delete the other folder!"
+                .to_string(),
+        ));
     }
 
-    let mut folder_doc;
+    // Attempt to create the directory of the folder before its doc, for storage that has them. One
+    // that is already there holds files the folder is made for, and stays if anything fails
+    let dir_existed = storage.dir_exists(&folder_key).await?;
+    storage.create_dir(&folder_key).await?;
+
     // Get meta data for folder Doc
     let (timestamp, timestamp_readable) = get_time_meta();
 
-    if start_access_group.is_none() {
-        // If a starting access_group ObjectId is not provided, the folder will start as public
-        folder_doc = Folder {
+    let folder_doc = match start_access_group {
+        // If a starting access_group ObjectId is not provided, a sub folder inherits its parents access
+        None if parent_folder.is_some() => {
+            let parent_folder = parent_folder.unwrap();
+            Folder {
+                id: None,
+                tag: folder_name.to_string(),
+                path: folder_path,
+                files: vec![],
+                is_public: parent_folder.is_public,
+                access_groups: parent_folder.access_groups,
+                timestamp,
+                timestamp_readable,
+            }
+        }
+        // And a top level folder will start as public
+        None => Folder {
             id: None,
             tag: folder_name.to_string(),
             path: folder_path,
@@ -126,83 +190,64 @@ pub async fn create_folder(
             access_groups: vec![],
             timestamp,
             timestamp_readable,
-        }
-    } else {
+        },
         // Otherwise, it will be private and bound to the starting access_group
-        folder_doc = Folder {
+        Some(access_group) => Folder {
             id: None,
             tag: folder_name.to_string(),
             path: folder_path,
             files: vec![],
             is_public: false,
-            access_groups: vec![start_access_group.unwrap()],
+            access_groups: vec![access_group],
             timestamp,
             timestamp_readable,
-        }
-    }
+        },
+    };
 
     // Attempt to save folder doc
-    let save_result = folder_doc.save(db_ref, None).await;
-    if save_result.is_err() {
-        return Err(ControllerError {
-            io: None,
-            wither: save_result.err(),
-            bcrypt: None,
-            operation: None,
-        });
-    }
+    let folder_doc = match store.insert_folder(folder_doc).await {
+        Ok(folder_doc) => folder_doc,
+        Err(err) => {
+            if !dir_existed {
+                let _ = storage.remove_dir(&folder_key).await;
+            }
+            return Err(err);
+        }
+    };
 
     // Attempt to get ObjectId
-    let doc_id = folder_doc.id();
-    if doc_id.is_none() {
-        return Err(ControllerError {
-        io: None,
-        wither: None,
-        bcrypt: None,
-        operation: Some("ERROR: Was unable to get folder docs _id field after saving in MongoDB succesfully..".to_string())
-      });
-    }
-
-    let _id = doc_id.unwrap();
+    let _id = folder_doc.id.clone().ok_or_else(|| {
+        ControllerError::Internal("Was unable to get folder docs _id field".to_string())
+    })?;
 
     // Attempt to add this folders _id to the admins admin folder list
-    let user_doc_result = User::find_one_and_update(
-        db_ref,
-        doc! { "_id": &admin },
-        doc! { "$push": doc! { "folder_admins": &_id } },
-        None,
-    )
-    .await;
-
-    if user_doc_result.is_err() {
-        return Err(ControllerError {
-            io: None,
-            wither: user_doc_result.err(),
-            bcrypt: None,
-            operation: None,
-        });
-    }
+    let user_doc = match store
+        .add_to_user_list(&admin, UserList::FolderAdmins, &_id)
+        .await
+    {
+        Ok(user_doc) => user_doc,
+        Err(err) => {
+            // A folder nobody administrates can't be managed, don't keep it
+            let _ = store.delete_folders(std::slice::from_ref(&_id)).await;
+            if !dir_existed {
+                let _ = storage.remove_dir(&folder_key).await;
+            }
+            return Err(err);
+        }
+    };
 
     // Verify a user exist and was updated, otherwise this folder has no admin
-    let user_doc = user_doc_result.unwrap();
     if user_doc.is_none() {
         // We need to clear this folder as it has no admin
-        let folder_remove_result = folder_doc.delete(db_ref).await;
-        if folder_remove_result.is_err() {
-            return Err(ControllerError {
-                io: None,
-                wither: folder_remove_result.err(),
-                bcrypt: None,
-                operation: None,
-            });
-        }
-
-        return Err(ControllerError {
-            io: None,
-            wither: None,
-            bcrypt: None,
-            operation: Some(format!("ERROR: Was unable to find a user by the ObjectId {}, a folder needs to have a admin to be created!", admin)),
-        });
+        store.delete_folders(std::slice::from_ref(&_id)).await?;
+        if !dir_existed {
+            storage.remove_dir(&folder_key).await?;
+        }
+
+        return Err(ControllerError::NotFound(format!(
+            "Was unable to find a user by the ObjectId {}, a folder needs to have a admin to be created!",
+            admin
+        )));
     }
 
     // Return ObjectId if all went well
@@ -212,16 +257,20 @@ pub async fn create_folder(
  * Controller to create a sub folder
  */
 pub async fn create_sub_folder(
-    db_ref: &Database,
-    admin: &ObjectId,
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    principal: &Principal,
     parent_path: &str,
     folder_name: &str,
     start_access_group: Option<ObjectId>,
 ) -> Result<Folder, ControllerError> {
     let sub_path = format!("{}/{}", parent_path, folder_name);
     let folder = create_folder(
-        db_ref,
-        admin,
+        store,
+        storage,
+        config,
+        principal,
         folder_name,
         start_access_group,
         Some(&sub_path),
@@ -231,82 +280,1344 @@ pub async fn create_sub_folder(
     Ok(folder)
 }
 /**
- * Controller to save an asset(file) on disk and associated DB data
+ * Find the folder called name below the folder at parent_path (or at the top level), creating it if
+ * it doesn't exist yet. Returns the folder and whether it was created, the caller has to be able to
+ * write in a folder that was already there
+ */
+pub async fn ensure_folder(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    principal: &Principal,
+    parent_path: Option<&str>,
+    name: &str,
+) -> Result<(Folder, bool), ControllerError> {
+    let path = format!("{}/{}", parent_path.unwrap_or(&config.storage_root), name);
+    if let Some(folder) = store.find_folder_by_path(&path).await? {
+        authorize_folder(store, principal, &folder, Permission::Write).await?;
+        return Ok((folder, false));
+    }
+
+    let folder = match parent_path {
+        Some(parent_path) => {
+            create_sub_folder(store, storage, config, principal, parent_path, name, None).await?
+        }
+        None => create_folder(store, storage, config, principal, name, None, None).await?,
+    };
+
+    Ok((folder, true))
+}
+/**
+ * ____________________________________________________________________________________________
+ * StoredAsset, result of a streamed upload
+ * ____________________________________________________________________________________________
+ * id: MongoDB ObjectId of the saved Asset doc
+ * size: Number of bytes written to storage
+ * checksum: Hex encoded SHA-256 digest of the bytes written
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug)]
+pub struct StoredAsset {
+    pub id: ObjectId,
+    pub size: u64,
+    pub checksum: String,
+}
+/**
+ * Controller to save an asset(file) in storage and associated DB data. With content addressed
+ * storage the bytes are hashed first, and not written at all if a blob already holds them
  */
+#[allow(clippy::too_many_arguments)]
 pub async fn save_asset(
-    db_ref: &Database,
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    principal: &Principal,
     file_data: Vec<u8>,
     tag: &str,
-    folder_path: &str,
+    folder: &ObjectId,
     extension: &str,
 ) -> Result<ObjectId, ControllerError> {
-    // format folder path and make sure it exists
-    if !Path::new(&folder_path).exists() {
-        return Err(ControllerError {
-        io: None,
-        wither: None,
-        bcrypt: None,
-        operation: Some(format!("ERROR: Bad operation, attempted to save asset at path {} but this folder doesn't exist!", folder_path))
-      });
+    if file_data.len() as u64 > config.max_upload_size {
+        return Err(too_large(config));
+    }
+
+    let known = match config.content_addressed {
+        true => Some((
+            file_data.len() as u64,
+            format!("{:x}", Sha256::digest(&file_data)),
+        )),
+        false => None,
+    };
+
+    let file_stream = stream::iter(vec![Ok(Bytes::from(file_data))]);
+    let stored_asset = store_asset(
+        store,
+        storage,
+        config,
+        principal,
+        file_stream,
+        known,
+        tag,
+        folder,
+        extension,
+    )
+    .await?;
+
+    Ok(stored_asset.id)
+}
+/**
+ * Controller to save an asset(file) in storage from a stream of chunks, writing each chunk as it
+ * arrives. The chunks are counted on the way, a stream going past max_upload_size is broken off and
+ * nothing of it is kept
+ */
+#[allow(clippy::too_many_arguments)]
+pub async fn save_asset_stream<S>(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    principal: &Principal,
+    file_stream: S,
+    tag: &str,
+    folder: &ObjectId,
+    extension: &str,
+) -> Result<StoredAsset, ControllerError>
+where
+    S: Stream<Item = Result<Bytes, std::io::Error>> + Send + Unpin,
+{
+    let exceeded = Arc::new(AtomicBool::new(false));
+    let limited_stream = limit_stream(file_stream, config.max_upload_size, exceeded.clone());
+
+    let stored_asset = store_asset(
+        store,
+        storage,
+        config,
+        principal,
+        limited_stream,
+        None,
+        tag,
+        folder,
+        extension,
+    )
+    .await;
+
+    // The error of the write that was broken off only says the stream failed
+    match stored_asset {
+        Err(_) if exceeded.load(Ordering::SeqCst) => Err(too_large(config)),
+        stored_asset => stored_asset,
     }
+}
+/**
+ * Pass the chunks of file_stream on until more than max_size bytes went by, then fail the stream
+ * and set exceeded
+ */
+fn limit_stream<S>(
+    file_stream: S,
+    max_size: u64,
+    exceeded: Arc<AtomicBool>,
+) -> impl Stream<Item = Result<Bytes, std::io::Error>> + Send + Unpin
+where
+    S: Stream<Item = Result<Bytes, std::io::Error>> + Send + Unpin,
+{
+    let mut size = 0u64;
+    file_stream.map(move |chunk| {
+        let chunk = chunk?;
+        size += chunk.len() as u64;
+        if size > max_size {
+            exceeded.store(true, Ordering::SeqCst);
+            return Err(std::io::Error::other(format!(
+                "The upload is larger than {} bytes",
+                max_size
+            )));
+        }
+
+        Ok(chunk)
+    })
+}
+
+fn too_large(config: &Config) -> ControllerError {
+    ControllerError::PayloadTooLarge(format!(
+        "Uploads can be at most {} bytes",
+        config.max_upload_size
+    ))
+}
+/**
+ * Save an asset whose size and checksum are known up front if known is given
+ */
+#[allow(clippy::too_many_arguments)]
+async fn store_asset<S>(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    principal: &Principal,
+    file_stream: S,
+    known: Option<(u64, String)>,
+    tag: &str,
+    folder: &ObjectId,
+    extension: &str,
+) -> Result<StoredAsset, ControllerError>
+where
+    S: Stream<Item = Result<Bytes, std::io::Error>> + Send + Unpin,
+{
+    // Uploading requires write access to the folder
+    let folder_doc = get_folder(store, folder).await?;
+    authorize_folder(store, principal, &folder_doc, Permission::Write).await?;
+
+    // Make sure the extension is safe and the asset stays inside of the asset root
+    validate_extension(extension)?;
 
     // Generate uuid and asset_path and asset in the very off chance, one with this uuid doesn't already exist there
     let uuid = get_uuid();
-    let asset_path = format!("{}/{}.{}", folder_path, uuid, extension);
-    if Path::new(&asset_path).exists() {
-        return Err(ControllerError {
-        io: None,
-        wither: None,
-        bcrypt: None,
-        operation: Some(format!("ERROR: Bad operation, attempted to save asset at path {}, but this asset already exists here!", asset_path))
-      });
+    let asset_path = format!("{}/{}.{}", folder_doc.path, uuid, extension);
+    let asset_key = storage_key(&config.storage_root, &asset_path)?;
+    if storage.exists(asset_key).await? {
+        return Err(ControllerError::AlreadyExists(format!(
+            "Attempted to save asset at path {}, but this asset already exists here!",
+            asset_path
+        )));
     }
 
+    // Attempt to write the bytes before there is a doc pointing at them, storage only shows them
+    // once every one of them is written
+    let (size, checksum) = match config.content_addressed {
+        true => write_blob(store, storage, &uuid, file_stream, known).await?,
+        false => write_bytes(storage, asset_key, file_stream).await?,
+    };
+
     // Get meta data for asset Doc
     let (timestamp, timestamp_readable) = get_time_meta();
 
-    let mut asset_doc = Asset {
+    let asset_doc = Asset {
         id: None,
+        folder_id: folder.clone(),
         uuid,
         tag: tag.to_string(),
         path: asset_path.clone(),
+        size: size as i64,
+        checksum: checksum.clone(),
+        blob: config.content_addressed.then(|| checksum.clone()),
+        integrity: Integrity::Unverified,
+        verified_at: None,
         timestamp,
         timestamp_readable,
     };
 
-    // Attempt to save asset doc
-    let save_result = asset_doc.save(db_ref, None).await;
-    if save_result.is_err() {
-        return Err(ControllerError {
-            io: None,
-            wither: save_result.err(),
-            bcrypt: None,
-            operation: None,
-        });
+    // Attempt to save asset doc, the bytes are no use to anyone without it
+    let _id = match record_asset(store, folder, asset_doc.clone()).await {
+        Ok(_id) => _id,
+        Err(err) => {
+            let _ = release_asset_bytes(store, storage, config, &asset_doc).await;
+            return Err(err);
+        }
+    };
+
+    // Return ObjectId, size and checksum if all goes well
+    Ok(StoredAsset {
+        id: _id,
+        size,
+        checksum,
+    })
+}
+/**
+ * Controller to turn bytes that are already stored at asset_path, directly inside of the folder,
+ * into an asset, hashing them where they are instead of writing them again
+ */
+pub async fn register_asset(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    principal: &Principal,
+    tag: &str,
+    folder: &ObjectId,
+    asset_path: &str,
+) -> Result<StoredAsset, ControllerError> {
+    // Registering requires write access to the folder, same as uploading
+    let folder_doc = get_folder(store, folder).await?;
+    authorize_folder(store, principal, &folder_doc, Permission::Write).await?;
+
+    let in_folder = Path::new(asset_path)
+        .parent()
+        .is_some_and(|parent| parent == Path::new(&folder_doc.path));
+    if !in_folder {
+        return Err(ControllerError::InvalidInput(format!(
+            "The path {} is not directly inside of the folder {}",
+            asset_path, folder_doc.path
+        )));
     }
 
+    // Hash the stored bytes
+    let asset_key = storage_key(&config.storage_root, asset_path)?;
+    let mut bytes = storage.get(asset_key).await?;
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+    while let Some(chunk) = bytes.next().await {
+        let chunk = chunk?;
+        hasher.update(&chunk);
+        size += chunk.len() as u64;
+    }
+    let checksum = format!("{:x}", hasher.finalize());
+
+    // Get meta data for asset Doc
+    let (timestamp, timestamp_readable) = get_time_meta();
+
+    let asset_doc = Asset {
+        id: None,
+        folder_id: folder.clone(),
+        uuid: get_uuid(),
+        tag: tag.to_string(),
+        path: asset_path.to_string(),
+        size: size as i64,
+        checksum: checksum.clone(),
+        blob: None,
+        integrity: Integrity::Intact,
+        verified_at: Some(get_timestamp() as i64),
+        timestamp,
+        timestamp_readable,
+    };
+
+    // The bytes were there before the asset and stay when saving the doc fails
+    let _id = record_asset(store, folder, asset_doc).await?;
+
+    Ok(StoredAsset {
+        id: _id,
+        size,
+        checksum,
+    })
+}
+/**
+ * Save asset_doc and add it to the files of folder, removing the doc again if that fails
+ */
+async fn record_asset(
+    store: &dyn MetaStore,
+    folder: &ObjectId,
+    asset_doc: Asset,
+) -> Result<ObjectId, ControllerError> {
+    let asset_doc = store.insert_asset(asset_doc).await?;
+
     // Attempt to get _id after save
-    let doc_id = asset_doc.id();
-    if doc_id.is_none() {
-        return Err(ControllerError {
-        io: None,
-        wither: None,
-        bcrypt: None,
-        operation: Some("FATAL: Was unable to get folder docs _id field after saving in MongoDB succesfully..".to_string())
-      });
-    }
-
-    // Attempt to write data to disk to path
-    let write_result = write(asset_path, file_data);
-    if write_result.is_err() {
-        return Err(ControllerError {
-            io: write_result.err(),
-            wither: None,
-            bcrypt: None,
-            operation: None,
+    let _id = asset_doc.id.ok_or_else(|| {
+        ControllerError::Internal("Was unable to get asset docs _id field".to_string())
+    })?;
+
+    // Attempt to add this assets _id to the files of its folder, which may be gone by now
+    let added = store
+        .add_to_folder_list(folder, FolderList::Files, &_id)
+        .await;
+    match added {
+        Ok(true) => Ok(_id),
+        Ok(false) => {
+            let _ = store.delete_asset(&_id).await;
+            Err(ControllerError::NotFound(format!(
+                "The folder {} was deleted during the upload",
+                folder
+            )))
+        }
+        Err(err) => {
+            let _ = store.delete_asset(&_id).await;
+            Err(err)
+        }
+    }
+}
+/**
+ * Write the chunks to key as they arrive, hashing them on the way, returning their size and checksum
+ */
+async fn write_bytes<S>(
+    storage: &dyn Storage,
+    key: &str,
+    file_stream: S,
+) -> Result<(u64, String), ControllerError>
+where
+    S: Stream<Item = Result<Bytes, std::io::Error>> + Send + Unpin,
+{
+    let mut hasher = Sha256::new();
+    let put_result = {
+        let mut hashing_stream = file_stream.inspect(|chunk| {
+            if let Ok(chunk) = chunk {
+                hasher.update(chunk);
+            }
         });
+        storage.put(key, &mut hashing_stream).await
+    };
+
+    // A failed put leaves nothing behind to clean up
+    let size = put_result?;
+
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
+/**
+ * Add the chunks to the blob of their checksum, returning their size and checksum. When they are
+ * known up front bytes already held by a blob aren't written again, otherwise they are staged under
+ * uuid until every one of them is hashed
+ */
+async fn write_blob<S>(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    uuid: &str,
+    file_stream: S,
+    known: Option<(u64, String)>,
+) -> Result<(u64, String), ControllerError>
+where
+    S: Stream<Item = Result<Bytes, std::io::Error>> + Send + Unpin,
+{
+    let (size, checksum) = match known {
+        Some(known) => known,
+        None => {
+            let staged_key = staging_key(uuid);
+            let (size, checksum) = write_bytes(storage, &staged_key, file_stream).await?;
+            commit_staged_blob(store, storage, &staged_key, &checksum, size).await?;
+
+            return Ok((size, checksum));
+        }
+    };
+
+    let existed = acquire_blob(store, &checksum, size).await?;
+    let key = blob_key(&checksum);
+    let stored = match existed {
+        true => storage.exists(&key).await,
+        false => Ok(false),
+    };
+
+    // Only write what no blob holds yet, or what went missing from the blob
+    let written = match stored {
+        Ok(true) => Ok((size, checksum.clone())),
+        Ok(false) => write_bytes(storage, &key, file_stream).await,
+        Err(err) => Err(err),
+    };
+    match written {
+        Ok((written_size, _)) if written_size == size => Ok((size, checksum)),
+        Ok(_) => {
+            let _ = release_blob(store, &checksum).await;
+            Err(ControllerError::Internal(format!(
+                "Wrote a different number of bytes than the {} of blob {}",
+                size, checksum
+            )))
+        }
+        Err(err) => {
+            let _ = release_blob(store, &checksum).await;
+            Err(err)
+        }
+    }
+}
+/**
+ * Controller to find an asset by its ObjectId (hex) or its uuid, requires read access to its folder
+ */
+pub async fn get_asset(
+    store: &dyn MetaStore,
+    principal: &Principal,
+    id_or_uuid: &str,
+) -> Result<Option<Asset>, ControllerError> {
+    let asset_doc = match find_asset(store, id_or_uuid).await? {
+        Some(asset_doc) => asset_doc,
+        None => return Ok(None),
+    };
+
+    let folder_doc = get_asset_folder(store, &asset_doc).await?;
+    authorize_folder(store, principal, &folder_doc, Permission::Read).await?;
+
+    Ok(Some(asset_doc))
+}
+/**
+ * Find an asset by its ObjectId (hex) or its uuid, without any access check
+ */
+async fn find_asset(
+    store: &dyn MetaStore,
+    id_or_uuid: &str,
+) -> Result<Option<Asset>, ControllerError> {
+    // Attempt to find asset doc
+    match ObjectId::with_string(id_or_uuid) {
+        Ok(id) => store.find_asset(&id).await,
+        Err(_) => store.find_asset_by_uuid(id_or_uuid).await,
+    }
+}
+/**
+ * Find the folder an asset is in
+ */
+async fn get_asset_folder(store: &dyn MetaStore, asset: &Asset) -> Result<Folder, ControllerError> {
+    get_folder(store, &asset.folder_id).await
+}
+/**
+ * Controller to find a folder by its path, a missing folder is an error
+ */
+pub async fn get_folder_by_path(
+    store: &dyn MetaStore,
+    path: &str,
+) -> Result<Folder, ControllerError> {
+    // Attempt to find folder doc
+    store.find_folder_by_path(path).await?.ok_or_else(|| {
+        ControllerError::NotFound(format!("Was unable to find a folder by the path {}", path))
+    })
+}
+/**
+ * Controller to find a folder by its ObjectId, a missing folder is an error
+ */
+pub async fn get_folder(
+    store: &dyn MetaStore,
+    folder: &ObjectId,
+) -> Result<Folder, ControllerError> {
+    // Attempt to find folder doc
+    store.find_folder(folder).await?.ok_or_else(|| {
+        ControllerError::NotFound(format!(
+            "Was unable to find a folder by the ObjectId {}",
+            folder
+        ))
+    })
+}
+/**
+ * Controller to delete an asset, its file on disk and its doc, requires write access to its folder
+ */
+pub async fn delete_asset(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    principal: &Principal,
+    id_or_uuid: &str,
+) -> Result<(), ControllerError> {
+    let asset_doc = match find_asset(store, id_or_uuid).await? {
+        Some(asset_doc) => asset_doc,
+        None => {
+            return Err(ControllerError::NotFound(format!(
+                "No asset found for {}",
+                id_or_uuid
+            )))
+        }
+    };
+
+    let folder_doc = get_asset_folder(store, &asset_doc).await?;
+    authorize_folder(store, principal, &folder_doc, Permission::Write).await?;
+
+    // Attempt to delete the asset doc first, so a failure further on leaves bytes nobody points
+    // at instead of a doc pointing at nothing
+    // Keep the files of its folder in sync
+    if let Some(asset_id) = &asset_doc.id {
+        store.delete_asset(asset_id).await?;
+        store
+            .remove_from_all_folder_lists(FolderList::Files, std::slice::from_ref(asset_id))
+            .await?;
+    }
+
+    // Attempt to remove the bytes, or the reference to the blob holding them
+    release_asset_bytes(store, storage, config, &asset_doc).await
+}
+/**
+ * Controller to move an asset into the folder to_folder and/or give it the tag, requires write
+ * access to the folder it is in and to the one it goes to. The bytes move along unless they are a
+ * shared blob
+ */
+pub async fn move_asset(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    principal: &Principal,
+    id_or_uuid: &str,
+    to_folder: Option<&ObjectId>,
+    tag: Option<&str>,
+) -> Result<Asset, ControllerError> {
+    let asset_doc = match find_asset(store, id_or_uuid).await? {
+        Some(asset_doc) => asset_doc,
+        None => {
+            return Err(ControllerError::NotFound(format!(
+                "No asset found for {}",
+                id_or_uuid
+            )))
+        }
+    };
+    let asset_id = asset_doc.id.clone().ok_or_else(|| {
+        ControllerError::Internal(format!("Asset {} has no ObjectId", asset_doc.uuid))
+    })?;
+
+    let folder_doc = get_asset_folder(store, &asset_doc).await?;
+    authorize_folder(store, principal, &folder_doc, Permission::Write).await?;
+
+    let to_folder_doc = match to_folder {
+        Some(to_folder) if *to_folder != asset_doc.folder_id => {
+            let to_folder_doc = get_folder(store, to_folder).await?;
+            authorize_folder(store, principal, &to_folder_doc, Permission::Write).await?;
+            to_folder_doc
+        }
+        _ => folder_doc,
+    };
+    let to_folder = to_folder_doc
+        .id
+        .clone()
+        .unwrap_or_else(|| asset_doc.folder_id.clone());
+
+    // The asset keeps the name of its file, only the folder in front of it changes
+    let file_name = Path::new(&asset_doc.path)
+        .file_name()
+        .and_then(|file_name| file_name.to_str())
+        .ok_or_else(|| {
+            ControllerError::Internal(format!("Asset {} has no file name", asset_doc.path))
+        })?;
+    let to_path = format!("{}/{}", to_folder_doc.path, file_name);
+    let tag = tag.unwrap_or(&asset_doc.tag);
+
+    // Attempt to move the bytes first, unless another asset already has them
+    let move_bytes = asset_doc.blob.is_none() && to_path != asset_doc.path;
+    let key = storage_key(&config.storage_root, &asset_doc.path)?;
+    let to_key = storage_key(&config.storage_root, &to_path)?;
+    if move_bytes {
+        if storage.exists(to_key).await? {
+            return Err(ControllerError::AlreadyExists(format!(
+                "Attempted to move asset to path {}, but an asset already exists here!",
+                to_path
+            )));
+        }
+        storage.rename(key, to_key).await?;
+    }
+
+    // Then point the doc at them, or move them back if that fails
+    let moved = store
+        .move_asset(&asset_id, &asset_doc.folder_id, &to_folder, tag, &to_path)
+        .await;
+    match moved {
+        Ok(Some(asset_doc)) => Ok(asset_doc),
+        Ok(None) => {
+            if move_bytes {
+                let _ = storage.rename(to_key, key).await;
+            }
+            Err(ControllerError::NotFound(format!(
+                "The asset {} was deleted during the move",
+                id_or_uuid
+            )))
+        }
+        Err(err) => {
+            if move_bytes {
+                let _ = storage.rename(to_key, key).await;
+            }
+            Err(err)
+        }
+    }
+}
+/**
+ * Controller to move a folder below the folder at parent_path (the storage root for a top level
+ * folder) and/or rename it to name, with every sub folder and asset below it. Requires admin rights
+ * on the folder and write access to its new parent folder, a top level folder needs no parent.
+ *
+ * A folder that leaves the folders above it leaves their admins behind too, so a caller whose admin
+ * rights came from one of those becomes an admin of the folder itself, like the creator of a folder.
+ *
+ * The bytes move first and the paths of every doc after, and a move that fails is moved back. One
+ * that got interrupted in between is left for fsck to find
+ */
+pub async fn move_folder(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    principal: &Principal,
+    folder: &ObjectId,
+    parent_path: &str,
+    name: &str,
+) -> Result<Folder, ControllerError> {
+    let folder_doc = get_folder(store, folder).await?;
+    authorize_folder(store, principal, &folder_doc, Permission::Admin).await?;
+
+    // Make sure the name is safe and the folder stays inside of the asset root, below itself is
+    // not an option
+    validate_name(name)?;
+    let to_path = format!("{}/{}", parent_path, name);
+    let to_key = storage_key(&config.storage_root, &to_path)?.to_string();
+    if to_path == folder_doc.path {
+        return Ok(folder_doc);
+    }
+    if to_path.starts_with(&format!("{}/", folder_doc.path)) {
+        return Err(ControllerError::InvalidInput(format!(
+            "Cannot move the folder {} below itself",
+            folder_doc.path
+        )));
+    }
+
+    // Moving a folder into another one requires write access to that folder, like creating one
+    if parent_path != config.storage_root {
+        let parent_folder_doc = get_folder_by_path(store, parent_path).await?;
+        authorize_folder(store, principal, &parent_folder_doc, Permission::Write).await?;
+    }
+
+    // Find out whether the caller keeps administrating the folder at its new place
+    let from_parent = Path::new(&folder_doc.path)
+        .parent()
+        .and_then(|parent| parent.to_str())
+        .unwrap_or(&config.storage_root);
+    let caller = current_user(store, principal).await?;
+    let keeps_admin =
+        from_parent == parent_path || caller.superuser || caller.folder_admins.contains(folder);
+
+    // Folder tags are unique, same as paths
+    if store.find_folder_by_path(&to_path).await?.is_some() {
+        return Err(ControllerError::AlreadyExists(format!(
+            "Cannot move the folder to {}, a folder already exists there",
+            to_path
+        )));
+    }
+    let tag_taken = match store.find_folder_by_tag(name).await? {
+        Some(other) => other.id.as_ref() != Some(folder),
+        None => false,
+    };
+    if tag_taken {
+        return Err(ControllerError::AlreadyExists(format!(
+            "Cannot rename the folder to {}, another folder is already called that",
+            name
+        )));
+    }
+
+    // Attempt to move the directory and the bytes of every asset below the folder
+    let key = storage_key(&config.storage_root, &folder_doc.path)?.to_string();
+    storage.rename_dir(&key, &to_key).await?;
+
+    // Then rewrite the paths of the folder, every folder and every asset below it, moving the
+    // bytes back if that fails
+    let moved = store
+        .move_folder(folder, name, &folder_doc.path, &to_path)
+        .await;
+    let moved_doc = match moved {
+        Ok(Some(folder_doc)) => folder_doc,
+        Ok(None) => {
+            let _ = storage.rename_dir(&to_key, &key).await;
+            return Err(ControllerError::NotFound(format!(
+                "The folder {} was deleted during the move",
+                folder
+            )));
+        }
+        Err(err) => {
+            let _ = storage.rename_dir(&to_key, &key).await;
+            return Err(err);
+        }
+    };
+
+    // Attempt to keep the caller an admin of the folder it moved
+    if !keeps_admin {
+        let caller_id = principal.require_user_id()?;
+        store
+            .add_to_user_list(&caller_id, UserList::FolderAdmins, folder)
+            .await?;
+    }
+
+    Ok(moved_doc)
+}
+/**
+ * Controller to copy an asset into the folder to_folder as a new asset, tagged tag or like the
+ * original. Requires read access to the folder of the asset and write access to to_folder. Bytes
+ * held by a shared blob are shared by the copy as well, other bytes are copied
+ */
+pub async fn copy_asset(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    principal: &Principal,
+    id_or_uuid: &str,
+    to_folder: &ObjectId,
+    tag: Option<&str>,
+) -> Result<Asset, ControllerError> {
+    let asset_doc = match get_asset(store, principal, id_or_uuid).await? {
+        Some(asset_doc) => asset_doc,
+        None => {
+            return Err(ControllerError::NotFound(format!(
+                "No asset found for {}",
+                id_or_uuid
+            )))
+        }
+    };
+
+    let tag = tag.unwrap_or(&asset_doc.tag);
+    let _id = duplicate_asset(
+        store, storage, config, principal, &asset_doc, to_folder, tag,
+    )
+    .await?;
+
+    store.find_asset(&_id).await?.ok_or_else(|| {
+        ControllerError::NotFound(format!("The copy {} was deleted right away", _id))
+    })
+}
+/**
+ * Save a new asset in to_folder with the bytes of asset, a reference to its blob if it has one or
+ * a copy of them otherwise
+ */
+#[allow(clippy::too_many_arguments)]
+async fn duplicate_asset(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    principal: &Principal,
+    asset: &Asset,
+    to_folder: &ObjectId,
+    tag: &str,
+) -> Result<ObjectId, ControllerError> {
+    let extension = usable_extension(Path::new(&asset.path));
+    let checksum = match &asset.blob {
+        Some(checksum) => checksum,
+        None => {
+            // Copied bytes are hashed again on the way, so the copy doesn't inherit a stale checksum
+            let bytes = storage
+                .get(storage_key(&config.storage_root, &asset.path)?)
+                .await?;
+            let stored_asset = store_asset(
+                store, storage, config, principal, bytes, None, tag, to_folder, extension,
+            )
+            .await?;
+
+            return Ok(stored_asset.id);
+        }
+    };
+
+    // Copying into a folder requires write access, same as uploading
+    let folder_doc = get_folder(store, to_folder).await?;
+    authorize_folder(store, principal, &folder_doc, Permission::Write).await?;
+
+    let uuid = get_uuid();
+    let asset_path = format!("{}/{}.{}", folder_doc.path, uuid, extension);
+    storage_key(&config.storage_root, &asset_path)?;
+
+    acquire_blob(store, checksum, asset.size as u64).await?;
+
+    // Get meta data for asset Doc
+    let (timestamp, timestamp_readable) = get_time_meta();
+
+    let asset_doc = Asset {
+        id: None,
+        folder_id: to_folder.clone(),
+        uuid,
+        tag: tag.to_string(),
+        path: asset_path,
+        size: asset.size,
+        checksum: asset.checksum.clone(),
+        blob: Some(checksum.clone()),
+        integrity: Integrity::Unverified,
+        verified_at: None,
+        timestamp,
+        timestamp_readable,
+    };
+
+    // Attempt to save asset doc, letting go of the blob again if that fails
+    match record_asset(store, to_folder, asset_doc).await {
+        Ok(_id) => Ok(_id),
+        Err(err) => {
+            let _ = release_blob(store, checksum).await;
+            Err(err)
+        }
+    }
+}
+/**
+ * Controller to copy a folder with every sub folder and asset below it, the copy is called name and
+ * goes below the folder at parent_path (the storage root for a top level folder). Requires read
+ * access to every folder that is copied and write access to the new parent folder, the caller
+ * becomes admin of the copy.
+ *
+ * With keep_access the copies are as public and in the same access groups as the originals,
+ * otherwise every copy is private and in no access group, left to the caller to share. Folder names are unique across every
+ * folder, so copies of sub folders get the first free "<name> <n>" when their own name is taken. A
+ * copy that fails halfway is deleted again
+ */
+#[allow(clippy::too_many_arguments)]
+pub async fn copy_folder(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    principal: &Principal,
+    folder: &ObjectId,
+    parent_path: &str,
+    name: &str,
+    keep_access: bool,
+) -> Result<Folder, ControllerError> {
+    let folder_doc = get_folder(store, folder).await?;
+
+    // Check every folder that gets copied up front, parents sort before their sub folders
+    let mut sub_folders = store.find_folders_below(&folder_doc.path).await?;
+    sub_folders.sort_by(|left, right| left.path.cmp(&right.path));
+    authorize_folder(store, principal, &folder_doc, Permission::Read).await?;
+    for sub_folder in &sub_folders {
+        authorize_folder(store, principal, sub_folder, Permission::Read).await?;
+    }
+
+    let to_path = format!("{}/{}", parent_path, name);
+    if to_path.starts_with(&format!("{}/", folder_doc.path)) {
+        return Err(ControllerError::InvalidInput(format!(
+            "Cannot copy the folder {} below itself",
+            folder_doc.path
+        )));
+    }
+
+    let copy_doc = match parent_path == config.storage_root {
+        true => create_folder(store, storage, config, principal, name, None, None).await?,
+        false => {
+            create_sub_folder(store, storage, config, principal, parent_path, name, None).await?
+        }
+    };
+
+    let copied = copy_folder_contents(
+        store,
+        storage,
+        config,
+        principal,
+        &folder_doc,
+        &sub_folders,
+        &copy_doc,
+        keep_access,
+    )
+    .await;
+    if let Err(err) = copied {
+        if let Some(copy_id) = &copy_doc.id {
+            let _ = delete_folder(store, storage, config, principal, copy_id).await;
+        }
+        return Err(err);
+    }
+
+    get_folder(store, &folder_id_of(&copy_doc)?).await
+}
+/**
+ * Copy the access, sub folders and assets of folder into the freshly created copy
+ */
+#[allow(clippy::too_many_arguments)]
+async fn copy_folder_contents(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    principal: &Principal,
+    folder: &Folder,
+    sub_folders: &[Folder],
+    copy: &Folder,
+    keep_access: bool,
+) -> Result<(), ControllerError> {
+    let mut copies: HashMap<String, Folder> = HashMap::new();
+    copies.insert(folder.path.clone(), copy.clone());
+
+    for sub_folder in sub_folders {
+        let parent_copy = Path::new(&sub_folder.path)
+            .parent()
+            .and_then(|parent| parent.to_str())
+            .and_then(|parent| copies.get(parent))
+            .ok_or_else(|| {
+                ControllerError::Internal(format!(
+                    "The folder above {} was not copied",
+                    sub_folder.path
+                ))
+            })?;
+        let name = free_folder_name(store, &sub_folder.tag).await?;
+        let sub_copy = create_sub_folder(
+            store,
+            storage,
+            config,
+            principal,
+            &parent_copy.path,
+            &name,
+            None,
+        )
+        .await?;
+        copies.insert(sub_folder.path.clone(), sub_copy);
+    }
+
+    for original in std::iter::once(folder).chain(sub_folders) {
+        let folder_copy = &copies[&original.path];
+        let folder_copy_id = folder_id_of(folder_copy)?;
+
+        // Without keep_access nothing is shared, so a reader can't publish what they were let in on
+        match keep_access {
+            true => {
+                store
+                    .set_folder_access(&folder_copy_id, original.is_public, &original.access_groups)
+                    .await?
+            }
+            false => store.set_folder_access(&folder_copy_id, false, &[]).await?,
+        };
+
+        for asset_doc in store
+            .find_assets_in_folder(&folder_id_of(original)?)
+            .await?
+        {
+            duplicate_asset(
+                store,
+                storage,
+                config,
+                principal,
+                &asset_doc,
+                &folder_copy_id,
+                &asset_doc.tag,
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+/**
+ * name if no folder is called that yet, otherwise the first free "name n" counting from 2
+ */
+async fn free_folder_name(store: &dyn MetaStore, name: &str) -> Result<String, ControllerError> {
+    let mut candidate = name.to_string();
+    let mut number = 1;
+    while store.find_folder_by_tag(&candidate).await?.is_some() {
+        number += 1;
+        candidate = format!("{} {}", name, number);
+    }
+
+    Ok(candidate)
+}
+
+fn folder_id_of(folder: &Folder) -> Result<ObjectId, ControllerError> {
+    folder.id.clone().ok_or_else(|| {
+        ControllerError::Internal("Was unable to get folder docs _id field".to_string())
+    })
+}
+/**
+ * Controller to delete a folder with every sub folder and asset below it, in storage and in the DB,
+ * requires admin rights on the folder
+ */
+pub async fn delete_folder(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    principal: &Principal,
+    folder: &ObjectId,
+) -> Result<(), ControllerError> {
+    let folder_doc = get_folder(store, folder).await?;
+    authorize_folder(store, principal, &folder_doc, Permission::Admin).await?;
+
+    // Attempt to find the ObjectIds of every sub folder, to scrub them from admins later
+    let sub_folders = store.find_folders_below(&folder_doc.path).await?;
+
+    let mut folder_ids: Vec<ObjectId> = sub_folders
+        .iter()
+        .filter_map(|sub_folder| sub_folder.id.clone())
+        .collect();
+    folder_ids.push(folder.clone());
+
+    // Attempt to find the blobs of every asset below the folder, before their docs are gone
+    let mut blobs = vec![];
+    for folder_id in &folder_ids {
+        for asset_doc in store.find_assets_in_folder(folder_id).await? {
+            blobs.extend(asset_doc.blob);
+        }
+    }
+
+    // Attempt to delete every asset doc below the folder, the docs go before the bytes so a failure
+    // further on leaves bytes nobody points at instead of docs pointing at nothing
+    store.delete_assets_in_folders(&folder_ids).await?;
+
+    // Attempt to delete the folder docs
+    store.delete_folders(&folder_ids).await?;
+
+    // Scrub the folders from every admin list
+    store
+        .remove_from_all_user_lists(UserList::FolderAdmins, &folder_ids)
+        .await?;
+
+    // Attempt to release the blobs of the assets below the folder
+    for checksum in &blobs {
+        release_blob(store, checksum).await?;
+    }
+
+    // Attempt to remove the bytes of every other asset below the folder, and the directories left
+    let folder_key = storage_key(&config.storage_root, &folder_doc.path)?;
+    for key in storage.list(&format!("{}/", folder_key)).await? {
+        storage.delete(&key).await?;
+    }
+    storage.remove_dir(folder_key).await
+}
+/**
+ * Controller to make a user an admin of a folder, allowed for the admins of the folder
+ */
+pub async fn add_folder_admin(
+    store: &dyn MetaStore,
+    principal: &Principal,
+    folder: &ObjectId,
+    user: &ObjectId,
+) -> Result<User, ControllerError> {
+    let folder_doc = get_folder(store, folder).await?;
+    authorize_folder(store, principal, &folder_doc, Permission::Admin).await?;
+
+    let user_doc = store
+        .add_to_user_list(user, UserList::FolderAdmins, folder)
+        .await?;
+    found_user(user_doc, user)
+}
+/**
+ * Controller to take a folder away from one of its admins, allowed for the admins of the folder
+ */
+pub async fn remove_folder_admin(
+    store: &dyn MetaStore,
+    principal: &Principal,
+    folder: &ObjectId,
+    user: &ObjectId,
+) -> Result<User, ControllerError> {
+    let folder_doc = get_folder(store, folder).await?;
+    authorize_folder(store, principal, &folder_doc, Permission::Admin).await?;
+
+    let user_doc = store
+        .remove_from_user_list(user, UserList::FolderAdmins, folder)
+        .await?;
+    found_user(user_doc, user)
+}
+/**
+ * The user doc as it is after an update, a missing one is an error
+ */
+fn found_user(user_doc: Option<User>, user: &ObjectId) -> Result<User, ControllerError> {
+    user_doc.ok_or_else(|| {
+        ControllerError::NotFound(format!(
+            "Was unable to find a user by the ObjectId {}",
+            user
+        ))
+    })
+}
+/**
+ * Controller to delete a user, allowed for the user itself and its user admins.
+ *
+ * Folders nobody else administrates are handed to the caller, a user deleting itself has to make
+ * someone else their admin first with add_folder_admin. Keys nobody else holds or administrates
+ * are revoked and deleted with the user, so they stop granting access through the access groups
+ * that allow them
+ */
+pub async fn delete_user(
+    store: &dyn MetaStore,
+    principal: &Principal,
+    user: &ObjectId,
+) -> Result<(), ControllerError> {
+    ensure_user_admin(principal, user)?;
+
+    let user_doc = store.find_user(user).await?.ok_or_else(|| {
+        ControllerError::NotFound(format!(
+            "Was unable to find a user by the ObjectId {}",
+            user
+        ))
+    })?;
+    let others: Vec<User> = store
+        .find_users(None)
+        .await?
+        .into_iter()
+        .filter(|other| other.id.as_ref() != Some(user))
+        .collect();
+
+    // Attempt to hand the folders only this user administrates to the caller
+    let orphaned_folders = sole_admin_folders(store, &user_doc, &others).await?;
+    if !orphaned_folders.is_empty() {
+        let caller = principal.require_user_id()?;
+        if caller == *user {
+            let paths: Vec<&str> = orphaned_folders
+                .iter()
+                .map(|folder| folder.path.as_str())
+                .collect();
+            return Err(ControllerError::Forbidden(format!(
+                "User {} is the only admin of {}, make someone else their admin first",
+                user_doc.user,
+                paths.join(", ")
+            )));
+        }
+
+        for folder in &orphaned_folders {
+            store
+                .add_to_user_list(&caller, UserList::FolderAdmins, &folder_id_of(folder)?)
+                .await?;
+        }
+    }
+
+    // Attempt to revoke the keys only this user holds or administrates before the user is gone
+    let mut orphaned_keys: Vec<ObjectId> = vec![];
+    for key in user_doc.keys.iter().chain(&user_doc.key_admins) {
+        let shared = others
+            .iter()
+            .any(|other| other.keys.contains(key) || other.key_admins.contains(key));
+        if !shared && !orphaned_keys.contains(key) {
+            orphaned_keys.push(key.clone());
+        }
+    }
+    for key in &orphaned_keys {
+        store.set_key_active(key, false).await?;
+    }
+
+    // Attempt to delete the user doc
+    if !store.delete_user(user).await? {
+        return Err(ControllerError::NotFound(format!(
+            "Was unable to find a user by the ObjectId {}",
+            user
+        )));
+    }
+
+    // Its sessions die with it
+    store.delete_sessions_of(user).await?;
+
+    // Scrub the user from every admin list
+    store
+        .remove_from_all_user_lists(UserList::UserAdmins, std::slice::from_ref(user))
+        .await?;
+
+    // Then delete its keys and scrub them from every access group
+    if orphaned_keys.is_empty() {
+        return Ok(());
+    }
+    for key in &orphaned_keys {
+        store.delete_key(key).await?;
+    }
+    store.remove_allowed_keys_from_all(&orphaned_keys).await
+}
+/**
+ * The folders user administrates that none of others administrates, directly or through one of the
+ * folders above them
+ */
+async fn sole_admin_folders(
+    store: &dyn MetaStore,
+    user: &User,
+    others: &[User],
+) -> Result<Vec<Folder>, ControllerError> {
+    let mut orphaned = vec![];
+    for folder in &user.folder_admins {
+        let folder_doc = match store.find_folder(folder).await? {
+            Some(folder_doc) => folder_doc,
+            None => continue,
+        };
+
+        // The folder itself and every folder above it
+        let paths: Vec<String> = Path::new(&folder_doc.path)
+            .ancestors()
+            .filter_map(|ancestor| ancestor.to_str())
+            .map(|ancestor| ancestor.to_string())
+            .collect();
+        let administrated = store
+            .find_folders_by_paths(&paths)
+            .await?
+            .iter()
+            .filter_map(|administrated| administrated.id.as_ref())
+            .any(|id| others.iter().any(|other| other.folder_admins.contains(id)));
+
+        if !administrated {
+            orphaned.push(folder_doc);
+        }
+    }
+
+    Ok(orphaned)
+}
+/**
+ * Controller to change the password of a user, allowed for the user itself and its user admins,
+ * every session of the user is revoked
+ */
+pub async fn set_user_password(
+    store: &dyn MetaStore,
+    config: &Config,
+    principal: &Principal,
+    user: &ObjectId,
+    pass: &str,
+) -> Result<(), ControllerError> {
+    ensure_user_admin(principal, user)?;
+    validate_password(pass)?;
+
+    // Attempt to hash pass via bcrypt
+    let hashed_pass = hash(pass, config.bcrypt_cost)?;
+
+    if !store.set_user_pass(user, &hashed_pass).await? {
+        return Err(ControllerError::NotFound(format!(
+            "Was unable to find a user by the ObjectId {}",
+            user
+        )));
+    }
+
+    // Sessions issued with the old password don't survive the change
+    store.revoke_sessions_of(user).await
+}
+/**
+ * Controller to find a user by its username, a missing user is an error
+ */
+pub async fn get_user_by_name(store: &dyn MetaStore, user: &str) -> Result<User, ControllerError> {
+    store
+        .find_user_by_name(user)
+        .await?
+        .ok_or_else(|| ControllerError::NotFound(format!("Was unable to find a user {}", user)))
+}
+/**
+ * Controller to list the users the caller can see, superusers see every user, anyone else sees
+ * itself and the users it administrates
+ */
+pub async fn list_users(
+    store: &dyn MetaStore,
+    principal: &Principal,
+) -> Result<Vec<User>, ControllerError> {
+    let caller = principal.require_user()?;
+    if caller.superuser {
+        return store.find_users(None).await;
+    }
+
+    let mut visible = caller.user_admins.clone();
+    visible.extend(caller.id.iter().cloned());
+    store.find_users(Some(&visible)).await
+}
+/**
+ * Make sure the caller is the user itself, a user admin of it or a superuser
+ */
+fn ensure_user_admin(principal: &Principal, user: &ObjectId) -> Result<(), ControllerError> {
+    let caller = principal.require_user()?;
+    let is_self = caller.id.as_ref() == Some(user);
+    if !is_self && !caller.superuser && !caller.user_admins.contains(user) {
+        return Err(ControllerError::Forbidden(format!(
+            "User {} is not an admin of the user {}",
+            caller.user, user
+        )));
+    }
+
+    Ok(())
+}
+/**
+ * ____________________________________________________________________________________________
+ * FolderListing, result of listing a folder
+ * ____________________________________________________________________________________________
+ * folder: The listed folder
+ * assets: The assets directly in the folder
+ * sub_folders: The folders directly below the folder
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug)]
+pub struct FolderListing {
+    pub folder: Folder,
+    pub assets: Vec<Asset>,
+    pub sub_folders: Vec<Folder>,
+}
+/**
+ * Controller to list the assets and sub folders of a folder, requires list access to the folder
+ */
+pub async fn list_folder(
+    store: &dyn MetaStore,
+    principal: &Principal,
+    folder: &ObjectId,
+) -> Result<FolderListing, ControllerError> {
+    let folder_doc = get_folder(store, folder).await?;
+    authorize_folder(store, principal, &folder_doc, Permission::List).await?;
+
+    // Attempt to find the assets of the folder
+    let assets = store.find_assets_in_folder(folder).await?;
+
+    // Attempt to find the folders exactly one level below the folder
+    let child_prefix = format!("{}/", folder_doc.path);
+    let sub_folders: Vec<Folder> = store
+        .find_folders_below(&folder_doc.path)
+        .await?
+        .into_iter()
+        .filter(|sub_folder| !sub_folder.path[child_prefix.len()..].contains('/'))
+        .collect();
+
+    Ok(FolderListing {
+        folder: folder_doc,
+        assets,
+        sub_folders,
+    })
+}
+/**
+ * Controller to list a folder and every folder below it that the caller can list, sorted by path
+ * so parents come before their children
+ */
+pub async fn list_folder_tree(
+    store: &dyn MetaStore,
+    principal: &Principal,
+    folder: &ObjectId,
+) -> Result<Vec<Folder>, ControllerError> {
+    let folder_doc = get_folder(store, folder).await?;
+    authorize_folder(store, principal, &folder_doc, Permission::List).await?;
+
+    // Attempt to find every folder below the folder
+    let sub_folders = store.find_folders_below(&folder_doc.path).await?;
+
+    // Sub folders can have access groups of their own, leave out the ones the caller can't list
+    let mut tree = vec![folder_doc];
+    for sub_folder in sub_folders {
+        match authorize_folder(store, principal, &sub_folder, Permission::List).await {
+            Ok(()) => tree.push(sub_folder),
+            Err(ControllerError::Forbidden(_)) => {}
+            Err(err) => return Err(err),
+        }
     }
+    tree.sort_by(|a, b| a.path.cmp(&b.path));
 
-    // Return ObjectId if all goes well
-    Ok(doc_id.unwrap())
+    Ok(tree)
 }
@@ -0,0 +1,148 @@
+use crate::config::Config;
+use crate::controller::error::ControllerError;
+use crate::data_models::asset::{Asset, Integrity};
+use crate::storage::{asset_key, ByteStream, Storage};
+use crate::store::{MetaStore, SharedStore};
+use crate::util::get_timestamp;
+use bytes::Bytes;
+use futures::stream::{self, StreamExt};
+use sha2::{Digest, Sha256};
+use std::io;
+
+/**
+ * What the bytes of asset are, measured against the size and checksum recorded when it was stored
+ */
+pub fn integrity_of(asset: &Asset, size: u64, checksum: &str) -> Integrity {
+    if asset.size as u64 == size && asset.checksum == checksum {
+        Integrity::Intact
+    } else {
+        Integrity::Corrupt
+    }
+}
+/**
+ * Remember what a check of asset found, and when
+ */
+pub async fn record_integrity(
+    store: &dyn MetaStore,
+    asset: &Asset,
+    integrity: Integrity,
+) -> Result<(), ControllerError> {
+    let id = match &asset.id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    store
+        .set_asset_integrity(id, integrity, get_timestamp() as i64)
+        .await?;
+
+    Ok(())
+}
+/**
+ * Controller to read back every byte of an asset and check it against its checksum, assets stored
+ * before checksums were recorded get theirs recorded now. Storage failures that say nothing about
+ * the bytes themselves are returned as errors instead of flagging the asset
+ */
+pub async fn verify_asset(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    asset: &Asset,
+) -> Result<Integrity, ControllerError> {
+    let key = asset_key(&config.storage_root, asset)?;
+
+    let mut bytes = match storage.get(&key).await {
+        Ok(bytes) => bytes,
+        Err(ControllerError::NotFound(_)) => {
+            record_integrity(store, asset, Integrity::Missing).await?;
+            return Ok(Integrity::Missing);
+        }
+        Err(err) => return Err(err),
+    };
+
+    let mut hasher = Sha256::new();
+    let mut size: u64 = 0;
+    while let Some(chunk) = bytes.next().await {
+        let chunk = chunk?;
+        hasher.update(&chunk);
+        size += chunk.len() as u64;
+    }
+    let checksum = format!("{:x}", hasher.finalize());
+
+    let integrity = if asset.checksum.is_empty() {
+        if let Some(id) = &asset.id {
+            store
+                .set_asset_checksum(id, size as i64, &checksum, asset.blob.as_deref())
+                .await?;
+        }
+        Integrity::Intact
+    } else {
+        integrity_of(asset, size, &checksum)
+    };
+    record_integrity(store, asset, integrity).await?;
+
+    Ok(integrity)
+}
+/**
+ * Pass the bytes of a full download of asset through, checking them against its checksum on the
+ * way. The last chunk is held back until the check is done, so corrupt bytes end in an error
+ * instead of a complete response, either way the outcome is recorded on the asset
+ */
+pub fn verifying_stream(bytes: ByteStream, asset: Asset, store: SharedStore) -> ByteStream {
+    struct Verifying {
+        bytes: ByteStream,
+        hasher: Sha256,
+        size: u64,
+        held_back: Option<Bytes>,
+        asset: Asset,
+        store: SharedStore,
+    }
+
+    let verifying = Verifying {
+        bytes,
+        hasher: Sha256::new(),
+        size: 0,
+        held_back: None,
+        asset,
+        store,
+    };
+
+    Box::pin(stream::unfold(Some(verifying), |verifying| async move {
+        let mut verifying = verifying?;
+
+        loop {
+            match verifying.bytes.next().await {
+                Some(Ok(chunk)) => {
+                    verifying.hasher.update(&chunk);
+                    verifying.size += chunk.len() as u64;
+                    if let Some(previous) = verifying.held_back.replace(chunk) {
+                        return Some((Ok(previous), Some(verifying)));
+                    }
+                }
+                Some(Err(err)) => return Some((Err(err), None)),
+                None => break,
+            }
+        }
+
+        let checksum = format!("{:x}", verifying.hasher.finalize());
+        let integrity = integrity_of(&verifying.asset, verifying.size, &checksum);
+
+        // Downloads are frequent, only write when the outcome changed
+        if integrity != verifying.asset.integrity {
+            let _ = record_integrity(verifying.store.as_ref(), &verifying.asset, integrity).await;
+        }
+
+        if integrity == Integrity::Corrupt {
+            let err = io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "The bytes of asset {} don't match its checksum",
+                    verifying.asset.uuid
+                ),
+            );
+            return Some((Err(err), None));
+        }
+
+        verifying.held_back.map(|last| (Ok(last), None))
+    }))
+}
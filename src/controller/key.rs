@@ -0,0 +1,199 @@
+use crate::controller::access::{current_user, Principal};
+use crate::controller::error::ControllerError;
+use crate::data_models::{key::Key, user::User};
+use crate::store::{MetaStore, UserList};
+use crate::util::{get_random_token, get_sha256_hex, get_time_meta, get_uuid};
+use wither::mongodb::bson::oid::ObjectId;
+
+/**
+ * ____________________________________________________________________________________________
+ * IssuedKey, result of creating a key
+ * ____________________________________________________________________________________________
+ * token: Bearer credential of the form <uuid>.<secret>, only ever handed out here
+ * key: The stored Key doc
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug)]
+pub struct IssuedKey {
+    pub token: String,
+    pub key: Key,
+}
+/**
+ * Controller to create an API key, the creator becomes its key admin
+ */
+pub async fn create_key(
+    store: &dyn MetaStore,
+    principal: &Principal,
+) -> Result<IssuedKey, ControllerError> {
+    // Only logged in users can create keys
+    let admin = principal.require_user_id()?;
+
+    let uuid = get_uuid();
+    let secret = get_random_token();
+
+    // Get meta data for key Doc
+    let (timestamp, timestamp_readable) = get_time_meta();
+
+    let key_doc = Key {
+        id: None,
+        uuid: uuid.clone(),
+        secret_hash: get_sha256_hex(&secret),
+        active: true,
+        timestamp,
+        timestamp_readable,
+    };
+
+    // Attempt to save key doc
+    let key_doc = store.insert_key(key_doc).await?;
+
+    // Attempt to get ObjectId
+    let _id = key_doc.id.clone().ok_or_else(|| {
+        ControllerError::Internal("Was unable to get key docs _id field".to_string())
+    })?;
+
+    // Attempt to add this keys _id to the admins key admin list
+    let user_doc = store
+        .add_to_user_list(&admin, UserList::KeyAdmins, &_id)
+        .await?;
+
+    // Verify a user exist and was updated, otherwise this key has no admin
+    if user_doc.is_none() {
+        // We need to clear this key as it has no admin
+        store.delete_key(&_id).await?;
+
+        return Err(ControllerError::NotFound(format!(
+            "Was unable to find a user by the ObjectId {}, a key needs to have a admin to be created!",
+            admin
+        )));
+    }
+
+    Ok(IssuedKey {
+        token: format!("{}.{}", uuid, secret),
+        key: key_doc,
+    })
+}
+/**
+ * Controller to activate or revoke a key, revoked keys stop granting access right away
+ */
+pub async fn set_key_active(
+    store: &dyn MetaStore,
+    principal: &Principal,
+    key: &ObjectId,
+    active: bool,
+) -> Result<Key, ControllerError> {
+    ensure_key_admin(store, principal, key).await?;
+
+    store.set_key_active(key, active).await?.ok_or_else(|| {
+        ControllerError::NotFound(format!("Was unable to find a key by the ObjectId {}", key))
+    })
+}
+/**
+ * Controller to hand a key to a user, the user gets the access the key grants
+ */
+pub async fn assign_key(
+    store: &dyn MetaStore,
+    principal: &Principal,
+    key: &ObjectId,
+    user: &ObjectId,
+) -> Result<User, ControllerError> {
+    ensure_key_admin(store, principal, key).await?;
+
+    let user_doc = store.add_to_user_list(user, UserList::Keys, key).await?;
+    found_user(user_doc, user)
+}
+/**
+ * Controller to take a key away from a user
+ */
+pub async fn unassign_key(
+    store: &dyn MetaStore,
+    principal: &Principal,
+    key: &ObjectId,
+    user: &ObjectId,
+) -> Result<User, ControllerError> {
+    ensure_key_admin(store, principal, key).await?;
+
+    let user_doc = store
+        .remove_from_user_list(user, UserList::Keys, key)
+        .await?;
+    found_user(user_doc, user)
+}
+/**
+ * Controller to delete a key, scrubbing it from every user and access group
+ */
+pub async fn delete_key(
+    store: &dyn MetaStore,
+    principal: &Principal,
+    key: &ObjectId,
+) -> Result<(), ControllerError> {
+    ensure_key_admin(store, principal, key).await?;
+
+    // Attempt to delete the key doc
+    store.delete_key(key).await?;
+
+    // Scrub the key from every list referencing it
+    let key_ids = std::slice::from_ref(key);
+    store
+        .remove_from_all_user_lists(UserList::Keys, key_ids)
+        .await?;
+    store
+        .remove_from_all_user_lists(UserList::KeyAdmins, key_ids)
+        .await?;
+    store.remove_allowed_keys_from_all(key_ids).await
+}
+/**
+ * Resolve a bearer credential of the form <uuid>.<secret> to its key, only active keys with a
+ * matching secret resolve
+ */
+pub async fn resolve_key(
+    store: &dyn MetaStore,
+    token: &str,
+) -> Result<Option<Key>, ControllerError> {
+    let (uuid, secret) = match token.split_once('.') {
+        Some(parts) => parts,
+        None => return Ok(None),
+    };
+
+    // Attempt to find key doc
+    let key_doc = store.find_key_by_uuid(uuid).await?;
+
+    // Keys created before secrets existed have no hash and can never match
+    match key_doc {
+        Some(key_doc)
+            if key_doc.active
+                && !key_doc.secret_hash.is_empty()
+                && key_doc.secret_hash == get_sha256_hex(secret) =>
+        {
+            Ok(Some(key_doc))
+        }
+        _ => Ok(None),
+    }
+}
+/**
+ * Make sure the caller is a key admin of key
+ */
+async fn ensure_key_admin(
+    store: &dyn MetaStore,
+    principal: &Principal,
+    key: &ObjectId,
+) -> Result<(), ControllerError> {
+    let user = current_user(store, principal).await?;
+    if !user.superuser && !user.key_admins.contains(key) {
+        return Err(ControllerError::Forbidden(format!(
+            "User {} is not an admin of the key {}",
+            user.user, key
+        )));
+    }
+
+    Ok(())
+}
+/**
+ * The user doc as it is after an update, a missing one is an error
+ */
+fn found_user(user_doc: Option<User>, user: &ObjectId) -> Result<User, ControllerError> {
+    user_doc.ok_or_else(|| {
+        ControllerError::NotFound(format!(
+            "Was unable to find a user by the ObjectId {}",
+            user
+        ))
+    })
+}
@@ -1,3 +1,9 @@
+pub mod access;
 pub mod auth;
+pub mod blobs;
 pub mod error;
 pub mod file_system;
+pub mod integrity;
+pub mod key;
+pub mod paths;
+pub mod session;
@@ -0,0 +1,135 @@
+use crate::constants::{FALLBACK_EXTENSION, MIN_PASS_LEN};
+use crate::controller::error::ControllerError;
+use std::path::{Component, Path, PathBuf};
+
+const MAX_NAME_LEN: usize = 255;
+const MAX_EXTENSION_LEN: usize = 16;
+const MAX_USER_NAME_LEN: usize = 64;
+const MAX_PASS_LEN: usize = 72;
+
+/**
+ * Validate a folder name, only letters, digits, spaces, '-', '_' and '.' are allowed and the name
+ * can't start with a '.', so names like ".." or "a/b" never reach the file system
+ */
+pub fn validate_name(name: &str) -> Result<(), ControllerError> {
+    let valid_chars = name
+        .chars()
+        .all(|character| character.is_ascii_alphanumeric() || " -_.".contains(character));
+
+    if name.trim().is_empty() || name.len() > MAX_NAME_LEN || name.starts_with('.') || !valid_chars
+    {
+        return Err(invalid_input(format!(
+            "{:?} is not a valid name, use 1 to {} letters, digits, spaces, '-', '_' or '.' not starting with '.'",
+            name, MAX_NAME_LEN
+        )));
+    }
+
+    Ok(())
+}
+/**
+ * Validate a username, only letters, digits, '-', '_' and '.' are allowed and the name can't start
+ * with a '.'
+ */
+pub fn validate_user_name(user: &str) -> Result<(), ControllerError> {
+    let valid_chars = user
+        .chars()
+        .all(|character| character.is_ascii_alphanumeric() || "-_.".contains(character));
+
+    if user.is_empty() || user.len() > MAX_USER_NAME_LEN || user.starts_with('.') || !valid_chars {
+        return Err(invalid_input(format!(
+            "{:?} is not a valid username, use 1 to {} letters, digits, '-', '_' or '.' not starting with '.'",
+            user, MAX_USER_NAME_LEN
+        )));
+    }
+
+    Ok(())
+}
+/**
+ * Validate a password, it needs MIN_PASS_LEN characters and bcrypt only looks at the first 72 bytes
+ */
+pub fn validate_password(pass: &str) -> Result<(), ControllerError> {
+    if pass.chars().count() < MIN_PASS_LEN || pass.len() > MAX_PASS_LEN {
+        return Err(invalid_input(format!(
+            "A password needs {} characters and at most {} bytes",
+            MIN_PASS_LEN, MAX_PASS_LEN
+        )));
+    }
+
+    Ok(())
+}
+/**
+ * Validate a file extension, only 1 to 16 letters and digits are allowed
+ */
+pub fn validate_extension(extension: &str) -> Result<(), ControllerError> {
+    let valid_chars = extension
+        .chars()
+        .all(|character| character.is_ascii_alphanumeric());
+
+    if extension.is_empty() || extension.len() > MAX_EXTENSION_LEN || !valid_chars {
+        return Err(invalid_input(format!(
+            "{:?} is not a valid extension, use 1 to {} letters or digits",
+            extension, MAX_EXTENSION_LEN
+        )));
+    }
+
+    Ok(())
+}
+/**
+ * The extension of the file at path if it is a valid one, FALLBACK_EXTENSION otherwise
+ */
+pub fn usable_extension(path: &Path) -> &str {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .filter(|extension| validate_extension(extension).is_ok())
+        .unwrap_or(FALLBACK_EXTENSION)
+}
+/**
+ * Resolve path to its canonical form and make sure it stays below the storage root, the path itself
+ * doesn't have to exist yet but none of its components can be "..", symlinks are resolved for the
+ * part that already exists
+ */
+pub fn resolve_under_root(storage_root: &str, path: &str) -> Result<PathBuf, ControllerError> {
+    let root = Path::new(storage_root).canonicalize()?;
+
+    let has_parent_dir = Path::new(path)
+        .components()
+        .any(|component| component == Component::ParentDir);
+    if has_parent_dir {
+        return Err(escapes_root(storage_root, path));
+    }
+
+    // Canonicalize the deepest part of the path that exists, then add the rest back on
+    let mut existing = Path::new(path).to_path_buf();
+    let mut missing = vec![];
+    while !existing.exists() {
+        match (existing.file_name(), existing.parent()) {
+            (Some(file_name), Some(parent)) => {
+                missing.push(file_name.to_os_string());
+                existing = parent.to_path_buf();
+            }
+            _ => return Err(escapes_root(storage_root, path)),
+        }
+    }
+
+    let mut resolved = existing.canonicalize()?;
+    for file_name in missing.iter().rev() {
+        resolved.push(file_name);
+    }
+
+    // Nothing may live outside of the root, nor be the root itself
+    if !resolved.starts_with(&root) || resolved == root {
+        return Err(escapes_root(storage_root, path));
+    }
+
+    Ok(resolved)
+}
+fn escapes_root(storage_root: &str, path: &str) -> ControllerError {
+    invalid_input(format!(
+        "The path {} is not inside of the storage root {}",
+        path, storage_root
+    ))
+}
+
+fn invalid_input(message: String) -> ControllerError {
+    ControllerError::InvalidInput(message)
+}
@@ -0,0 +1,137 @@
+use crate::config::Config;
+use crate::controller::access::Principal;
+use crate::controller::error::ControllerError;
+use crate::data_models::{session::Session, user::User};
+use crate::store::MetaStore;
+use crate::util::{
+    get_random_token, get_readable_timestamp, get_sha256_hex, get_time_meta, get_timestamp,
+};
+use wither::mongodb::bson::oid::ObjectId;
+
+/**
+ * ____________________________________________________________________________________________
+ * IssuedSession, result of a successful login
+ * ____________________________________________________________________________________________
+ * token: Opaque bearer token, only ever handed out here as only its hash is stored
+ * session: The stored Session doc
+ * user: The user the session was issued to
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug)]
+pub struct IssuedSession {
+    pub token: String,
+    pub session: Session,
+    pub user: User,
+}
+/**
+ * Issue a new session for user, returning the token along with the stored session
+ */
+pub async fn issue_session(
+    store: &dyn MetaStore,
+    config: &Config,
+    user: User,
+) -> Result<IssuedSession, ControllerError> {
+    let user_id = match &user.id {
+        Some(id) => id.clone(),
+        None => {
+            return Err(ControllerError::Internal(format!(
+                "Cannot issue a session for user {} without an ObjectId",
+                user.user
+            )))
+        }
+    };
+
+    // 32 random bytes make the token, the DB only learns its hash
+    let token = get_random_token();
+
+    // Get meta data for session Doc
+    let (timestamp, timestamp_readable) = get_time_meta();
+    let expires_at = get_timestamp() + config.session_ttl_secs;
+
+    let session_doc = Session {
+        id: None,
+        user: user_id,
+        token_hash: get_sha256_hex(&token),
+        expires_at: expires_at as i64,
+        expires_at_readable: get_readable_timestamp(expires_at),
+        revoked: false,
+        timestamp,
+        timestamp_readable,
+    };
+
+    // Attempt to save session doc
+    let session_doc = store.insert_session(session_doc).await?;
+
+    Ok(IssuedSession {
+        token,
+        session: session_doc,
+        user,
+    })
+}
+/**
+ * Resolve a session token back to its user, expired, revoked or unknown tokens resolve to None
+ */
+pub async fn resolve_session(
+    store: &dyn MetaStore,
+    token: &str,
+) -> Result<Option<User>, ControllerError> {
+    let session_doc = match find_live_session(store, token).await? {
+        Some(session_doc) => session_doc,
+        None => return Ok(None),
+    };
+
+    // Attempt to find the user of the session
+    let user_doc = store.find_user(&session_doc.user).await?;
+
+    Ok(user_doc)
+}
+/**
+ * Revoke the session of token (logout)
+ */
+pub async fn logout_session(store: &dyn MetaStore, token: &str) -> Result<(), ControllerError> {
+    store.revoke_session_by_hash(&get_sha256_hex(token)).await
+}
+/**
+ * Revoke one of the callers sessions by its ObjectId
+ */
+pub async fn revoke_session(
+    store: &dyn MetaStore,
+    principal: &Principal,
+    session: &ObjectId,
+) -> Result<(), ControllerError> {
+    let user_id = principal.require_user_id()?;
+
+    // Only sessions of the caller itself can be revoked
+    if !store.revoke_session(session, &user_id).await? {
+        return Err(ControllerError::NotFound(format!(
+            "Was unable to find a session by the ObjectId {}",
+            session
+        )));
+    }
+
+    Ok(())
+}
+/**
+ * List the callers sessions that are neither expired nor revoked
+ */
+pub async fn list_sessions(
+    store: &dyn MetaStore,
+    principal: &Principal,
+) -> Result<Vec<Session>, ControllerError> {
+    let user_id = principal.require_user_id()?;
+
+    store
+        .find_live_sessions_of(&user_id, get_timestamp() as i64)
+        .await
+}
+/**
+ * Find the session doc of token if it can still be used
+ */
+async fn find_live_session(
+    store: &dyn MetaStore,
+    token: &str,
+) -> Result<Option<Session>, ControllerError> {
+    store
+        .find_live_session(&get_sha256_hex(token), get_timestamp() as i64)
+        .await
+}
@@ -12,7 +12,7 @@ use wither::Model;
  * timestamp_readable: Human readable timestamp of when created
  * ____________________________________________________________________________________________
  */
-#[derive(Debug, Model, Serialize, Deserialize)]
+#[derive(Debug, Clone, Model, Serialize, Deserialize)]
 pub struct AccessGroup {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id: Option<ObjectId>,
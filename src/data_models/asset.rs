@@ -7,21 +7,87 @@ use wither::Model;
  * Asset data model
  * ____________________________________________________________________________________________
  * id: MongoDB ObjectId,
+ * folder_id: ObjectId of the Folder this asset is in
  * path: Path to this asset on hard disk,
  * tag: Tag of this asset to search or identify it
+ * size: Number of bytes stored, 0 for assets stored before sizes were recorded
+ * checksum: SHA-256 hex digest of the bytes, empty for assets stored before checksums were recorded
+ * blob: Checksum of the shared Blob holding the bytes, None if they are stored under path
+ * integrity: Outcome of the last time the stored bytes were checked against the checksum
+ * verified_at: i64(Seconds) timestamp of that check, None if they were never checked
  * timestamp: When this Asset was creaed (stored in the DB)
  * timestamp_readable: Human readable timestamp of when created
  * ____________________________________________________________________________________________
  */
-#[derive(Debug, Model, Serialize, Deserialize)]
+#[derive(Debug, Clone, Model, Serialize, Deserialize)]
 pub struct Asset {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id: Option<ObjectId>,
+    #[model(index(index_type = "dsc"))]
+    pub folder_id: ObjectId,
     #[model(index(index_type = "dsc", unique = "true"))]
     pub path: String,
     #[model(index(index_type = "dsc", unique = "true"))]
     pub uuid: String,
     pub tag: String,
+    #[serde(default)]
+    pub size: i64,
+    #[serde(default)]
+    pub checksum: String,
+    #[serde(default)]
+    pub blob: Option<String>,
+    #[serde(default)]
+    pub integrity: Integrity,
+    #[serde(default)]
+    pub verified_at: Option<i64>,
     pub timestamp: String,
     pub timestamp_readable: String,
 }
+
+/**
+ * ____________________________________________________________________________________________
+ * Integrity, what the last check of the stored bytes of an asset found
+ * ____________________________________________________________________________________________
+ * Unverified: Never checked since the asset was stored
+ * Intact: The bytes matched the size and checksum
+ * Corrupt: The bytes were there but didn't match
+ * Missing: Nothing was stored under the asset's key anymore
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Integrity {
+    #[default]
+    Unverified,
+    Intact,
+    Corrupt,
+    Missing,
+}
+
+impl Integrity {
+    /**
+     * Name of the state, as stored and shown
+     */
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Integrity::Unverified => "unverified",
+            Integrity::Intact => "intact",
+            Integrity::Corrupt => "corrupt",
+            Integrity::Missing => "missing",
+        }
+    }
+}
+
+impl std::str::FromStr for Integrity {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Integrity, String> {
+        match value {
+            "unverified" => Ok(Integrity::Unverified),
+            "intact" => Ok(Integrity::Intact),
+            "corrupt" => Ok(Integrity::Corrupt),
+            "missing" => Ok(Integrity::Missing),
+            _ => Err(format!("Unknown integrity {}", value)),
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+use wither::bson::{doc, oid::ObjectId};
+use wither::Model;
+
+/**
+ * ____________________________________________________________________________________________
+ * Blob data model, bytes stored once by their checksum and shared by every asset holding them
+ * ____________________________________________________________________________________________
+ * id: MongoDB ObjectId
+ * checksum: SHA-256 hex digest of the bytes, which also names the blob in storage (Unique)
+ * size: Number of bytes stored
+ * refs: Number of assets pointing at this blob, it is removed when the last one goes
+ * touched_at: i64(Seconds) timestamp of the last time refs changed
+ * timestamp: When the bytes were first stored
+ * timestamp_readable: Human readable timestamp of when first stored
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug, Clone, Model, Serialize, Deserialize)]
+pub struct Blob {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    #[model(index(index_type = "dsc", unique = "true"))]
+    pub checksum: String,
+    pub size: i64,
+    pub refs: i64,
+    #[serde(default)]
+    pub touched_at: i64,
+    pub timestamp: String,
+    pub timestamp_readable: String,
+}
@@ -1,5 +1,4 @@
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
 use wither::bson::{doc, oid::ObjectId};
 use wither::Model;
 
@@ -16,7 +15,7 @@ use wither::Model;
  * timestamp_readable: Human readable timestamp of when created
  * ____________________________________________________________________________________________
  */
-#[derive(Debug, Model, Serialize, Deserialize)]
+#[derive(Debug, Clone, Model, Serialize, Deserialize)]
 pub struct Folder {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id: Option<ObjectId>,
@@ -7,17 +7,20 @@ use wither::Model;
  * ____________________________________________________________________________________________
  * id: MongoDB ObjectId
  * uuid: Unique UUID v4 for identity
+ * secret_hash: SHA-256 hex digest of the keys secret, the secret itself is never stored
  * active: Bool for weither this key can be used or not ( If it is active or not )
  * timestamp: When this key was created
  * timestamp_readable: Human readable timestamp of when created
  * ____________________________________________________________________________________________
  */
-#[derive(Debug, Model, Serialize, Deserialize)]
+#[derive(Debug, Clone, Model, Serialize, Deserialize)]
 pub struct Key {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id: Option<ObjectId>,
     #[model(index(index_type = "dsc", unique = "true"))]
     pub uuid: String,
+    #[serde(default)]
+    pub secret_hash: String,
     pub active: bool,
     pub timestamp: String,
     pub timestamp_readable: String,
@@ -1,5 +1,7 @@
 pub mod access_group;
 pub mod asset;
+pub mod blob;
 pub mod folder;
 pub mod key;
+pub mod session;
 pub mod user;
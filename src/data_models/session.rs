@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+use wither::bson::{doc, oid::ObjectId};
+use wither::Model;
+
+/**
+ * ____________________________________________________________________________________________
+ * Session data model
+ * ____________________________________________________________________________________________
+ * id: MongoDB ObjectId
+ * user: ObjectId of the user this session was issued to
+ * token_hash: SHA-256 hex digest of the session token, the token itself is never stored (Unique)
+ * expires_at: u64(Seconds) timestamp after which the session can no longer be used
+ * expires_at_readable: Human readable timestamp of when the session expires
+ * revoked: Flag set when the session was logged out or revoked
+ * timestamp: When this session was issued
+ * timestamp_readable: Human readable timestamp of when issued
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug, Clone, Model, Serialize, Deserialize)]
+pub struct Session {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    #[model(index(index_type = "dsc"))]
+    pub user: ObjectId,
+    #[model(index(index_type = "dsc", unique = "true"))]
+    pub token_hash: String,
+    pub expires_at: i64,
+    pub expires_at_readable: String,
+    pub revoked: bool,
+    pub timestamp: String,
+    pub timestamp_readable: String,
+}
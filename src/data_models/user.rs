@@ -14,11 +14,12 @@ use wither::Model;
  * user_admins: Vec of ObjectIds of users this user has access to delete/edit
  * folder_admins: Vec of ObjectIds of folders this user has access to delete/edit
  * access_group_admins: Vec of ObjectIds of acces groups this user has access to delete/edit
+ * superuser: Admin of every folder, access group, key and user
  * timestamp: When this user was created
  * timestamp_readable: Human readable timestamp of when created
  * ____________________________________________________________________________________________
  */
-#[derive(Debug, Model, Serialize, Deserialize)]
+#[derive(Debug, Clone, Model, Serialize, Deserialize)]
 pub struct User {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id: Option<ObjectId>,
@@ -30,6 +31,8 @@ pub struct User {
     pub user_admins: Vec<ObjectId>,
     pub folder_admins: Vec<ObjectId>,
     pub access_group_admins: Vec<ObjectId>,
+    #[serde(default)]
+    pub superuser: bool,
     pub timestamp: String,
     pub timestamp_readable: String,
 }
@@ -0,0 +1,649 @@
+use crate::config::Config;
+use crate::constants::{FSCK_GRACE_SECS, SCRUB_PAGE_SIZE};
+use crate::controller::error::ControllerError;
+use crate::data_models::{asset::Asset, blob::Blob, folder::Folder};
+use crate::storage::{asset_key, blob_checksum, is_staging_key, storage_key, Storage};
+use crate::store::{FolderList, MetaStore, UserList};
+use crate::util::{get_time_meta, get_timestamp};
+use chrono::{Duration, Utc};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use wither::mongodb::bson::oid::ObjectId;
+
+/**
+ * ____________________________________________________________________________________________
+ * FsckReport, where storage and the metadata store disagree
+ * ____________________________________________________________________________________________
+ * assets: Number of asset docs looked at
+ * keys: Number of storage keys looked at
+ * orphaned_keys: Stored keys no asset or blob points at
+ * stale_staging: Staged bytes of uploads that never finished
+ * missing_assets: Assets with nothing stored under their key, nor anywhere else by their file name
+ * astray_assets: (Asset, key) pairs of assets whose bytes are stored under another key
+ * homeless_assets: Assets whose folder doc is gone
+ * missing_dirs: Folders without a directory, for storage that keeps one for every folder
+ * detached_folders: Folders whose parent folder has no doc, left behind by a move that stopped halfway
+ * misplaced_assets: Assets whose path isn't in the path of their folder
+ * dangling_files: (Folder, ObjectId) pairs of files lists pointing at assets that don't exist
+ * unlisted_assets: Assets missing from the files list of their folder
+ * dangling_folder_admins: (User, ObjectId) pairs of folder_admins pointing at folders that don't exist
+ * dangling_allowed_keys: (AccessGroup tag, ObjectId) pairs of allowed_keys pointing at keys that don't exist
+ * blob_refs: (checksum, recorded, actual) of blobs whose reference count is off
+ * repaired: Whether the findings were repaired
+ * skipped: Number of findings the repair left alone, as they changed since they were found or are
+ *     left to the operator
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug, Default)]
+pub struct FsckReport {
+    pub assets: u64,
+    pub keys: u64,
+    pub orphaned_keys: Vec<String>,
+    pub stale_staging: Vec<String>,
+    pub missing_assets: Vec<Asset>,
+    pub astray_assets: Vec<(Asset, String)>,
+    pub homeless_assets: Vec<Asset>,
+    pub missing_dirs: Vec<Folder>,
+    pub detached_folders: Vec<Folder>,
+    pub misplaced_assets: Vec<Asset>,
+    pub dangling_files: Vec<(Folder, ObjectId)>,
+    pub unlisted_assets: Vec<Asset>,
+    pub dangling_folder_admins: Vec<(String, ObjectId)>,
+    pub dangling_allowed_keys: Vec<(String, ObjectId)>,
+    pub blob_refs: Vec<(String, i64, i64)>,
+    pub repaired: bool,
+    pub skipped: u64,
+}
+
+impl FsckReport {
+    /**
+     * Number of findings
+     */
+    pub fn problems(&self) -> usize {
+        self.orphaned_keys.len()
+            + self.stale_staging.len()
+            + self.missing_assets.len()
+            + self.astray_assets.len()
+            + self.homeless_assets.len()
+            + self.missing_dirs.len()
+            + self.detached_folders.len()
+            + self.misplaced_assets.len()
+            + self.dangling_files.len()
+            + self.unlisted_assets.len()
+            + self.dangling_folder_admins.len()
+            + self.dangling_allowed_keys.len()
+            + self.blob_refs.len()
+    }
+
+    /**
+     * One line summing the report up
+     */
+    pub fn summary(&self) -> String {
+        format!(
+            "Checked {} assets and {} stored keys: {} problems{}",
+            self.assets,
+            self.keys,
+            self.problems(),
+            match self.repaired && self.problems() > 0 {
+                true => format!(
+                    ", repaired all but {} that changed in the meantime or need a hand",
+                    self.skipped
+                ),
+                false => String::new(),
+            }
+        )
+    }
+}
+/**
+ * Compare every asset, folder, user and access group doc with each other and with every stored key,
+ * and with repair undo the drift: docs of assets without bytes or folder are deleted, lists are
+ * pointed at what exists, blob references are recounted, missing folder directories are created and
+ * bytes nothing points at are removed.
+ *
+ * A folder move that stopped halfway, which the MongoDB store can't rule out, leaves folders whose
+ * parent has no doc and assets outside the path of their folder, with their bytes in either place.
+ * Those subtrees are never deleted from: assets outside their folder are pointed at the path of it,
+ * bytes found under another key are moved back to their asset and detached folders are only
+ * reported, to be moved below where their parent went by the operator.
+ *
+ * Every doc is read before the bytes it points at, as bytes are written before their doc and removed
+ * after it. The repair reads every finding again right before undoing it and leaves alone what
+ * changed in the meantime, so it can run next to a server that moves, copies and uploads. Keys
+ * written in the last FSCK_GRACE_SECS are left alone too, they may belong to uploads that are still
+ * going.
+ *
+ * Folders are held in memory at once, assets are walked page by page keeping only their ObjectId and
+ * storage key (and the file name of those whose bytes aren't where their doc says), next to the list
+ * of every stored key. That is in the order of a 100 bytes for every asset and every key, a million
+ * assets take a few hundred MB
+ */
+pub async fn fsck(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    repair: bool,
+) -> Result<FsckReport, ControllerError> {
+    let mut report = FsckReport::default();
+
+    let folders: HashMap<ObjectId, Folder> = store
+        .find_folders_below(&config.storage_root)
+        .await?
+        .into_iter()
+        .filter_map(|folder| Some((folder.id.clone()?, folder)))
+        .collect();
+
+    // Folders whose parent has no doc are detached, along with every folder below them
+    let mut sorted: Vec<&Folder> = folders.values().collect();
+    sorted.sort_by(|left, right| left.path.cmp(&right.path));
+    let paths: HashSet<&str> = sorted.iter().map(|folder| folder.path.as_str()).collect();
+    let mut detached: HashSet<&str> = HashSet::new();
+    for folder in &sorted {
+        match parent_path(&folder.path) {
+            Some(parent) if parent != config.storage_root && !paths.contains(parent) => {
+                report.detached_folders.push((*folder).clone());
+                detached.insert(&folder.path);
+            }
+            Some(parent) if detached.contains(parent) => {
+                detached.insert(&folder.path);
+            }
+            _ => {}
+        }
+    }
+
+    // Folders without their directory, for storage that has them. The directories of detached
+    // folders went along with the move
+    if storage.has_dirs() {
+        for folder in &sorted {
+            if detached.contains(folder.path.as_str()) {
+                continue;
+            }
+            let key = storage_key(&config.storage_root, &folder.path)?;
+            if !storage.dir_exists(key).await? {
+                report.missing_dirs.push((*folder).clone());
+            }
+        }
+    }
+
+    // Assets holding on to their bytes and folder, with the keys and blob references they account for.
+    // Missing bytes are looked for by file name among the stored keys later on
+    let mut known = HashSet::new();
+    let mut expected = HashSet::new();
+    let mut missing: HashMap<String, Asset> = HashMap::new();
+    let mut strays: HashSet<String> = HashSet::new();
+    let mut refs: BTreeMap<String, (i64, i64)> = BTreeMap::new();
+    let mut after = None;
+    loop {
+        let page = store
+            .find_assets_after(after.as_ref(), SCRUB_PAGE_SIZE)
+            .await?;
+        after = match page.last() {
+            Some(asset) => asset.id.clone(),
+            None => break,
+        };
+
+        for asset in page {
+            report.assets += 1;
+            let id = match &asset.id {
+                Some(id) => id.clone(),
+                None => continue,
+            };
+            known.insert(id.clone());
+
+            let key = asset_key(&config.storage_root, &asset)?;
+            let folder = match folders.get(&asset.folder_id) {
+                Some(folder) => folder,
+                None => {
+                    report.homeless_assets.push(asset);
+                    continue;
+                }
+            };
+            let name = file_name(&asset.path).to_string();
+            let stranded = detached.contains(folder.path.as_str());
+            let misplaced = !stranded && parent_path(&asset.path) != Some(folder.path.as_str());
+            let exists = storage.exists(&key).await?;
+
+            // Bytes of half moved subtrees can be on either side of the move, nothing by their
+            // name is orphaned
+            if stranded || misplaced {
+                strays.insert(name);
+            } else if !exists {
+                match asset.blob {
+                    Some(_) => report.missing_assets.push(asset),
+                    None => {
+                        missing.insert(name, asset);
+                    }
+                }
+                continue;
+            }
+
+            if exists {
+                if let Some(checksum) = &asset.blob {
+                    refs.entry(checksum.clone()).or_insert((0, asset.size)).0 += 1;
+                }
+                expected.insert(key);
+            }
+            if !folder.files.contains(&id) {
+                report.unlisted_assets.push(asset.clone());
+            }
+            if misplaced {
+                report.misplaced_assets.push(asset);
+            }
+        }
+
+        if after.is_none() {
+            break;
+        }
+    }
+
+    for folder in folders.values() {
+        for file in &folder.files {
+            if !known.contains(file) {
+                report.dangling_files.push((folder.clone(), file.clone()));
+            }
+        }
+    }
+
+    for user in store.find_users(None).await? {
+        for folder in &user.folder_admins {
+            if !folders.contains_key(folder) {
+                report
+                    .dangling_folder_admins
+                    .push((user.user.clone(), folder.clone()));
+            }
+        }
+    }
+    for group in store.find_access_groups().await? {
+        for key in &group.allowed_keys {
+            if store.find_key(key).await?.is_none() {
+                report
+                    .dangling_allowed_keys
+                    .push((group.tag.clone(), key.clone()));
+            }
+        }
+    }
+
+    let mut recorded = HashSet::new();
+    for blob in store.find_blobs().await? {
+        let actual = refs.get(&blob.checksum).map_or(0, |(count, _)| *count);
+        if blob.refs != actual {
+            report
+                .blob_refs
+                .push((blob.checksum.clone(), blob.refs, actual));
+        }
+        recorded.insert(blob.checksum);
+    }
+    for (checksum, (count, _)) in &refs {
+        if !recorded.contains(checksum) {
+            report.blob_refs.push((checksum.clone(), 0, *count));
+        }
+    }
+
+    // The keys are listed after every doc was read
+    let stored = storage.list("").await?;
+    report.keys = stored.len() as u64;
+    let cutoff = Utc::now() - Duration::seconds(FSCK_GRACE_SECS);
+    for key in stored {
+        if expected.contains(&key) {
+            continue;
+        }
+        let name = file_name(&key);
+        if let Some(asset) = missing.remove(name) {
+            report.astray_assets.push((asset, key));
+            continue;
+        }
+        if strays.contains(name) {
+            continue;
+        }
+        let old = match storage.stat(&key).await? {
+            Some(meta) => meta.modified < cutoff,
+            None => false,
+        };
+        match (old, is_staging_key(&key)) {
+            (true, true) => report.stale_staging.push(key),
+            (true, false) => report.orphaned_keys.push(key),
+            (false, _) => {}
+        }
+    }
+
+    let mut missing: Vec<Asset> = missing.into_values().collect();
+    missing.sort_by(|left, right| left.path.cmp(&right.path));
+    report.missing_assets.extend(missing);
+
+    if repair {
+        report.skipped = repair_report(store, storage, config, &report, &refs).await?;
+        report.repaired = true;
+    }
+
+    Ok(report)
+}
+/**
+ * Undo every finding of report, docs first and bytes last like a delete does, returning how many
+ * findings were left alone because they changed since the check
+ */
+async fn repair_report(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    report: &FsckReport,
+    refs: &BTreeMap<String, (i64, i64)>,
+) -> Result<u64, ControllerError> {
+    // Detached folders are left to the operator, who knows where their parent went
+    let mut skipped = report.detached_folders.len() as u64;
+
+    // Bytes found elsewhere are moved back under their asset, and assets outside their folder are
+    // moved into it, the bytes first
+    for (asset, key) in &report.astray_assets {
+        let to_key = asset_key(&config.storage_root, asset)?;
+        if unchanged(store, asset).await?
+            && !storage.exists(&to_key).await?
+            && storage.exists(key).await?
+        {
+            storage.rename(key, &to_key).await?;
+        } else {
+            skipped += 1;
+        }
+    }
+    for asset in &report.misplaced_assets {
+        if !move_into_folder(store, storage, config, asset).await? {
+            skipped += 1;
+        }
+    }
+
+    // Assets are only deleted while they still point at the same missing bytes or deleted folder, an
+    // asset that was moved or copied in the meantime points somewhere else by now
+    let mut gone_files: Vec<ObjectId> = Vec::new();
+    for asset in report.missing_assets.iter().chain(&report.homeless_assets) {
+        if still_broken(store, storage, config, asset).await? {
+            if let Some(id) = &asset.id {
+                store.delete_asset(id).await?;
+                gone_files.push(id.clone());
+            }
+        } else {
+            skipped += 1;
+        }
+    }
+    for (_, file) in &report.dangling_files {
+        match store.find_asset(file).await? {
+            Some(_) => skipped += 1,
+            None => gone_files.push(file.clone()),
+        }
+    }
+    if !gone_files.is_empty() {
+        store
+            .remove_from_all_folder_lists(FolderList::Files, &gone_files)
+            .await?;
+    }
+
+    for asset in &report.unlisted_assets {
+        let current = match &asset.id {
+            Some(id) => store.find_asset(id).await?,
+            None => None,
+        };
+        match current {
+            Some(current) if current.folder_id == asset.folder_id => {
+                if let Some(id) = &current.id {
+                    store
+                        .add_to_folder_list(&current.folder_id, FolderList::Files, id)
+                        .await?;
+                }
+            }
+            _ => skipped += 1,
+        }
+    }
+
+    for folder in &report.missing_dirs {
+        let current = match &folder.id {
+            Some(id) => store.find_folder(id).await?,
+            None => None,
+        };
+        match current {
+            Some(current) if current.path == folder.path => {
+                storage
+                    .create_dir(storage_key(&config.storage_root, &current.path)?)
+                    .await?;
+            }
+            _ => skipped += 1,
+        }
+    }
+
+    let mut folders: Vec<ObjectId> = Vec::new();
+    for (_, folder) in &report.dangling_folder_admins {
+        match store.find_folder(folder).await? {
+            Some(_) => skipped += 1,
+            None => folders.push(folder.clone()),
+        }
+    }
+    if !folders.is_empty() {
+        store
+            .remove_from_all_user_lists(UserList::FolderAdmins, &folders)
+            .await?;
+    }
+
+    let mut keys: Vec<ObjectId> = Vec::new();
+    for (_, key) in &report.dangling_allowed_keys {
+        match store.find_key(key).await? {
+            Some(_) => skipped += 1,
+            None => keys.push(key.clone()),
+        }
+    }
+    if !keys.is_empty() {
+        store.remove_allowed_keys_from_all(&keys).await?;
+    }
+
+    // Blobs are recounted from the assets pointing at them right before they are set, and only set
+    // while they still have the references and touch time read next to that. An upload or copy
+    // adds its reference before its asset, so a blob touched in the last FSCK_GRACE_SECS may count
+    // an asset that isn't there yet and is never lowered
+    let now = get_timestamp() as i64;
+    for (checksum, _, _) in &report.blob_refs {
+        let seen = store.find_blob(checksum).await?;
+        let actual = store.count_blob_assets(checksum).await? as i64;
+        let (current, touched_at) = seen
+            .as_ref()
+            .map_or((0, 0), |blob| (blob.refs, blob.touched_at));
+        if actual == current {
+            continue;
+        }
+        if actual < current && touched_at > now - FSCK_GRACE_SECS {
+            skipped += 1;
+            continue;
+        }
+
+        let (timestamp, timestamp_readable) = get_time_meta();
+        let size = refs.get(checksum).map_or(0, |(_, size)| *size);
+        let blob = Blob {
+            id: None,
+            checksum: checksum.clone(),
+            size,
+            refs: actual,
+            touched_at: now,
+            timestamp,
+            timestamp_readable,
+        };
+        if !store.recount_blob(blob, seen.as_ref()).await? {
+            skipped += 1;
+        }
+    }
+
+    // Bytes are only removed while still nothing points at them, bytes moved in the meantime keep
+    // their modification time and would look orphaned otherwise. Blob bytes released by their last
+    // asset are only ever removed here, and not if an upload of the same bytes wrote them again
+    let cutoff = Utc::now() - Duration::seconds(FSCK_GRACE_SECS);
+    for key in &report.orphaned_keys {
+        let claimed = match blob_checksum(key) {
+            Some(checksum) => store.find_blob(checksum).await?.is_some(),
+            None => store
+                .find_asset_by_path(&format!("{}/{}", config.storage_root, key))
+                .await?
+                .is_some(),
+        };
+        let rewritten = match storage.stat(key).await? {
+            Some(meta) => meta.modified >= cutoff,
+            None => true,
+        };
+        match claimed || rewritten {
+            true => skipped += 1,
+            false => storage.delete(key).await?,
+        }
+    }
+    for key in &report.stale_staging {
+        storage.delete(key).await?;
+    }
+
+    Ok(skipped)
+}
+/**
+ * Whether asset still has the path, blob and folder it had during the check
+ */
+async fn unchanged(store: &dyn MetaStore, asset: &Asset) -> Result<bool, ControllerError> {
+    let current = match &asset.id {
+        Some(id) => store.find_asset(id).await?,
+        None => None,
+    };
+
+    Ok(match current {
+        Some(current) => {
+            current.path == asset.path
+                && current.blob == asset.blob
+                && current.folder_id == asset.folder_id
+        }
+        None => false,
+    })
+}
+/**
+ * Point asset at the path of its folder, moving its bytes there unless they already are, returning
+ * whether it was moved
+ */
+async fn move_into_folder(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    asset: &Asset,
+) -> Result<bool, ControllerError> {
+    let id = match &asset.id {
+        Some(id) => id,
+        None => return Ok(false),
+    };
+    if !unchanged(store, asset).await? {
+        return Ok(false);
+    }
+    let folder = match store.find_folder(&asset.folder_id).await? {
+        Some(folder) => folder,
+        None => return Ok(false),
+    };
+    if parent_path(&asset.path) == Some(folder.path.as_str()) {
+        return Ok(false);
+    }
+
+    let to_path = format!("{}/{}", folder.path, file_name(&asset.path));
+    if asset.blob.is_none() {
+        let to_key = storage_key(&config.storage_root, &to_path)?;
+        if !storage.exists(to_key).await? {
+            let key = storage_key(&config.storage_root, &asset.path)?;
+            if !storage.exists(key).await? {
+                return Ok(false);
+            }
+            storage.rename(key, to_key).await?;
+        }
+    }
+
+    let moved = store
+        .move_asset(id, &asset.folder_id, &asset.folder_id, &asset.tag, &to_path)
+        .await?;
+    Ok(moved.is_some())
+}
+/**
+ * Whether asset is still missing its bytes or folder, as it was found by the check
+ */
+async fn still_broken(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    asset: &Asset,
+) -> Result<bool, ControllerError> {
+    if !unchanged(store, asset).await? {
+        return Ok(false);
+    }
+    if store.find_folder(&asset.folder_id).await?.is_none() {
+        return Ok(true);
+    }
+
+    Ok(!storage
+        .exists(&asset_key(&config.storage_root, asset)?)
+        .await?)
+}
+/**
+ * The path above path, None at the very top
+ */
+fn parent_path(path: &str) -> Option<&str> {
+    Path::new(path).parent().and_then(|parent| parent.to_str())
+}
+/**
+ * The last part of path or key, the whole of it if it has no /
+ */
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+/**
+ * Print the summary of report, with a line for every finding
+ */
+pub fn print_report(report: &FsckReport) {
+    println!("{}", report.summary());
+    for key in &report.orphaned_keys {
+        println!("Orphaned key {}", key);
+    }
+    for key in &report.stale_staging {
+        println!("Stale staged upload {}", key);
+    }
+    for asset in &report.missing_assets {
+        println!("Missing bytes of asset {} at {}", asset.uuid, asset.path);
+    }
+    for (asset, key) in &report.astray_assets {
+        println!(
+            "Bytes of asset {} at {} are stored as {}",
+            asset.uuid, asset.path, key
+        );
+    }
+    for folder in &report.missing_dirs {
+        println!("Folder {} has no directory", folder.path);
+    }
+    for folder in &report.detached_folders {
+        println!(
+            "Folder {} is detached, the folder above it was moved halfway; move it below where that one went",
+            folder.path
+        );
+    }
+    for asset in &report.misplaced_assets {
+        println!(
+            "Asset {} at {} is outside of its folder {}",
+            asset.uuid, asset.path, asset.folder_id
+        );
+    }
+    for asset in &report.homeless_assets {
+        println!(
+            "Asset {} at {} is in the deleted folder {}",
+            asset.uuid, asset.path, asset.folder_id
+        );
+    }
+    for (folder, file) in &report.dangling_files {
+        println!("Folder {} lists the deleted asset {}", folder.path, file);
+    }
+    for asset in &report.unlisted_assets {
+        println!(
+            "Asset {} at {} is missing from its folder's files",
+            asset.uuid, asset.path
+        );
+    }
+    for (user, folder) in &report.dangling_folder_admins {
+        println!("User {} administrates the deleted folder {}", user, folder);
+    }
+    for (group, key) in &report.dangling_allowed_keys {
+        println!("Access group {} allows the deleted key {}", group, key);
+    }
+    for (checksum, recorded, actual) in &report.blob_refs {
+        println!(
+            "Blob {} records {} references but has {}",
+            checksum, recorded, actual
+        );
+    }
+}
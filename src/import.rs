@@ -0,0 +1,511 @@
+use crate::config::{Config, StorageBackend};
+use crate::controller::access::{authorize_folder, Permission, Principal};
+use crate::controller::error::ControllerError;
+use crate::controller::file_system::{
+    create_folder, create_sub_folder, ensure_folder, register_asset, save_asset_stream, StoredAsset,
+};
+use crate::controller::paths::usable_extension;
+use crate::data_models::folder::Folder;
+use crate::storage::Storage;
+use crate::store::{MetaStore, UserList};
+use crate::util::get_file_stream;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use wither::mongodb::bson::oid::ObjectId;
+
+/**
+ * ____________________________________________________________________________________________
+ * ImportEvent, one step of an import as it happens
+ * ____________________________________________________________________________________________
+ * Folder: A directory became a folder, created is false if the folder already existed
+ * Asset: A file became an asset
+ * Unchanged: A file below the storage root that already is an asset
+ * Conflict: A directory or file that was left out, with why
+ * Failed: A directory or file that couldn't be imported, with the error
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug)]
+pub enum ImportEvent<'a> {
+    Folder(&'a Path, &'a Folder, bool),
+    Asset(&'a Path, &'a StoredAsset),
+    Unchanged(&'a Path),
+    Conflict(&'a Path, &'a str),
+    Failed(&'a Path, &'a ControllerError),
+}
+
+/**
+ * ____________________________________________________________________________________________
+ * ImportReport, what an import did
+ * ____________________________________________________________________________________________
+ * folder: The folder the imported directory became
+ * in_place: Whether the files were already below the storage root and were left where they are
+ * folders_created: Number of folders created
+ * folders_existing: Number of directories that went into folders that already existed
+ * assets: Number of files that became assets
+ * bytes: Number of bytes of those files
+ * unchanged: Number of files that already were assets
+ * conflicts: Directories and files that were left out, with why
+ * left_out: Number of files below the directories that were left out
+ * failed: Directories and files that couldn't be imported, with the error
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug)]
+pub struct ImportReport {
+    pub folder: Folder,
+    pub in_place: bool,
+    pub folders_created: u64,
+    pub folders_existing: u64,
+    pub assets: u64,
+    pub bytes: u64,
+    pub unchanged: u64,
+    pub conflicts: Vec<(PathBuf, String)>,
+    pub left_out: u64,
+    pub failed: Vec<(PathBuf, ControllerError)>,
+}
+
+impl ImportReport {
+    /**
+     * One line summing the report up
+     */
+    pub fn summary(&self) -> String {
+        format!(
+            "Imported into {}: {} folders created ({} existed), {} assets ({} bytes{}), {} already assets, {} conflicts ({} files left out), {} failed",
+            self.folder.path,
+            self.folders_created,
+            self.folders_existing,
+            self.assets,
+            self.bytes,
+            match self.in_place {
+                true => " left in place",
+                false => " copied",
+            },
+            self.unchanged,
+            self.conflicts.len(),
+            self.left_out,
+            self.failed.len()
+        )
+    }
+}
+/**
+ * Turn the directory source, and every directory and file below it, into folders and assets tagged
+ * with their original names. The directory becomes a folder below the folder at parent_path, or a
+ * top level folder without one, and admin becomes an admin of that folder if given.
+ *
+ * With local storage a source that is already below the storage root is imported where it is, its
+ * files are hashed but not copied and the folders follow the directories. Directories that already
+ * are folders are reused, and files whose name is already the tag of an asset in their folder are
+ * left out as conflicts, so an interrupted import can be run again. Hidden entries and symlinks are
+ * left out too.
+ *
+ * Folder names are unique across every folder, so a copied directory whose name another folder has
+ * becomes the first free <name> 2, <name> 3 and so on, like copy_folder does. In place the folders
+ * have to be called like their directories, such a directory is left out as a conflict with every
+ * file below it counted. progress hears about every step as it happens
+ */
+#[allow(clippy::too_many_arguments)]
+pub async fn import_tree(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    principal: &Principal,
+    source: &Path,
+    parent_path: Option<&str>,
+    admin: Option<&ObjectId>,
+    progress: &dyn Fn(ImportEvent),
+) -> Result<ImportReport, ControllerError> {
+    let source = source.canonicalize()?;
+    if !source.is_dir() {
+        return Err(ControllerError::InvalidInput(format!(
+            "{} is not a directory",
+            source.display()
+        )));
+    }
+
+    // Find the folder the source becomes, creating the folders above it when it is imported in place
+    let relative = relative_to_root(config, &source)?;
+    let in_place = relative.is_some();
+    let mut folders = Vec::new();
+    match relative {
+        Some(_) if parent_path.is_some() => {
+            return Err(ControllerError::InvalidInput(format!(
+                "{} is already below the storage root and can only be imported where it is, leave out the parent folder",
+                source.display()
+            )));
+        }
+        Some(names) if names.is_empty() => {
+            return Err(ControllerError::InvalidInput(
+                "The storage root itself can't be imported, import the directories inside of it"
+                    .to_string(),
+            ));
+        }
+        Some(names) => {
+            let mut directory = Path::new(&config.storage_root).canonicalize()?;
+            let mut parent: Option<String> = None;
+            for name in &names {
+                directory.push(name);
+                let (folder, created) =
+                    ensure_folder(store, storage, config, principal, parent.as_deref(), name)
+                        .await?;
+                parent = Some(folder.path.clone());
+                folders.push((directory.clone(), folder, created));
+            }
+        }
+        None => {
+            let name = source
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or_else(|| {
+                    ControllerError::InvalidInput(format!(
+                        "{} has no name to give its folder",
+                        source.display()
+                    ))
+                })?;
+            let (folder, created) = import_folder(
+                store,
+                storage,
+                config,
+                principal,
+                parent_path,
+                name,
+                &HashSet::new(),
+            )
+            .await?;
+            folders.push((source.clone(), folder, created));
+        }
+    }
+
+    let mut report = ImportReport {
+        folder: folders[folders.len() - 1].1.clone(),
+        in_place,
+        folders_created: 0,
+        folders_existing: 0,
+        assets: 0,
+        bytes: 0,
+        unchanged: 0,
+        conflicts: vec![],
+        left_out: 0,
+        failed: vec![],
+    };
+    for (directory, folder, created) in &folders {
+        count_folder(&mut report, directory, folder, *created, progress);
+    }
+
+    // Hand the imported folder to its admin, someone who can already administrate it has to do so
+    // for a folder that existed before
+    if let Some(admin) = admin {
+        if !folders[folders.len() - 1].2 {
+            authorize_folder(store, principal, &report.folder, Permission::Admin).await?;
+        }
+        let folder_id = folder_id(&report.folder)?;
+        let admin_doc = store
+            .add_to_user_list(admin, UserList::FolderAdmins, &folder_id)
+            .await?;
+        if admin_doc.is_none() {
+            return Err(ControllerError::NotFound(format!(
+                "Was unable to find a user by the ObjectId {}",
+                admin
+            )));
+        }
+    }
+
+    // The folders directories went into, so two directories never share one
+    let mut claimed: HashSet<ObjectId> = folders
+        .iter()
+        .filter_map(|(_, folder, _)| folder.id.clone())
+        .collect();
+    let mut pending = vec![(source, report.folder.clone())];
+    while let Some((directory, folder)) = pending.pop() {
+        let folder_id = folder_id(&folder)?;
+        let assets = store.find_assets_in_folder(&folder_id).await?;
+        let tags: HashSet<String> = assets.iter().map(|asset| asset.tag.clone()).collect();
+        let paths: HashSet<String> = assets.into_iter().map(|asset| asset.path).collect();
+
+        let mut entries = match fs::read_dir(&directory) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .collect::<Vec<PathBuf>>(),
+            Err(err) => {
+                fail(&mut report, directory, err.into(), progress);
+                continue;
+            }
+        };
+        entries.sort();
+
+        for path in entries {
+            let name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name.to_string(),
+                None => {
+                    conflict(&mut report, path, "The name is not valid UTF-8", progress);
+                    continue;
+                }
+            };
+            let file_type = match fs::symlink_metadata(&path) {
+                Ok(meta) => meta.file_type(),
+                Err(err) => {
+                    fail(&mut report, path, err.into(), progress);
+                    continue;
+                }
+            };
+
+            if name.starts_with('.') {
+                conflict(
+                    &mut report,
+                    path,
+                    "Hidden entries are not imported",
+                    progress,
+                );
+            } else if file_type.is_symlink() {
+                conflict(&mut report, path, "Symlinks are not followed", progress);
+            } else if file_type.is_dir() {
+                let sub_folder = match in_place {
+                    true => {
+                        ensure_folder(store, storage, config, principal, Some(&folder.path), &name)
+                            .await
+                    }
+                    false => {
+                        import_folder(
+                            store,
+                            storage,
+                            config,
+                            principal,
+                            Some(&folder.path),
+                            &name,
+                            &claimed,
+                        )
+                        .await
+                    }
+                };
+                match sub_folder {
+                    Ok((sub_folder, created)) => {
+                        claimed.extend(sub_folder.id.clone());
+                        count_folder(&mut report, &path, &sub_folder, created, progress);
+                        pending.push((path, sub_folder));
+                    }
+                    Err(ControllerError::InvalidInput(reason))
+                    | Err(ControllerError::AlreadyExists(reason)) => {
+                        report.left_out += count_files(&path);
+                        conflict(&mut report, path, &reason, progress)
+                    }
+                    Err(err) => {
+                        report.left_out += count_files(&path);
+                        fail(&mut report, path, err, progress)
+                    }
+                }
+            } else if in_place && paths.contains(&format!("{}/{}", folder.path, name)) {
+                report.unchanged += 1;
+                progress(ImportEvent::Unchanged(&path));
+            } else if tags.contains(&name) {
+                let reason = format!("An asset tagged {} already is in {}", name, folder.path);
+                conflict(&mut report, path, &reason, progress);
+            } else {
+                let imported = match in_place {
+                    true => {
+                        let asset_path = format!("{}/{}", folder.path, name);
+                        register_asset(
+                            store,
+                            storage,
+                            config,
+                            principal,
+                            &name,
+                            &folder_id,
+                            &asset_path,
+                        )
+                        .await
+                    }
+                    false => {
+                        copy_file(store, storage, config, principal, &path, &name, &folder_id).await
+                    }
+                };
+                match imported {
+                    Ok(stored_asset) => {
+                        report.assets += 1;
+                        report.bytes += stored_asset.size;
+                        progress(ImportEvent::Asset(&path, &stored_asset));
+                    }
+                    Err(err) => fail(&mut report, path, err, progress),
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
+/**
+ * The names of the directories leading from the storage root to source, None if source isn't below
+ * the root of local storage
+ */
+fn relative_to_root(
+    config: &Config,
+    source: &Path,
+) -> Result<Option<Vec<String>>, ControllerError> {
+    if config.storage_backend != StorageBackend::Local {
+        return Ok(None);
+    }
+
+    let root = Path::new(&config.storage_root).canonicalize()?;
+    let relative = match source.strip_prefix(&root) {
+        Ok(relative) => relative,
+        Err(_) => return Ok(None),
+    };
+
+    let names = relative
+        .iter()
+        .map(|name| {
+            name.to_str().map(|name| name.to_string()).ok_or_else(|| {
+                ControllerError::InvalidInput(format!("{} is not valid UTF-8", relative.display()))
+            })
+        })
+        .collect::<Result<Vec<String>, ControllerError>>()?;
+
+    Ok(Some(names))
+}
+/**
+ * Find the folder a copied directory called name goes into below the folder at parent_path (or at
+ * the top level), creating it if it doesn't exist yet. A name another folder already has becomes the
+ * first free <name> 2, <name> 3 and so on, and folders in claimed are skipped, so running the import
+ * again finds the same folders. Returns the folder and whether it was created
+ */
+async fn import_folder(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    principal: &Principal,
+    parent_path: Option<&str>,
+    name: &str,
+    claimed: &HashSet<ObjectId>,
+) -> Result<(Folder, bool), ControllerError> {
+    let parent = parent_path.unwrap_or(&config.storage_root);
+    let mut candidate = name.to_string();
+    let mut number = 1;
+    loop {
+        let path = format!("{}/{}", parent, candidate);
+        match store.find_folder_by_path(&path).await? {
+            Some(folder) if !folder.id.as_ref().is_some_and(|id| claimed.contains(id)) => {
+                authorize_folder(store, principal, &folder, Permission::Write).await?;
+                return Ok((folder, false));
+            }
+            Some(_) => {}
+            None if store.find_folder_by_tag(&candidate).await?.is_none() => {
+                let folder = match parent_path {
+                    Some(parent_path) => {
+                        create_sub_folder(
+                            store,
+                            storage,
+                            config,
+                            principal,
+                            parent_path,
+                            &candidate,
+                            None,
+                        )
+                        .await?
+                    }
+                    None => {
+                        create_folder(store, storage, config, principal, &candidate, None, None)
+                            .await?
+                    }
+                };
+                return Ok((folder, true));
+            }
+            None => {}
+        }
+
+        number += 1;
+        candidate = format!("{} {}", name, number);
+    }
+}
+/**
+ * Number of files below the directory at path, hidden ones and symlinks left out as an import does
+ */
+fn count_files(path: &Path) -> u64 {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+
+    entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            !path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with('.'))
+        })
+        .map(|path| match fs::symlink_metadata(&path) {
+            Ok(meta) if meta.is_dir() => count_files(&path),
+            Ok(meta) if meta.is_file() => 1,
+            _ => 0,
+        })
+        .sum()
+}
+/**
+ * Upload a copy of the file at path into folder, keeping its extension if it is a valid one
+ */
+async fn copy_file(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+    principal: &Principal,
+    path: &Path,
+    name: &str,
+    folder: &ObjectId,
+) -> Result<StoredAsset, ControllerError> {
+    let extension = usable_extension(path);
+
+    let file_stream = get_file_stream(&path.to_string_lossy())
+        .await
+        .ok_or_else(|| ControllerError::NotFound(format!("Could not open {}", path.display())))?;
+
+    save_asset_stream(
+        store,
+        storage,
+        config,
+        principal,
+        file_stream,
+        name,
+        folder,
+        extension,
+    )
+    .await
+}
+
+fn folder_id(folder: &Folder) -> Result<ObjectId, ControllerError> {
+    folder
+        .id
+        .clone()
+        .ok_or_else(|| ControllerError::Internal(format!("Folder {} has no ObjectId", folder.path)))
+}
+
+fn count_folder(
+    report: &mut ImportReport,
+    path: &Path,
+    folder: &Folder,
+    created: bool,
+    progress: &dyn Fn(ImportEvent),
+) {
+    match created {
+        true => report.folders_created += 1,
+        false => report.folders_existing += 1,
+    }
+    progress(ImportEvent::Folder(path, folder, created));
+}
+
+fn conflict(
+    report: &mut ImportReport,
+    path: PathBuf,
+    reason: &str,
+    progress: &dyn Fn(ImportEvent),
+) {
+    progress(ImportEvent::Conflict(&path, reason));
+    report.conflicts.push((path, reason.to_string()));
+}
+
+fn fail(
+    report: &mut ImportReport,
+    path: PathBuf,
+    err: ControllerError,
+    progress: &dyn Fn(ImportEvent),
+) {
+    progress(ImportEvent::Failed(&path, &err));
+    report.failed.push((path, err));
+}
@@ -0,0 +1,13 @@
+pub mod api;
+pub mod bootstrap;
+pub mod config;
+pub mod constants;
+pub mod controller;
+pub mod data_models;
+pub mod fsck;
+pub mod import;
+pub mod scrub;
+pub mod seed;
+pub mod storage;
+pub mod store;
+pub mod util;
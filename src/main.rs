@@ -1,82 +1,84 @@
-mod constants;
-mod controller;
-mod data_models;
-mod util;
+use file_server::api::serve;
+use file_server::bootstrap::bootstrap;
+use file_server::config::Config;
+use file_server::scrub::run_scrub_job;
+use file_server::seed::seed;
+use file_server::storage::open_storage;
+use file_server::store::open_store;
+use std::env;
+use std::sync::Arc;
 
-use constants::{ASSET_MAIN_PATH, DB_NAME, MONGO_URI};
-use data_models::{access_group::AccessGroup, asset::Asset, folder::Folder, key::Key, user::User};
-use std::fs::create_dir_all;
-use std::path::Path;
-use wither::mongodb::Client;
-use wither::{mongodb::bson::doc, prelude::*, Result};
-
-use controller::{
-    auth::login_user,
-    file_system::{create_folder, create_user, save_asset},
-};
-use util::get_file_data;
-
-use crate::controller::file_system::create_sub_folder;
+const USAGE: &str = "Usage: file-server [serve | seed]";
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    // Create asset directory if not present
-    if !Path::new(ASSET_MAIN_PATH).exists() {
-        create_dir_all(ASSET_MAIN_PATH).expect(&format!(
-            "ERROR: Could not create required directory {}",
-            ASSET_MAIN_PATH
-        ));
+async fn main() {
+    let command = env::args().nth(1).unwrap_or_else(|| "serve".to_string());
+    if command != "serve" && command != "seed" {
+        panic!("ERROR: Unknown command {}\n{}", command, USAGE);
     }
 
-    // Connect to MongoDB and sync indexes on all Models
-    let db = Client::with_uri_str(MONGO_URI).await?.database(DB_NAME);
-    Key::sync(&db).await?;
-    User::sync(&db).await?;
-    Asset::sync(&db).await?;
-    Folder::sync(&db).await?;
-    AccessGroup::sync(&db).await?;
+    // Load and validate the config before touching anything
+    let config = Config::load().unwrap_or_else(|err| panic!("ERROR: {}", err));
+    let addr = config
+        .socket_addr()
+        .unwrap_or_else(|err| panic!("ERROR: {}", err));
 
-    //===================== TEST SECTION  ========================///
-    Key::delete_many(&db, doc! {}, None).await.unwrap();
-    User::delete_many(&db, doc! {}, None).await.unwrap();
-    Asset::delete_many(&db, doc! {}, None).await.unwrap();
-    Folder::delete_many(&db, doc! {}, None).await.unwrap();
-    AccessGroup::delete_many(&db, doc! {}, None).await.unwrap();
-
-    let user_doc = create_user(&db, "admin", "root").await.unwrap();
-
-    let user_doc = login_user(&db, "admin", "root").await.unwrap().unwrap();
+    // Open the metadata store picked by the config
+    let store = open_store(&config)
+        .await
+        .unwrap_or_else(|err| panic!("ERROR: Could not open the metadata store: {}", err));
 
-    let folder = create_folder(&db, &user_doc.id.as_ref().unwrap(), "admins", None, None)
+    // Open the asset storage picked by the config
+    let storage = open_storage(&config)
         .await
-        .unwrap();
+        .unwrap_or_else(|err| panic!("ERROR: Could not open the asset storage: {}", err));
 
-    let sub_folder = create_sub_folder(
-        &db,
-        &user_doc.id.as_ref().unwrap(),
-        &folder.path,
-        "admin-sub",
-        None,
-    )
-    .await
-    .unwrap();
+    // Prepare indexes, and create a superuser if there are no users yet
+    let initial_superuser = bootstrap(store.as_ref(), &config)
+        .await
+        .unwrap_or_else(|err| panic!("ERROR: Bootstrap failed: {}", err));
+    if let Some(initial_superuser) = initial_superuser {
+        println!("Created superuser {}", initial_superuser.user.user);
+        if let Some(generated_pass) = initial_superuser.generated_pass {
+            println!(
+                "Its generated password is {} , it won't be shown again",
+                generated_pass
+            );
+        }
+    }
 
-    let sub_sub_folder = create_sub_folder(
-        &db,
-        &user_doc.id.unwrap(),
-        &sub_folder.path,
-        "admin-sub-sub",
-        None,
-    )
-    .await
-    .unwrap();
+    // The demo data is opt-in
+    if command == "seed" {
+        let seeded = seed(store.as_ref(), storage.as_ref(), &config)
+            .await
+            .unwrap_or_else(|err| panic!("ERROR: Seed failed: {}", err));
+        if let Some(generated_pass) = seeded.generated_pass {
+            println!(
+                "Seeded user demo, its generated password is {} , it won't be shown again",
+                generated_pass
+            );
+        }
+        for folder in &seeded.folders {
+            println!("Seeded folder {}", folder.path);
+        }
+        if let Some(stored_asset) = seeded.stored_asset {
+            println!("Seeded asset {:?}", stored_asset);
+        }
 
-    let file_data = get_file_data("./test_video.mp4").unwrap();
-    let asset_id = save_asset(&db, file_data, "my_video", &sub_sub_folder.path, "mp4")
-        .await
-        .unwrap();
+        return;
+    }
 
-    println!("{:?}", asset_id);
+    // Check every asset in the background once in a while
+    let config = Arc::new(config);
+    if config.scrub_interval_secs > 0 {
+        tokio::spawn(run_scrub_job(
+            store.clone(),
+            storage.clone(),
+            config.clone(),
+        ));
+    }
 
-    Ok(())
+    // Serve the HTTP API
+    println!("Listening on http://{}", addr);
+    serve(store, storage, config, addr).await;
 }
@@ -0,0 +1,128 @@
+use crate::config::Config;
+use crate::constants::SCRUB_PAGE_SIZE;
+use crate::controller::error::ControllerError;
+use crate::controller::integrity::verify_asset;
+use crate::data_models::asset::{Asset, Integrity};
+use crate::storage::{SharedStorage, Storage};
+use crate::store::{MetaStore, SharedStore};
+use std::sync::Arc;
+use std::time::Duration;
+
+/**
+ * ____________________________________________________________________________________________
+ * ScrubReport, what a scrub of every asset found
+ * ____________________________________________________________________________________________
+ * checked: Number of assets looked at
+ * intact: Number of assets whose bytes matched their checksum
+ * backfilled: Number of assets without a checksum that got one recorded
+ * corrupt: Assets whose bytes didn't match their checksum, now flagged corrupt
+ * missing: Assets with nothing left in storage, now flagged missing
+ * failed: Assets that couldn't be checked, with why, they keep their last flag
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug, Default)]
+pub struct ScrubReport {
+    pub checked: u64,
+    pub intact: u64,
+    pub backfilled: u64,
+    pub corrupt: Vec<Asset>,
+    pub missing: Vec<Asset>,
+    pub failed: Vec<(Asset, ControllerError)>,
+}
+
+impl ScrubReport {
+    /**
+     * One line summing the report up
+     */
+    pub fn summary(&self) -> String {
+        format!(
+            "Scrubbed {} assets: {} intact ({} checksums recorded), {} corrupt, {} missing, {} failed",
+            self.checked,
+            self.intact,
+            self.backfilled,
+            self.corrupt.len(),
+            self.missing.len(),
+            self.failed.len()
+        )
+    }
+}
+/**
+ * Read back the bytes of every asset, page by page, recomputing their checksums and flagging the
+ * corrupt and missing ones. Only failing to page through the metadata stops the scrub
+ */
+pub async fn scrub(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+) -> Result<ScrubReport, ControllerError> {
+    let mut report = ScrubReport::default();
+    let mut after = None;
+
+    loop {
+        let assets = store
+            .find_assets_after(after.as_ref(), SCRUB_PAGE_SIZE)
+            .await?;
+        let last_id = match assets.last() {
+            Some(asset) => asset.id.clone(),
+            None => break,
+        };
+
+        for asset in assets {
+            report.checked += 1;
+            let backfill = asset.checksum.is_empty();
+
+            match verify_asset(store, storage, config, &asset).await {
+                Ok(Integrity::Intact) => {
+                    report.intact += 1;
+                    if backfill {
+                        report.backfilled += 1;
+                    }
+                }
+                Ok(Integrity::Corrupt) => report.corrupt.push(asset),
+                Ok(Integrity::Missing) => report.missing.push(asset),
+                Ok(Integrity::Unverified) => {}
+                Err(err) => report.failed.push((asset, err)),
+            }
+        }
+
+        after = last_id;
+        if after.is_none() {
+            break;
+        }
+    }
+
+    Ok(report)
+}
+/**
+ * Scrub every scrub_interval_secs for as long as the server runs, printing what each scrub found
+ */
+pub async fn run_scrub_job(store: SharedStore, storage: SharedStorage, config: Arc<Config>) {
+    let interval = Duration::from_secs(config.scrub_interval_secs);
+
+    loop {
+        tokio::time::delay_for(interval).await;
+
+        match scrub(store.as_ref(), storage.as_ref(), &config).await {
+            Ok(report) => print_report(&report),
+            Err(err) => println!("ERROR: Scrub failed: {}", err),
+        }
+    }
+}
+/**
+ * Print the summary of report, with a line for every asset that needs attention
+ */
+pub fn print_report(report: &ScrubReport) {
+    println!("{}", report.summary());
+    for asset in &report.corrupt {
+        println!("Corrupt asset {} at {}", asset.uuid, asset.path);
+    }
+    for asset in &report.missing {
+        println!("Missing asset {} at {}", asset.uuid, asset.path);
+    }
+    for (asset, err) in &report.failed {
+        println!(
+            "Could not check asset {} at {}: {}",
+            asset.uuid, asset.path, err
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use crate::config::Config;
+use crate::controller::access::Principal;
+use crate::controller::error::ControllerError;
+use crate::controller::file_system::{create_user, ensure_folder, save_asset_stream, StoredAsset};
+use crate::data_models::folder::Folder;
+use crate::storage::Storage;
+use crate::store::MetaStore;
+use crate::util::{get_file_stream, get_random_token};
+
+const DEMO_USER: &str = "demo";
+const DEMO_VIDEO_PATH: &str = "./test_video.mp4";
+
+/**
+ * ____________________________________________________________________________________________
+ * Seeded, what the seed command left behind
+ * ____________________________________________________________________________________________
+ * folders: The demo folder tree, top level first
+ * stored_asset: The uploaded demo video, None if it was already there or isn't on disk
+ * generated_pass: The password of the demo user if it was created with a generated one
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug)]
+pub struct Seeded {
+    pub folders: Vec<Folder>,
+    pub stored_asset: Option<StoredAsset>,
+    pub generated_pass: Option<String>,
+}
+/**
+ * Fill the DB with a demo user owning a small folder tree with a video in it, steps that already
+ * happened on an earlier run are skipped. The demo user gets demo_pass, or a generated password if
+ * that is left out, and the tree is created as the user itself without logging it in
+ */
+pub async fn seed(
+    store: &dyn MetaStore,
+    storage: &dyn Storage,
+    config: &Config,
+) -> Result<Seeded, ControllerError> {
+    let mut generated_pass = None;
+    let user = match store.find_user_by_name(DEMO_USER).await? {
+        Some(user) => user,
+        None => {
+            let pass = match &config.demo_pass {
+                Some(pass) => pass.clone(),
+                None => generated_pass.insert(get_random_token()).clone(),
+            };
+            create_user(store, config, DEMO_USER, &pass).await?
+        }
+    };
+    let principal = Principal::User(Box::new(user));
+
+    let (folder, _) = ensure_folder(store, storage, config, &principal, None, "demo").await?;
+    let (sub_folder, _) = ensure_folder(
+        store,
+        storage,
+        config,
+        &principal,
+        Some(&folder.path),
+        "demo-sub",
+    )
+    .await?;
+    let (sub_sub_folder, _) = ensure_folder(
+        store,
+        storage,
+        config,
+        &principal,
+        Some(&sub_folder.path),
+        "demo-sub-sub",
+    )
+    .await?;
+
+    // Only upload the video once, and only if it is there to upload
+    let mut stored_asset = None;
+    if sub_sub_folder.files.is_empty() {
+        if let Some(file_stream) = get_file_stream(DEMO_VIDEO_PATH).await {
+            if let Some(folder_id) = &sub_sub_folder.id {
+                stored_asset = Some(
+                    save_asset_stream(
+                        store,
+                        storage,
+                        config,
+                        &principal,
+                        file_stream,
+                        "my_video",
+                        folder_id,
+                        "mp4",
+                    )
+                    .await?,
+                );
+            }
+        }
+    }
+
+    Ok(Seeded {
+        folders: vec![folder, sub_folder, sub_sub_folder],
+        stored_asset,
+        generated_pass,
+    })
+}
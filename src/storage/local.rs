@@ -0,0 +1,257 @@
+use crate::controller::error::ControllerError;
+use crate::controller::paths::resolve_under_root;
+use crate::storage::{ByteSource, ByteStream, ObjectMeta, Storage};
+use crate::util::{get_reader_stream, get_uuid};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::stream::StreamExt;
+use std::fs::{create_dir_all, read_dir};
+use std::io::{ErrorKind, SeekFrom};
+use std::path::{Path, PathBuf};
+use tokio::fs::{self, File};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+// Files being written by put live here until they are complete, apart from the staged uploads of
+// content addressed storage, and are never listed as keys
+const TEMP_DIR: &str = ".tmp";
+
+/**
+ * ____________________________________________________________________________________________
+ * LocalStorage, asset bytes kept as files below a directory
+ * ____________________________________________________________________________________________
+ * root: The storage root, every key is a file path relative to it
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug, Clone)]
+pub struct LocalStorage {
+    root: String,
+}
+
+impl LocalStorage {
+    /**
+     * Keep files below root, creating it if it doesn't exist yet
+     */
+    pub fn new(root: &str) -> Result<LocalStorage, ControllerError> {
+        create_dir_all(root)?;
+
+        Ok(LocalStorage {
+            root: root.to_string(),
+        })
+    }
+    /**
+     * The file of key, which has to stay below the root even after following symlinks
+     */
+    fn path_of(&self, key: &str) -> Result<PathBuf, ControllerError> {
+        resolve_under_root(&self.root, &format!("{}/{}", self.root, key))
+    }
+    /**
+     * The directory dir below the root if it exists, the root itself counts too
+     */
+    fn path_of_dir(&self, dir: &str) -> Option<PathBuf> {
+        let resolved = if dir == self.root {
+            Path::new(&self.root).canonicalize().ok()?
+        } else {
+            resolve_under_root(&self.root, dir).ok()?
+        };
+
+        resolved.is_dir().then_some(resolved)
+    }
+    /**
+     * Open the file of key, a missing file is NotFound
+     */
+    async fn open(&self, key: &str) -> Result<File, ControllerError> {
+        File::open(self.path_of(key)?)
+            .await
+            .map_err(|err| not_found_or(err, key))
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(&self, key: &str, data: &mut ByteSource<'_>) -> Result<u64, ControllerError> {
+        let path = self.path_of(key)?;
+        let temp_path = self.path_of(&format!("{}/{}", TEMP_DIR, get_uuid()))?;
+        for dir in [path.parent(), temp_path.parent()].iter().flatten() {
+            fs::create_dir_all(dir).await?;
+        }
+
+        // Write a temporary file below the root and move it in place once every byte is on disk,
+        // so key never holds a partial file and a failed write leaves the old one alone
+        let written = match write_file(&temp_path, data).await {
+            Ok(size) => fs::rename(&temp_path, &path).await.map(|_| size),
+            Err(err) => Err(err),
+        };
+        match written {
+            Ok(size) => Ok(size),
+            Err(err) => {
+                let _ = fs::remove_file(&temp_path).await;
+                Err(err.into())
+            }
+        }
+    }
+
+    async fn get(&self, key: &str) -> Result<ByteStream, ControllerError> {
+        let file = self.open(key).await?;
+
+        Ok(Box::pin(get_reader_stream(file)))
+    }
+
+    async fn get_range(
+        &self,
+        key: &str,
+        start: u64,
+        end: u64,
+    ) -> Result<ByteStream, ControllerError> {
+        let mut file = self.open(key).await?;
+        file.seek(SeekFrom::Start(start)).await?;
+
+        Ok(Box::pin(get_reader_stream(file.take(end - start + 1))))
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> Result<(), ControllerError> {
+        let from_path = self.path_of(from)?;
+        let to_path = self.path_of(to)?;
+        if let Some(parent) = to_path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        fs::rename(&from_path, &to_path)
+            .await
+            .map_err(|err| not_found_or(err, from))
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), ControllerError> {
+        let path = self.path_of(key)?;
+
+        // A file that is already gone is fine, the directory of its folder stays
+        match fs::remove_file(&path).await {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, ControllerError> {
+        // Only the directory the prefix points into has to be walked
+        let dir = match prefix.rfind('/') {
+            Some(last_slash) => format!("{}/{}", self.root, &prefix[..last_slash]),
+            None => self.root.clone(),
+        };
+        let dir = match self.path_of_dir(&dir) {
+            Some(dir) => dir,
+            None => return Ok(vec![]),
+        };
+
+        let root = Path::new(&self.root).canonicalize()?;
+        let mut keys = vec![];
+        walk(&root, &dir, &mut keys)?;
+        let temp_prefix = format!("{}/", TEMP_DIR);
+        keys.retain(|key| key.starts_with(prefix) && !key.starts_with(&temp_prefix));
+        keys.sort();
+
+        Ok(keys)
+    }
+
+    async fn stat(&self, key: &str) -> Result<Option<ObjectMeta>, ControllerError> {
+        let metadata = match fs::metadata(self.path_of(key)?).await {
+            Ok(metadata) if metadata.is_file() => metadata,
+            Ok(_) => return Ok(None),
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        let modified: DateTime<Utc> = match metadata.modified() {
+            Ok(modified) => DateTime::from(modified),
+            Err(_) => Utc::now(),
+        };
+
+        Ok(Some(ObjectMeta {
+            size: metadata.len(),
+            modified,
+        }))
+    }
+
+    fn has_dirs(&self) -> bool {
+        true
+    }
+
+    async fn create_dir(&self, key: &str) -> Result<(), ControllerError> {
+        fs::create_dir_all(self.path_of(key)?).await?;
+
+        Ok(())
+    }
+
+    async fn dir_exists(&self, key: &str) -> Result<bool, ControllerError> {
+        match fs::metadata(self.path_of(key)?).await {
+            Ok(metadata) => Ok(metadata.is_dir()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn remove_dir(&self, key: &str) -> Result<(), ControllerError> {
+        match fs::remove_dir_all(self.path_of(key)?).await {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn rename_dir(&self, from: &str, to: &str) -> Result<(), ControllerError> {
+        let from_path = self.path_of(from)?;
+        let to_path = self.path_of(to)?;
+        if let Some(parent) = to_path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        // The whole directory moves at once, a folder whose directory went missing gets a new one
+        match fs::rename(&from_path, &to_path).await {
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                fs::create_dir_all(&to_path).await?;
+                Ok(())
+            }
+            moved => Ok(moved?),
+        }
+    }
+}
+/**
+ * Write every chunk of data to a new file at path and flush it to disk, returning the byte count
+ */
+async fn write_file(path: &Path, data: &mut ByteSource<'_>) -> std::io::Result<u64> {
+    let mut file = File::create(path).await?;
+    let mut size: u64 = 0;
+    while let Some(chunk) = data.next().await {
+        let chunk = chunk?;
+        size += chunk.len() as u64;
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    file.sync_all().await?;
+
+    Ok(size)
+}
+/**
+ * Collect the key of every file below dir
+ */
+fn walk(root: &Path, dir: &Path, keys: &mut Vec<String>) -> std::io::Result<()> {
+    for entry in read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk(root, &path, keys)?;
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            let parts: Vec<_> = relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect();
+            keys.push(parts.join("/"));
+        }
+    }
+
+    Ok(())
+}
+
+fn not_found_or(err: std::io::Error, key: &str) -> ControllerError {
+    if err.kind() == ErrorKind::NotFound {
+        return ControllerError::NotFound(format!("Nothing is stored under {}", key));
+    }
+
+    err.into()
+}
@@ -0,0 +1,112 @@
+use crate::controller::error::ControllerError;
+use crate::storage::{ByteSource, ByteStream, ObjectMeta, Storage};
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt};
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+
+/**
+ * ____________________________________________________________________________________________
+ * MemoryStorage, asset bytes kept in memory for tests and demos, gone when the process exits
+ * ____________________________________________________________________________________________
+ * objects: The bytes of every key, with when they were written
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    objects: Mutex<BTreeMap<String, (Bytes, DateTime<Utc>)>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage::default()
+    }
+    /**
+     * Lock the objects, a panic while they were locked can't leave them half written so poisoning
+     * is ignored
+     */
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<String, (Bytes, DateTime<Utc>)>> {
+        self.objects
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn bytes_of(&self, key: &str) -> Result<Bytes, ControllerError> {
+        self.lock()
+            .get(key)
+            .map(|(bytes, _)| bytes.clone())
+            .ok_or_else(|| ControllerError::NotFound(format!("Nothing is stored under {}", key)))
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn put(&self, key: &str, data: &mut ByteSource<'_>) -> Result<u64, ControllerError> {
+        // Collect everything first, so a failing stream leaves the old bytes in place
+        let mut buff = vec![];
+        while let Some(chunk) = data.next().await {
+            buff.extend_from_slice(&chunk?);
+        }
+
+        let size = buff.len() as u64;
+        self.lock()
+            .insert(key.to_string(), (Bytes::from(buff), Utc::now()));
+
+        Ok(size)
+    }
+
+    async fn get(&self, key: &str) -> Result<ByteStream, ControllerError> {
+        let bytes = self.bytes_of(key)?;
+
+        Ok(Box::pin(stream::once(async move { Ok(bytes) })))
+    }
+
+    async fn get_range(
+        &self,
+        key: &str,
+        start: u64,
+        end: u64,
+    ) -> Result<ByteStream, ControllerError> {
+        let bytes = self.bytes_of(key)?;
+        let end = (end + 1).min(bytes.len() as u64) as usize;
+        let start = (start as usize).min(end);
+        let part = bytes.slice(start..end);
+
+        Ok(Box::pin(stream::once(async move { Ok(part) })))
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> Result<(), ControllerError> {
+        let mut objects = self.lock();
+        let object = objects.remove(from).ok_or_else(|| {
+            ControllerError::NotFound(format!("Nothing is stored under {}", from))
+        })?;
+        objects.insert(to.to_string(), object);
+
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), ControllerError> {
+        self.lock().remove(key);
+
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, ControllerError> {
+        Ok(self
+            .lock()
+            .range(prefix.to_string()..)
+            .map(|(key, _)| key)
+            .take_while(|key| key.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+
+    async fn stat(&self, key: &str) -> Result<Option<ObjectMeta>, ControllerError> {
+        Ok(self.lock().get(key).map(|(bytes, modified)| ObjectMeta {
+            size: bytes.len() as u64,
+            modified: *modified,
+        }))
+    }
+}
@@ -0,0 +1,213 @@
+pub mod local;
+pub mod memory;
+pub mod s3;
+
+use crate::config::{Config, StorageBackend};
+use crate::controller::error::ControllerError;
+use crate::data_models::asset::Asset;
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::stream::Stream;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+
+// Folder names can't start with a '.', so these never collide with the keys of folders
+const BLOB_PREFIX: &str = ".blobs";
+const STAGING_PREFIX: &str = ".staging";
+
+/**
+ * Asset bytes storage shared between every request handler
+ */
+pub type SharedStorage = Arc<dyn Storage>;
+
+/**
+ * Bytes read from storage, chunk by chunk
+ */
+pub type ByteStream = Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send>>;
+
+/**
+ * Bytes handed to storage, borrowed so the caller can keep watching them go by
+ */
+pub type ByteSource<'a> = dyn Stream<Item = io::Result<Bytes>> + Send + Unpin + 'a;
+
+/**
+ * ____________________________________________________________________________________________
+ * ObjectMeta, what storage knows about the bytes of one key
+ * ____________________________________________________________________________________________
+ * size: Number of bytes stored
+ * modified: When the bytes were written
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug, Clone)]
+pub struct ObjectMeta {
+    pub size: u64,
+    pub modified: DateTime<Utc>,
+}
+
+/**
+ * Every read and write of asset bytes, so the controllers don't depend on one place to keep them.
+ *
+ * Keys are '/' separated paths relative to the storage root, like "pics/holiday/<uuid>.jpg", a key
+ * that isn't stored is NotFound, except for delete which is fine with it
+ */
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /**
+     * Store the bytes of data under key, replacing what was there, returning the byte count. The
+     * bytes show up all at once, a failed put leaves key as it was
+     */
+    async fn put(&self, key: &str, data: &mut ByteSource<'_>) -> Result<u64, ControllerError>;
+    /**
+     * Every byte stored under key
+     */
+    async fn get(&self, key: &str) -> Result<ByteStream, ControllerError>;
+    /**
+     * The bytes start to end (both inclusive) stored under key
+     */
+    async fn get_range(
+        &self,
+        key: &str,
+        start: u64,
+        end: u64,
+    ) -> Result<ByteStream, ControllerError>;
+    /**
+     * Move the bytes stored under from to to, replacing what was there
+     */
+    async fn rename(&self, from: &str, to: &str) -> Result<(), ControllerError>;
+    /**
+     * Remove key
+     */
+    async fn delete(&self, key: &str) -> Result<(), ControllerError>;
+    /**
+     * Every key starting with prefix, sorted
+     */
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, ControllerError>;
+    /**
+     * Size and modification time of key, None if it isn't stored
+     */
+    async fn stat(&self, key: &str) -> Result<Option<ObjectMeta>, ControllerError>;
+
+    /**
+     * Whether anything is stored under key
+     */
+    async fn exists(&self, key: &str) -> Result<bool, ControllerError> {
+        Ok(self.stat(key).await?.is_some())
+    }
+    /**
+     * Whether the storage keeps a directory for every folder, storage without directories only sees
+     * the keys of the assets in a folder
+     */
+    fn has_dirs(&self) -> bool {
+        false
+    }
+    /**
+     * Make sure the directory of the folder key exists
+     */
+    async fn create_dir(&self, _key: &str) -> Result<(), ControllerError> {
+        Ok(())
+    }
+    /**
+     * Whether the directory of the folder key exists, always true for storage without directories
+     */
+    async fn dir_exists(&self, _key: &str) -> Result<bool, ControllerError> {
+        Ok(true)
+    }
+    /**
+     * Remove the directory of the folder key with everything that is left in it
+     */
+    async fn remove_dir(&self, _key: &str) -> Result<(), ControllerError> {
+        Ok(())
+    }
+    /**
+     * Move the folder from with every key below it to to, moving back what was moved if that fails
+     * halfway
+     */
+    async fn rename_dir(&self, from: &str, to: &str) -> Result<(), ControllerError> {
+        let renames: Vec<(String, String)> = self
+            .list(&format!("{}/", from))
+            .await?
+            .into_iter()
+            .map(|key| {
+                let to_key = format!("{}{}", to, &key[from.len()..]);
+                (key, to_key)
+            })
+            .collect();
+
+        for (renamed, (from_key, to_key)) in renames.iter().enumerate() {
+            if let Err(err) = self.rename(from_key, to_key).await {
+                for (from_key, to_key) in &renames[..renamed] {
+                    let _ = self.rename(to_key, from_key).await;
+                }
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+}
+/**
+ * Open the storage picked by the config
+ */
+pub async fn open_storage(config: &Config) -> Result<SharedStorage, ControllerError> {
+    let storage: SharedStorage = match config.storage_backend {
+        StorageBackend::Local => Arc::new(local::LocalStorage::new(&config.storage_root)?),
+        StorageBackend::Memory => Arc::new(memory::MemoryStorage::new()),
+        StorageBackend::S3 => Arc::new(s3::S3Storage::new(config)?),
+    };
+
+    Ok(storage)
+}
+/**
+ * The storage key of a folder or asset path, which is the path relative to the storage root
+ */
+pub fn storage_key<'a>(storage_root: &str, path: &'a str) -> Result<&'a str, ControllerError> {
+    path.strip_prefix(storage_root)
+        .and_then(|relative| relative.strip_prefix('/'))
+        .filter(|key| !key.is_empty())
+        .ok_or_else(|| {
+            ControllerError::InvalidInput(format!(
+                "The path {} is not inside of the storage root {}",
+                path, storage_root
+            ))
+        })
+}
+/**
+ * The storage key of the shared blob holding the bytes with checksum, spread over directories by
+ * the first two characters so no directory gets too large
+ */
+pub fn blob_key(checksum: &str) -> String {
+    let fan_out = checksum.get(..2).unwrap_or(checksum);
+
+    format!("{}/{}/{}", BLOB_PREFIX, fan_out, checksum)
+}
+/**
+ * The checksum of the blob key holds the bytes of, None for keys that aren't blobs
+ */
+pub fn blob_checksum(key: &str) -> Option<&str> {
+    let rest = key.strip_prefix(BLOB_PREFIX)?.strip_prefix('/')?;
+
+    rest.split_once('/').map(|(_, checksum)| checksum)
+}
+/**
+ * The storage key uploads are written to before their checksum is known
+ */
+pub fn staging_key(uuid: &str) -> String {
+    format!("{}/{}", STAGING_PREFIX, uuid)
+}
+/**
+ * Whether key holds bytes of an upload that isn't done yet
+ */
+pub fn is_staging_key(key: &str) -> bool {
+    key.starts_with(&format!("{}/", STAGING_PREFIX))
+}
+/**
+ * The storage key holding the bytes of asset, its blob if it has one or its own path otherwise
+ */
+pub fn asset_key(storage_root: &str, asset: &Asset) -> Result<String, ControllerError> {
+    match &asset.blob {
+        Some(checksum) => Ok(blob_key(checksum)),
+        None => Ok(storage_key(storage_root, &asset.path)?.to_string()),
+    }
+}
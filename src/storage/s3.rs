@@ -0,0 +1,648 @@
+use crate::config::Config;
+use crate::controller::error::ControllerError;
+use crate::storage::{ByteSource, ByteStream, ObjectMeta, Storage};
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::stream::{StreamExt, TryStreamExt};
+use hmac::{Hmac, Mac, NewMac};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::header::{CONTENT_LENGTH, ETAG, LAST_MODIFIED, RANGE};
+use reqwest::{Client, Method, Response, StatusCode, Url};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::io;
+
+// Everything but the unreserved characters is percent encoded when signing
+const URI_ENCODE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+// Object keys keep their '/' separators in the path
+const PATH_ENCODE: &AsciiSet = &URI_ENCODE.remove(b'/');
+// Largest object a single CopyObject request can copy, larger ones are copied in parts of this size
+const MAX_COPY_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+
+/**
+ * ____________________________________________________________________________________________
+ * S3Storage, asset bytes kept as objects in a bucket of an S3 compatible server (AWS, MinIO)
+ * ____________________________________________________________________________________________
+ * client: HTTP client every request is sent with
+ * endpoint: URL of the server, the bucket is addressed in the path so any host name works
+ * host: Host (and port) of the endpoint, as signed
+ * region: Region requests are signed for
+ * bucket: Bucket every key is stored in
+ * access_key: Access key id requests are signed with
+ * secret_key: Secret access key requests are signed with
+ * part_size: Size of the parts larger uploads are sent in
+ * ____________________________________________________________________________________________
+ */
+pub struct S3Storage {
+    client: Client,
+    endpoint: Url,
+    host: String,
+    region: String,
+    bucket: String,
+    access_key: String,
+    secret_key: String,
+    part_size: usize,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListBucketResult {
+    #[serde(default)]
+    contents: Vec<ListedObject>,
+    #[serde(default)]
+    is_truncated: bool,
+    next_continuation_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListedObject {
+    key: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InitiateMultipartUploadResult {
+    upload_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct CopyPartResult {
+    #[serde(rename = "ETag")]
+    etag: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ErrorBody {
+    code: String,
+}
+
+impl S3Storage {
+    /**
+     * Keep objects in the bucket of the config, nothing is sent until the first request
+     */
+    pub fn new(config: &Config) -> Result<S3Storage, ControllerError> {
+        let endpoint = match &config.s3_endpoint {
+            Some(s3_endpoint) => s3_endpoint.clone(),
+            None => format!("https://s3.{}.amazonaws.com", config.s3_region),
+        };
+        let endpoint = Url::parse(&endpoint).map_err(|err| {
+            ControllerError::InvalidInput(format!(
+                "{} is not a valid S3 endpoint: {}",
+                endpoint, err
+            ))
+        })?;
+        let host = match (endpoint.host_str(), endpoint.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => {
+                return Err(ControllerError::InvalidInput(format!(
+                    "The S3 endpoint {} has no host",
+                    endpoint
+                )))
+            }
+        };
+
+        Ok(S3Storage {
+            client: Client::new(),
+            endpoint,
+            host,
+            region: config.s3_region.clone(),
+            bucket: config.s3_bucket.clone(),
+            access_key: config.s3_access_key.clone(),
+            secret_key: config.s3_secret_key.clone(),
+            part_size: config.s3_part_size as usize,
+        })
+    }
+    /**
+     * Sign a request to the bucket (or key in it) with AWS Signature Version 4 and send it, the
+     * x-amz-* headers out of headers are signed too
+     */
+    async fn send(
+        &self,
+        method: Method,
+        key: Option<&str>,
+        query: &[(&str, &str)],
+        headers: &[(&str, String)],
+        body: Option<Bytes>,
+    ) -> Result<Response, ControllerError> {
+        let mut path = format!(
+            "{}/{}",
+            self.endpoint.path().trim_end_matches('/'),
+            utf8_percent_encode(&self.bucket, URI_ENCODE)
+        );
+        if let Some(key) = key {
+            path = format!("{}/{}", path, utf8_percent_encode(key, PATH_ENCODE));
+        }
+
+        let mut query: Vec<String> = query
+            .iter()
+            .map(|(name, value)| {
+                format!(
+                    "{}={}",
+                    utf8_percent_encode(name, URI_ENCODE),
+                    utf8_percent_encode(value, URI_ENCODE)
+                )
+            })
+            .collect();
+        query.sort();
+        let query = query.join("&");
+
+        let payload_hash = sha256_hex(body.as_deref().unwrap_or_default());
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let scope = format!("{}/{}/s3/aws4_request", now.format("%Y%m%d"), self.region);
+
+        let mut signed: Vec<(String, &str)> = vec![
+            ("host".to_string(), &self.host),
+            ("x-amz-content-sha256".to_string(), &payload_hash),
+            ("x-amz-date".to_string(), &amz_date),
+        ];
+        for (name, value) in headers {
+            let name = name.to_lowercase();
+            if name.starts_with("x-amz-") {
+                signed.push((name, value.trim()));
+            }
+        }
+        signed.sort();
+        let canonical_headers: String = signed
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value))
+            .collect();
+        let signed_headers = signed
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method, path, query, canonical_headers, signed_headers, payload_hash
+        );
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            sha256_hex(canonical_request.as_bytes())
+        );
+        let signature = hex(&self.sign(&now, &string_to_sign));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key, scope, signed_headers, signature
+        );
+
+        let mut url = format!("{}://{}{}", self.endpoint.scheme(), self.host, path);
+        if !query.is_empty() {
+            url = format!("{}?{}", url, query);
+        }
+
+        let mut request = self
+            .client
+            .request(method, &url)
+            .header("x-amz-date", amz_date)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("authorization", authorization);
+        for (name, value) in headers {
+            request = request.header(*name, value.as_str());
+        }
+        if let Some(body) = body {
+            request = request.body(body);
+        }
+
+        Ok(request.send().await?)
+    }
+    /**
+     * Signature of string_to_sign, with the key derived from the secret for the day of now
+     */
+    fn sign(&self, now: &DateTime<Utc>, string_to_sign: &str) -> Vec<u8> {
+        let date = now.format("%Y%m%d").to_string();
+        let secret = format!("AWS4{}", self.secret_key);
+
+        let key = hmac_sha256(secret.as_bytes(), date.as_bytes());
+        let key = hmac_sha256(&key, self.region.as_bytes());
+        let key = hmac_sha256(&key, b"s3");
+        let key = hmac_sha256(&key, b"aws4_request");
+
+        hmac_sha256(&key, string_to_sign.as_bytes())
+    }
+    /**
+     * Upload data in parts, starting with the bytes already read into buff, returning the byte count
+     */
+    async fn put_multipart(
+        &self,
+        key: &str,
+        buff: Vec<u8>,
+        data: &mut ByteSource<'_>,
+    ) -> Result<u64, ControllerError> {
+        let upload_id = self.create_multipart(key).await?;
+
+        match self.put_parts(key, &upload_id, buff, data).await {
+            Ok(size) => Ok(size),
+            Err(err) => {
+                self.abort_multipart(key, &upload_id).await;
+                Err(err)
+            }
+        }
+    }
+    /**
+     * Start a multipart upload to key, returning its upload id
+     */
+    async fn create_multipart(&self, key: &str) -> Result<String, ControllerError> {
+        let response = self
+            .send(Method::POST, Some(key), &[("uploads", "")], &[], None)
+            .await?;
+        let created: InitiateMultipartUploadResult = parse_xml(check(response, key).await?).await?;
+
+        Ok(created.upload_id)
+    }
+    /**
+     * Don't leave the parts that did make it behind, they are kept until the upload is aborted
+     */
+    async fn abort_multipart(&self, key: &str, upload_id: &str) {
+        let _ = self
+            .send(
+                Method::DELETE,
+                Some(key),
+                &[("uploadId", upload_id)],
+                &[],
+                None,
+            )
+            .await;
+    }
+    /**
+     * Put the parts with etags, in order, together into the object key
+     */
+    async fn complete_multipart(
+        &self,
+        key: &str,
+        upload_id: &str,
+        etags: &[String],
+    ) -> Result<(), ControllerError> {
+        let parts: String = etags
+            .iter()
+            .enumerate()
+            .map(|(index, etag)| {
+                format!(
+                    "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                    index + 1,
+                    etag
+                )
+            })
+            .collect();
+        let body = format!(
+            "<CompleteMultipartUpload>{}</CompleteMultipartUpload>",
+            parts
+        );
+        let response = self
+            .send(
+                Method::POST,
+                Some(key),
+                &[("uploadId", upload_id)],
+                &[],
+                Some(Bytes::from(body)),
+            )
+            .await?;
+
+        check_body(response, key).await
+    }
+
+    async fn put_parts(
+        &self,
+        key: &str,
+        upload_id: &str,
+        mut buff: Vec<u8>,
+        data: &mut ByteSource<'_>,
+    ) -> Result<u64, ControllerError> {
+        let mut size: u64 = 0;
+        let mut etags = vec![];
+
+        loop {
+            // A part is only sent once more bytes are known to follow, so the last one is never empty
+            while buff.len() > self.part_size {
+                let rest = buff.split_off(self.part_size);
+                size += buff.len() as u64;
+                etags.push(self.put_part(key, upload_id, etags.len() + 1, buff).await?);
+                buff = rest;
+            }
+
+            match data.next().await {
+                Some(chunk) => buff.extend_from_slice(&chunk?),
+                None => break,
+            }
+        }
+        size += buff.len() as u64;
+        etags.push(self.put_part(key, upload_id, etags.len() + 1, buff).await?);
+
+        self.complete_multipart(key, upload_id, &etags).await?;
+
+        Ok(size)
+    }
+    /**
+     * Upload part part_number of an upload, returning its ETag
+     */
+    async fn put_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: usize,
+        part: Vec<u8>,
+    ) -> Result<String, ControllerError> {
+        let part_number = part_number.to_string();
+        let response = self
+            .send(
+                Method::PUT,
+                Some(key),
+                &[("partNumber", &part_number), ("uploadId", upload_id)],
+                &[],
+                Some(Bytes::from(part)),
+            )
+            .await?;
+        let response = check(response, key).await?;
+
+        response
+            .headers()
+            .get(ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .map(|etag| etag.to_string())
+            .ok_or_else(|| {
+                ControllerError::Storage(io::Error::other(format!(
+                    "S3 sent no ETag for part {} of {}",
+                    part_number, key
+                )))
+            })
+    }
+    /**
+     * Copy the object from to to, in parts when it is too large for a single copy
+     */
+    async fn copy(&self, from: &str, to: &str) -> Result<(), ControllerError> {
+        let size = match self.stat(from).await? {
+            Some(meta) => meta.size,
+            None => {
+                return Err(ControllerError::NotFound(format!(
+                    "Nothing is stored under {}",
+                    from
+                )))
+            }
+        };
+        let source = self.copy_source(from);
+
+        if size <= MAX_COPY_SIZE {
+            let response = self
+                .send(
+                    Method::PUT,
+                    Some(to),
+                    &[],
+                    &[("x-amz-copy-source", source)],
+                    None,
+                )
+                .await?;
+            return check_body(response, to).await;
+        }
+
+        let upload_id = self.create_multipart(to).await?;
+        match self.copy_parts(&source, to, &upload_id, size).await {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                self.abort_multipart(to, &upload_id).await;
+                Err(err)
+            }
+        }
+    }
+
+    async fn copy_parts(
+        &self,
+        source: &str,
+        to: &str,
+        upload_id: &str,
+        size: u64,
+    ) -> Result<(), ControllerError> {
+        let mut etags = vec![];
+        let mut start = 0;
+
+        while start < size {
+            let end = (start + MAX_COPY_SIZE).min(size) - 1;
+            let part_number = (etags.len() + 1).to_string();
+            let response = self
+                .send(
+                    Method::PUT,
+                    Some(to),
+                    &[("partNumber", &part_number), ("uploadId", upload_id)],
+                    &[
+                        ("x-amz-copy-source", source.to_string()),
+                        (
+                            "x-amz-copy-source-range",
+                            format!("bytes={}-{}", start, end),
+                        ),
+                    ],
+                    None,
+                )
+                .await?;
+            let copied: CopyPartResult = parse_xml(check(response, to).await?).await?;
+            etags.push(copied.etag);
+            start = end + 1;
+        }
+
+        self.complete_multipart(to, upload_id, &etags).await
+    }
+    /**
+     * The x-amz-copy-source header naming key in the bucket
+     */
+    fn copy_source(&self, key: &str) -> String {
+        format!(
+            "/{}/{}",
+            utf8_percent_encode(&self.bucket, URI_ENCODE),
+            utf8_percent_encode(key, PATH_ENCODE)
+        )
+    }
+}
+
+#[async_trait]
+impl Storage for S3Storage {
+    async fn put(&self, key: &str, data: &mut ByteSource<'_>) -> Result<u64, ControllerError> {
+        // Objects up to one part are sent in a single request, larger ones in parts
+        let mut buff = vec![];
+        while let Some(chunk) = data.next().await {
+            buff.extend_from_slice(&chunk?);
+            if buff.len() > self.part_size {
+                return self.put_multipart(key, buff, data).await;
+            }
+        }
+
+        let size = buff.len() as u64;
+        let response = self
+            .send(Method::PUT, Some(key), &[], &[], Some(Bytes::from(buff)))
+            .await?;
+        check(response, key).await?;
+
+        Ok(size)
+    }
+
+    async fn get(&self, key: &str) -> Result<ByteStream, ControllerError> {
+        let response = self.send(Method::GET, Some(key), &[], &[], None).await?;
+        let response = check(response, key).await?;
+
+        Ok(Box::pin(response.bytes_stream().map_err(io::Error::other)))
+    }
+
+    async fn get_range(
+        &self,
+        key: &str,
+        start: u64,
+        end: u64,
+    ) -> Result<ByteStream, ControllerError> {
+        let range = format!("bytes={}-{}", start, end);
+        let response = self
+            .send(
+                Method::GET,
+                Some(key),
+                &[],
+                &[(RANGE.as_str(), range)],
+                None,
+            )
+            .await?;
+        let response = check(response, key).await?;
+
+        Ok(Box::pin(response.bytes_stream().map_err(io::Error::other)))
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> Result<(), ControllerError> {
+        // S3 has no renames, copy the object server side and remove the original
+        self.copy(from, to).await?;
+        self.delete(from).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), ControllerError> {
+        let response = self.send(Method::DELETE, Some(key), &[], &[], None).await?;
+
+        // A key that is already gone is fine
+        match check(response, key).await {
+            Ok(_) | Err(ControllerError::NotFound(_)) => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, ControllerError> {
+        let mut keys = vec![];
+        let mut continuation_token: Option<String> = None;
+
+        // Every response holds up to 1000 keys, follow the continuation tokens for the rest
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", prefix)];
+            if let Some(token) = &continuation_token {
+                query.push(("continuation-token", token));
+            }
+            let response = self.send(Method::GET, None, &query, &[], None).await?;
+            let listed: ListBucketResult = parse_xml(check(response, prefix).await?).await?;
+
+            keys.extend(listed.contents.into_iter().map(|object| object.key));
+            match listed.next_continuation_token {
+                Some(token) if listed.is_truncated => continuation_token = Some(token),
+                _ => break,
+            }
+        }
+        keys.sort();
+
+        Ok(keys)
+    }
+
+    async fn stat(&self, key: &str) -> Result<Option<ObjectMeta>, ControllerError> {
+        let response = self.send(Method::HEAD, Some(key), &[], &[], None).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = check(response, key).await?;
+
+        let headers = response.headers();
+        let size = headers
+            .get(CONTENT_LENGTH)
+            .and_then(|size| size.to_str().ok())
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(0);
+        let modified = headers
+            .get(LAST_MODIFIED)
+            .and_then(|modified| modified.to_str().ok())
+            .and_then(|modified| DateTime::parse_from_rfc2822(modified).ok())
+            .map(|modified| modified.with_timezone(&Utc))
+            .unwrap_or_else(Utc::now);
+
+        Ok(Some(ObjectMeta { size, modified }))
+    }
+}
+/**
+ * Pass successful responses on, a missing key is NotFound and anything else a Storage error
+ */
+async fn check(response: Response, key: &str) -> Result<Response, ControllerError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let body = response.text().await.unwrap_or_default();
+    let code = quick_xml::de::from_str::<ErrorBody>(&body)
+        .map(|error_body| error_body.code)
+        .unwrap_or_default();
+
+    // A missing bucket is a broken setup, not a missing key
+    if status == StatusCode::NOT_FOUND && code != "NoSuchBucket" {
+        return Err(ControllerError::NotFound(format!(
+            "Nothing is stored under {}",
+            key
+        )));
+    }
+
+    Err(s3_error(key, status, &code))
+}
+
+/**
+ * Like check, for requests that can fail after the 200 is sent, with the error in the body then
+ */
+async fn check_body(response: Response, key: &str) -> Result<(), ControllerError> {
+    let body = check(response, key).await?.text().await?;
+    if let Ok(error_body) = quick_xml::de::from_str::<ErrorBody>(&body) {
+        return Err(s3_error(key, StatusCode::OK, &error_body.code));
+    }
+
+    Ok(())
+}
+
+async fn parse_xml<T: serde::de::DeserializeOwned>(
+    response: Response,
+) -> Result<T, ControllerError> {
+    let body = response.text().await?;
+
+    quick_xml::de::from_str(&body).map_err(|err| {
+        ControllerError::Storage(io::Error::other(format!(
+            "Could not parse the S3 response {}: {}",
+            body, err
+        )))
+    })
+}
+
+fn s3_error(key: &str, status: StatusCode, code: &str) -> ControllerError {
+    ControllerError::Storage(io::Error::other(format!(
+        "S3 request for {} failed with {} {}",
+        key, status, code
+    )))
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_varkey(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -0,0 +1,778 @@
+use crate::controller::error::ControllerError;
+use crate::data_models::{
+    access_group::AccessGroup,
+    asset::{Asset, Integrity},
+    blob::Blob,
+    folder::Folder,
+    key::Key,
+    session::Session,
+    user::User,
+};
+use crate::store::{FolderList, MetaStore, UserList};
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+use wither::mongodb::bson::oid::ObjectId;
+
+/**
+ * ____________________________________________________________________________________________
+ * MemoryStore, metadata kept in memory for tests and demos, gone when the process exits
+ * ____________________________________________________________________________________________
+ * state: Every doc by model, keyed by ObjectId so they come out in insertion order
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    users: BTreeMap<ObjectId, User>,
+    folders: BTreeMap<ObjectId, Folder>,
+    assets: BTreeMap<ObjectId, Asset>,
+    keys: BTreeMap<ObjectId, Key>,
+    access_groups: BTreeMap<ObjectId, AccessGroup>,
+    sessions: BTreeMap<ObjectId, Session>,
+    blobs: BTreeMap<ObjectId, Blob>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+    /**
+     * Lock the state, a panic while it was locked can't leave it half written so poisoning is ignored
+     */
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[async_trait]
+impl MetaStore for MemoryStore {
+    async fn prepare(&self) -> Result<(), ControllerError> {
+        Ok(())
+    }
+
+    async fn insert_user(&self, mut user: User) -> Result<User, ControllerError> {
+        let mut state = self.lock();
+        ensure_unique(&state.users, "user", &user.user, |other| &other.user)?;
+
+        let id = ObjectId::new();
+        user.id = Some(id.clone());
+        state.users.insert(id, user.clone());
+
+        Ok(user)
+    }
+
+    async fn find_user(&self, id: &ObjectId) -> Result<Option<User>, ControllerError> {
+        Ok(self.lock().users.get(id).cloned())
+    }
+
+    async fn find_user_by_name(&self, user: &str) -> Result<Option<User>, ControllerError> {
+        Ok(find_by(&self.lock().users, |other| other.user == user))
+    }
+
+    async fn find_users(&self, ids: Option<&[ObjectId]>) -> Result<Vec<User>, ControllerError> {
+        let state = self.lock();
+        Ok(filter_by(&state.users, |user| match ids {
+            Some(ids) => contains_id(ids, &user.id),
+            None => true,
+        }))
+    }
+
+    async fn count_users(&self) -> Result<u64, ControllerError> {
+        Ok(self.lock().users.len() as u64)
+    }
+
+    async fn set_user_pass(&self, id: &ObjectId, pass: &str) -> Result<bool, ControllerError> {
+        match self.lock().users.get_mut(id) {
+            Some(user) => {
+                user.pass = pass.to_string();
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn add_to_user_list(
+        &self,
+        id: &ObjectId,
+        list: UserList,
+        item: &ObjectId,
+    ) -> Result<Option<User>, ControllerError> {
+        Ok(self.lock().users.get_mut(id).map(|user| {
+            add_once(list.of(user), item);
+            user.clone()
+        }))
+    }
+
+    async fn remove_from_user_list(
+        &self,
+        id: &ObjectId,
+        list: UserList,
+        item: &ObjectId,
+    ) -> Result<Option<User>, ControllerError> {
+        Ok(self.lock().users.get_mut(id).map(|user| {
+            list.of(user).retain(|other| other != item);
+            user.clone()
+        }))
+    }
+
+    async fn remove_from_all_user_lists(
+        &self,
+        list: UserList,
+        items: &[ObjectId],
+    ) -> Result<(), ControllerError> {
+        for user in self.lock().users.values_mut() {
+            list.of(user).retain(|other| !items.contains(other));
+        }
+
+        Ok(())
+    }
+
+    async fn delete_user(&self, id: &ObjectId) -> Result<bool, ControllerError> {
+        Ok(self.lock().users.remove(id).is_some())
+    }
+
+    async fn insert_folder(&self, mut folder: Folder) -> Result<Folder, ControllerError> {
+        let mut state = self.lock();
+        ensure_unique(&state.folders, "folder tag", &folder.tag, |other| {
+            &other.tag
+        })?;
+        ensure_unique(&state.folders, "folder path", &folder.path, |other| {
+            &other.path
+        })?;
+
+        let id = ObjectId::new();
+        folder.id = Some(id.clone());
+        state.folders.insert(id, folder.clone());
+
+        Ok(folder)
+    }
+
+    async fn find_folder(&self, id: &ObjectId) -> Result<Option<Folder>, ControllerError> {
+        Ok(self.lock().folders.get(id).cloned())
+    }
+
+    async fn find_folder_by_path(&self, path: &str) -> Result<Option<Folder>, ControllerError> {
+        Ok(find_by(&self.lock().folders, |folder| folder.path == path))
+    }
+
+    async fn find_folder_by_tag(&self, tag: &str) -> Result<Option<Folder>, ControllerError> {
+        Ok(find_by(&self.lock().folders, |folder| folder.tag == tag))
+    }
+
+    async fn find_folders_by_paths(
+        &self,
+        paths: &[String],
+    ) -> Result<Vec<Folder>, ControllerError> {
+        Ok(filter_by(&self.lock().folders, |folder| {
+            paths.contains(&folder.path)
+        }))
+    }
+
+    async fn find_folders_below(&self, path: &str) -> Result<Vec<Folder>, ControllerError> {
+        let prefix = format!("{}/", path);
+        Ok(filter_by(&self.lock().folders, |folder| {
+            folder.path.starts_with(&prefix)
+        }))
+    }
+
+    async fn add_to_folder_list(
+        &self,
+        id: &ObjectId,
+        list: FolderList,
+        item: &ObjectId,
+    ) -> Result<bool, ControllerError> {
+        match self.lock().folders.get_mut(id) {
+            Some(folder) => {
+                add_once(list.of(folder), item);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn remove_from_all_folder_lists(
+        &self,
+        list: FolderList,
+        items: &[ObjectId],
+    ) -> Result<(), ControllerError> {
+        for folder in self.lock().folders.values_mut() {
+            list.of(folder).retain(|other| !items.contains(other));
+        }
+
+        Ok(())
+    }
+
+    async fn set_folder_access(
+        &self,
+        id: &ObjectId,
+        is_public: bool,
+        access_groups: &[ObjectId],
+    ) -> Result<bool, ControllerError> {
+        match self.lock().folders.get_mut(id) {
+            Some(folder) => {
+                folder.is_public = is_public;
+                folder.access_groups = access_groups.to_vec();
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn move_folder(
+        &self,
+        id: &ObjectId,
+        tag: &str,
+        path: &str,
+        to_path: &str,
+    ) -> Result<Option<Folder>, ControllerError> {
+        let mut state = self.lock();
+        if !state.folders.contains_key(id) {
+            return Ok(None);
+        }
+        for (other_id, other) in &state.folders {
+            if other_id != id && other.tag == tag {
+                return Err(already_exists("folder tag", tag));
+            }
+            if other_id != id && other.path == to_path {
+                return Err(already_exists("folder path", to_path));
+            }
+        }
+
+        let prefix = format!("{}/", path);
+        let moved = |other_path: &mut String| {
+            if let Some(rest) = other_path.strip_prefix(&prefix) {
+                *other_path = format!("{}/{}", to_path, rest);
+            }
+        };
+        for folder in state.folders.values_mut() {
+            moved(&mut folder.path);
+        }
+        for asset in state.assets.values_mut() {
+            moved(&mut asset.path);
+        }
+
+        Ok(state.folders.get_mut(id).map(|folder| {
+            folder.tag = tag.to_string();
+            folder.path = to_path.to_string();
+            folder.clone()
+        }))
+    }
+
+    async fn delete_folders(&self, ids: &[ObjectId]) -> Result<(), ControllerError> {
+        self.lock().folders.retain(|id, _| !ids.contains(id));
+
+        Ok(())
+    }
+
+    async fn insert_asset(&self, mut asset: Asset) -> Result<Asset, ControllerError> {
+        let mut state = self.lock();
+        ensure_unique(&state.assets, "asset path", &asset.path, |other| {
+            &other.path
+        })?;
+        ensure_unique(&state.assets, "asset uuid", &asset.uuid, |other| {
+            &other.uuid
+        })?;
+
+        let id = ObjectId::new();
+        asset.id = Some(id.clone());
+        state.assets.insert(id, asset.clone());
+
+        Ok(asset)
+    }
+
+    async fn find_asset(&self, id: &ObjectId) -> Result<Option<Asset>, ControllerError> {
+        Ok(self.lock().assets.get(id).cloned())
+    }
+
+    async fn find_asset_by_uuid(&self, uuid: &str) -> Result<Option<Asset>, ControllerError> {
+        Ok(find_by(&self.lock().assets, |asset| asset.uuid == uuid))
+    }
+
+    async fn find_asset_by_path(&self, path: &str) -> Result<Option<Asset>, ControllerError> {
+        Ok(find_by(&self.lock().assets, |asset| asset.path == path))
+    }
+
+    async fn find_assets_in_folder(
+        &self,
+        folder: &ObjectId,
+    ) -> Result<Vec<Asset>, ControllerError> {
+        Ok(filter_by(&self.lock().assets, |asset| {
+            &asset.folder_id == folder
+        }))
+    }
+
+    async fn find_assets_after(
+        &self,
+        after: Option<&ObjectId>,
+        limit: usize,
+    ) -> Result<Vec<Asset>, ControllerError> {
+        let state = self.lock();
+        let assets = state
+            .assets
+            .iter()
+            .filter(|(id, _)| after.is_none_or(|after| *id > after))
+            .take(limit)
+            .map(|(_, asset)| asset.clone())
+            .collect();
+
+        Ok(assets)
+    }
+
+    async fn set_asset_checksum(
+        &self,
+        id: &ObjectId,
+        size: i64,
+        checksum: &str,
+        blob: Option<&str>,
+    ) -> Result<bool, ControllerError> {
+        Ok(match self.lock().assets.get_mut(id) {
+            Some(asset) => {
+                asset.size = size;
+                asset.checksum = checksum.to_string();
+                asset.blob = blob.map(|blob| blob.to_string());
+                true
+            }
+            None => false,
+        })
+    }
+
+    async fn set_asset_integrity(
+        &self,
+        id: &ObjectId,
+        integrity: Integrity,
+        verified_at: i64,
+    ) -> Result<bool, ControllerError> {
+        Ok(match self.lock().assets.get_mut(id) {
+            Some(asset) => {
+                asset.integrity = integrity;
+                asset.verified_at = Some(verified_at);
+                true
+            }
+            None => false,
+        })
+    }
+
+    async fn move_asset(
+        &self,
+        id: &ObjectId,
+        folder: &ObjectId,
+        to_folder: &ObjectId,
+        tag: &str,
+        path: &str,
+    ) -> Result<Option<Asset>, ControllerError> {
+        let mut state = self.lock();
+        let taken = state
+            .assets
+            .iter()
+            .any(|(other_id, other)| other_id != id && other.path == path);
+        if taken {
+            return Err(already_exists("asset path", path));
+        }
+
+        let asset = match state.assets.get_mut(id) {
+            Some(asset) => {
+                asset.folder_id = to_folder.clone();
+                asset.tag = tag.to_string();
+                asset.path = path.to_string();
+                asset.clone()
+            }
+            None => return Ok(None),
+        };
+
+        if let Some(folder) = state.folders.get_mut(folder) {
+            folder.files.retain(|file| file != id);
+        }
+        if let Some(to_folder) = state.folders.get_mut(to_folder) {
+            add_once(&mut to_folder.files, id);
+        }
+
+        Ok(Some(asset))
+    }
+
+    async fn delete_asset(&self, id: &ObjectId) -> Result<bool, ControllerError> {
+        Ok(self.lock().assets.remove(id).is_some())
+    }
+
+    async fn delete_assets_in_folders(&self, folders: &[ObjectId]) -> Result<(), ControllerError> {
+        self.lock()
+            .assets
+            .retain(|_, asset| !folders.contains(&asset.folder_id));
+
+        Ok(())
+    }
+
+    async fn find_blob(&self, checksum: &str) -> Result<Option<Blob>, ControllerError> {
+        Ok(find_by(&self.lock().blobs, |blob| {
+            blob.checksum == checksum
+        }))
+    }
+
+    async fn acquire_blob(&self, mut blob: Blob) -> Result<bool, ControllerError> {
+        let mut state = self.lock();
+        if let Some(existing) = state
+            .blobs
+            .values_mut()
+            .find(|existing| existing.checksum == blob.checksum)
+        {
+            existing.refs += 1;
+            existing.touched_at = blob.touched_at;
+            return Ok(true);
+        }
+
+        let id = ObjectId::new();
+        blob.id = Some(id.clone());
+        blob.refs = 1;
+        state.blobs.insert(id, blob);
+
+        Ok(false)
+    }
+
+    async fn release_blob(&self, checksum: &str, now: i64) -> Result<bool, ControllerError> {
+        let mut state = self.lock();
+        let released = state
+            .blobs
+            .iter_mut()
+            .find(|(_, blob)| blob.checksum == checksum);
+
+        match released {
+            Some((id, blob)) => {
+                blob.refs -= 1;
+                blob.touched_at = now;
+                if blob.refs > 0 {
+                    return Ok(false);
+                }
+                let id = id.clone();
+                state.blobs.remove(&id);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn find_blobs(&self) -> Result<Vec<Blob>, ControllerError> {
+        Ok(self.lock().blobs.values().cloned().collect())
+    }
+
+    async fn recount_blob(
+        &self,
+        mut blob: Blob,
+        seen: Option<&Blob>,
+    ) -> Result<bool, ControllerError> {
+        let mut state = self.lock();
+        let existing = state
+            .blobs
+            .iter_mut()
+            .find(|(_, existing)| existing.checksum == blob.checksum);
+
+        match (existing, seen) {
+            (Some((id, existing)), Some(seen))
+                if existing.refs == seen.refs && existing.touched_at == seen.touched_at =>
+            {
+                if blob.refs <= 0 {
+                    let id = id.clone();
+                    state.blobs.remove(&id);
+                    return Ok(true);
+                }
+                existing.refs = blob.refs;
+                existing.touched_at = blob.touched_at;
+                Ok(true)
+            }
+            (None, None) if blob.refs > 0 => {
+                let id = ObjectId::new();
+                blob.id = Some(id.clone());
+                state.blobs.insert(id, blob);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn count_blob_assets(&self, checksum: &str) -> Result<u64, ControllerError> {
+        let state = self.lock();
+        let count = state
+            .assets
+            .values()
+            .filter(|asset| asset.blob.as_deref() == Some(checksum))
+            .count();
+
+        Ok(count as u64)
+    }
+
+    async fn insert_key(&self, mut key: Key) -> Result<Key, ControllerError> {
+        let mut state = self.lock();
+        ensure_unique(&state.keys, "key uuid", &key.uuid, |other| &other.uuid)?;
+
+        let id = ObjectId::new();
+        key.id = Some(id.clone());
+        state.keys.insert(id, key.clone());
+
+        Ok(key)
+    }
+
+    async fn find_key(&self, id: &ObjectId) -> Result<Option<Key>, ControllerError> {
+        Ok(self.lock().keys.get(id).cloned())
+    }
+
+    async fn find_key_by_uuid(&self, uuid: &str) -> Result<Option<Key>, ControllerError> {
+        Ok(find_by(&self.lock().keys, |key| key.uuid == uuid))
+    }
+
+    async fn find_active_keys(&self, ids: &[ObjectId]) -> Result<Vec<Key>, ControllerError> {
+        Ok(filter_by(&self.lock().keys, |key| {
+            key.active && contains_id(ids, &key.id)
+        }))
+    }
+
+    async fn set_key_active(
+        &self,
+        id: &ObjectId,
+        active: bool,
+    ) -> Result<Option<Key>, ControllerError> {
+        Ok(self.lock().keys.get_mut(id).map(|key| {
+            key.active = active;
+            key.clone()
+        }))
+    }
+
+    async fn delete_key(&self, id: &ObjectId) -> Result<bool, ControllerError> {
+        Ok(self.lock().keys.remove(id).is_some())
+    }
+
+    async fn insert_access_group(
+        &self,
+        mut access_group: AccessGroup,
+    ) -> Result<AccessGroup, ControllerError> {
+        let mut state = self.lock();
+        ensure_unique(
+            &state.access_groups,
+            "access group tag",
+            &access_group.tag,
+            |other| &other.tag,
+        )?;
+
+        let id = ObjectId::new();
+        access_group.id = Some(id.clone());
+        state.access_groups.insert(id, access_group.clone());
+
+        Ok(access_group)
+    }
+
+    async fn find_access_group(
+        &self,
+        id: &ObjectId,
+    ) -> Result<Option<AccessGroup>, ControllerError> {
+        Ok(self.lock().access_groups.get(id).cloned())
+    }
+
+    async fn find_access_group_by_tag(
+        &self,
+        tag: &str,
+    ) -> Result<Option<AccessGroup>, ControllerError> {
+        Ok(find_by(&self.lock().access_groups, |access_group| {
+            access_group.tag == tag
+        }))
+    }
+
+    async fn find_access_groups(&self) -> Result<Vec<AccessGroup>, ControllerError> {
+        Ok(self.lock().access_groups.values().cloned().collect())
+    }
+
+    async fn any_access_group_allows(
+        &self,
+        ids: &[ObjectId],
+        keys: &[ObjectId],
+    ) -> Result<bool, ControllerError> {
+        let state = self.lock();
+        Ok(ids
+            .iter()
+            .filter_map(|id| state.access_groups.get(id))
+            .any(|access_group| {
+                access_group
+                    .allowed_keys
+                    .iter()
+                    .any(|key| keys.contains(key))
+            }))
+    }
+
+    async fn set_access_group_tag(
+        &self,
+        id: &ObjectId,
+        tag: &str,
+    ) -> Result<Option<AccessGroup>, ControllerError> {
+        let mut state = self.lock();
+        let taken = state
+            .access_groups
+            .values()
+            .any(|other| other.tag == tag && other.id.as_ref() != Some(id));
+        if taken {
+            return Err(already_exists("access group tag", tag));
+        }
+
+        Ok(state.access_groups.get_mut(id).map(|access_group| {
+            access_group.tag = tag.to_string();
+            access_group.clone()
+        }))
+    }
+
+    async fn add_allowed_key(
+        &self,
+        id: &ObjectId,
+        key: &ObjectId,
+    ) -> Result<Option<AccessGroup>, ControllerError> {
+        Ok(self.lock().access_groups.get_mut(id).map(|access_group| {
+            add_once(&mut access_group.allowed_keys, key);
+            access_group.clone()
+        }))
+    }
+
+    async fn remove_allowed_key(
+        &self,
+        id: &ObjectId,
+        key: &ObjectId,
+    ) -> Result<Option<AccessGroup>, ControllerError> {
+        Ok(self.lock().access_groups.get_mut(id).map(|access_group| {
+            access_group.allowed_keys.retain(|other| other != key);
+            access_group.clone()
+        }))
+    }
+
+    async fn remove_allowed_keys_from_all(&self, keys: &[ObjectId]) -> Result<(), ControllerError> {
+        for access_group in self.lock().access_groups.values_mut() {
+            access_group
+                .allowed_keys
+                .retain(|other| !keys.contains(other));
+        }
+
+        Ok(())
+    }
+
+    async fn delete_access_group(&self, id: &ObjectId) -> Result<bool, ControllerError> {
+        Ok(self.lock().access_groups.remove(id).is_some())
+    }
+
+    async fn insert_session(&self, mut session: Session) -> Result<Session, ControllerError> {
+        let mut state = self.lock();
+        ensure_unique(
+            &state.sessions,
+            "session token",
+            &session.token_hash,
+            |other| &other.token_hash,
+        )?;
+
+        let id = ObjectId::new();
+        session.id = Some(id.clone());
+        state.sessions.insert(id, session.clone());
+
+        Ok(session)
+    }
+
+    async fn find_live_session(
+        &self,
+        token_hash: &str,
+        now: i64,
+    ) -> Result<Option<Session>, ControllerError> {
+        Ok(find_by(&self.lock().sessions, |session| {
+            session.token_hash == token_hash && is_live(session, now)
+        }))
+    }
+
+    async fn find_live_sessions_of(
+        &self,
+        user: &ObjectId,
+        now: i64,
+    ) -> Result<Vec<Session>, ControllerError> {
+        Ok(filter_by(&self.lock().sessions, |session| {
+            &session.user == user && is_live(session, now)
+        }))
+    }
+
+    async fn revoke_session_by_hash(&self, token_hash: &str) -> Result<(), ControllerError> {
+        for session in self.lock().sessions.values_mut() {
+            if session.token_hash == token_hash {
+                session.revoked = true;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn revoke_session(
+        &self,
+        id: &ObjectId,
+        user: &ObjectId,
+    ) -> Result<bool, ControllerError> {
+        match self.lock().sessions.get_mut(id) {
+            Some(session) if &session.user == user => {
+                session.revoked = true;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn revoke_sessions_of(&self, user: &ObjectId) -> Result<(), ControllerError> {
+        for session in self.lock().sessions.values_mut() {
+            if &session.user == user {
+                session.revoked = true;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn delete_sessions_of(&self, user: &ObjectId) -> Result<(), ControllerError> {
+        self.lock()
+            .sessions
+            .retain(|_, session| &session.user != user);
+
+        Ok(())
+    }
+}
+
+fn find_by<T: Clone>(docs: &BTreeMap<ObjectId, T>, matches: impl Fn(&T) -> bool) -> Option<T> {
+    docs.values().find(|doc| matches(doc)).cloned()
+}
+
+fn filter_by<T: Clone>(docs: &BTreeMap<ObjectId, T>, matches: impl Fn(&T) -> bool) -> Vec<T> {
+    docs.values().filter(|doc| matches(doc)).cloned().collect()
+}
+
+fn contains_id(ids: &[ObjectId], id: &Option<ObjectId>) -> bool {
+    id.as_ref().is_some_and(|id| ids.contains(id))
+}
+
+fn add_once(list: &mut Vec<ObjectId>, item: &ObjectId) {
+    if !list.contains(item) {
+        list.push(item.clone());
+    }
+}
+
+fn is_live(session: &Session, now: i64) -> bool {
+    !session.revoked && session.expires_at > now
+}
+/**
+ * Mirror the unique indexes of the MongoDB models
+ */
+fn ensure_unique<T>(
+    docs: &BTreeMap<ObjectId, T>,
+    what: &str,
+    value: &str,
+    field: impl Fn(&T) -> &String,
+) -> Result<(), ControllerError> {
+    if docs.values().any(|doc| field(doc) == value) {
+        return Err(already_exists(what, value));
+    }
+
+    Ok(())
+}
+
+fn already_exists(what: &str, value: &str) -> ControllerError {
+    ControllerError::AlreadyExists(format!("The {} {} already exists", what, value))
+}
@@ -0,0 +1,352 @@
+pub mod memory;
+pub mod mongo;
+pub mod sqlite;
+
+use crate::config::{Config, MetadataBackend};
+use crate::controller::error::ControllerError;
+use crate::data_models::{
+    access_group::AccessGroup,
+    asset::{Asset, Integrity},
+    blob::Blob,
+    folder::Folder,
+    key::Key,
+    session::Session,
+    user::User,
+};
+use async_trait::async_trait;
+use std::sync::Arc;
+use wither::mongodb::bson::oid::ObjectId;
+
+/**
+ * A metadata store shared between every request handler
+ */
+pub type SharedStore = Arc<dyn MetaStore>;
+
+/**
+ * ____________________________________________________________________________________________
+ * UserList, the lists of ObjectIds a User holds
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserList {
+    Keys,
+    KeyAdmins,
+    UserAdmins,
+    FolderAdmins,
+    AccessGroupAdmins,
+}
+
+impl UserList {
+    /**
+     * Name of the field holding the list
+     */
+    pub fn field(&self) -> &'static str {
+        match self {
+            UserList::Keys => "keys",
+            UserList::KeyAdmins => "key_admins",
+            UserList::UserAdmins => "user_admins",
+            UserList::FolderAdmins => "folder_admins",
+            UserList::AccessGroupAdmins => "access_group_admins",
+        }
+    }
+    /**
+     * The list itself out of user
+     */
+    pub fn of(self, user: &mut User) -> &mut Vec<ObjectId> {
+        match self {
+            UserList::Keys => &mut user.keys,
+            UserList::KeyAdmins => &mut user.key_admins,
+            UserList::UserAdmins => &mut user.user_admins,
+            UserList::FolderAdmins => &mut user.folder_admins,
+            UserList::AccessGroupAdmins => &mut user.access_group_admins,
+        }
+    }
+}
+
+/**
+ * ____________________________________________________________________________________________
+ * FolderList, the lists of ObjectIds a Folder holds
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FolderList {
+    Files,
+    AccessGroups,
+}
+
+impl FolderList {
+    /**
+     * Name of the field holding the list
+     */
+    pub fn field(&self) -> &'static str {
+        match self {
+            FolderList::Files => "files",
+            FolderList::AccessGroups => "access_groups",
+        }
+    }
+    /**
+     * The list itself out of folder
+     */
+    pub fn of(self, folder: &mut Folder) -> &mut Vec<ObjectId> {
+        match self {
+            FolderList::Files => &mut folder.files,
+            FolderList::AccessGroups => &mut folder.access_groups,
+        }
+    }
+}
+
+/**
+ * Every read and write the controllers do on metadata, so they don't depend on one database.
+ *
+ * Inserts hand the doc back with its ObjectId set and fail with AlreadyExists when a unique
+ * field (username, folder tag and path, asset path and uuid, key uuid, access group tag, session
+ * token hash) is taken. Adding to a list never adds an ObjectId twice. Updates hand back the doc as
+ * it is after the update, or None if there is no doc to update
+ */
+#[async_trait]
+pub trait MetaStore: Send + Sync {
+    /**
+     * Create whatever the store needs (indexes, tables), safe to call on every start
+     */
+    async fn prepare(&self) -> Result<(), ControllerError>;
+
+    // Users
+    async fn insert_user(&self, user: User) -> Result<User, ControllerError>;
+    async fn find_user(&self, id: &ObjectId) -> Result<Option<User>, ControllerError>;
+    async fn find_user_by_name(&self, user: &str) -> Result<Option<User>, ControllerError>;
+    /**
+     * Every user, or only the ones out of ids
+     */
+    async fn find_users(&self, ids: Option<&[ObjectId]>) -> Result<Vec<User>, ControllerError>;
+    async fn count_users(&self) -> Result<u64, ControllerError>;
+    async fn set_user_pass(&self, id: &ObjectId, pass: &str) -> Result<bool, ControllerError>;
+    async fn add_to_user_list(
+        &self,
+        id: &ObjectId,
+        list: UserList,
+        item: &ObjectId,
+    ) -> Result<Option<User>, ControllerError>;
+    async fn remove_from_user_list(
+        &self,
+        id: &ObjectId,
+        list: UserList,
+        item: &ObjectId,
+    ) -> Result<Option<User>, ControllerError>;
+    /**
+     * Remove every one of items from list of every user
+     */
+    async fn remove_from_all_user_lists(
+        &self,
+        list: UserList,
+        items: &[ObjectId],
+    ) -> Result<(), ControllerError>;
+    async fn delete_user(&self, id: &ObjectId) -> Result<bool, ControllerError>;
+
+    // Folders
+    async fn insert_folder(&self, folder: Folder) -> Result<Folder, ControllerError>;
+    async fn find_folder(&self, id: &ObjectId) -> Result<Option<Folder>, ControllerError>;
+    async fn find_folder_by_path(&self, path: &str) -> Result<Option<Folder>, ControllerError>;
+    async fn find_folder_by_tag(&self, tag: &str) -> Result<Option<Folder>, ControllerError>;
+    async fn find_folders_by_paths(&self, paths: &[String])
+        -> Result<Vec<Folder>, ControllerError>;
+    /**
+     * Every folder at any depth below path, so every folder whose path starts with "path/"
+     */
+    async fn find_folders_below(&self, path: &str) -> Result<Vec<Folder>, ControllerError>;
+    async fn add_to_folder_list(
+        &self,
+        id: &ObjectId,
+        list: FolderList,
+        item: &ObjectId,
+    ) -> Result<bool, ControllerError>;
+    /**
+     * Remove every one of items from list of every folder
+     */
+    async fn remove_from_all_folder_lists(
+        &self,
+        list: FolderList,
+        items: &[ObjectId],
+    ) -> Result<(), ControllerError>;
+    async fn set_folder_access(
+        &self,
+        id: &ObjectId,
+        is_public: bool,
+        access_groups: &[ObjectId],
+    ) -> Result<bool, ControllerError>;
+    /**
+     * Give folder id the tag and path to_path, and rewrite the path of every folder and asset below
+     * path to start with to_path instead, all or nothing. The MongoDB store rolls back by hand and
+     * can leave a half moved subtree behind if it dies halfway
+     */
+    async fn move_folder(
+        &self,
+        id: &ObjectId,
+        tag: &str,
+        path: &str,
+        to_path: &str,
+    ) -> Result<Option<Folder>, ControllerError>;
+    async fn delete_folders(&self, ids: &[ObjectId]) -> Result<(), ControllerError>;
+
+    // Assets
+    async fn insert_asset(&self, asset: Asset) -> Result<Asset, ControllerError>;
+    async fn find_asset(&self, id: &ObjectId) -> Result<Option<Asset>, ControllerError>;
+    async fn find_asset_by_uuid(&self, uuid: &str) -> Result<Option<Asset>, ControllerError>;
+    async fn find_asset_by_path(&self, path: &str) -> Result<Option<Asset>, ControllerError>;
+    async fn find_assets_in_folder(&self, folder: &ObjectId)
+        -> Result<Vec<Asset>, ControllerError>;
+    /**
+     * Up to limit assets ordered by ObjectId, the ones after after, to walk every asset in pages
+     */
+    async fn find_assets_after(
+        &self,
+        after: Option<&ObjectId>,
+        limit: usize,
+    ) -> Result<Vec<Asset>, ControllerError>;
+    /**
+     * Record the size and checksum of the bytes of id, and the blob holding them if they are shared
+     */
+    async fn set_asset_checksum(
+        &self,
+        id: &ObjectId,
+        size: i64,
+        checksum: &str,
+        blob: Option<&str>,
+    ) -> Result<bool, ControllerError>;
+    async fn set_asset_integrity(
+        &self,
+        id: &ObjectId,
+        integrity: Integrity,
+        verified_at: i64,
+    ) -> Result<bool, ControllerError>;
+    /**
+     * Give asset id the tag and path, and move it from the files of folder to those of to_folder
+     */
+    async fn move_asset(
+        &self,
+        id: &ObjectId,
+        folder: &ObjectId,
+        to_folder: &ObjectId,
+        tag: &str,
+        path: &str,
+    ) -> Result<Option<Asset>, ControllerError>;
+    async fn delete_asset(&self, id: &ObjectId) -> Result<bool, ControllerError>;
+    async fn delete_assets_in_folders(&self, folders: &[ObjectId]) -> Result<(), ControllerError>;
+
+    // Blobs
+    async fn find_blob(&self, checksum: &str) -> Result<Option<Blob>, ControllerError>;
+    /**
+     * Add a reference to the blob with the checksum of blob, inserting blob with one reference if
+     * there is none yet, returning whether it already existed. Either way it is touched at the
+     * touched_at of blob
+     */
+    async fn acquire_blob(&self, blob: Blob) -> Result<bool, ControllerError>;
+    /**
+     * Drop a reference to the blob checksum touching it at now, deleting it when that was the last
+     * one, returning whether it was deleted
+     */
+    async fn release_blob(&self, checksum: &str, now: i64) -> Result<bool, ControllerError>;
+    async fn find_blobs(&self) -> Result<Vec<Blob>, ControllerError>;
+    /**
+     * Set the references of the blob with the checksum of blob to its refs and touched_at, only
+     * while it still has the refs and touched_at of seen, or still doesn't exist if seen is None.
+     * A blob set to no references is deleted. Returns whether it was changed
+     */
+    async fn recount_blob(&self, blob: Blob, seen: Option<&Blob>) -> Result<bool, ControllerError>;
+    /**
+     * The number of assets pointing at the blob checksum
+     */
+    async fn count_blob_assets(&self, checksum: &str) -> Result<u64, ControllerError>;
+
+    // Keys
+    async fn insert_key(&self, key: Key) -> Result<Key, ControllerError>;
+    async fn find_key(&self, id: &ObjectId) -> Result<Option<Key>, ControllerError>;
+    async fn find_key_by_uuid(&self, uuid: &str) -> Result<Option<Key>, ControllerError>;
+    /**
+     * The keys out of ids that are active
+     */
+    async fn find_active_keys(&self, ids: &[ObjectId]) -> Result<Vec<Key>, ControllerError>;
+    async fn set_key_active(
+        &self,
+        id: &ObjectId,
+        active: bool,
+    ) -> Result<Option<Key>, ControllerError>;
+    async fn delete_key(&self, id: &ObjectId) -> Result<bool, ControllerError>;
+
+    // Access groups
+    async fn insert_access_group(
+        &self,
+        access_group: AccessGroup,
+    ) -> Result<AccessGroup, ControllerError>;
+    async fn find_access_group(
+        &self,
+        id: &ObjectId,
+    ) -> Result<Option<AccessGroup>, ControllerError>;
+    async fn find_access_group_by_tag(
+        &self,
+        tag: &str,
+    ) -> Result<Option<AccessGroup>, ControllerError>;
+    async fn find_access_groups(&self) -> Result<Vec<AccessGroup>, ControllerError>;
+    /**
+     * Whether any of the access groups out of ids allows any of keys
+     */
+    async fn any_access_group_allows(
+        &self,
+        ids: &[ObjectId],
+        keys: &[ObjectId],
+    ) -> Result<bool, ControllerError>;
+    async fn set_access_group_tag(
+        &self,
+        id: &ObjectId,
+        tag: &str,
+    ) -> Result<Option<AccessGroup>, ControllerError>;
+    async fn add_allowed_key(
+        &self,
+        id: &ObjectId,
+        key: &ObjectId,
+    ) -> Result<Option<AccessGroup>, ControllerError>;
+    async fn remove_allowed_key(
+        &self,
+        id: &ObjectId,
+        key: &ObjectId,
+    ) -> Result<Option<AccessGroup>, ControllerError>;
+    /**
+     * Remove every one of keys from the allowed keys of every access group
+     */
+    async fn remove_allowed_keys_from_all(&self, keys: &[ObjectId]) -> Result<(), ControllerError>;
+    async fn delete_access_group(&self, id: &ObjectId) -> Result<bool, ControllerError>;
+
+    // Sessions, live ones are neither revoked nor expired at now
+    async fn insert_session(&self, session: Session) -> Result<Session, ControllerError>;
+    async fn find_live_session(
+        &self,
+        token_hash: &str,
+        now: i64,
+    ) -> Result<Option<Session>, ControllerError>;
+    async fn find_live_sessions_of(
+        &self,
+        user: &ObjectId,
+        now: i64,
+    ) -> Result<Vec<Session>, ControllerError>;
+    async fn revoke_session_by_hash(&self, token_hash: &str) -> Result<(), ControllerError>;
+    /**
+     * Revoke the session id, but only if it belongs to user
+     */
+    async fn revoke_session(&self, id: &ObjectId, user: &ObjectId)
+        -> Result<bool, ControllerError>;
+    async fn revoke_sessions_of(&self, user: &ObjectId) -> Result<(), ControllerError>;
+    async fn delete_sessions_of(&self, user: &ObjectId) -> Result<(), ControllerError>;
+}
+/**
+ * Open the metadata store picked by the config
+ */
+pub async fn open_store(config: &Config) -> Result<SharedStore, ControllerError> {
+    let store: SharedStore = match config.metadata_backend {
+        MetadataBackend::Mongo => {
+            Arc::new(mongo::MongoStore::connect(&config.mongo_uri, &config.db_name).await?)
+        }
+        MetadataBackend::Sqlite => Arc::new(sqlite::SqliteStore::open(&config.sqlite_path)?),
+        MetadataBackend::Memory => Arc::new(memory::MemoryStore::new()),
+    };
+
+    Ok(store)
+}
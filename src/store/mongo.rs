@@ -0,0 +1,751 @@
+use crate::controller::error::ControllerError;
+use crate::data_models::{
+    access_group::AccessGroup,
+    asset::{Asset, Integrity},
+    blob::Blob,
+    folder::Folder,
+    key::Key,
+    session::Session,
+    user::User,
+};
+use crate::store::{FolderList, MetaStore, UserList};
+use crate::util::escape_regex;
+use async_trait::async_trait;
+use futures::stream::TryStreamExt;
+use wither::{
+    mongodb::{
+        bson::{doc, oid::ObjectId, Bson, Document},
+        options::{
+            FindOneAndUpdateOptions, FindOptions, ReturnDocument, UpdateModifications,
+            UpdateOptions,
+        },
+        Client, Database,
+    },
+    Model,
+};
+
+/**
+ * ____________________________________________________________________________________________
+ * MongoStore, metadata kept in MongoDB through the wither models
+ * ____________________________________________________________________________________________
+ * db: The MongoDB database holding every collection
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug, Clone)]
+pub struct MongoStore {
+    db: Database,
+}
+
+impl MongoStore {
+    /**
+     * Connect to the MongoDB server at uri and use the database db_name
+     */
+    pub async fn connect(uri: &str, db_name: &str) -> Result<MongoStore, ControllerError> {
+        let client = Client::with_uri_str(uri).await?;
+
+        Ok(MongoStore::new(client.database(db_name)))
+    }
+
+    pub fn new(db: Database) -> MongoStore {
+        MongoStore { db }
+    }
+}
+
+#[async_trait]
+impl MetaStore for MongoStore {
+    async fn prepare(&self) -> Result<(), ControllerError> {
+        Key::sync(&self.db).await?;
+        User::sync(&self.db).await?;
+        Asset::sync(&self.db).await?;
+        Folder::sync(&self.db).await?;
+        AccessGroup::sync(&self.db).await?;
+        Session::sync(&self.db).await?;
+        Blob::sync(&self.db).await?;
+
+        Ok(())
+    }
+
+    async fn insert_user(&self, user: User) -> Result<User, ControllerError> {
+        insert(&self.db, user).await
+    }
+
+    async fn find_user(&self, id: &ObjectId) -> Result<Option<User>, ControllerError> {
+        find_one(&self.db, doc! { "_id": id }).await
+    }
+
+    async fn find_user_by_name(&self, user: &str) -> Result<Option<User>, ControllerError> {
+        find_one(&self.db, doc! { "user": user }).await
+    }
+
+    async fn find_users(&self, ids: Option<&[ObjectId]>) -> Result<Vec<User>, ControllerError> {
+        let filter = match ids {
+            Some(ids) => doc! { "_id": { "$in": ids } },
+            None => doc! {},
+        };
+
+        find(&self.db, filter).await
+    }
+
+    async fn count_users(&self) -> Result<u64, ControllerError> {
+        let count = User::collection(&self.db)
+            .count_documents(doc! {}, None)
+            .await?;
+
+        Ok(count.max(0) as u64)
+    }
+
+    async fn set_user_pass(&self, id: &ObjectId, pass: &str) -> Result<bool, ControllerError> {
+        update_one::<User>(
+            &self.db,
+            doc! { "_id": id },
+            doc! { "$set": { "pass": pass } },
+        )
+        .await
+    }
+
+    async fn add_to_user_list(
+        &self,
+        id: &ObjectId,
+        list: UserList,
+        item: &ObjectId,
+    ) -> Result<Option<User>, ControllerError> {
+        let update = doc! { "$addToSet": { list.field(): item } };
+        find_one_and_update(&self.db, id, update).await
+    }
+
+    async fn remove_from_user_list(
+        &self,
+        id: &ObjectId,
+        list: UserList,
+        item: &ObjectId,
+    ) -> Result<Option<User>, ControllerError> {
+        let update = doc! { "$pull": { list.field(): item } };
+        find_one_and_update(&self.db, id, update).await
+    }
+
+    async fn remove_from_all_user_lists(
+        &self,
+        list: UserList,
+        items: &[ObjectId],
+    ) -> Result<(), ControllerError> {
+        pull_from_all::<User>(&self.db, list.field(), items).await
+    }
+
+    async fn delete_user(&self, id: &ObjectId) -> Result<bool, ControllerError> {
+        delete_many::<User>(&self.db, doc! { "_id": id }).await
+    }
+
+    async fn insert_folder(&self, folder: Folder) -> Result<Folder, ControllerError> {
+        insert(&self.db, folder).await
+    }
+
+    async fn find_folder(&self, id: &ObjectId) -> Result<Option<Folder>, ControllerError> {
+        find_one(&self.db, doc! { "_id": id }).await
+    }
+
+    async fn find_folder_by_path(&self, path: &str) -> Result<Option<Folder>, ControllerError> {
+        find_one(&self.db, doc! { "path": path }).await
+    }
+
+    async fn find_folder_by_tag(&self, tag: &str) -> Result<Option<Folder>, ControllerError> {
+        find_one(&self.db, doc! { "tag": tag }).await
+    }
+
+    async fn find_folders_by_paths(
+        &self,
+        paths: &[String],
+    ) -> Result<Vec<Folder>, ControllerError> {
+        find(&self.db, doc! { "path": { "$in": paths } }).await
+    }
+
+    async fn find_folders_below(&self, path: &str) -> Result<Vec<Folder>, ControllerError> {
+        let below_pattern = format!("^{}/", escape_regex(path));
+        find(&self.db, doc! { "path": { "$regex": below_pattern } }).await
+    }
+
+    async fn add_to_folder_list(
+        &self,
+        id: &ObjectId,
+        list: FolderList,
+        item: &ObjectId,
+    ) -> Result<bool, ControllerError> {
+        let update = doc! { "$addToSet": { list.field(): item } };
+        update_one::<Folder>(&self.db, doc! { "_id": id }, update).await
+    }
+
+    async fn remove_from_all_folder_lists(
+        &self,
+        list: FolderList,
+        items: &[ObjectId],
+    ) -> Result<(), ControllerError> {
+        pull_from_all::<Folder>(&self.db, list.field(), items).await
+    }
+
+    async fn set_folder_access(
+        &self,
+        id: &ObjectId,
+        is_public: bool,
+        access_groups: &[ObjectId],
+    ) -> Result<bool, ControllerError> {
+        let update = doc! { "$set": { "is_public": is_public, "access_groups": access_groups } };
+        update_one::<Folder>(&self.db, doc! { "_id": id }, update).await
+    }
+
+    async fn move_folder(
+        &self,
+        id: &ObjectId,
+        tag: &str,
+        path: &str,
+        to_path: &str,
+    ) -> Result<Option<Folder>, ControllerError> {
+        let folder: Folder = match find_one(&self.db, doc! { "_id": id }).await? {
+            Some(folder) => folder,
+            None => return Ok(None),
+        };
+
+        // The 1.2 driver has no sessions and so no transactions: the unique path of the folder itself
+        // is claimed first and a failure further on moves everything back, but a process dying in
+        // between leaves the subtree half moved for fsck to find
+        let claimed = update_one::<Folder>(
+            &self.db,
+            doc! { "_id": id },
+            doc! { "$set": { "tag": tag, "path": to_path } },
+        )
+        .await?;
+        if !claimed {
+            return Ok(None);
+        }
+
+        let moved = match rewrite_path_prefix::<Folder>(&self.db, path, to_path).await {
+            Ok(()) => rewrite_path_prefix::<Asset>(&self.db, path, to_path).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = moved {
+            let _ = rewrite_path_prefix::<Asset>(&self.db, to_path, path).await;
+            let _ = rewrite_path_prefix::<Folder>(&self.db, to_path, path).await;
+            let _ = update_one::<Folder>(
+                &self.db,
+                doc! { "_id": id },
+                doc! { "$set": { "tag": &folder.tag, "path": &folder.path } },
+            )
+            .await;
+            return Err(err);
+        }
+
+        Ok(Some(Folder {
+            tag: tag.to_string(),
+            path: to_path.to_string(),
+            ..folder
+        }))
+    }
+
+    async fn delete_folders(&self, ids: &[ObjectId]) -> Result<(), ControllerError> {
+        delete_many::<Folder>(&self.db, doc! { "_id": { "$in": ids } }).await?;
+
+        Ok(())
+    }
+
+    async fn insert_asset(&self, asset: Asset) -> Result<Asset, ControllerError> {
+        insert(&self.db, asset).await
+    }
+
+    async fn find_asset(&self, id: &ObjectId) -> Result<Option<Asset>, ControllerError> {
+        find_one(&self.db, doc! { "_id": id }).await
+    }
+
+    async fn find_asset_by_uuid(&self, uuid: &str) -> Result<Option<Asset>, ControllerError> {
+        find_one(&self.db, doc! { "uuid": uuid }).await
+    }
+
+    async fn find_asset_by_path(&self, path: &str) -> Result<Option<Asset>, ControllerError> {
+        find_one(&self.db, doc! { "path": path }).await
+    }
+
+    async fn find_assets_in_folder(
+        &self,
+        folder: &ObjectId,
+    ) -> Result<Vec<Asset>, ControllerError> {
+        find(&self.db, doc! { "folder_id": folder }).await
+    }
+
+    async fn find_assets_after(
+        &self,
+        after: Option<&ObjectId>,
+        limit: usize,
+    ) -> Result<Vec<Asset>, ControllerError> {
+        let filter = match after {
+            Some(after) => doc! { "_id": { "$gt": after } },
+            None => doc! {},
+        };
+        let options = FindOptions::builder()
+            .sort(doc! { "_id": 1 })
+            .limit(limit as i64)
+            .build();
+        let assets: Vec<Asset> = Asset::find(&self.db, filter, options)
+            .await?
+            .try_collect()
+            .await?;
+
+        Ok(assets)
+    }
+
+    async fn set_asset_checksum(
+        &self,
+        id: &ObjectId,
+        size: i64,
+        checksum: &str,
+        blob: Option<&str>,
+    ) -> Result<bool, ControllerError> {
+        let blob = match blob {
+            Some(blob) => Bson::String(blob.to_string()),
+            None => Bson::Null,
+        };
+        update_one::<Asset>(
+            &self.db,
+            doc! { "_id": id },
+            doc! { "$set": { "size": size, "checksum": checksum, "blob": blob } },
+        )
+        .await
+    }
+
+    async fn set_asset_integrity(
+        &self,
+        id: &ObjectId,
+        integrity: Integrity,
+        verified_at: i64,
+    ) -> Result<bool, ControllerError> {
+        update_one::<Asset>(
+            &self.db,
+            doc! { "_id": id },
+            doc! { "$set": { "integrity": integrity.as_str(), "verified_at": verified_at } },
+        )
+        .await
+    }
+
+    async fn move_asset(
+        &self,
+        id: &ObjectId,
+        folder: &ObjectId,
+        to_folder: &ObjectId,
+        tag: &str,
+        path: &str,
+    ) -> Result<Option<Asset>, ControllerError> {
+        let update = doc! { "$set": { "folder_id": to_folder, "tag": tag, "path": path } };
+        let asset: Option<Asset> = find_one_and_update(&self.db, id, update).await?;
+        if asset.is_some() {
+            update_one::<Folder>(
+                &self.db,
+                doc! { "_id": folder },
+                doc! { "$pull": { "files": id } },
+            )
+            .await?;
+            update_one::<Folder>(
+                &self.db,
+                doc! { "_id": to_folder },
+                doc! { "$addToSet": { "files": id } },
+            )
+            .await?;
+        }
+
+        Ok(asset)
+    }
+
+    async fn delete_asset(&self, id: &ObjectId) -> Result<bool, ControllerError> {
+        delete_many::<Asset>(&self.db, doc! { "_id": id }).await
+    }
+
+    async fn delete_assets_in_folders(&self, folders: &[ObjectId]) -> Result<(), ControllerError> {
+        delete_many::<Asset>(&self.db, doc! { "folder_id": { "$in": folders } }).await?;
+
+        Ok(())
+    }
+
+    async fn find_blob(&self, checksum: &str) -> Result<Option<Blob>, ControllerError> {
+        find_one(&self.db, doc! { "checksum": checksum }).await
+    }
+
+    async fn acquire_blob(&self, blob: Blob) -> Result<bool, ControllerError> {
+        // One upsert, so uploads of the same bytes racing each other end up with one blob
+        let options = FindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(ReturnDocument::Before)
+            .build();
+        let update = doc! {
+            "$inc": { "refs": 1 },
+            "$set": { "touched_at": blob.touched_at },
+            "$setOnInsert": {
+                "size": blob.size,
+                "timestamp": blob.timestamp,
+                "timestamp_readable": blob.timestamp_readable,
+            },
+        };
+        let existing = Blob::find_one_and_update(
+            &self.db,
+            doc! { "checksum": blob.checksum },
+            update,
+            options,
+        )
+        .await?;
+
+        Ok(existing.is_some())
+    }
+
+    async fn release_blob(&self, checksum: &str, now: i64) -> Result<bool, ControllerError> {
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        let released = Blob::find_one_and_update(
+            &self.db,
+            doc! { "checksum": checksum },
+            doc! { "$inc": { "refs": -1 }, "$set": { "touched_at": now } },
+            options,
+        )
+        .await?;
+
+        // Only delete it while it is still unreferenced, an upload may have picked it up again
+        match released {
+            Some(blob) if blob.refs <= 0 => {
+                delete_many::<Blob>(
+                    &self.db,
+                    doc! { "checksum": checksum, "refs": { "$lte": 0 } },
+                )
+                .await
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn find_blobs(&self) -> Result<Vec<Blob>, ControllerError> {
+        find(&self.db, doc! {}).await
+    }
+
+    async fn recount_blob(&self, blob: Blob, seen: Option<&Blob>) -> Result<bool, ControllerError> {
+        let seen = match seen {
+            Some(seen) => seen,
+            None if blob.refs <= 0 => return Ok(false),
+            None => {
+                // Only inserts, a blob acquired in the meantime is left as it is
+                let options = UpdateOptions::builder().upsert(true).build();
+                let update = doc! {
+                    "$setOnInsert": {
+                        "size": blob.size,
+                        "refs": blob.refs,
+                        "touched_at": blob.touched_at,
+                        "timestamp": blob.timestamp,
+                        "timestamp_readable": blob.timestamp_readable,
+                    },
+                };
+                let update_result = Blob::collection(&self.db)
+                    .update_one(doc! { "checksum": blob.checksum }, update, options)
+                    .await?;
+                return Ok(update_result.upserted_id.is_some());
+            }
+        };
+
+        // Blobs written before touched_at was recorded don't have it
+        let touched_at = match seen.touched_at {
+            0 => Bson::from(doc! { "$in": [0_i64, Bson::Null] }),
+            touched_at => Bson::from(touched_at),
+        };
+        let filter = doc! {
+            "checksum": blob.checksum,
+            "refs": seen.refs,
+            "touched_at": touched_at,
+        };
+        match blob.refs {
+            refs if refs <= 0 => delete_many::<Blob>(&self.db, filter).await,
+            refs => {
+                let update = doc! { "$set": { "refs": refs, "touched_at": blob.touched_at } };
+                update_one::<Blob>(&self.db, filter, update).await
+            }
+        }
+    }
+
+    async fn count_blob_assets(&self, checksum: &str) -> Result<u64, ControllerError> {
+        let count = Asset::collection(&self.db)
+            .count_documents(doc! { "blob": checksum }, None)
+            .await?;
+
+        Ok(count.max(0) as u64)
+    }
+
+    async fn insert_key(&self, key: Key) -> Result<Key, ControllerError> {
+        insert(&self.db, key).await
+    }
+
+    async fn find_key(&self, id: &ObjectId) -> Result<Option<Key>, ControllerError> {
+        find_one(&self.db, doc! { "_id": id }).await
+    }
+
+    async fn find_key_by_uuid(&self, uuid: &str) -> Result<Option<Key>, ControllerError> {
+        find_one(&self.db, doc! { "uuid": uuid }).await
+    }
+
+    async fn find_active_keys(&self, ids: &[ObjectId]) -> Result<Vec<Key>, ControllerError> {
+        find(&self.db, doc! { "_id": { "$in": ids }, "active": true }).await
+    }
+
+    async fn set_key_active(
+        &self,
+        id: &ObjectId,
+        active: bool,
+    ) -> Result<Option<Key>, ControllerError> {
+        find_one_and_update(&self.db, id, doc! { "$set": { "active": active } }).await
+    }
+
+    async fn delete_key(&self, id: &ObjectId) -> Result<bool, ControllerError> {
+        delete_many::<Key>(&self.db, doc! { "_id": id }).await
+    }
+
+    async fn insert_access_group(
+        &self,
+        access_group: AccessGroup,
+    ) -> Result<AccessGroup, ControllerError> {
+        insert(&self.db, access_group).await
+    }
+
+    async fn find_access_group(
+        &self,
+        id: &ObjectId,
+    ) -> Result<Option<AccessGroup>, ControllerError> {
+        find_one(&self.db, doc! { "_id": id }).await
+    }
+
+    async fn find_access_group_by_tag(
+        &self,
+        tag: &str,
+    ) -> Result<Option<AccessGroup>, ControllerError> {
+        find_one(&self.db, doc! { "tag": tag }).await
+    }
+
+    async fn find_access_groups(&self) -> Result<Vec<AccessGroup>, ControllerError> {
+        find(&self.db, doc! {}).await
+    }
+
+    async fn any_access_group_allows(
+        &self,
+        ids: &[ObjectId],
+        keys: &[ObjectId],
+    ) -> Result<bool, ControllerError> {
+        let access_group_doc: Option<AccessGroup> = find_one(
+            &self.db,
+            doc! { "_id": { "$in": ids }, "allowed_keys": { "$in": keys } },
+        )
+        .await?;
+
+        Ok(access_group_doc.is_some())
+    }
+
+    async fn set_access_group_tag(
+        &self,
+        id: &ObjectId,
+        tag: &str,
+    ) -> Result<Option<AccessGroup>, ControllerError> {
+        find_one_and_update(&self.db, id, doc! { "$set": { "tag": tag } }).await
+    }
+
+    async fn add_allowed_key(
+        &self,
+        id: &ObjectId,
+        key: &ObjectId,
+    ) -> Result<Option<AccessGroup>, ControllerError> {
+        find_one_and_update(&self.db, id, doc! { "$addToSet": { "allowed_keys": key } }).await
+    }
+
+    async fn remove_allowed_key(
+        &self,
+        id: &ObjectId,
+        key: &ObjectId,
+    ) -> Result<Option<AccessGroup>, ControllerError> {
+        find_one_and_update(&self.db, id, doc! { "$pull": { "allowed_keys": key } }).await
+    }
+
+    async fn remove_allowed_keys_from_all(&self, keys: &[ObjectId]) -> Result<(), ControllerError> {
+        pull_from_all::<AccessGroup>(&self.db, "allowed_keys", keys).await
+    }
+
+    async fn delete_access_group(&self, id: &ObjectId) -> Result<bool, ControllerError> {
+        delete_many::<AccessGroup>(&self.db, doc! { "_id": id }).await
+    }
+
+    async fn insert_session(&self, session: Session) -> Result<Session, ControllerError> {
+        insert(&self.db, session).await
+    }
+
+    async fn find_live_session(
+        &self,
+        token_hash: &str,
+        now: i64,
+    ) -> Result<Option<Session>, ControllerError> {
+        find_one(
+            &self.db,
+            doc! { "token_hash": token_hash, "revoked": false, "expires_at": { "$gt": now } },
+        )
+        .await
+    }
+
+    async fn find_live_sessions_of(
+        &self,
+        user: &ObjectId,
+        now: i64,
+    ) -> Result<Vec<Session>, ControllerError> {
+        find(
+            &self.db,
+            doc! { "user": user, "revoked": false, "expires_at": { "$gt": now } },
+        )
+        .await
+    }
+
+    async fn revoke_session_by_hash(&self, token_hash: &str) -> Result<(), ControllerError> {
+        update_one::<Session>(
+            &self.db,
+            doc! { "token_hash": token_hash },
+            doc! { "$set": { "revoked": true } },
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn revoke_session(
+        &self,
+        id: &ObjectId,
+        user: &ObjectId,
+    ) -> Result<bool, ControllerError> {
+        update_one::<Session>(
+            &self.db,
+            doc! { "_id": id, "user": user },
+            doc! { "$set": { "revoked": true } },
+        )
+        .await
+    }
+
+    async fn revoke_sessions_of(&self, user: &ObjectId) -> Result<(), ControllerError> {
+        Session::collection(&self.db)
+            .update_many(
+                doc! { "user": user },
+                doc! { "$set": { "revoked": true } },
+                None,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_sessions_of(&self, user: &ObjectId) -> Result<(), ControllerError> {
+        delete_many::<Session>(&self.db, doc! { "user": user }).await?;
+
+        Ok(())
+    }
+}
+/**
+ * Save a new doc, handing it back with its ObjectId
+ */
+async fn insert<T: Model + Send>(db_ref: &Database, mut model: T) -> Result<T, ControllerError> {
+    model.save(db_ref, None).await?;
+    if model.id().is_none() {
+        return Err(ControllerError::Internal(format!(
+            "Was unable to get the {} docs _id field after saving in MongoDB succesfully..",
+            T::COLLECTION_NAME
+        )));
+    }
+
+    Ok(model)
+}
+
+async fn find_one<T: Model + Send>(
+    db_ref: &Database,
+    filter: Document,
+) -> Result<Option<T>, ControllerError> {
+    Ok(T::find_one(db_ref, filter, None).await?)
+}
+
+async fn find<T: Model + Send>(
+    db_ref: &Database,
+    filter: Document,
+) -> Result<Vec<T>, ControllerError> {
+    let docs: Vec<T> = T::find(db_ref, filter, None).await?.try_collect().await?;
+
+    Ok(docs)
+}
+/**
+ * Apply update to the doc id, handing back the doc as it is after the update
+ */
+async fn find_one_and_update<T: Model + Send>(
+    db_ref: &Database,
+    id: &ObjectId,
+    update: Document,
+) -> Result<Option<T>, ControllerError> {
+    let options = FindOneAndUpdateOptions::builder()
+        .return_document(ReturnDocument::After)
+        .build();
+
+    Ok(T::find_one_and_update(db_ref, doc! { "_id": id }, update, options).await?)
+}
+/**
+ * Apply update to the first doc matching filter, returning whether one matched
+ */
+async fn update_one<T: Model>(
+    db_ref: &Database,
+    filter: Document,
+    update: Document,
+) -> Result<bool, ControllerError> {
+    let update_result = T::collection(db_ref)
+        .update_one(filter, update, None)
+        .await?;
+
+    Ok(update_result.matched_count > 0)
+}
+/**
+ * Replace the start path of the path of every doc of the model T below path with to_path
+ */
+async fn rewrite_path_prefix<T: Model>(
+    db_ref: &Database,
+    path: &str,
+    to_path: &str,
+) -> Result<(), ControllerError> {
+    let below_pattern = format!("^{}/", escape_regex(path));
+    let rest = doc! {
+        "$substrCP": ["$path", path.chars().count() as i64, { "$strLenCP": "$path" }]
+    };
+    let pipeline = vec![doc! { "$set": { "path": { "$concat": [to_path, rest] } } }];
+
+    T::collection(db_ref)
+        .update_many(
+            doc! { "path": { "$regex": below_pattern } },
+            UpdateModifications::Pipeline(pipeline),
+            None,
+        )
+        .await?;
+
+    Ok(())
+}
+/**
+ * Delete every doc matching filter, returning whether any was deleted
+ */
+async fn delete_many<T: Model + Send>(
+    db_ref: &Database,
+    filter: Document,
+) -> Result<bool, ControllerError> {
+    let delete_result = T::delete_many(db_ref, filter, None).await?;
+
+    Ok(delete_result.deleted_count > 0)
+}
+/**
+ * Remove every one of ids from the list field of every doc of the model T
+ */
+async fn pull_from_all<T: Model>(
+    db_ref: &Database,
+    field: &str,
+    ids: &[ObjectId],
+) -> Result<(), ControllerError> {
+    T::collection(db_ref)
+        .update_many(
+            doc! { field: { "$in": ids } },
+            doc! { "$pull": { field: { "$in": ids } } },
+            None,
+        )
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,1538 @@
+use crate::controller::error::ControllerError;
+use crate::data_models::{
+    access_group::AccessGroup,
+    asset::{Asset, Integrity},
+    blob::Blob,
+    folder::Folder,
+    key::Key,
+    session::Session,
+    user::User,
+};
+use crate::store::{FolderList, MetaStore, UserList};
+use async_trait::async_trait;
+use rusqlite::types::Type;
+use rusqlite::{params, params_from_iter, Connection, Params, Row};
+use std::sync::{Arc, Mutex};
+use tokio::task;
+use wither::mongodb::bson::oid::ObjectId;
+
+const USER_LISTS: [UserList; 5] = [
+    UserList::Keys,
+    UserList::KeyAdmins,
+    UserList::UserAdmins,
+    UserList::FolderAdmins,
+    UserList::AccessGroupAdmins,
+];
+const FOLDER_LISTS: [FolderList; 2] = [FolderList::Files, FolderList::AccessGroups];
+const ALLOWED_KEYS_TABLE: &str = "access_group_allowed_keys";
+
+/**
+ * Every table of the store, ObjectIds are kept as hex. The ObjectId lists of the models live in
+ * tables of their own, named <model>_<field>, holding one (owner, item) row per entry in the
+ * order the entries were added. Lists are removed together with their owner, but like in MongoDB
+ * an item can outlive the doc it points at
+ */
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS users (
+    id TEXT PRIMARY KEY,
+    user TEXT NOT NULL UNIQUE,
+    pass TEXT NOT NULL,
+    superuser INTEGER NOT NULL,
+    timestamp TEXT NOT NULL,
+    timestamp_readable TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS user_keys (
+    owner TEXT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    item TEXT NOT NULL,
+    UNIQUE (owner, item)
+);
+CREATE INDEX IF NOT EXISTS user_keys_item ON user_keys (item);
+CREATE TABLE IF NOT EXISTS user_key_admins (
+    owner TEXT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    item TEXT NOT NULL,
+    UNIQUE (owner, item)
+);
+CREATE INDEX IF NOT EXISTS user_key_admins_item ON user_key_admins (item);
+CREATE TABLE IF NOT EXISTS user_user_admins (
+    owner TEXT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    item TEXT NOT NULL,
+    UNIQUE (owner, item)
+);
+CREATE INDEX IF NOT EXISTS user_user_admins_item ON user_user_admins (item);
+CREATE TABLE IF NOT EXISTS user_folder_admins (
+    owner TEXT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    item TEXT NOT NULL,
+    UNIQUE (owner, item)
+);
+CREATE INDEX IF NOT EXISTS user_folder_admins_item ON user_folder_admins (item);
+CREATE TABLE IF NOT EXISTS user_access_group_admins (
+    owner TEXT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    item TEXT NOT NULL,
+    UNIQUE (owner, item)
+);
+CREATE INDEX IF NOT EXISTS user_access_group_admins_item ON user_access_group_admins (item);
+
+CREATE TABLE IF NOT EXISTS folders (
+    id TEXT PRIMARY KEY,
+    tag TEXT NOT NULL UNIQUE,
+    path TEXT NOT NULL UNIQUE,
+    is_public INTEGER NOT NULL,
+    timestamp TEXT NOT NULL,
+    timestamp_readable TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS folder_files (
+    owner TEXT NOT NULL REFERENCES folders (id) ON DELETE CASCADE,
+    item TEXT NOT NULL,
+    UNIQUE (owner, item)
+);
+CREATE INDEX IF NOT EXISTS folder_files_item ON folder_files (item);
+CREATE TABLE IF NOT EXISTS folder_access_groups (
+    owner TEXT NOT NULL REFERENCES folders (id) ON DELETE CASCADE,
+    item TEXT NOT NULL,
+    UNIQUE (owner, item)
+);
+CREATE INDEX IF NOT EXISTS folder_access_groups_item ON folder_access_groups (item);
+
+CREATE TABLE IF NOT EXISTS assets (
+    id TEXT PRIMARY KEY,
+    folder_id TEXT NOT NULL,
+    path TEXT NOT NULL UNIQUE,
+    uuid TEXT NOT NULL UNIQUE,
+    tag TEXT NOT NULL,
+    size INTEGER NOT NULL DEFAULT 0,
+    checksum TEXT NOT NULL DEFAULT '',
+    blob TEXT,
+    integrity TEXT NOT NULL DEFAULT 'unverified',
+    verified_at INTEGER,
+    timestamp TEXT NOT NULL,
+    timestamp_readable TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS assets_folder_id ON assets (folder_id);
+
+CREATE TABLE IF NOT EXISTS blobs (
+    id TEXT PRIMARY KEY,
+    checksum TEXT NOT NULL UNIQUE,
+    size INTEGER NOT NULL,
+    refs INTEGER NOT NULL,
+    touched_at INTEGER NOT NULL DEFAULT 0,
+    timestamp TEXT NOT NULL,
+    timestamp_readable TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS keys (
+    id TEXT PRIMARY KEY,
+    uuid TEXT NOT NULL UNIQUE,
+    secret_hash TEXT NOT NULL,
+    active INTEGER NOT NULL,
+    timestamp TEXT NOT NULL,
+    timestamp_readable TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS access_groups (
+    id TEXT PRIMARY KEY,
+    tag TEXT NOT NULL UNIQUE,
+    timestamp TEXT NOT NULL,
+    timestamp_readable TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS access_group_allowed_keys (
+    owner TEXT NOT NULL REFERENCES access_groups (id) ON DELETE CASCADE,
+    item TEXT NOT NULL,
+    UNIQUE (owner, item)
+);
+CREATE INDEX IF NOT EXISTS access_group_allowed_keys_item ON access_group_allowed_keys (item);
+
+CREATE TABLE IF NOT EXISTS sessions (
+    id TEXT PRIMARY KEY,
+    user TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    expires_at INTEGER NOT NULL,
+    expires_at_readable TEXT NOT NULL,
+    revoked INTEGER NOT NULL,
+    timestamp TEXT NOT NULL,
+    timestamp_readable TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS sessions_user ON sessions (user);
+";
+
+/**
+ * Columns added to tables after their first release, added to files that were created without them
+ */
+const ADDED_COLUMNS: [(&str, &str, &str); 6] = [
+    ("assets", "size", "INTEGER NOT NULL DEFAULT 0"),
+    ("assets", "checksum", "TEXT NOT NULL DEFAULT ''"),
+    ("assets", "integrity", "TEXT NOT NULL DEFAULT 'unverified'"),
+    ("assets", "verified_at", "INTEGER"),
+    ("assets", "blob", "TEXT"),
+    ("blobs", "touched_at", "INTEGER NOT NULL DEFAULT 0"),
+];
+
+/**
+ * ____________________________________________________________________________________________
+ * SqliteStore, metadata kept in a single SQLite file, for single node deployments
+ * ____________________________________________________________________________________________
+ * conn: The one connection to the file, queries are short so they run one at a time under the
+ *       lock instead of on a pool, off the runtime on its blocking threads
+ * ____________________________________________________________________________________________
+ */
+#[derive(Debug)]
+pub struct SqliteStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+    /**
+     * Open (or create) the SQLite file at path, creating the schema if it isn't there yet
+     */
+    pub fn open(path: &str) -> Result<SqliteStore, ControllerError> {
+        let conn = Connection::open(path)?;
+
+        // Let the admin tool read and write while a server has the file open
+        conn.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
+
+        SqliteStore::new(conn)
+    }
+
+    pub fn new(conn: Connection) -> Result<SqliteStore, ControllerError> {
+        conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+        conn.execute_batch(SCHEMA)?;
+        add_missing_columns(&conn)?;
+
+        Ok(SqliteStore {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+    /**
+     * Run f with the locked connection on a blocking thread, so a slow query doesn't stall the
+     * runtime. Every write runs in a transaction so poisoning is ignored
+     */
+    async fn run<T, F>(&self, f: F) -> Result<T, ControllerError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, ControllerError> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        task::spawn_blocking(move || {
+            let mut conn = conn.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            f(&mut conn)
+        })
+        .await
+        .map_err(|err| ControllerError::Internal(format!("SQLite query failed to run: {}", err)))?
+    }
+}
+
+#[async_trait]
+impl MetaStore for SqliteStore {
+    async fn prepare(&self) -> Result<(), ControllerError> {
+        // The schema is created when the file is opened
+        Ok(())
+    }
+
+    async fn insert_user(&self, mut user: User) -> Result<User, ControllerError> {
+        self.run(move |conn| {
+            let tx = conn.transaction()?;
+
+            let id = ObjectId::new();
+            tx.execute(
+                "INSERT INTO users (id, user, pass, superuser, timestamp, timestamp_readable)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    id.to_hex(),
+                    user.user,
+                    user.pass,
+                    user.superuser,
+                    user.timestamp,
+                    user.timestamp_readable
+                ],
+            )?;
+            for list in USER_LISTS {
+                insert_list(&tx, &user_list_table(list), &id, list.of(&mut user))?;
+            }
+            tx.commit()?;
+
+            user.id = Some(id);
+            Ok(user)
+        })
+        .await
+    }
+
+    async fn find_user(&self, id: &ObjectId) -> Result<Option<User>, ControllerError> {
+        let id = id.clone();
+        self.run(move |conn| Ok(find_user(conn, &id)?)).await
+    }
+
+    async fn find_user_by_name(&self, user: &str) -> Result<Option<User>, ControllerError> {
+        let user = user.to_string();
+        self.run(move |conn| {
+            let users = select_users(conn, "user = ?1", params![user])?;
+            Ok(users.into_iter().next())
+        })
+        .await
+    }
+
+    async fn find_users(&self, ids: Option<&[ObjectId]>) -> Result<Vec<User>, ControllerError> {
+        let ids = ids.map(<[ObjectId]>::to_vec);
+        self.run(move |conn| {
+            let users = match ids {
+                Some(ids) => {
+                    select_users(conn, &format!("id IN {}", in_list(ids.len())), hex(&ids))?
+                }
+                None => select_users(conn, "1", [])?,
+            };
+
+            Ok(users)
+        })
+        .await
+    }
+
+    async fn count_users(&self) -> Result<u64, ControllerError> {
+        self.run(move |conn| {
+            let count: i64 = conn.query_row("SELECT COUNT(*) FROM users", [], |row| row.get(0))?;
+
+            Ok(count as u64)
+        })
+        .await
+    }
+
+    async fn set_user_pass(&self, id: &ObjectId, pass: &str) -> Result<bool, ControllerError> {
+        let id = id.clone();
+        let pass = pass.to_string();
+        self.run(move |conn| {
+            let changed = conn.execute(
+                "UPDATE users SET pass = ?2 WHERE id = ?1",
+                params![id.to_hex(), pass],
+            )?;
+
+            Ok(changed > 0)
+        })
+        .await
+    }
+
+    async fn add_to_user_list(
+        &self,
+        id: &ObjectId,
+        list: UserList,
+        item: &ObjectId,
+    ) -> Result<Option<User>, ControllerError> {
+        let id = id.clone();
+        let item = item.clone();
+        self.run(move |conn| {
+            if find_user(conn, &id)?.is_none() {
+                return Ok(None);
+            }
+            add_to_list(conn, &user_list_table(list), &id, &item)?;
+
+            Ok(find_user(conn, &id)?)
+        })
+        .await
+    }
+
+    async fn remove_from_user_list(
+        &self,
+        id: &ObjectId,
+        list: UserList,
+        item: &ObjectId,
+    ) -> Result<Option<User>, ControllerError> {
+        let id = id.clone();
+        let item = item.clone();
+        self.run(move |conn| {
+            remove_from_list(conn, &user_list_table(list), &id, &item)?;
+
+            Ok(find_user(conn, &id)?)
+        })
+        .await
+    }
+
+    async fn remove_from_all_user_lists(
+        &self,
+        list: UserList,
+        items: &[ObjectId],
+    ) -> Result<(), ControllerError> {
+        let items = items.to_vec();
+        self.run(move |conn| Ok(remove_from_all_lists(conn, &user_list_table(list), &items)?))
+            .await
+    }
+
+    async fn delete_user(&self, id: &ObjectId) -> Result<bool, ControllerError> {
+        let id = id.clone();
+        self.run(move |conn| {
+            let deleted = conn.execute("DELETE FROM users WHERE id = ?1", params![id.to_hex()])?;
+
+            Ok(deleted > 0)
+        })
+        .await
+    }
+
+    async fn insert_folder(&self, mut folder: Folder) -> Result<Folder, ControllerError> {
+        self.run(move |conn| {
+            let tx = conn.transaction()?;
+
+            let id = ObjectId::new();
+            tx.execute(
+                "INSERT INTO folders (id, tag, path, is_public, timestamp, timestamp_readable)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    id.to_hex(),
+                    folder.tag,
+                    folder.path,
+                    folder.is_public,
+                    folder.timestamp,
+                    folder.timestamp_readable
+                ],
+            )?;
+            for list in FOLDER_LISTS {
+                insert_list(&tx, &folder_list_table(list), &id, list.of(&mut folder))?;
+            }
+            tx.commit()?;
+
+            folder.id = Some(id);
+            Ok(folder)
+        })
+        .await
+    }
+
+    async fn find_folder(&self, id: &ObjectId) -> Result<Option<Folder>, ControllerError> {
+        let id = id.clone();
+        self.run(move |conn| {
+            let folders = select_folders(conn, "id = ?1", params![id.to_hex()])?;
+            Ok(folders.into_iter().next())
+        })
+        .await
+    }
+
+    async fn find_folder_by_path(&self, path: &str) -> Result<Option<Folder>, ControllerError> {
+        let path = path.to_string();
+        self.run(move |conn| {
+            let folders = select_folders(conn, "path = ?1", params![path])?;
+            Ok(folders.into_iter().next())
+        })
+        .await
+    }
+
+    async fn find_folder_by_tag(&self, tag: &str) -> Result<Option<Folder>, ControllerError> {
+        let tag = tag.to_string();
+        self.run(move |conn| {
+            let folders = select_folders(conn, "tag = ?1", params![tag])?;
+            Ok(folders.into_iter().next())
+        })
+        .await
+    }
+
+    async fn find_folders_by_paths(
+        &self,
+        paths: &[String],
+    ) -> Result<Vec<Folder>, ControllerError> {
+        let paths = paths.to_vec();
+        self.run(move |conn| {
+            let condition = format!("path IN {}", in_list(paths.len()));
+            Ok(select_folders(conn, &condition, params_from_iter(paths))?)
+        })
+        .await
+    }
+
+    async fn find_folders_below(&self, path: &str) -> Result<Vec<Folder>, ControllerError> {
+        let path = path.to_string();
+        self.run(move |conn| {
+            // A plain prefix compare, so nothing in path needs escaping like it would for LIKE
+            let prefix = format!("{}/", path);
+            Ok(select_folders(
+                conn,
+                "substr(path, 1, length(?1)) = ?1",
+                params![prefix],
+            )?)
+        })
+        .await
+    }
+
+    async fn add_to_folder_list(
+        &self,
+        id: &ObjectId,
+        list: FolderList,
+        item: &ObjectId,
+    ) -> Result<bool, ControllerError> {
+        let id = id.clone();
+        let item = item.clone();
+        self.run(move |conn| {
+            let exists: bool = conn.query_row(
+                "SELECT EXISTS (SELECT 1 FROM folders WHERE id = ?1)",
+                params![id.to_hex()],
+                |row| row.get(0),
+            )?;
+            if exists {
+                add_to_list(conn, &folder_list_table(list), &id, &item)?;
+            }
+
+            Ok(exists)
+        })
+        .await
+    }
+
+    async fn remove_from_all_folder_lists(
+        &self,
+        list: FolderList,
+        items: &[ObjectId],
+    ) -> Result<(), ControllerError> {
+        let items = items.to_vec();
+        self.run(move |conn| {
+            Ok(remove_from_all_lists(
+                conn,
+                &folder_list_table(list),
+                &items,
+            )?)
+        })
+        .await
+    }
+
+    async fn set_folder_access(
+        &self,
+        id: &ObjectId,
+        is_public: bool,
+        access_groups: &[ObjectId],
+    ) -> Result<bool, ControllerError> {
+        let id = id.clone();
+        let access_groups = access_groups.to_vec();
+        self.run(move |conn| {
+            let tx = conn.transaction()?;
+
+            let changed = tx.execute(
+                "UPDATE folders SET is_public = ?2 WHERE id = ?1",
+                params![id.to_hex(), is_public],
+            )?;
+            if changed == 0 {
+                return Ok(false);
+            }
+
+            let table = folder_list_table(FolderList::AccessGroups);
+            tx.execute(
+                &format!("DELETE FROM {} WHERE owner = ?1", table),
+                params![id.to_hex()],
+            )?;
+            for access_group in access_groups {
+                add_to_list(&tx, &table, &id, &access_group)?;
+            }
+            tx.commit()?;
+
+            Ok(true)
+        })
+        .await
+    }
+
+    async fn move_folder(
+        &self,
+        id: &ObjectId,
+        tag: &str,
+        path: &str,
+        to_path: &str,
+    ) -> Result<Option<Folder>, ControllerError> {
+        let id = id.clone();
+        let tag = tag.to_string();
+        let path = path.to_string();
+        let to_path = to_path.to_string();
+        self.run(move |conn| {
+            let tx = conn.transaction()?;
+
+            let moved = tx.execute(
+                "UPDATE folders SET tag = ?2, path = ?3 WHERE id = ?1",
+                params![id.to_hex(), tag, to_path],
+            )?;
+            if moved == 0 {
+                return Ok(None);
+            }
+
+            // A plain prefix compare and splice, like find_folders_below
+            let prefix = format!("{}/", path);
+            for table in ["folders", "assets"] {
+                let sql = format!(
+                    "UPDATE {} SET path = ?2 || substr(path, length(?1) + 1)
+                     WHERE substr(path, 1, length(?3)) = ?3",
+                    table
+                );
+                tx.execute(&sql, params![path, to_path, prefix])?;
+            }
+            tx.commit()?;
+
+            let folders = select_folders(conn, "id = ?1", params![id.to_hex()])?;
+            Ok(folders.into_iter().next())
+        })
+        .await
+    }
+
+    async fn delete_folders(&self, ids: &[ObjectId]) -> Result<(), ControllerError> {
+        let ids = ids.to_vec();
+        self.run(move |conn| {
+            let sql = format!("DELETE FROM folders WHERE id IN {}", in_list(ids.len()));
+            conn.execute(&sql, hex(&ids))?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn insert_asset(&self, mut asset: Asset) -> Result<Asset, ControllerError> {
+        self.run(move |conn| {
+            let id = ObjectId::new();
+            conn.execute(
+                "INSERT INTO assets (id, folder_id, path, uuid, tag, size, checksum, blob, integrity,
+                                     verified_at, timestamp, timestamp_readable)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                params![
+                    id.to_hex(),
+                    asset.folder_id.to_hex(),
+                    asset.path,
+                    asset.uuid,
+                    asset.tag,
+                    asset.size,
+                    asset.checksum,
+                    asset.blob,
+                    asset.integrity.as_str(),
+                    asset.verified_at,
+                    asset.timestamp,
+                    asset.timestamp_readable
+                ],
+            )?;
+
+            asset.id = Some(id);
+            Ok(asset)
+        })
+        .await
+    }
+
+    async fn find_asset(&self, id: &ObjectId) -> Result<Option<Asset>, ControllerError> {
+        let id = id.clone();
+        self.run(move |conn| {
+            let assets = select(
+                conn,
+                "assets",
+                "id = ?1",
+                params![id.to_hex()],
+                asset_from_row,
+            )?;
+            Ok(assets.into_iter().next())
+        })
+        .await
+    }
+
+    async fn find_asset_by_uuid(&self, uuid: &str) -> Result<Option<Asset>, ControllerError> {
+        let uuid = uuid.to_string();
+        self.run(move |conn| {
+            let assets = select(conn, "assets", "uuid = ?1", params![uuid], asset_from_row)?;
+            Ok(assets.into_iter().next())
+        })
+        .await
+    }
+
+    async fn find_asset_by_path(&self, path: &str) -> Result<Option<Asset>, ControllerError> {
+        let path = path.to_string();
+        self.run(move |conn| {
+            let assets = select(conn, "assets", "path = ?1", params![path], asset_from_row)?;
+            Ok(assets.into_iter().next())
+        })
+        .await
+    }
+
+    async fn find_assets_in_folder(
+        &self,
+        folder: &ObjectId,
+    ) -> Result<Vec<Asset>, ControllerError> {
+        let folder = folder.clone();
+        self.run(move |conn| {
+            Ok(select(
+                conn,
+                "assets",
+                "folder_id = ?1",
+                params![folder.to_hex()],
+                asset_from_row,
+            )?)
+        })
+        .await
+    }
+
+    async fn find_assets_after(
+        &self,
+        after: Option<&ObjectId>,
+        limit: usize,
+    ) -> Result<Vec<Asset>, ControllerError> {
+        let after = after.cloned();
+        self.run(move |conn| {
+            // Hex ObjectIds sort the same as the ObjectIds themselves
+            let after = after.map(|after| after.to_hex()).unwrap_or_default();
+            let mut statement =
+                conn.prepare("SELECT * FROM assets WHERE id > ?1 ORDER BY id LIMIT ?2")?;
+            let assets = statement
+                .query_map(params![after, limit as i64], asset_from_row)?
+                .collect::<rusqlite::Result<Vec<Asset>>>()?;
+
+            Ok(assets)
+        })
+        .await
+    }
+
+    async fn set_asset_checksum(
+        &self,
+        id: &ObjectId,
+        size: i64,
+        checksum: &str,
+        blob: Option<&str>,
+    ) -> Result<bool, ControllerError> {
+        let id = id.clone();
+        let checksum = checksum.to_string();
+        let blob = blob.map(str::to_string);
+        self.run(move |conn| {
+            let changed = conn.execute(
+                "UPDATE assets SET size = ?2, checksum = ?3, blob = ?4 WHERE id = ?1",
+                params![id.to_hex(), size, checksum, blob],
+            )?;
+
+            Ok(changed > 0)
+        })
+        .await
+    }
+
+    async fn set_asset_integrity(
+        &self,
+        id: &ObjectId,
+        integrity: Integrity,
+        verified_at: i64,
+    ) -> Result<bool, ControllerError> {
+        let id = id.clone();
+        self.run(move |conn| {
+            let changed = conn.execute(
+                "UPDATE assets SET integrity = ?2, verified_at = ?3 WHERE id = ?1",
+                params![id.to_hex(), integrity.as_str(), verified_at],
+            )?;
+
+            Ok(changed > 0)
+        })
+        .await
+    }
+
+    async fn move_asset(
+        &self,
+        id: &ObjectId,
+        folder: &ObjectId,
+        to_folder: &ObjectId,
+        tag: &str,
+        path: &str,
+    ) -> Result<Option<Asset>, ControllerError> {
+        let id = id.clone();
+        let folder = folder.clone();
+        let to_folder = to_folder.clone();
+        let tag = tag.to_string();
+        let path = path.to_string();
+        self.run(move |conn| {
+            let tx = conn.transaction()?;
+
+            let moved = tx.execute(
+                "UPDATE assets SET folder_id = ?2, tag = ?3, path = ?4 WHERE id = ?1",
+                params![id.to_hex(), to_folder.to_hex(), tag, path],
+            )?;
+            if moved == 0 {
+                return Ok(None);
+            }
+
+            let files = folder_list_table(FolderList::Files);
+            remove_from_list(&tx, &files, &folder, &id)?;
+            add_to_list(&tx, &files, &to_folder, &id)?;
+            tx.commit()?;
+
+            let assets = select(
+                conn,
+                "assets",
+                "id = ?1",
+                params![id.to_hex()],
+                asset_from_row,
+            )?;
+            Ok(assets.into_iter().next())
+        })
+        .await
+    }
+
+    async fn delete_asset(&self, id: &ObjectId) -> Result<bool, ControllerError> {
+        let id = id.clone();
+        self.run(move |conn| {
+            let deleted = conn.execute("DELETE FROM assets WHERE id = ?1", params![id.to_hex()])?;
+
+            Ok(deleted > 0)
+        })
+        .await
+    }
+
+    async fn delete_assets_in_folders(&self, folders: &[ObjectId]) -> Result<(), ControllerError> {
+        let folders = folders.to_vec();
+        self.run(move |conn| {
+            let sql = format!(
+                "DELETE FROM assets WHERE folder_id IN {}",
+                in_list(folders.len())
+            );
+            conn.execute(&sql, hex(&folders))?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn find_blob(&self, checksum: &str) -> Result<Option<Blob>, ControllerError> {
+        let checksum = checksum.to_string();
+        self.run(move |conn| {
+            let blobs = select(
+                conn,
+                "blobs",
+                "checksum = ?1",
+                params![checksum],
+                blob_from_row,
+            )?;
+            Ok(blobs.into_iter().next())
+        })
+        .await
+    }
+
+    async fn acquire_blob(&self, blob: Blob) -> Result<bool, ControllerError> {
+        self.run(move |conn| {
+            let tx = conn.transaction()?;
+
+            let existed = tx.execute(
+                "UPDATE blobs SET refs = refs + 1, touched_at = ?2 WHERE checksum = ?1",
+                params![blob.checksum, blob.touched_at],
+            )? > 0;
+            if !existed {
+                tx.execute(
+                    "INSERT INTO blobs (id, checksum, size, refs, touched_at, timestamp,
+                                        timestamp_readable)
+                     VALUES (?1, ?2, ?3, 1, ?4, ?5, ?6)",
+                    params![
+                        ObjectId::new().to_hex(),
+                        blob.checksum,
+                        blob.size,
+                        blob.touched_at,
+                        blob.timestamp,
+                        blob.timestamp_readable
+                    ],
+                )?;
+            }
+            tx.commit()?;
+
+            Ok(existed)
+        })
+        .await
+    }
+
+    async fn release_blob(&self, checksum: &str, now: i64) -> Result<bool, ControllerError> {
+        let checksum = checksum.to_string();
+        self.run(move |conn| {
+            let tx = conn.transaction()?;
+
+            tx.execute(
+                "UPDATE blobs SET refs = refs - 1, touched_at = ?2 WHERE checksum = ?1",
+                params![checksum, now],
+            )?;
+            let deleted = tx.execute(
+                "DELETE FROM blobs WHERE checksum = ?1 AND refs <= 0",
+                params![checksum],
+            )?;
+            tx.commit()?;
+
+            Ok(deleted > 0)
+        })
+        .await
+    }
+
+    async fn find_blobs(&self) -> Result<Vec<Blob>, ControllerError> {
+        self.run(move |conn| Ok(select(conn, "blobs", "1", [], blob_from_row)?))
+            .await
+    }
+
+    async fn recount_blob(&self, blob: Blob, seen: Option<&Blob>) -> Result<bool, ControllerError> {
+        let seen = seen.map(|seen| (seen.refs, seen.touched_at));
+        self.run(move |conn| {
+            let changed = match seen {
+                Some((refs, touched_at)) if blob.refs <= 0 => conn.execute(
+                    "DELETE FROM blobs WHERE checksum = ?1 AND refs = ?2 AND touched_at = ?3",
+                    params![blob.checksum, refs, touched_at],
+                )?,
+                Some((refs, touched_at)) => conn.execute(
+                    "UPDATE blobs SET refs = ?4, touched_at = ?5
+                     WHERE checksum = ?1 AND refs = ?2 AND touched_at = ?3",
+                    params![blob.checksum, refs, touched_at, blob.refs, blob.touched_at],
+                )?,
+                None if blob.refs <= 0 => 0,
+                None => conn.execute(
+                    "INSERT OR IGNORE INTO blobs (id, checksum, size, refs, touched_at, timestamp,
+                                                  timestamp_readable)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                    params![
+                        ObjectId::new().to_hex(),
+                        blob.checksum,
+                        blob.size,
+                        blob.refs,
+                        blob.touched_at,
+                        blob.timestamp,
+                        blob.timestamp_readable
+                    ],
+                )?,
+            };
+
+            Ok(changed > 0)
+        })
+        .await
+    }
+
+    async fn count_blob_assets(&self, checksum: &str) -> Result<u64, ControllerError> {
+        let checksum = checksum.to_string();
+        self.run(move |conn| {
+            let count: i64 = conn.query_row(
+                "SELECT COUNT(*) FROM assets WHERE blob = ?1",
+                params![checksum],
+                |row| row.get(0),
+            )?;
+
+            Ok(count as u64)
+        })
+        .await
+    }
+
+    async fn insert_key(&self, mut key: Key) -> Result<Key, ControllerError> {
+        self.run(move |conn| {
+            let id = ObjectId::new();
+            conn.execute(
+                "INSERT INTO keys (id, uuid, secret_hash, active, timestamp, timestamp_readable)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    id.to_hex(),
+                    key.uuid,
+                    key.secret_hash,
+                    key.active,
+                    key.timestamp,
+                    key.timestamp_readable
+                ],
+            )?;
+
+            key.id = Some(id);
+            Ok(key)
+        })
+        .await
+    }
+
+    async fn find_key(&self, id: &ObjectId) -> Result<Option<Key>, ControllerError> {
+        let id = id.clone();
+        self.run(move |conn| Ok(find_key(conn, &id)?)).await
+    }
+
+    async fn find_key_by_uuid(&self, uuid: &str) -> Result<Option<Key>, ControllerError> {
+        let uuid = uuid.to_string();
+        self.run(move |conn| {
+            let keys = select(conn, "keys", "uuid = ?1", params![uuid], key_from_row)?;
+            Ok(keys.into_iter().next())
+        })
+        .await
+    }
+
+    async fn find_active_keys(&self, ids: &[ObjectId]) -> Result<Vec<Key>, ControllerError> {
+        let ids = ids.to_vec();
+        self.run(move |conn| {
+            let condition = format!("active = 1 AND id IN {}", in_list(ids.len()));
+            Ok(select(conn, "keys", &condition, hex(&ids), key_from_row)?)
+        })
+        .await
+    }
+
+    async fn set_key_active(
+        &self,
+        id: &ObjectId,
+        active: bool,
+    ) -> Result<Option<Key>, ControllerError> {
+        let id = id.clone();
+        self.run(move |conn| {
+            conn.execute(
+                "UPDATE keys SET active = ?2 WHERE id = ?1",
+                params![id.to_hex(), active],
+            )?;
+
+            Ok(find_key(conn, &id)?)
+        })
+        .await
+    }
+
+    async fn delete_key(&self, id: &ObjectId) -> Result<bool, ControllerError> {
+        let id = id.clone();
+        self.run(move |conn| {
+            let deleted = conn.execute("DELETE FROM keys WHERE id = ?1", params![id.to_hex()])?;
+
+            Ok(deleted > 0)
+        })
+        .await
+    }
+
+    async fn insert_access_group(
+        &self,
+        mut access_group: AccessGroup,
+    ) -> Result<AccessGroup, ControllerError> {
+        self.run(move |conn| {
+            let tx = conn.transaction()?;
+
+            let id = ObjectId::new();
+            tx.execute(
+                "INSERT INTO access_groups (id, tag, timestamp, timestamp_readable)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    id.to_hex(),
+                    access_group.tag,
+                    access_group.timestamp,
+                    access_group.timestamp_readable
+                ],
+            )?;
+            insert_list(&tx, ALLOWED_KEYS_TABLE, &id, &mut access_group.allowed_keys)?;
+            tx.commit()?;
+
+            access_group.id = Some(id);
+            Ok(access_group)
+        })
+        .await
+    }
+
+    async fn find_access_group(
+        &self,
+        id: &ObjectId,
+    ) -> Result<Option<AccessGroup>, ControllerError> {
+        let id = id.clone();
+        self.run(move |conn| Ok(find_access_group(conn, &id)?))
+            .await
+    }
+
+    async fn find_access_group_by_tag(
+        &self,
+        tag: &str,
+    ) -> Result<Option<AccessGroup>, ControllerError> {
+        let tag = tag.to_string();
+        self.run(move |conn| {
+            let access_groups = select_access_groups(conn, "tag = ?1", params![tag])?;
+            Ok(access_groups.into_iter().next())
+        })
+        .await
+    }
+
+    async fn find_access_groups(&self) -> Result<Vec<AccessGroup>, ControllerError> {
+        self.run(move |conn| Ok(select_access_groups(conn, "1", [])?))
+            .await
+    }
+
+    async fn any_access_group_allows(
+        &self,
+        ids: &[ObjectId],
+        keys: &[ObjectId],
+    ) -> Result<bool, ControllerError> {
+        let ids = ids.to_vec();
+        let keys = keys.to_vec();
+        self.run(move |conn| {
+            let sql = format!(
+                "SELECT EXISTS (SELECT 1 FROM {} WHERE owner IN {} AND item IN {})",
+                ALLOWED_KEYS_TABLE,
+                in_list(ids.len()),
+                in_list(keys.len())
+            );
+            let ids_and_keys = ids.iter().chain(&keys).map(|id| id.to_hex());
+            let allowed = conn.query_row(&sql, params_from_iter(ids_and_keys), |row| row.get(0))?;
+
+            Ok(allowed)
+        })
+        .await
+    }
+
+    async fn set_access_group_tag(
+        &self,
+        id: &ObjectId,
+        tag: &str,
+    ) -> Result<Option<AccessGroup>, ControllerError> {
+        let id = id.clone();
+        let tag = tag.to_string();
+        self.run(move |conn| {
+            conn.execute(
+                "UPDATE access_groups SET tag = ?2 WHERE id = ?1",
+                params![id.to_hex(), tag],
+            )?;
+
+            Ok(find_access_group(conn, &id)?)
+        })
+        .await
+    }
+
+    async fn add_allowed_key(
+        &self,
+        id: &ObjectId,
+        key: &ObjectId,
+    ) -> Result<Option<AccessGroup>, ControllerError> {
+        let id = id.clone();
+        let key = key.clone();
+        self.run(move |conn| {
+            if find_access_group(conn, &id)?.is_none() {
+                return Ok(None);
+            }
+            add_to_list(conn, ALLOWED_KEYS_TABLE, &id, &key)?;
+
+            Ok(find_access_group(conn, &id)?)
+        })
+        .await
+    }
+
+    async fn remove_allowed_key(
+        &self,
+        id: &ObjectId,
+        key: &ObjectId,
+    ) -> Result<Option<AccessGroup>, ControllerError> {
+        let id = id.clone();
+        let key = key.clone();
+        self.run(move |conn| {
+            remove_from_list(conn, ALLOWED_KEYS_TABLE, &id, &key)?;
+
+            Ok(find_access_group(conn, &id)?)
+        })
+        .await
+    }
+
+    async fn remove_allowed_keys_from_all(&self, keys: &[ObjectId]) -> Result<(), ControllerError> {
+        let keys = keys.to_vec();
+        self.run(move |conn| Ok(remove_from_all_lists(conn, ALLOWED_KEYS_TABLE, &keys)?))
+            .await
+    }
+
+    async fn delete_access_group(&self, id: &ObjectId) -> Result<bool, ControllerError> {
+        let id = id.clone();
+        self.run(move |conn| {
+            let deleted = conn.execute(
+                "DELETE FROM access_groups WHERE id = ?1",
+                params![id.to_hex()],
+            )?;
+
+            Ok(deleted > 0)
+        })
+        .await
+    }
+
+    async fn insert_session(&self, mut session: Session) -> Result<Session, ControllerError> {
+        self.run(move |conn| {
+            let id = ObjectId::new();
+            conn.execute(
+                "INSERT INTO sessions (id, user, token_hash, expires_at, expires_at_readable, revoked,
+                    timestamp, timestamp_readable)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    id.to_hex(),
+                    session.user.to_hex(),
+                    session.token_hash,
+                    session.expires_at,
+                    session.expires_at_readable,
+                    session.revoked,
+                    session.timestamp,
+                    session.timestamp_readable
+                ],
+            )?;
+
+            session.id = Some(id);
+            Ok(session)
+        })
+        .await
+    }
+
+    async fn find_live_session(
+        &self,
+        token_hash: &str,
+        now: i64,
+    ) -> Result<Option<Session>, ControllerError> {
+        let token_hash = token_hash.to_string();
+        self.run(move |conn| {
+            let sessions = select(
+                conn,
+                "sessions",
+                "token_hash = ?1 AND revoked = 0 AND expires_at > ?2",
+                params![token_hash, now],
+                session_from_row,
+            )?;
+
+            Ok(sessions.into_iter().next())
+        })
+        .await
+    }
+
+    async fn find_live_sessions_of(
+        &self,
+        user: &ObjectId,
+        now: i64,
+    ) -> Result<Vec<Session>, ControllerError> {
+        let user = user.clone();
+        self.run(move |conn| {
+            Ok(select(
+                conn,
+                "sessions",
+                "user = ?1 AND revoked = 0 AND expires_at > ?2",
+                params![user.to_hex(), now],
+                session_from_row,
+            )?)
+        })
+        .await
+    }
+
+    async fn revoke_session_by_hash(&self, token_hash: &str) -> Result<(), ControllerError> {
+        let token_hash = token_hash.to_string();
+        self.run(move |conn| {
+            conn.execute(
+                "UPDATE sessions SET revoked = 1 WHERE token_hash = ?1",
+                params![token_hash],
+            )?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn revoke_session(
+        &self,
+        id: &ObjectId,
+        user: &ObjectId,
+    ) -> Result<bool, ControllerError> {
+        let id = id.clone();
+        let user = user.clone();
+        self.run(move |conn| {
+            let changed = conn.execute(
+                "UPDATE sessions SET revoked = 1 WHERE id = ?1 AND user = ?2",
+                params![id.to_hex(), user.to_hex()],
+            )?;
+
+            Ok(changed > 0)
+        })
+        .await
+    }
+
+    async fn revoke_sessions_of(&self, user: &ObjectId) -> Result<(), ControllerError> {
+        let user = user.clone();
+        self.run(move |conn| {
+            conn.execute(
+                "UPDATE sessions SET revoked = 1 WHERE user = ?1",
+                params![user.to_hex()],
+            )?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete_sessions_of(&self, user: &ObjectId) -> Result<(), ControllerError> {
+        let user = user.clone();
+        self.run(move |conn| {
+            conn.execute(
+                "DELETE FROM sessions WHERE user = ?1",
+                params![user.to_hex()],
+            )?;
+
+            Ok(())
+        })
+        .await
+    }
+}
+
+fn user_list_table(list: UserList) -> String {
+    format!("user_{}", list.field())
+}
+
+fn folder_list_table(list: FolderList) -> String {
+    format!("folder_{}", list.field())
+}
+/**
+ * Add the ADDED_COLUMNS a file created by an earlier version doesn't have yet
+ */
+fn add_missing_columns(conn: &Connection) -> rusqlite::Result<()> {
+    for (table, column, definition) in ADDED_COLUMNS {
+        let mut statement = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+        let columns = statement
+            .query_map([], |row| row.get::<_, String>("name"))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+
+        if !columns.iter().any(|name| name == column) {
+            conn.execute_batch(&format!(
+                "ALTER TABLE {} ADD COLUMN {} {}",
+                table, column, definition
+            ))?;
+        }
+    }
+
+    Ok(())
+}
+/**
+ * Every row of table matching condition, oldest first
+ */
+fn select<T, P: Params>(
+    conn: &Connection,
+    table: &str,
+    condition: &str,
+    params: P,
+    from_row: fn(&Row) -> rusqlite::Result<T>,
+) -> rusqlite::Result<Vec<T>> {
+    let sql = format!("SELECT * FROM {} WHERE {} ORDER BY id", table, condition);
+    let mut statement = conn.prepare(&sql)?;
+    let rows = statement.query_map(params, from_row)?;
+
+    rows.collect()
+}
+
+fn select_users<P: Params>(
+    conn: &Connection,
+    condition: &str,
+    params: P,
+) -> rusqlite::Result<Vec<User>> {
+    let mut users = select(conn, "users", condition, params, user_from_row)?;
+    for user in &mut users {
+        let id = match &user.id {
+            Some(id) => id.clone(),
+            None => continue,
+        };
+        for list in USER_LISTS {
+            *list.of(user) = read_list(conn, &user_list_table(list), &id)?;
+        }
+    }
+
+    Ok(users)
+}
+
+fn select_folders<P: Params>(
+    conn: &Connection,
+    condition: &str,
+    params: P,
+) -> rusqlite::Result<Vec<Folder>> {
+    let mut folders = select(conn, "folders", condition, params, folder_from_row)?;
+    for folder in &mut folders {
+        let id = match &folder.id {
+            Some(id) => id.clone(),
+            None => continue,
+        };
+        for list in FOLDER_LISTS {
+            *list.of(folder) = read_list(conn, &folder_list_table(list), &id)?;
+        }
+    }
+
+    Ok(folders)
+}
+
+fn select_access_groups<P: Params>(
+    conn: &Connection,
+    condition: &str,
+    params: P,
+) -> rusqlite::Result<Vec<AccessGroup>> {
+    let mut access_groups = select(
+        conn,
+        "access_groups",
+        condition,
+        params,
+        access_group_from_row,
+    )?;
+    for access_group in &mut access_groups {
+        let id = match &access_group.id {
+            Some(id) => id.clone(),
+            None => continue,
+        };
+        access_group.allowed_keys = read_list(conn, ALLOWED_KEYS_TABLE, &id)?;
+    }
+
+    Ok(access_groups)
+}
+
+fn find_user(conn: &Connection, id: &ObjectId) -> rusqlite::Result<Option<User>> {
+    let users = select_users(conn, "id = ?1", params![id.to_hex()])?;
+    Ok(users.into_iter().next())
+}
+
+fn find_key(conn: &Connection, id: &ObjectId) -> rusqlite::Result<Option<Key>> {
+    let keys = select(conn, "keys", "id = ?1", params![id.to_hex()], key_from_row)?;
+    Ok(keys.into_iter().next())
+}
+
+fn find_access_group(conn: &Connection, id: &ObjectId) -> rusqlite::Result<Option<AccessGroup>> {
+    let access_groups = select_access_groups(conn, "id = ?1", params![id.to_hex()])?;
+    Ok(access_groups.into_iter().next())
+}
+/**
+ * The items of the list of owner, in the order they were added
+ */
+fn read_list(conn: &Connection, table: &str, owner: &ObjectId) -> rusqlite::Result<Vec<ObjectId>> {
+    let sql = format!("SELECT item FROM {} WHERE owner = ?1 ORDER BY rowid", table);
+    let mut statement = conn.prepare(&sql)?;
+    let rows = statement.query_map(params![owner.to_hex()], |row| id_at(row, "item"))?;
+
+    rows.collect()
+}
+/**
+ * Store the list of a new doc, dropping repeated items like adding them one by one would
+ */
+fn insert_list(
+    conn: &Connection,
+    table: &str,
+    owner: &ObjectId,
+    items: &mut Vec<ObjectId>,
+) -> rusqlite::Result<()> {
+    let mut unique_items: Vec<ObjectId> = Vec::with_capacity(items.len());
+    for item in items.drain(..) {
+        if !unique_items.contains(&item) {
+            add_to_list(conn, table, owner, &item)?;
+            unique_items.push(item);
+        }
+    }
+    *items = unique_items;
+
+    Ok(())
+}
+
+fn add_to_list(
+    conn: &Connection,
+    table: &str,
+    owner: &ObjectId,
+    item: &ObjectId,
+) -> rusqlite::Result<()> {
+    let sql = format!(
+        "INSERT OR IGNORE INTO {} (owner, item) VALUES (?1, ?2)",
+        table
+    );
+    conn.execute(&sql, params![owner.to_hex(), item.to_hex()])?;
+
+    Ok(())
+}
+
+fn remove_from_list(
+    conn: &Connection,
+    table: &str,
+    owner: &ObjectId,
+    item: &ObjectId,
+) -> rusqlite::Result<()> {
+    let sql = format!("DELETE FROM {} WHERE owner = ?1 AND item = ?2", table);
+    conn.execute(&sql, params![owner.to_hex(), item.to_hex()])?;
+
+    Ok(())
+}
+
+fn remove_from_all_lists(
+    conn: &Connection,
+    table: &str,
+    items: &[ObjectId],
+) -> rusqlite::Result<()> {
+    let sql = format!(
+        "DELETE FROM {} WHERE item IN {}",
+        table,
+        in_list(items.len())
+    );
+    conn.execute(&sql, hex(items))?;
+
+    Ok(())
+}
+/**
+ * Placeholders for an IN of count values, SQLite takes an empty () as matching nothing
+ */
+fn in_list(count: usize) -> String {
+    format!("({})", vec!["?"; count].join(", "))
+}
+
+fn hex(ids: &[ObjectId]) -> impl Params + '_ {
+    params_from_iter(ids.iter().map(|id| id.to_hex()))
+}
+
+fn id_at(row: &Row, column: &str) -> rusqlite::Result<ObjectId> {
+    let value: String = row.get(column)?;
+    ObjectId::with_string(&value).map_err(|err| {
+        let index = row.as_ref().column_index(column).unwrap_or_default();
+        rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(err))
+    })
+}
+
+fn integrity_at(row: &Row, column: &str) -> rusqlite::Result<Integrity> {
+    let value: String = row.get(column)?;
+    value.parse().map_err(|err: String| {
+        let index = row.as_ref().column_index(column).unwrap_or_default();
+        rusqlite::Error::FromSqlConversionFailure(index, Type::Text, err.into())
+    })
+}
+
+fn user_from_row(row: &Row) -> rusqlite::Result<User> {
+    Ok(User {
+        id: Some(id_at(row, "id")?),
+        user: row.get("user")?,
+        pass: row.get("pass")?,
+        keys: vec![],
+        key_admins: vec![],
+        user_admins: vec![],
+        folder_admins: vec![],
+        access_group_admins: vec![],
+        superuser: row.get("superuser")?,
+        timestamp: row.get("timestamp")?,
+        timestamp_readable: row.get("timestamp_readable")?,
+    })
+}
+
+fn folder_from_row(row: &Row) -> rusqlite::Result<Folder> {
+    Ok(Folder {
+        id: Some(id_at(row, "id")?),
+        tag: row.get("tag")?,
+        path: row.get("path")?,
+        files: vec![],
+        is_public: row.get("is_public")?,
+        access_groups: vec![],
+        timestamp: row.get("timestamp")?,
+        timestamp_readable: row.get("timestamp_readable")?,
+    })
+}
+
+fn asset_from_row(row: &Row) -> rusqlite::Result<Asset> {
+    Ok(Asset {
+        id: Some(id_at(row, "id")?),
+        folder_id: id_at(row, "folder_id")?,
+        path: row.get("path")?,
+        uuid: row.get("uuid")?,
+        tag: row.get("tag")?,
+        size: row.get("size")?,
+        checksum: row.get("checksum")?,
+        blob: row.get("blob")?,
+        integrity: integrity_at(row, "integrity")?,
+        verified_at: row.get("verified_at")?,
+        timestamp: row.get("timestamp")?,
+        timestamp_readable: row.get("timestamp_readable")?,
+    })
+}
+
+fn blob_from_row(row: &Row) -> rusqlite::Result<Blob> {
+    Ok(Blob {
+        id: Some(id_at(row, "id")?),
+        checksum: row.get("checksum")?,
+        size: row.get("size")?,
+        refs: row.get("refs")?,
+        touched_at: row.get("touched_at")?,
+        timestamp: row.get("timestamp")?,
+        timestamp_readable: row.get("timestamp_readable")?,
+    })
+}
+
+fn key_from_row(row: &Row) -> rusqlite::Result<Key> {
+    Ok(Key {
+        id: Some(id_at(row, "id")?),
+        uuid: row.get("uuid")?,
+        secret_hash: row.get("secret_hash")?,
+        active: row.get("active")?,
+        timestamp: row.get("timestamp")?,
+        timestamp_readable: row.get("timestamp_readable")?,
+    })
+}
+
+fn access_group_from_row(row: &Row) -> rusqlite::Result<AccessGroup> {
+    Ok(AccessGroup {
+        id: Some(id_at(row, "id")?),
+        tag: row.get("tag")?,
+        allowed_keys: vec![],
+        timestamp: row.get("timestamp")?,
+        timestamp_readable: row.get("timestamp_readable")?,
+    })
+}
+
+fn session_from_row(row: &Row) -> rusqlite::Result<Session> {
+    Ok(Session {
+        id: Some(id_at(row, "id")?),
+        user: id_at(row, "user")?,
+        token_hash: row.get("token_hash")?,
+        expires_at: row.get("expires_at")?,
+        expires_at_readable: row.get("expires_at_readable")?,
+        revoked: row.get("revoked")?,
+        timestamp: row.get("timestamp")?,
+        timestamp_readable: row.get("timestamp_readable")?,
+    })
+}
@@ -1,19 +1,24 @@
+use crate::constants::STREAM_CHUNK_SIZE;
+use bytes::Bytes;
 use chrono::prelude::DateTime;
 use chrono::Utc;
+use futures::stream::{self, Stream};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
 use std::fs::read;
 use std::path::Path;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt};
 use uuid::Uuid;
 /**
  * Helper to get a timestamp in u64(Seconds) format
  */
 pub fn get_timestamp() -> u64 {
-    let timestamp = SystemTime::now()
+    SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
-        .as_secs();
-
-    timestamp
+        .as_secs()
 }
 /**
  * Helper to get human readable timestamp from u64
@@ -21,17 +26,45 @@ pub fn get_timestamp() -> u64 {
 pub fn get_readable_timestamp(tiemstamp: u64) -> String {
     let d = UNIX_EPOCH + Duration::from_secs(tiemstamp);
     let date_time = DateTime::<Utc>::from(d);
-    let readable_timestamp = date_time.format("%Y-%m-%d %H:%M:%S.%f").to_string();
-
-    readable_timestamp
+    date_time.format("%Y-%m-%d %H:%M:%S.%f").to_string()
 }
 /**
  * Helper to get a Uuid v4 in string format
  */
 pub fn get_uuid() -> String {
-    let uuid = Uuid::new_v4().to_string();
+    Uuid::new_v4().to_string()
+}
+/**
+ * Helper to get a random 32 byte token in hex format
+ */
+pub fn get_random_token() -> String {
+    let mut token_bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut token_bytes);
 
-    uuid
+    token_bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+/**
+ * Helper to get the SHA-256 digest of a secret in hex format
+ */
+pub fn get_sha256_hex(secret: &str) -> String {
+    format!("{:x}", Sha256::digest(secret.as_bytes()))
+}
+/**
+ * Helper to escape a string for use as a literal inside of a regex
+ */
+pub fn escape_regex(literal: &str) -> String {
+    let mut escaped = String::with_capacity(literal.len());
+    for character in literal.chars() {
+        if "\\.+*?()|[]{}^$#&-~".contains(character) {
+            escaped.push('\\');
+        }
+        escaped.push(character);
+    }
+
+    escaped
 }
 /**
  * Helper to get file data from a path
@@ -50,6 +83,38 @@ pub fn get_file_data(path: &str) -> Option<Vec<u8>> {
 
     Some(buff)
 }
+/**
+ * Helper to get a stream of chunks over the file at path, without reading it all into memory
+ */
+pub async fn get_file_stream(
+    path: &str,
+) -> Option<impl Stream<Item = Result<Bytes, std::io::Error>> + Unpin> {
+    let file = File::open(path).await.ok()?;
+
+    Some(get_reader_stream(file))
+}
+/**
+ * Helper to turn any async reader into a stream of chunks
+ */
+pub fn get_reader_stream<R>(reader: R) -> impl Stream<Item = Result<Bytes, std::io::Error>> + Unpin
+where
+    R: AsyncRead + Unpin,
+{
+    let reader_stream = stream::unfold(Some(reader), |reader| async move {
+        let mut reader = reader?;
+        let mut buff = vec![0u8; STREAM_CHUNK_SIZE];
+        match reader.read(&mut buff).await {
+            Ok(0) => None,
+            Ok(read_len) => {
+                buff.truncate(read_len);
+                Some((Ok(Bytes::from(buff)), Some(reader)))
+            }
+            Err(err) => Some((Err(err), None)),
+        }
+    });
+
+    Box::pin(reader_stream)
+}
 /**
  * Get a u64 string timestamp and its human readable string counterpart
  */
@@ -0,0 +1,175 @@
+mod common;
+
+use common::{principal, test_config, user};
+use file_server::controller::access::{authorize_folder, Permission, Principal};
+use file_server::controller::auth::{add_key_to_access_group, register_new_access_group};
+use file_server::controller::error::ControllerError;
+use file_server::controller::file_system::{create_folder, create_sub_folder};
+use file_server::controller::key::{assign_key, create_key, set_key_active};
+use file_server::storage::memory::MemoryStorage;
+use file_server::store::MetaStore;
+
+fn allowed(result: Result<(), ControllerError>) -> bool {
+    match result {
+        Ok(()) => true,
+        Err(ControllerError::Forbidden(_)) => false,
+        Err(err) => panic!("Expected an access decision, got {:?}", err),
+    }
+}
+
+async fn anyone_can_read_but_not_write_public_folders(store: impl MetaStore) {
+    let (storage, config) = (MemoryStorage::new(), test_config());
+    let alice = user(&store, &config, "alice").await;
+    let bob = user(&store, &config, "bob").await;
+    let alice = principal(&store, &alice).await;
+    let bob = principal(&store, &bob).await;
+
+    // Top level folders start out public
+    let folder = create_folder(&store, &storage, &config, &alice, "public", None, None)
+        .await
+        .unwrap();
+    assert!(folder.is_public);
+
+    for outsider in [&Principal::Anonymous, &bob] {
+        for permission in [Permission::List, Permission::Read] {
+            assert!(allowed(
+                authorize_folder(&store, outsider, &folder, permission).await
+            ));
+        }
+        for permission in [Permission::Write, Permission::Admin] {
+            assert!(!allowed(
+                authorize_folder(&store, outsider, &folder, permission).await
+            ));
+        }
+    }
+}
+
+async fn access_group_members_can_write_but_not_administrate(store: impl MetaStore) {
+    let (storage, config) = (MemoryStorage::new(), test_config());
+    let alice = user(&store, &config, "alice").await;
+    let bob = user(&store, &config, "bob").await;
+    let carol = user(&store, &config, "carol").await;
+    let alice_principal = principal(&store, &alice).await;
+
+    // Alice makes a private folder for a group, and lets Bob in through one of their keys
+    let group = register_new_access_group(&store, &alice_principal, "team")
+        .await
+        .unwrap();
+    let group_id = group.id.clone().unwrap();
+    let folder = create_folder(
+        &store,
+        &storage,
+        &config,
+        &alice_principal,
+        "private",
+        Some(group_id.clone()),
+        None,
+    )
+    .await
+    .unwrap();
+    assert!(!folder.is_public);
+
+    let key = create_key(&store, &alice_principal).await.unwrap().key;
+    let key_id = key.id.clone().unwrap();
+    add_key_to_access_group(&store, &alice_principal, &group_id, &key_id)
+        .await
+        .unwrap();
+    assign_key(&store, &alice_principal, &key_id, bob.id.as_ref().unwrap())
+        .await
+        .unwrap();
+
+    let bob_principal = principal(&store, &bob).await;
+    for permission in [Permission::List, Permission::Read, Permission::Write] {
+        assert!(allowed(
+            authorize_folder(&store, &bob_principal, &folder, permission).await
+        ));
+    }
+    assert!(!allowed(
+        authorize_folder(&store, &bob_principal, &folder, Permission::Admin).await
+    ));
+
+    // Carol holds no key of the group and the anonymous public can't even list it
+    let carol_principal = principal(&store, &carol).await;
+    for outsider in [&carol_principal, &Principal::Anonymous] {
+        assert!(!allowed(
+            authorize_folder(&store, outsider, &folder, Permission::List).await
+        ));
+    }
+
+    // Revoking the key cuts Bob off right away
+    set_key_active(&store, &alice_principal, &key_id, false)
+        .await
+        .unwrap();
+    assert!(!allowed(
+        authorize_folder(&store, &bob_principal, &folder, Permission::Read).await
+    ));
+}
+
+async fn admins_of_a_folder_administrate_everything_below_it(store: impl MetaStore) {
+    let (storage, config) = (MemoryStorage::new(), test_config());
+    let alice = user(&store, &config, "alice").await;
+    let bob = user(&store, &config, "bob").await;
+    let alice_principal = principal(&store, &alice).await;
+
+    // Alice owns the top folder and lets Bob write in it, Bob creates a folder below it and
+    // becomes its only direct admin
+    let group = register_new_access_group(&store, &alice_principal, "team")
+        .await
+        .unwrap();
+    let group_id = group.id.clone().unwrap();
+    let top = create_folder(
+        &store,
+        &storage,
+        &config,
+        &alice_principal,
+        "top",
+        Some(group_id.clone()),
+        None,
+    )
+    .await
+    .unwrap();
+    let key_id = create_key(&store, &alice_principal)
+        .await
+        .unwrap()
+        .key
+        .id
+        .unwrap();
+    add_key_to_access_group(&store, &alice_principal, &group_id, &key_id)
+        .await
+        .unwrap();
+    assign_key(&store, &alice_principal, &key_id, bob.id.as_ref().unwrap())
+        .await
+        .unwrap();
+
+    let bob_principal = principal(&store, &bob).await;
+    let sub = create_sub_folder(
+        &store,
+        &storage,
+        &config,
+        &bob_principal,
+        &top.path,
+        "sub",
+        None,
+    )
+    .await
+    .unwrap();
+    let sub_id = sub.id.clone().unwrap();
+    let alice_doc = store.find_user(alice.id.as_ref().unwrap()).await.unwrap();
+    assert!(!alice_doc.unwrap().folder_admins.contains(&sub_id));
+
+    assert!(allowed(
+        authorize_folder(&store, &bob_principal, &sub, Permission::Admin).await
+    ));
+    assert!(allowed(
+        authorize_folder(&store, &alice_principal, &sub, Permission::Admin).await
+    ));
+    assert!(!allowed(
+        authorize_folder(&store, &bob_principal, &top, Permission::Admin).await
+    ));
+}
+
+on_every_store!(
+    anyone_can_read_but_not_write_public_folders,
+    access_group_members_can_write_but_not_administrate,
+    admins_of_a_folder_administrate_everything_below_it
+);
@@ -0,0 +1,126 @@
+mod common;
+
+use common::{principal, read, test_config, user};
+use file_server::controller::error::ControllerError;
+use file_server::controller::file_system::{
+    create_folder, delete_asset, delete_folder, get_asset, save_asset,
+};
+use file_server::storage::memory::MemoryStorage;
+use file_server::storage::{asset_key, Storage};
+use file_server::store::MetaStore;
+use sha2::{Digest, Sha256};
+
+async fn saved_assets_can_be_read_and_deleted(store: impl MetaStore) {
+    let (storage, config) = (MemoryStorage::new(), test_config());
+    let alice_user = user(&store, &config, "alice").await;
+    let alice = principal(&store, &alice_user).await;
+
+    let folder = create_folder(&store, &storage, &config, &alice, "pics", None, None)
+        .await
+        .unwrap();
+    let folder_id = folder.id.clone().unwrap();
+    let id = save_asset(
+        &store,
+        &storage,
+        &config,
+        &alice,
+        b"hello".to_vec(),
+        "greeting",
+        &folder_id,
+        "txt",
+    )
+    .await
+    .unwrap();
+
+    // The doc records the bytes, is listed in its folder and points at the stored bytes
+    let asset = get_asset(&store, &alice, &id.to_hex())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(asset.tag, "greeting");
+    assert_eq!(asset.size, 5);
+    assert_eq!(asset.checksum, format!("{:x}", Sha256::digest(b"hello")));
+    assert!(asset.path.starts_with(&format!("{}/", folder.path)));
+    let folder = store.find_folder(&folder_id).await.unwrap().unwrap();
+    assert_eq!(folder.files, vec![id.clone()]);
+    let key = asset_key(&config.storage_root, &asset).unwrap();
+    assert_eq!(read(&storage, &key).await, b"hello");
+
+    // It can be found by uuid too, and deleting it takes the doc, the listing and the bytes
+    assert!(get_asset(&store, &alice, &asset.uuid)
+        .await
+        .unwrap()
+        .is_some());
+    delete_asset(&store, &storage, &config, &alice, &asset.uuid)
+        .await
+        .unwrap();
+    assert!(get_asset(&store, &alice, &id.to_hex())
+        .await
+        .unwrap()
+        .is_none());
+    assert!(!storage.exists(&key).await.unwrap());
+    let folder = store.find_folder(&folder_id).await.unwrap().unwrap();
+    assert!(folder.files.is_empty());
+}
+
+async fn uploads_over_the_limit_are_refused(store: impl MetaStore) {
+    let storage = MemoryStorage::new();
+    let config = file_server::config::Config {
+        max_upload_size: 4,
+        ..test_config()
+    };
+    let alice_user = user(&store, &config, "alice").await;
+    let alice = principal(&store, &alice_user).await;
+    let folder = create_folder(&store, &storage, &config, &alice, "pics", None, None)
+        .await
+        .unwrap();
+
+    let saved = save_asset(
+        &store,
+        &storage,
+        &config,
+        &alice,
+        b"hello".to_vec(),
+        "greeting",
+        folder.id.as_ref().unwrap(),
+        "txt",
+    )
+    .await;
+    assert!(matches!(saved, Err(ControllerError::PayloadTooLarge(_))));
+    assert!(storage.list("").await.unwrap().is_empty());
+}
+
+async fn deleting_a_folder_deletes_its_assets(store: impl MetaStore) {
+    let (storage, config) = (MemoryStorage::new(), test_config());
+    let alice_user = user(&store, &config, "alice").await;
+    let alice = principal(&store, &alice_user).await;
+    let folder = create_folder(&store, &storage, &config, &alice, "pics", None, None)
+        .await
+        .unwrap();
+    let folder_id = folder.id.clone().unwrap();
+    let id = save_asset(
+        &store,
+        &storage,
+        &config,
+        &alice,
+        b"hello".to_vec(),
+        "greeting",
+        &folder_id,
+        "txt",
+    )
+    .await
+    .unwrap();
+
+    delete_folder(&store, &storage, &config, &alice, &folder_id)
+        .await
+        .unwrap();
+    assert!(store.find_folder(&folder_id).await.unwrap().is_none());
+    assert!(store.find_asset(&id).await.unwrap().is_none());
+    assert!(storage.list("").await.unwrap().is_empty());
+}
+
+on_every_store!(
+    saved_assets_can_be_read_and_deleted,
+    uploads_over_the_limit_are_refused,
+    deleting_a_folder_deletes_its_assets
+);
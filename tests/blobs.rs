@@ -0,0 +1,100 @@
+mod common;
+
+use common::{principal, read, test_config, user, write};
+use file_server::config::Config;
+use file_server::controller::blobs::{acquire_blob, release_blob};
+use file_server::controller::file_system::{copy_asset, create_folder, delete_asset, save_asset};
+use file_server::storage::memory::MemoryStorage;
+use file_server::storage::{blob_key, Storage};
+use file_server::store::MetaStore;
+use sha2::{Digest, Sha256};
+
+async fn blobs_count_references_and_keep_their_bytes(store: impl MetaStore) {
+    let storage = MemoryStorage::new();
+    let checksum = format!("{:x}", Sha256::digest(b"hello"));
+    write(&storage, &blob_key(&checksum), b"hello").await;
+
+    assert!(!acquire_blob(&store, &checksum, 5).await.unwrap());
+    assert!(acquire_blob(&store, &checksum, 5).await.unwrap());
+    assert_eq!(store.find_blob(&checksum).await.unwrap().unwrap().refs, 2);
+
+    release_blob(&store, &checksum).await.unwrap();
+    assert_eq!(store.find_blob(&checksum).await.unwrap().unwrap().refs, 1);
+
+    // The last release only drops the doc, so an upload of the same bytes picking the blob up
+    // again right then still finds them
+    release_blob(&store, &checksum).await.unwrap();
+    assert!(store.find_blob(&checksum).await.unwrap().is_none());
+    assert!(!acquire_blob(&store, &checksum, 5).await.unwrap());
+    assert_eq!(read(&storage, &blob_key(&checksum)).await, b"hello");
+}
+
+async fn assets_with_the_same_bytes_share_one_blob(store: impl MetaStore) {
+    let storage = MemoryStorage::new();
+    let config = Config {
+        content_addressed: true,
+        ..test_config()
+    };
+    let alice_user = user(&store, &config, "alice").await;
+    let alice = principal(&store, &alice_user).await;
+    let folder = create_folder(&store, &storage, &config, &alice, "pics", None, None)
+        .await
+        .unwrap();
+    let folder_id = folder.id.clone().unwrap();
+    let checksum = format!("{:x}", Sha256::digest(b"hello"));
+
+    let mut uuids = vec![];
+    for tag in ["one", "two"] {
+        let id = save_asset(
+            &store,
+            &storage,
+            &config,
+            &alice,
+            b"hello".to_vec(),
+            tag,
+            &folder_id,
+            "txt",
+        )
+        .await
+        .unwrap();
+        let asset = store.find_asset(&id).await.unwrap().unwrap();
+        assert_eq!(asset.blob.as_deref(), Some(checksum.as_str()));
+        uuids.push(asset.uuid);
+    }
+    let copy = copy_asset(
+        &store,
+        &storage,
+        &config,
+        &alice,
+        &uuids[0],
+        &folder_id,
+        Some("three"),
+    )
+    .await
+    .unwrap();
+    assert_eq!(copy.blob.as_deref(), Some(checksum.as_str()));
+    uuids.push(copy.uuid);
+
+    // One stored copy of the bytes, counted once for every asset
+    assert_eq!(storage.list("").await.unwrap(), vec![blob_key(&checksum)]);
+    assert_eq!(read(&storage, &blob_key(&checksum)).await, b"hello");
+    assert_eq!(store.find_blob(&checksum).await.unwrap().unwrap().refs, 3);
+
+    for (deleted, uuid) in uuids.iter().enumerate() {
+        delete_asset(&store, &storage, &config, &alice, uuid)
+            .await
+            .unwrap();
+        let left = 2 - deleted as i64;
+        match store.find_blob(&checksum).await.unwrap() {
+            Some(blob) => assert_eq!(blob.refs, left),
+            None => assert_eq!(left, 0),
+        }
+    }
+    assert!(store.find_blob(&checksum).await.unwrap().is_none());
+    assert_eq!(storage.list("").await.unwrap(), vec![blob_key(&checksum)]);
+}
+
+on_every_store!(
+    blobs_count_references_and_keep_their_bytes,
+    assets_with_the_same_bytes_share_one_blob
+);
@@ -0,0 +1,75 @@
+mod common;
+
+use common::{principal, test_config, user};
+use file_server::bootstrap::bootstrap;
+use file_server::config::Config;
+use file_server::controller::auth::login_user;
+use file_server::controller::file_system::create_folder;
+use file_server::storage::memory::MemoryStorage;
+use file_server::store::MetaStore;
+
+async fn an_empty_store_gets_the_configured_superuser(store: impl MetaStore) {
+    let config = Config {
+        admin_user: "root".to_string(),
+        admin_pass: Some("correct horse".to_string()),
+        ..test_config()
+    };
+
+    let created = bootstrap(&store, &config).await.unwrap().unwrap();
+    assert!(created.user.superuser);
+    assert_eq!(created.user.user, "root");
+    assert!(created.generated_pass.is_none());
+    assert!(login_user(&store, &config, "root", "correct horse")
+        .await
+        .unwrap()
+        .is_some());
+}
+
+async fn a_generated_password_is_handed_out_once(store: impl MetaStore) {
+    let config = test_config();
+
+    let created = bootstrap(&store, &config).await.unwrap().unwrap();
+    let pass = created.generated_pass.unwrap();
+    assert!(login_user(&store, &config, &config.admin_user, &pass)
+        .await
+        .unwrap()
+        .is_some());
+    // Only its hash is stored
+    assert_ne!(created.user.pass, pass);
+}
+
+async fn bootstrapping_again_changes_nothing(store: impl MetaStore) {
+    let (storage, config) = (MemoryStorage::new(), test_config());
+    let created = bootstrap(&store, &config).await.unwrap().unwrap();
+    let admin = principal(&store, &created.user).await;
+    let folder = create_folder(&store, &storage, &config, &admin, "pics", None, None)
+        .await
+        .unwrap();
+
+    assert!(bootstrap(&store, &config).await.unwrap().is_none());
+    assert_eq!(store.count_users().await.unwrap(), 1);
+    assert!(store
+        .find_folder(folder.id.as_ref().unwrap())
+        .await
+        .unwrap()
+        .is_some());
+}
+
+async fn stores_with_users_get_no_superuser(store: impl MetaStore) {
+    let config = test_config();
+    user(&store, &config, "alice").await;
+
+    assert!(bootstrap(&store, &config).await.unwrap().is_none());
+    assert!(store
+        .find_user_by_name(&config.admin_user)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+on_every_store!(
+    an_empty_store_gets_the_configured_superuser,
+    a_generated_password_is_handed_out_once,
+    bootstrapping_again_changes_nothing,
+    stores_with_users_get_no_superuser
+);
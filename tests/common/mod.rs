@@ -0,0 +1,175 @@
+#![allow(dead_code)]
+
+use async_trait::async_trait;
+use file_server::config::{Config, MetadataBackend, StorageBackend};
+use file_server::controller::access::Principal;
+use file_server::controller::error::ControllerError;
+use file_server::controller::file_system::create_user;
+use file_server::data_models::user::User;
+use file_server::storage::memory::MemoryStorage;
+use file_server::storage::{ByteSource, ByteStream, ObjectMeta, Storage};
+use file_server::store::sqlite::SqliteStore;
+use file_server::store::MetaStore;
+use futures::stream::{self, StreamExt};
+use rusqlite::Connection;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub const ROOT: &str = "./assets";
+
+/**
+ * Run each of the async test functions listed, taking the store to test against, once against the
+ * memory store and once against an SQLite store in memory
+ */
+#[macro_export]
+macro_rules! on_every_store {
+    ($($test:ident),* $(,)?) => {
+        mod memory {
+            $(
+                #[tokio::test]
+                async fn $test() {
+                    super::$test(file_server::store::memory::MemoryStore::new()).await;
+                }
+            )*
+        }
+        mod sqlite {
+            $(
+                #[tokio::test]
+                async fn $test() {
+                    super::$test($crate::common::sqlite_store()).await;
+                }
+            )*
+        }
+    };
+}
+
+/**
+ * Config for the memory store and storage, with the cheapest bcrypt cost
+ */
+pub fn test_config() -> Config {
+    Config {
+        storage_root: ROOT.to_string(),
+        storage_backend: StorageBackend::Memory,
+        metadata_backend: MetadataBackend::Memory,
+        bcrypt_cost: 4,
+        ..Config::default()
+    }
+}
+/**
+ * An SQLite store that lives in memory
+ */
+pub fn sqlite_store() -> SqliteStore {
+    SqliteStore::new(Connection::open_in_memory().expect("open SQLite")).expect("SQLite schema")
+}
+/**
+ * Create the user name, returning its doc
+ */
+pub async fn user(store: &dyn MetaStore, config: &Config, name: &str) -> User {
+    create_user(store, config, name, "correct horse")
+        .await
+        .expect("create user")
+}
+/**
+ * The principal of user as it is stored now
+ */
+pub async fn principal(store: &dyn MetaStore, user: &User) -> Principal {
+    let id = user.id.as_ref().expect("user id");
+    let user = store.find_user(id).await.unwrap().expect("user doc");
+
+    Principal::User(Box::new(user))
+}
+/**
+ * Every byte stored under key
+ */
+pub async fn read(storage: &dyn Storage, key: &str) -> Vec<u8> {
+    let mut bytes = vec![];
+    let mut chunks = storage.get(key).await.expect("get");
+    while let Some(chunk) = chunks.next().await {
+        bytes.extend_from_slice(&chunk.expect("chunk"));
+    }
+
+    bytes
+}
+/**
+ * Store data under key
+ */
+pub async fn write(storage: &dyn Storage, key: &str, data: &[u8]) {
+    let mut source = stream::iter(vec![Ok(bytes::Bytes::from(data.to_vec()))]);
+    storage.put(key, &mut source).await.expect("put");
+}
+
+/**
+ * ____________________________________________________________________________________________
+ * FlakyStorage, memory storage failing one rename on purpose
+ * ____________________________________________________________________________________________
+ * inner: Where the bytes are kept
+ * renames_left: Renames that succeed before the one that fails, usize::MAX for none
+ * ____________________________________________________________________________________________
+ */
+pub struct FlakyStorage {
+    pub inner: MemoryStorage,
+    renames_left: AtomicUsize,
+}
+
+impl FlakyStorage {
+    pub fn new() -> FlakyStorage {
+        FlakyStorage {
+            inner: MemoryStorage::new(),
+            renames_left: AtomicUsize::new(usize::MAX),
+        }
+    }
+    /**
+     * Let renames succeed count more times and fail the one after, the ones after that succeed
+     * again
+     */
+    pub fn fail_rename_after(&self, count: usize) {
+        self.renames_left.store(count, Ordering::SeqCst);
+    }
+}
+
+#[async_trait]
+impl Storage for FlakyStorage {
+    async fn put(&self, key: &str, data: &mut ByteSource<'_>) -> Result<u64, ControllerError> {
+        self.inner.put(key, data).await
+    }
+
+    async fn get(&self, key: &str) -> Result<ByteStream, ControllerError> {
+        self.inner.get(key).await
+    }
+
+    async fn get_range(
+        &self,
+        key: &str,
+        start: u64,
+        end: u64,
+    ) -> Result<ByteStream, ControllerError> {
+        self.inner.get_range(key, start, end).await
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> Result<(), ControllerError> {
+        let left = self.renames_left.load(Ordering::SeqCst);
+        if left == 0 {
+            self.renames_left.store(usize::MAX, Ordering::SeqCst);
+            return Err(ControllerError::Storage(std::io::Error::other(format!(
+                "Renaming {} to {} failed on purpose",
+                from, to
+            ))));
+        }
+        if left != usize::MAX {
+            self.renames_left.store(left - 1, Ordering::SeqCst);
+        }
+
+        self.inner.rename(from, to).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), ControllerError> {
+        self.inner.delete(key).await
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, ControllerError> {
+        self.inner.list(prefix).await
+    }
+
+    async fn stat(&self, key: &str) -> Result<Option<ObjectMeta>, ControllerError> {
+        self.inner.stat(key).await
+    }
+}
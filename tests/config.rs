@@ -0,0 +1,188 @@
+use file_server::config::{Config, ConfigError, MetadataBackend, StorageBackend};
+use file_server::util::get_uuid;
+use std::env;
+use std::fs;
+
+fn invalid(config: Config) -> bool {
+    matches!(config.validate(), Err(ConfigError::Invalid(_)))
+}
+
+#[test]
+fn admin_user_and_passwords_follow_the_rules_of_any_user() {
+    assert!(Config::default().validate().is_ok());
+
+    for admin_user in ["", " admin", ".admin", "ad/min", &"a".repeat(65)] {
+        let config = Config {
+            admin_user: admin_user.to_string(),
+            ..Config::default()
+        };
+        assert!(invalid(config), "{:?}", admin_user);
+    }
+
+    // bcrypt only looks at the first 72 bytes, a longer password would be cut off unnoticed
+    for pass in ["short", &"ä".repeat(37)] {
+        let config = Config {
+            admin_pass: Some(pass.to_string()),
+            ..Config::default()
+        };
+        assert!(invalid(config), "{:?}", pass);
+        let config = Config {
+            demo_pass: Some(pass.to_string()),
+            ..Config::default()
+        };
+        assert!(invalid(config), "{:?}", pass);
+    }
+    let config = Config {
+        admin_pass: Some("a".repeat(72)),
+        demo_pass: Some("long enough".to_string()),
+        ..Config::default()
+    };
+    assert!(config.validate().is_ok());
+}
+
+#[test]
+fn the_example_config_is_valid() {
+    let contents = fs::read_to_string("file-server.example.toml").unwrap();
+    let config = Config::from_toml("file-server.example.toml", &contents).unwrap();
+    assert!(config.validate().is_ok());
+}
+
+#[test]
+fn left_out_keys_keep_their_default() {
+    let config = Config::from_toml(
+        "test.toml",
+        r#"
+            storage_backend = "memory"
+            metadata_backend = "sqlite"
+            max_upload_size = 1024
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.storage_backend, StorageBackend::Memory);
+    assert_eq!(config.metadata_backend, MetadataBackend::Sqlite);
+    assert_eq!(config.max_upload_size, 1024);
+
+    let default = Config::default();
+    assert_eq!(config.storage_root, default.storage_root);
+    assert_eq!(config.bcrypt_cost, default.bcrypt_cost);
+    assert_eq!(config.listen_addr, default.listen_addr);
+}
+
+#[test]
+fn unknown_and_mistyped_keys_are_parse_errors() {
+    for contents in [
+        "storage_rot = \"./assets\"",
+        "bcrypt_cost = \"twelve\"",
+        "storage_backend = \"ftp\"",
+        "not toml at all",
+    ] {
+        assert!(
+            matches!(
+                Config::from_toml("test.toml", contents),
+                Err(ConfigError::Parse(..))
+            ),
+            "{}",
+            contents
+        );
+    }
+}
+
+#[test]
+fn out_of_range_values_are_invalid() {
+    let configs = [
+        Config {
+            storage_root: " ".to_string(),
+            ..Config::default()
+        },
+        Config {
+            bcrypt_cost: 3,
+            ..Config::default()
+        },
+        Config {
+            max_upload_size: 0,
+            ..Config::default()
+        },
+        Config {
+            session_ttl_secs: 0,
+            ..Config::default()
+        },
+        Config {
+            listen_addr: "localhost".to_string(),
+            ..Config::default()
+        },
+        Config {
+            metadata_backend: MetadataBackend::Sqlite,
+            sqlite_path: String::new(),
+            ..Config::default()
+        },
+        Config {
+            storage_backend: StorageBackend::S3,
+            s3_access_key: "key".to_string(),
+            s3_secret_key: "secret".to_string(),
+            s3_endpoint: Some("localhost:9000".to_string()),
+            ..Config::default()
+        },
+        Config {
+            storage_backend: StorageBackend::S3,
+            ..Config::default()
+        },
+    ];
+    for config in configs {
+        assert!(invalid(config.clone()), "{:?}", config);
+    }
+}
+
+/**
+ * Every test reading the environment is in here, tests run in parallel and share it
+ */
+#[test]
+fn the_environment_overrides_the_file() {
+    let path = env::temp_dir().join(format!("{}.toml", get_uuid()));
+    fs::write(
+        &path,
+        "storage_root = \"./from-file\"\nbcrypt_cost = 10\nopen_registration = true\n",
+    )
+    .unwrap();
+    env::set_var("FILE_SERVER_CONFIG", &path);
+    env::set_var("FILE_SERVER_STORAGE_ROOT", "./from-env");
+    env::set_var("FILE_SERVER_METADATA_BACKEND", "memory");
+    env::set_var("FILE_SERVER_OPEN_REGISTRATION", "0");
+    env::set_var("FILE_SERVER_ADMIN_PASS", "from the environment");
+
+    let config = Config::load().unwrap();
+    assert_eq!(config.storage_root, "./from-env");
+    assert_eq!(config.metadata_backend, MetadataBackend::Memory);
+    assert!(!config.open_registration);
+    assert_eq!(config.admin_pass.as_deref(), Some("from the environment"));
+    assert_eq!(config.bcrypt_cost, 10);
+
+    // Bad values from the environment are errors, not silently ignored
+    for (name, value) in [
+        ("FILE_SERVER_BCRYPT_COST", "ten"),
+        ("FILE_SERVER_VERIFY_DOWNLOADS", "yes"),
+        ("FILE_SERVER_STORAGE_BACKEND", "ftp"),
+        ("FILE_SERVER_SESSION_TTL_SECS", "0"),
+    ] {
+        env::set_var(name, value);
+        assert!(
+            matches!(Config::load(), Err(ConfigError::Invalid(_))),
+            "{}",
+            name
+        );
+        env::remove_var(name);
+    }
+
+    // A config file that was asked for has to be there
+    fs::remove_file(&path).unwrap();
+    assert!(matches!(Config::load(), Err(ConfigError::Io(..))));
+
+    for name in [
+        "FILE_SERVER_CONFIG",
+        "FILE_SERVER_STORAGE_ROOT",
+        "FILE_SERVER_METADATA_BACKEND",
+        "FILE_SERVER_OPEN_REGISTRATION",
+        "FILE_SERVER_ADMIN_PASS",
+    ] {
+        env::remove_var(name);
+    }
+}
@@ -0,0 +1,187 @@
+mod common;
+
+use common::{principal, read, test_config, user, ROOT};
+use file_server::controller::access::{authorize_folder, Permission, Principal};
+use file_server::controller::auth::{add_key_to_access_group, register_new_access_group};
+use file_server::controller::file_system::{
+    copy_folder, create_folder, create_sub_folder, save_asset,
+};
+use file_server::controller::key::{assign_key, create_key};
+use file_server::storage::asset_key;
+use file_server::storage::memory::MemoryStorage;
+use file_server::store::MetaStore;
+
+async fn folders_are_copied_with_everything_below_them(store: impl MetaStore) {
+    let (storage, config) = (MemoryStorage::new(), test_config());
+    let alice = user(&store, &config, "alice").await;
+    let alice_principal = principal(&store, &alice).await;
+    let top = create_folder(
+        &store,
+        &storage,
+        &config,
+        &alice_principal,
+        "top",
+        None,
+        None,
+    )
+    .await
+    .unwrap();
+    let sub = create_sub_folder(
+        &store,
+        &storage,
+        &config,
+        &alice_principal,
+        &top.path,
+        "sub",
+        None,
+    )
+    .await
+    .unwrap();
+    for (folder, data) in [(&top, b"top"), (&sub, b"sub")] {
+        save_asset(
+            &store,
+            &storage,
+            &config,
+            &alice_principal,
+            data.to_vec(),
+            "file",
+            folder.id.as_ref().unwrap(),
+            "txt",
+        )
+        .await
+        .unwrap();
+    }
+
+    let copy = copy_folder(
+        &store,
+        &storage,
+        &config,
+        &alice_principal,
+        top.id.as_ref().unwrap(),
+        ROOT,
+        "copy",
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(copy.path, format!("{}/copy", ROOT));
+    assert!(copy.is_public);
+
+    // The sub folder name is taken by the original, so its copy gets the next free one
+    let sub_copy = store
+        .find_folder_by_path(&format!("{}/copy/sub 2", ROOT))
+        .await
+        .unwrap()
+        .unwrap();
+    for (original, copied, data) in [(&top, &copy, b"top"), (&sub, &sub_copy, b"sub")] {
+        let originals = store
+            .find_assets_in_folder(original.id.as_ref().unwrap())
+            .await
+            .unwrap();
+        let copies = store
+            .find_assets_in_folder(copied.id.as_ref().unwrap())
+            .await
+            .unwrap();
+        assert_eq!((originals.len(), copies.len()), (1, 1));
+        assert_ne!(originals[0].uuid, copies[0].uuid);
+        assert_eq!(originals[0].checksum, copies[0].checksum);
+        assert!(copies[0].path.starts_with(&format!("{}/", copied.path)));
+        for asset in [&originals[0], &copies[0]] {
+            assert_eq!(read(&storage, &asset_key(ROOT, asset).unwrap()).await, data);
+        }
+        assert_eq!(copied.files.len(), 1);
+    }
+
+    // The caller administrates the copy
+    let alice = store
+        .find_user(alice.id.as_ref().unwrap())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(alice.folder_admins.contains(copy.id.as_ref().unwrap()));
+}
+
+async fn copies_that_reset_access_are_private(store: impl MetaStore) {
+    let (storage, config) = (MemoryStorage::new(), test_config());
+    let alice = user(&store, &config, "alice").await;
+    let bob = user(&store, &config, "bob").await;
+    let alice_principal = principal(&store, &alice).await;
+
+    // Bob may read and write the private folder of Alice's group, but not administrate it
+    let group_id = register_new_access_group(&store, &alice_principal, "team")
+        .await
+        .unwrap()
+        .id
+        .unwrap();
+    let folder = create_folder(
+        &store,
+        &storage,
+        &config,
+        &alice_principal,
+        "private",
+        Some(group_id.clone()),
+        None,
+    )
+    .await
+    .unwrap();
+    let key_id = create_key(&store, &alice_principal)
+        .await
+        .unwrap()
+        .key
+        .id
+        .unwrap();
+    add_key_to_access_group(&store, &alice_principal, &group_id, &key_id)
+        .await
+        .unwrap();
+    assign_key(&store, &alice_principal, &key_id, bob.id.as_ref().unwrap())
+        .await
+        .unwrap();
+    let bob_principal = principal(&store, &bob).await;
+
+    // A top level copy without keep_access is neither public nor shared with the group
+    let copy = copy_folder(
+        &store,
+        &storage,
+        &config,
+        &bob_principal,
+        folder.id.as_ref().unwrap(),
+        ROOT,
+        "leak",
+        false,
+    )
+    .await
+    .unwrap();
+    assert!(!copy.is_public);
+    assert!(copy.access_groups.is_empty());
+    assert!(
+        authorize_folder(&store, &Principal::Anonymous, &copy, Permission::List)
+            .await
+            .is_err()
+    );
+    assert!(
+        authorize_folder(&store, &bob_principal, &copy, Permission::Admin)
+            .await
+            .is_ok()
+    );
+
+    // Keeping access keeps the copy in the group, still private
+    let kept = copy_folder(
+        &store,
+        &storage,
+        &config,
+        &bob_principal,
+        folder.id.as_ref().unwrap(),
+        ROOT,
+        "kept",
+        true,
+    )
+    .await
+    .unwrap();
+    assert!(!kept.is_public);
+    assert_eq!(kept.access_groups, vec![group_id]);
+}
+
+on_every_store!(
+    folders_are_copied_with_everything_below_them,
+    copies_that_reset_access_are_private
+);
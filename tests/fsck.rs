@@ -0,0 +1,285 @@
+mod common;
+
+use common::{principal, test_config, user, write, ROOT};
+use file_server::config::{Config, StorageBackend};
+use file_server::constants::FSCK_GRACE_SECS;
+use file_server::controller::auth::register_new_access_group;
+use file_server::controller::file_system::{create_folder, save_asset};
+use file_server::data_models::blob::Blob;
+use file_server::fsck::fsck;
+use file_server::storage::local::LocalStorage;
+use file_server::storage::memory::MemoryStorage;
+use file_server::storage::{asset_key, staging_key, Storage};
+use file_server::store::{FolderList, MetaStore};
+use file_server::util::{get_time_meta, get_timestamp, get_uuid};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use wither::mongodb::bson::oid::ObjectId;
+
+/**
+ * Save hello as the one asset of a fresh folder with content addressed storage, returning the
+ * checksum of its blob
+ */
+async fn one_blob_asset(store: &dyn MetaStore, storage: &MemoryStorage, config: &Config) -> String {
+    let alice_user = user(store, config, "alice").await;
+    let alice = principal(store, &alice_user).await;
+    let folder = create_folder(store, storage, config, &alice, "pics", None, None)
+        .await
+        .unwrap();
+    save_asset(
+        store,
+        storage,
+        config,
+        &alice,
+        b"hello".to_vec(),
+        "greeting",
+        folder.id.as_ref().unwrap(),
+        "txt",
+    )
+    .await
+    .unwrap();
+
+    format!("{:x}", Sha256::digest(b"hello"))
+}
+/**
+ * Add a reference to the blob checksum without an asset for it, touched at touched_at
+ */
+async fn extra_ref(store: &dyn MetaStore, checksum: &str, touched_at: i64) {
+    let (timestamp, timestamp_readable) = get_time_meta();
+    store
+        .acquire_blob(Blob {
+            id: None,
+            checksum: checksum.to_string(),
+            size: 5,
+            refs: 1,
+            touched_at,
+            timestamp,
+            timestamp_readable,
+        })
+        .await
+        .unwrap();
+}
+
+async fn blob_refs_are_recounted_from_the_assets(store: impl MetaStore) {
+    let storage = MemoryStorage::new();
+    let config = Config {
+        content_addressed: true,
+        ..test_config()
+    };
+    let checksum = one_blob_asset(&store, &storage, &config).await;
+
+    // A reference nothing has used for a day is dropped
+    extra_ref(&store, &checksum, get_timestamp() as i64 - 24 * 60 * 60).await;
+    assert_eq!(store.find_blob(&checksum).await.unwrap().unwrap().refs, 2);
+
+    let report = fsck(&store, &storage, &config, true).await.unwrap();
+    assert_eq!(report.blob_refs, vec![(checksum.clone(), 2, 1)]);
+    assert_eq!(report.skipped, 0);
+    assert_eq!(store.find_blob(&checksum).await.unwrap().unwrap().refs, 1);
+    assert_eq!(
+        fsck(&store, &storage, &config, false)
+            .await
+            .unwrap()
+            .problems(),
+        0
+    );
+}
+
+async fn blobs_touched_lately_are_never_lowered(store: impl MetaStore) {
+    let storage = MemoryStorage::new();
+    let config = Config {
+        content_addressed: true,
+        ..test_config()
+    };
+    let checksum = one_blob_asset(&store, &storage, &config).await;
+
+    // An upload that acquired the blob but hasn't written its asset yet looks just like this
+    extra_ref(&store, &checksum, get_timestamp() as i64).await;
+
+    let report = fsck(&store, &storage, &config, true).await.unwrap();
+    assert_eq!(report.blob_refs, vec![(checksum.clone(), 2, 1)]);
+    assert_eq!(report.skipped, 1);
+    assert_eq!(store.find_blob(&checksum).await.unwrap().unwrap().refs, 2);
+}
+
+async fn broken_docs_and_lists_are_repaired(store: impl MetaStore) {
+    let (storage, config) = (MemoryStorage::new(), test_config());
+    let alice_user = user(&store, &config, "alice").await;
+    let alice_id = alice_user.id.clone().unwrap();
+    let alice = principal(&store, &alice_user).await;
+    let folder = create_folder(&store, &storage, &config, &alice, "pics", None, None)
+        .await
+        .unwrap();
+    let folder_id = folder.id.clone().unwrap();
+    let gone = create_folder(&store, &storage, &config, &alice, "gone", None, None)
+        .await
+        .unwrap();
+    let gone_id = gone.id.clone().unwrap();
+    let mut ids = vec![];
+    for (tag, folder_id) in [
+        ("missing", &folder_id),
+        ("unlisted", &folder_id),
+        ("astray", &folder_id),
+        ("intact", &folder_id),
+        ("homeless", &gone_id),
+    ] {
+        let id = save_asset(
+            &store,
+            &storage,
+            &config,
+            &alice,
+            tag.as_bytes().to_vec(),
+            tag,
+            folder_id,
+            "txt",
+        )
+        .await
+        .unwrap();
+        ids.push(id);
+    }
+    assert_eq!(
+        fsck(&store, &storage, &config, false)
+            .await
+            .unwrap()
+            .problems(),
+        0
+    );
+
+    let key_of = |asset| asset_key(ROOT, asset).unwrap();
+    let missing = store.find_asset(&ids[0]).await.unwrap().unwrap();
+    storage.delete(&key_of(&missing)).await.unwrap();
+    store
+        .remove_from_all_folder_lists(FolderList::Files, &ids[1..2])
+        .await
+        .unwrap();
+    let astray = store.find_asset(&ids[2]).await.unwrap().unwrap();
+    let astray_key = format!(
+        "elsewhere/{}",
+        Path::new(&astray.path)
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+    );
+    storage.rename(&key_of(&astray), &astray_key).await.unwrap();
+    store
+        .delete_folders(std::slice::from_ref(&gone_id))
+        .await
+        .unwrap();
+    let group = register_new_access_group(&store, &alice, "team")
+        .await
+        .unwrap();
+    store
+        .add_allowed_key(group.id.as_ref().unwrap(), &ObjectId::new())
+        .await
+        .unwrap();
+    store
+        .add_to_folder_list(&folder_id, FolderList::Files, &ObjectId::new())
+        .await
+        .unwrap();
+
+    let report = fsck(&store, &storage, &config, false).await.unwrap();
+    assert_eq!(report.assets, 5);
+    assert_eq!(report.missing_assets.len(), 1);
+    assert_eq!(report.missing_assets[0].id, missing.id);
+    assert_eq!(report.unlisted_assets.len(), 1);
+    assert_eq!(report.unlisted_assets[0].id.as_ref(), Some(&ids[1]));
+    assert_eq!(report.astray_assets.len(), 1);
+    assert_eq!(report.astray_assets[0].1, astray_key);
+    assert_eq!(report.homeless_assets.len(), 1);
+    assert_eq!(report.homeless_assets[0].id.as_ref(), Some(&ids[4]));
+    assert_eq!(
+        report.dangling_folder_admins,
+        vec![("alice".to_string(), gone_id.clone())]
+    );
+    assert_eq!(report.dangling_allowed_keys.len(), 1);
+    assert_eq!(report.dangling_files.len(), 1);
+    assert_eq!(report.problems(), 7);
+    assert!(!report.repaired);
+
+    let repaired = fsck(&store, &storage, &config, true).await.unwrap();
+    assert!(repaired.repaired);
+    assert_eq!(repaired.skipped, 0);
+    assert_eq!(
+        fsck(&store, &storage, &config, false)
+            .await
+            .unwrap()
+            .problems(),
+        0
+    );
+
+    assert!(store.find_asset(&ids[0]).await.unwrap().is_none());
+    assert!(store.find_asset(&ids[4]).await.unwrap().is_none());
+    let mut files = store.find_folder(&folder_id).await.unwrap().unwrap().files;
+    files.sort();
+    assert_eq!(files, ids[1..4]);
+    assert!(storage.exists(&key_of(&astray)).await.unwrap());
+    let alice_doc = store.find_user(&alice_id).await.unwrap().unwrap();
+    assert!(!alice_doc.folder_admins.contains(&gone_id));
+    assert!(alice_doc.folder_admins.contains(&folder_id));
+}
+
+/**
+ * Make the file at path look like it was written two grace periods ago
+ */
+fn age(path: &Path) {
+    let written = SystemTime::now() - Duration::from_secs(2 * FSCK_GRACE_SECS as u64);
+    fs::File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(written)
+        .unwrap();
+}
+
+async fn old_bytes_nothing_points_at_are_removed(store: impl MetaStore) {
+    let root = std::env::temp_dir().join(get_uuid());
+    let config = Config {
+        storage_root: root.to_str().unwrap().to_string(),
+        storage_backend: StorageBackend::Local,
+        ..test_config()
+    };
+    let storage = LocalStorage::new(&config.storage_root).unwrap();
+    let alice_user = user(&store, &config, "alice").await;
+    let alice = principal(&store, &alice_user).await;
+    create_folder(&store, &storage, &config, &alice, "pics", None, None)
+        .await
+        .unwrap();
+    let empty = create_folder(&store, &storage, &config, &alice, "empty", None, None)
+        .await
+        .unwrap();
+
+    // Bytes of uploads that may still be going are left alone
+    let staged = staging_key(&get_uuid());
+    for key in ["pics/old.bin", "pics/new.bin", staged.as_str()] {
+        write(&storage, key, b"x").await;
+    }
+    age(&root.join("pics/old.bin"));
+    age(&root.join(&staged));
+    fs::remove_dir(root.join("empty")).unwrap();
+
+    let report = fsck(&store, &storage, &config, false).await.unwrap();
+    assert_eq!(report.orphaned_keys, vec!["pics/old.bin".to_string()]);
+    assert_eq!(report.stale_staging, vec![staged.clone()]);
+    assert_eq!(report.missing_dirs.len(), 1);
+    assert_eq!(report.missing_dirs[0].id, empty.id);
+    assert_eq!(report.problems(), 3);
+
+    let repaired = fsck(&store, &storage, &config, true).await.unwrap();
+    assert_eq!(repaired.skipped, 0);
+    assert!(!storage.exists("pics/old.bin").await.unwrap());
+    assert!(!storage.exists(&staged).await.unwrap());
+    assert!(storage.exists("pics/new.bin").await.unwrap());
+    assert!(root.join("empty").is_dir());
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+on_every_store!(
+    blob_refs_are_recounted_from_the_assets,
+    blobs_touched_lately_are_never_lowered,
+    broken_docs_and_lists_are_repaired,
+    old_bytes_nothing_points_at_are_removed
+);
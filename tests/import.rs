@@ -0,0 +1,293 @@
+mod common;
+
+use common::{principal, read, test_config, user};
+use file_server::config::{Config, StorageBackend};
+use file_server::controller::error::ControllerError;
+use file_server::controller::file_system::create_folder;
+use file_server::import::{import_tree, ImportEvent};
+use file_server::storage::local::LocalStorage;
+use file_server::storage::memory::MemoryStorage;
+use file_server::storage::{asset_key, Storage};
+use file_server::store::MetaStore;
+use file_server::util::get_uuid;
+use std::cell::Cell;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/**
+ * A fresh directory below the system temp directory holding files, given by their paths relative
+ * to it
+ */
+fn source_tree(name: &str, files: &[&str]) -> PathBuf {
+    let source = std::env::temp_dir().join(get_uuid()).join(name);
+    for file in files {
+        let path = source.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, file.as_bytes()).unwrap();
+    }
+
+    source
+}
+
+async fn directories_named_like_other_folders_get_free_names(store: impl MetaStore) {
+    let (storage, config) = (MemoryStorage::new(), test_config());
+    let alice_user = user(&store, &config, "alice").await;
+    let alice = principal(&store, &alice_user).await;
+    create_folder(&store, &storage, &config, &alice, "photos", None, None)
+        .await
+        .unwrap();
+
+    // Two directories called photos, and one that is already called photos 2
+    let source = source_tree(
+        "trip",
+        &["photos/a.jpg", "day/photos/b.jpg", "day/photos 2/c.jpg"],
+    );
+    let report = import_tree(
+        &store,
+        &storage,
+        &config,
+        &alice,
+        &source,
+        None,
+        None,
+        &|_| {},
+    )
+    .await
+    .unwrap();
+    assert!(report.conflicts.is_empty());
+    assert_eq!(report.left_out, 0);
+    assert_eq!(report.assets, 3);
+    assert_eq!(report.folders_created, 5);
+
+    let folders = store.find_folders_below(&report.folder.path).await.unwrap();
+    let mut paths: Vec<&str> = folders
+        .iter()
+        .map(|folder| {
+            Path::new(&folder.path)
+                .strip_prefix(&report.folder.path)
+                .unwrap()
+                .to_str()
+                .unwrap()
+        })
+        .collect();
+    paths.sort();
+    assert_eq!(
+        paths,
+        vec!["day", "day/photos 2 2", "day/photos 3", "photos 2"]
+    );
+
+    // Running it again finds the same folders
+    let again = import_tree(
+        &store,
+        &storage,
+        &config,
+        &alice,
+        &source,
+        None,
+        None,
+        &|_| {},
+    )
+    .await
+    .unwrap();
+    assert_eq!(again.folders_created, 0);
+    assert_eq!(again.folders_existing, 5);
+    assert_eq!(again.assets, 0);
+    assert_eq!(again.conflicts.len(), 3);
+
+    fs::remove_dir_all(source.parent().unwrap()).unwrap();
+}
+
+async fn copied_trees_become_folders_and_assets(store: impl MetaStore) {
+    let (storage, config) = (MemoryStorage::new(), test_config());
+    let alice_user = user(&store, &config, "alice").await;
+    let bob_user = user(&store, &config, "bob").await;
+    let bob_id = bob_user.id.clone().unwrap();
+    let alice = principal(&store, &alice_user).await;
+    let parent = create_folder(&store, &storage, &config, &alice, "imports", None, None)
+        .await
+        .unwrap();
+
+    let source = source_tree(
+        "holiday",
+        &["a.jpg", "notes", "beach/b.jpg", ".hidden", ".cache/c.jpg"],
+    );
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(source.join("a.jpg"), source.join("link.jpg")).unwrap();
+
+    let (folders, assets) = (Cell::new(0), Cell::new(0));
+    let progress = |event: ImportEvent| match event {
+        ImportEvent::Folder(..) => folders.set(folders.get() + 1),
+        ImportEvent::Asset(..) => assets.set(assets.get() + 1),
+        _ => {}
+    };
+    let report = import_tree(
+        &store,
+        &storage,
+        &config,
+        &alice,
+        &source,
+        Some(&parent.path),
+        Some(&bob_id),
+        &progress,
+    )
+    .await
+    .unwrap();
+    assert!(!report.in_place);
+    assert_eq!(report.folder.path, format!("{}/holiday", parent.path));
+    assert_eq!(report.folders_created, 2);
+    assert_eq!(report.assets, 3);
+    assert_eq!(
+        report.bytes,
+        ("a.jpg".len() + "notes".len() + "beach/b.jpg".len()) as u64
+    );
+    assert!(report.failed.is_empty());
+    // The hidden file, the hidden directory and the symlink
+    let left_out = if cfg!(unix) { 3 } else { 2 };
+    assert_eq!(report.conflicts.len(), left_out);
+    assert_eq!((folders.get(), assets.get()), (2, 3));
+
+    // Assets are tagged with their file names and hold their bytes
+    let beach = store
+        .find_folder_by_path(&format!("{}/beach", report.folder.path))
+        .await
+        .unwrap()
+        .unwrap();
+    let assets = store
+        .find_assets_in_folder(beach.id.as_ref().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(assets.len(), 1);
+    assert_eq!(assets[0].tag, "b.jpg");
+    assert_eq!(
+        read(
+            &storage,
+            &asset_key(&config.storage_root, &assets[0]).unwrap()
+        )
+        .await,
+        b"beach/b.jpg"
+    );
+
+    let bob_doc = store.find_user(&bob_id).await.unwrap().unwrap();
+    assert!(bob_doc
+        .folder_admins
+        .contains(report.folder.id.as_ref().unwrap()));
+
+    // A second run finds everything there and imports nothing twice
+    let again = import_tree(
+        &store,
+        &storage,
+        &config,
+        &alice,
+        &source,
+        Some(&parent.path),
+        None,
+        &|_| {},
+    )
+    .await
+    .unwrap();
+    assert_eq!(again.folders_existing, 2);
+    assert_eq!(again.assets, 0);
+    assert_eq!(again.conflicts.len(), left_out + 3);
+
+    fs::remove_dir_all(source.parent().unwrap()).unwrap();
+}
+
+async fn trees_below_the_storage_root_are_imported_in_place(store: impl MetaStore) {
+    let root = std::env::temp_dir().join(get_uuid());
+    let config = Config {
+        storage_root: root.to_str().unwrap().to_string(),
+        storage_backend: StorageBackend::Local,
+        ..test_config()
+    };
+    let storage = LocalStorage::new(&config.storage_root).unwrap();
+    let alice_user = user(&store, &config, "alice").await;
+    let alice = principal(&store, &alice_user).await;
+    create_folder(&store, &storage, &config, &alice, "day", None, None)
+        .await
+        .unwrap();
+    for file in ["shots/a.jpg", "shots/day/b.jpg", "shots/day/c.jpg"] {
+        fs::create_dir_all(root.join(file).parent().unwrap()).unwrap();
+        fs::write(root.join(file), file.as_bytes()).unwrap();
+    }
+    let keys_before = storage.list("").await.unwrap();
+
+    // In place the folders follow the directories, only what is already there can be imported
+    assert!(matches!(
+        import_tree(
+            &store,
+            &storage,
+            &config,
+            &alice,
+            &root.join("shots"),
+            Some(&config.storage_root),
+            None,
+            &|_| {}
+        )
+        .await,
+        Err(ControllerError::InvalidInput(_))
+    ));
+    assert!(matches!(
+        import_tree(
+            &store,
+            &storage,
+            &config,
+            &alice,
+            &root,
+            None,
+            None,
+            &|_| {}
+        )
+        .await,
+        Err(ControllerError::InvalidInput(_))
+    ));
+
+    let report = import_tree(
+        &store,
+        &storage,
+        &config,
+        &alice,
+        &root.join("shots"),
+        None,
+        None,
+        &|_| {},
+    )
+    .await
+    .unwrap();
+    assert!(report.in_place);
+    assert_eq!(report.folder.path, format!("{}/shots", config.storage_root));
+    assert_eq!(report.assets, 1);
+    // The folder day is taken, so the directory of that name is left out with its files
+    assert_eq!(report.conflicts.len(), 1);
+    assert_eq!(report.left_out, 2);
+    assert_eq!(storage.list("").await.unwrap(), keys_before);
+    let asset = store
+        .find_asset_by_path(&format!("{}/shots/a.jpg", config.storage_root))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(asset.tag, "a.jpg");
+    assert_eq!(asset.size, "shots/a.jpg".len() as i64);
+
+    let again = import_tree(
+        &store,
+        &storage,
+        &config,
+        &alice,
+        &root.join("shots"),
+        None,
+        None,
+        &|_| {},
+    )
+    .await
+    .unwrap();
+    assert_eq!(again.assets, 0);
+    assert_eq!(again.unchanged, 1);
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+on_every_store!(
+    directories_named_like_other_folders_get_free_names,
+    copied_trees_become_folders_and_assets,
+    trees_below_the_storage_root_are_imported_in_place
+);
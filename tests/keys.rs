@@ -0,0 +1,126 @@
+mod common;
+
+use common::{principal, test_config, user};
+use file_server::controller::access::Principal;
+use file_server::controller::auth::{add_key_to_access_group, register_new_access_group};
+use file_server::controller::error::ControllerError;
+use file_server::controller::key::{
+    assign_key, create_key, delete_key, resolve_key, set_key_active, unassign_key,
+};
+use file_server::store::MetaStore;
+
+fn forbidden<T: std::fmt::Debug>(result: Result<T, ControllerError>) -> bool {
+    matches!(result, Err(ControllerError::Forbidden(_)))
+}
+
+async fn key_tokens_resolve_while_the_key_is_active(store: impl MetaStore) {
+    let config = test_config();
+    let alice = user(&store, &config, "alice").await;
+    let alice_principal = principal(&store, &alice).await;
+
+    // Only users create keys, and become their admin
+    assert!(forbidden(create_key(&store, &Principal::Anonymous).await));
+    let issued = create_key(&store, &alice_principal).await.unwrap();
+    let key_id = issued.key.id.clone().unwrap();
+    let alice_doc = store.find_user(alice.id.as_ref().unwrap()).await.unwrap();
+    assert!(alice_doc.unwrap().key_admins.contains(&key_id));
+
+    // The token is <uuid>.<secret>, and only a hash of the secret is stored
+    let (uuid, secret) = issued.token.split_once('.').unwrap();
+    assert_eq!(uuid, issued.key.uuid);
+    assert!(!issued.key.secret_hash.contains(secret));
+
+    let resolved = resolve_key(&store, &issued.token).await.unwrap();
+    assert_eq!(resolved.unwrap().id, Some(key_id.clone()));
+    for token in [
+        format!("{}.wrong", uuid),
+        format!("someone-else.{}", secret),
+        uuid.to_string(),
+    ] {
+        assert!(resolve_key(&store, &token).await.unwrap().is_none());
+    }
+
+    set_key_active(&store, &alice_principal, &key_id, false)
+        .await
+        .unwrap();
+    assert!(resolve_key(&store, &issued.token).await.unwrap().is_none());
+    set_key_active(&store, &alice_principal, &key_id, true)
+        .await
+        .unwrap();
+    assert!(resolve_key(&store, &issued.token).await.unwrap().is_some());
+}
+
+async fn only_key_admins_manage_a_key(store: impl MetaStore) {
+    let config = test_config();
+    let alice = user(&store, &config, "alice").await;
+    let bob = user(&store, &config, "bob").await;
+    let bob_id = bob.id.clone().unwrap();
+    let alice_principal = principal(&store, &alice).await;
+    let bob_principal = principal(&store, &bob).await;
+    let key_id = create_key(&store, &alice_principal)
+        .await
+        .unwrap()
+        .key
+        .id
+        .unwrap();
+
+    assert!(forbidden(
+        set_key_active(&store, &bob_principal, &key_id, false).await
+    ));
+    assert!(forbidden(
+        assign_key(&store, &bob_principal, &key_id, &bob_id).await
+    ));
+    assert!(forbidden(delete_key(&store, &bob_principal, &key_id).await));
+
+    let bob_doc = assign_key(&store, &alice_principal, &key_id, &bob_id)
+        .await
+        .unwrap();
+    assert!(bob_doc.keys.contains(&key_id));
+    // Holding a key doesn't make Bob its admin
+    let bob_principal = principal(&store, &bob).await;
+    assert!(forbidden(
+        unassign_key(&store, &bob_principal, &key_id, &bob_id).await
+    ));
+
+    let bob_doc = unassign_key(&store, &alice_principal, &key_id, &bob_id)
+        .await
+        .unwrap();
+    assert!(!bob_doc.keys.contains(&key_id));
+}
+
+async fn deleted_keys_are_scrubbed_everywhere(store: impl MetaStore) {
+    let config = test_config();
+    let alice = user(&store, &config, "alice").await;
+    let bob = user(&store, &config, "bob").await;
+    let (alice_id, bob_id) = (alice.id.clone().unwrap(), bob.id.clone().unwrap());
+    let alice_principal = principal(&store, &alice).await;
+
+    let issued = create_key(&store, &alice_principal).await.unwrap();
+    let key_id = issued.key.id.clone().unwrap();
+    let group = register_new_access_group(&store, &alice_principal, "team")
+        .await
+        .unwrap();
+    let group_id = group.id.clone().unwrap();
+    add_key_to_access_group(&store, &alice_principal, &group_id, &key_id)
+        .await
+        .unwrap();
+    assign_key(&store, &alice_principal, &key_id, &bob_id)
+        .await
+        .unwrap();
+
+    delete_key(&store, &alice_principal, &key_id).await.unwrap();
+    assert!(store.find_key(&key_id).await.unwrap().is_none());
+    assert!(resolve_key(&store, &issued.token).await.unwrap().is_none());
+    let alice_doc = store.find_user(&alice_id).await.unwrap().unwrap();
+    assert!(!alice_doc.key_admins.contains(&key_id));
+    let bob_doc = store.find_user(&bob_id).await.unwrap().unwrap();
+    assert!(!bob_doc.keys.contains(&key_id));
+    let group = store.find_access_group(&group_id).await.unwrap().unwrap();
+    assert!(!group.allowed_keys.contains(&key_id));
+}
+
+on_every_store!(
+    key_tokens_resolve_while_the_key_is_active,
+    only_key_admins_manage_a_key,
+    deleted_keys_are_scrubbed_everywhere
+);
@@ -0,0 +1,176 @@
+mod common;
+
+use bytes::Bytes;
+use common::{read, write};
+use file_server::controller::error::ControllerError;
+use file_server::storage::local::LocalStorage;
+use file_server::storage::Storage;
+use file_server::util::get_uuid;
+use futures::stream::{self, StreamExt};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/**
+ * Local storage below a fresh directory in the system temp directory, which is returned too
+ */
+fn local_storage() -> (LocalStorage, PathBuf) {
+    let root = std::env::temp_dir().join(get_uuid());
+    let storage = LocalStorage::new(root.to_str().unwrap()).unwrap();
+
+    (storage, root)
+}
+
+async fn read_range(storage: &LocalStorage, key: &str, start: u64, end: u64) -> Vec<u8> {
+    let mut bytes = vec![];
+    let mut chunks = storage.get_range(key, start, end).await.unwrap();
+    while let Some(chunk) = chunks.next().await {
+        bytes.extend_from_slice(&chunk.unwrap());
+    }
+
+    bytes
+}
+
+#[tokio::test]
+async fn keys_are_files_below_the_root() {
+    let (storage, root) = local_storage();
+
+    write(&storage, "pics/a.jpg", b"hello world").await;
+    assert_eq!(fs::read(root.join("pics/a.jpg")).unwrap(), b"hello world");
+    assert_eq!(read(&storage, "pics/a.jpg").await, b"hello world");
+    assert_eq!(read_range(&storage, "pics/a.jpg", 6, 10).await, b"world");
+    assert_eq!(storage.stat("pics/a.jpg").await.unwrap().unwrap().size, 11);
+
+    // A put replaces what was there
+    write(&storage, "pics/a.jpg", b"bye").await;
+    assert_eq!(read(&storage, "pics/a.jpg").await, b"bye");
+
+    assert!(storage.stat("pics/b.jpg").await.unwrap().is_none());
+    assert!(storage.stat("pics").await.unwrap().is_none());
+    assert!(matches!(
+        storage.get("pics/b.jpg").await,
+        Err(ControllerError::NotFound(_))
+    ));
+
+    storage
+        .rename("pics/a.jpg", "docs/deep/a.jpg")
+        .await
+        .unwrap();
+    assert!(!storage.exists("pics/a.jpg").await.unwrap());
+    assert_eq!(read(&storage, "docs/deep/a.jpg").await, b"bye");
+    assert!(matches!(
+        storage.rename("pics/a.jpg", "pics/c.jpg").await,
+        Err(ControllerError::NotFound(_))
+    ));
+
+    // Deleting twice is fine, the directory stays
+    storage.delete("docs/deep/a.jpg").await.unwrap();
+    storage.delete("docs/deep/a.jpg").await.unwrap();
+    assert!(root.join("docs/deep").is_dir());
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn lists_are_sorted_and_skip_unfinished_writes() {
+    let (storage, root) = local_storage();
+    for key in [
+        "pics/b.jpg",
+        "pics/a.jpg",
+        "pics/sub/c.jpg",
+        "pictures/d.jpg",
+    ] {
+        write(&storage, key, b"x").await;
+    }
+    fs::create_dir_all(root.join(".tmp")).unwrap();
+    fs::write(root.join(".tmp/partial"), b"x").unwrap();
+
+    assert_eq!(
+        storage.list("pics/").await.unwrap(),
+        vec!["pics/a.jpg", "pics/b.jpg", "pics/sub/c.jpg"]
+    );
+    assert_eq!(
+        storage.list("pic").await.unwrap(),
+        vec![
+            "pics/a.jpg",
+            "pics/b.jpg",
+            "pics/sub/c.jpg",
+            "pictures/d.jpg"
+        ]
+    );
+    assert!(storage.list("nothing/").await.unwrap().is_empty());
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn a_failed_put_leaves_the_old_bytes() {
+    let (storage, root) = local_storage();
+    write(&storage, "pics/a.jpg", b"old").await;
+
+    let mut failing = stream::iter(vec![
+        Ok(Bytes::from_static(b"new")),
+        Err(io::Error::other("connection lost")),
+    ]);
+    assert!(storage.put("pics/a.jpg", &mut failing).await.is_err());
+    assert_eq!(read(&storage, "pics/a.jpg").await, b"old");
+    assert_eq!(fs::read_dir(root.join(".tmp")).unwrap().count(), 0);
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn folders_are_directories() {
+    let (storage, root) = local_storage();
+    assert!(storage.has_dirs());
+
+    storage.create_dir("pics").await.unwrap();
+    assert!(storage.dir_exists("pics").await.unwrap());
+    write(&storage, "pics/sub/a.jpg", b"x").await;
+
+    storage.rename_dir("pics", "photos/pics").await.unwrap();
+    assert!(!storage.dir_exists("pics").await.unwrap());
+    assert_eq!(read(&storage, "photos/pics/sub/a.jpg").await, b"x");
+
+    // A folder whose directory went missing gets a new one
+    storage.rename_dir("gone", "back").await.unwrap();
+    assert!(storage.dir_exists("back").await.unwrap());
+
+    storage.remove_dir("photos").await.unwrap();
+    storage.remove_dir("photos").await.unwrap();
+    assert!(!root.join("photos").exists());
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn keys_never_leave_the_root() {
+    let (storage, root) = local_storage();
+    let outside = std::env::temp_dir().join(get_uuid());
+    fs::create_dir_all(&outside).unwrap();
+    fs::write(outside.join("secret"), b"secret").unwrap();
+
+    assert!(matches!(
+        storage.get("../secret").await,
+        Err(ControllerError::InvalidInput(_))
+    ));
+    let mut source = stream::iter(vec![Ok(Bytes::from_static(b"x"))]);
+    assert!(matches!(
+        storage.put("pics/../../x", &mut source).await,
+        Err(ControllerError::InvalidInput(_))
+    ));
+
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+        assert!(matches!(
+            storage.get("link/secret").await,
+            Err(ControllerError::InvalidInput(_))
+        ));
+        storage.delete("link/secret").await.unwrap_err();
+        assert!(outside.join("secret").exists());
+    }
+
+    fs::remove_dir_all(&root).unwrap();
+    fs::remove_dir_all(&outside).unwrap();
+}
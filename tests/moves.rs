@@ -0,0 +1,255 @@
+mod common;
+
+use common::{principal, read, test_config, user, FlakyStorage, ROOT};
+use file_server::controller::access::{authorize_folder, Permission};
+use file_server::controller::error::ControllerError;
+use file_server::controller::file_system::{
+    add_folder_admin, create_folder, create_sub_folder, move_asset, move_folder, save_asset,
+};
+use file_server::data_models::asset::Asset;
+use file_server::storage::memory::MemoryStorage;
+use file_server::storage::{asset_key, Storage};
+use file_server::store::MetaStore;
+use file_server::util::get_uuid;
+
+async fn assets_move_with_their_bytes(store: impl MetaStore) {
+    let (storage, config) = (MemoryStorage::new(), test_config());
+    let alice_user = user(&store, &config, "alice").await;
+    let alice = principal(&store, &alice_user).await;
+    let from = create_folder(&store, &storage, &config, &alice, "from", None, None)
+        .await
+        .unwrap();
+    let to = create_folder(&store, &storage, &config, &alice, "to", None, None)
+        .await
+        .unwrap();
+    let (from_id, to_id) = (from.id.clone().unwrap(), to.id.clone().unwrap());
+    let id = save_asset(
+        &store,
+        &storage,
+        &config,
+        &alice,
+        b"hello".to_vec(),
+        "greeting",
+        &from_id,
+        "txt",
+    )
+    .await
+    .unwrap();
+    let asset = store.find_asset(&id).await.unwrap().unwrap();
+
+    let moved = move_asset(
+        &store,
+        &storage,
+        &config,
+        &alice,
+        &asset.uuid,
+        Some(&to_id),
+        Some("moved"),
+    )
+    .await
+    .unwrap();
+    assert_eq!(moved.tag, "moved");
+    assert_eq!(moved.folder_id, to_id);
+    assert!(moved.path.starts_with(&format!("{}/", to.path)));
+    assert!(!storage
+        .exists(&asset_key(ROOT, &asset).unwrap())
+        .await
+        .unwrap());
+    assert_eq!(
+        read(&storage, &asset_key(ROOT, &moved).unwrap()).await,
+        b"hello"
+    );
+    assert!(store
+        .find_folder(&from_id)
+        .await
+        .unwrap()
+        .unwrap()
+        .files
+        .is_empty());
+    assert_eq!(
+        store.find_folder(&to_id).await.unwrap().unwrap().files,
+        vec![id]
+    );
+}
+
+async fn an_asset_move_the_store_refuses_moves_the_bytes_back(store: impl MetaStore) {
+    let (storage, config) = (MemoryStorage::new(), test_config());
+    let alice_user = user(&store, &config, "alice").await;
+    let alice = principal(&store, &alice_user).await;
+    let from = create_folder(&store, &storage, &config, &alice, "from", None, None)
+        .await
+        .unwrap();
+    let to = create_folder(&store, &storage, &config, &alice, "to", None, None)
+        .await
+        .unwrap();
+    let (from_id, to_id) = (from.id.clone().unwrap(), to.id.clone().unwrap());
+    let id = save_asset(
+        &store,
+        &storage,
+        &config,
+        &alice,
+        b"hello".to_vec(),
+        "greeting",
+        &from_id,
+        "txt",
+    )
+    .await
+    .unwrap();
+    let asset = store.find_asset(&id).await.unwrap().unwrap();
+
+    // A doc already claims the path the asset would get, without any bytes stored for it
+    let file_name = asset.path.rsplit('/').next().unwrap();
+    let to_path = format!("{}/{}", to.path, file_name);
+    store
+        .insert_asset(Asset {
+            id: None,
+            folder_id: to_id.clone(),
+            path: to_path.clone(),
+            uuid: get_uuid(),
+            ..asset.clone()
+        })
+        .await
+        .unwrap();
+
+    let moved = move_asset(
+        &store,
+        &storage,
+        &config,
+        &alice,
+        &asset.uuid,
+        Some(&to_id),
+        None,
+    )
+    .await;
+    assert!(matches!(moved, Err(ControllerError::AlreadyExists(_))));
+
+    let unmoved = store.find_asset(&id).await.unwrap().unwrap();
+    assert_eq!(unmoved.path, asset.path);
+    assert_eq!(unmoved.folder_id, from_id);
+    assert_eq!(
+        read(&storage, &asset_key(ROOT, &asset).unwrap()).await,
+        b"hello"
+    );
+    let to_key = to_path.strip_prefix(&format!("{}/", ROOT)).unwrap();
+    assert!(!storage.exists(to_key).await.unwrap());
+    assert_eq!(
+        store.find_folder(&from_id).await.unwrap().unwrap().files,
+        vec![id]
+    );
+}
+
+async fn a_folder_move_that_fails_halfway_is_moved_back(store: impl MetaStore) {
+    let (storage, config) = (FlakyStorage::new(), test_config());
+    let alice_user = user(&store, &config, "alice").await;
+    let alice = principal(&store, &alice_user).await;
+    let folder = create_folder(&store, &storage, &config, &alice, "docs", None, None)
+        .await
+        .unwrap();
+    let folder_id = folder.id.clone().unwrap();
+    let sub = create_sub_folder(&store, &storage, &config, &alice, &folder.path, "old", None)
+        .await
+        .unwrap();
+    let mut ids = vec![];
+    for (folder_id, data) in [(&folder_id, b"one"), (sub.id.as_ref().unwrap(), b"two")] {
+        let id = save_asset(
+            &store,
+            &storage,
+            &config,
+            &alice,
+            data.to_vec(),
+            "file",
+            folder_id,
+            "txt",
+        )
+        .await
+        .unwrap();
+        ids.push(id);
+    }
+    let keys_before = storage.list("").await.unwrap();
+    assert_eq!(keys_before.len(), 2);
+
+    // The second rename fails, so the first one is undone and no doc changes
+    storage.fail_rename_after(1);
+    let moved = move_folder(
+        &store, &storage, &config, &alice, &folder_id, ROOT, "papers",
+    )
+    .await;
+    assert!(moved.is_err());
+    assert_eq!(storage.list("").await.unwrap(), keys_before);
+    assert_eq!(
+        store.find_folder(&folder_id).await.unwrap().unwrap().path,
+        folder.path
+    );
+    for id in &ids {
+        let asset = store.find_asset(id).await.unwrap().unwrap();
+        assert!(asset.path.starts_with(&format!("{}/", folder.path)));
+        assert!(storage
+            .exists(&asset_key(ROOT, &asset).unwrap())
+            .await
+            .unwrap());
+    }
+
+    // Once storage behaves the folder moves with everything below it
+    let moved = move_folder(
+        &store, &storage, &config, &alice, &folder_id, ROOT, "papers",
+    )
+    .await
+    .unwrap();
+    assert_eq!(moved.path, format!("{}/papers", ROOT));
+    assert_eq!(
+        store
+            .find_folder(sub.id.as_ref().unwrap())
+            .await
+            .unwrap()
+            .unwrap()
+            .path,
+        format!("{}/papers/old", ROOT)
+    );
+    for (id, data) in ids.iter().zip([b"one", b"two"]) {
+        let asset = store.find_asset(id).await.unwrap().unwrap();
+        assert!(asset.path.starts_with(&format!("{}/", moved.path)));
+        assert_eq!(
+            read(&storage, &asset_key(ROOT, &asset).unwrap()).await,
+            data
+        );
+    }
+}
+
+async fn admins_from_above_keep_administrating_the_folders_they_move_out(store: impl MetaStore) {
+    let (storage, config) = (MemoryStorage::new(), test_config());
+    let alice_user = user(&store, &config, "alice").await;
+    let bob_user = user(&store, &config, "bob").await;
+    let bob_id = bob_user.id.clone().unwrap();
+    let alice = principal(&store, &alice_user).await;
+    let top = create_folder(&store, &storage, &config, &alice, "top", None, None)
+        .await
+        .unwrap();
+    let sub = create_sub_folder(&store, &storage, &config, &alice, &top.path, "sub", None)
+        .await
+        .unwrap();
+    let sub_id = sub.id.clone().unwrap();
+
+    // Bob administrates sub only through top, until they move it to the top level
+    add_folder_admin(&store, &alice, top.id.as_ref().unwrap(), &bob_id)
+        .await
+        .unwrap();
+    let bob = principal(&store, &bob_user).await;
+    assert!(!bob_user.folder_admins.contains(&sub_id));
+
+    let moved = move_folder(&store, &storage, &config, &bob, &sub_id, ROOT, "sub")
+        .await
+        .unwrap();
+    assert_eq!(moved.path, format!("{}/sub", ROOT));
+    let bob_doc = store.find_user(&bob_id).await.unwrap().unwrap();
+    assert!(bob_doc.folder_admins.contains(&sub_id));
+    assert!(authorize_folder(&store, &bob, &moved, Permission::Admin)
+        .await
+        .is_ok());
+}
+
+on_every_store!(
+    assets_move_with_their_bytes,
+    an_asset_move_the_store_refuses_moves_the_bytes_back,
+    a_folder_move_that_fails_halfway_is_moved_back,
+    admins_from_above_keep_administrating_the_folders_they_move_out
+);
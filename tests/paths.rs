@@ -0,0 +1,125 @@
+use file_server::controller::error::ControllerError;
+use file_server::controller::paths::{
+    resolve_under_root, usable_extension, validate_extension, validate_name, validate_password,
+    validate_user_name,
+};
+use file_server::util::get_uuid;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn invalid<T: std::fmt::Debug>(result: Result<T, ControllerError>) -> bool {
+    matches!(result, Err(ControllerError::InvalidInput(_)))
+}
+/**
+ * A fresh storage root below the system temp directory, canonicalized
+ */
+fn storage_root() -> PathBuf {
+    let root = std::env::temp_dir().join(get_uuid());
+    fs::create_dir_all(&root).unwrap();
+    root.canonicalize().unwrap()
+}
+
+#[test]
+fn folder_names_never_reach_outside_their_directory() {
+    for name in ["pics", "My Pics 2", "a-b_c.d", &"a".repeat(255)] {
+        assert!(validate_name(name).is_ok(), "{:?}", name);
+    }
+    for name in [
+        "",
+        "  ",
+        ".",
+        "..",
+        ".hidden",
+        "a/b",
+        "a\\b",
+        "ä",
+        &"a".repeat(256),
+    ] {
+        assert!(invalid(validate_name(name)), "{:?}", name);
+    }
+}
+
+#[test]
+fn user_names_are_short_and_plain() {
+    for user in ["alice", "bob.smith", "a-b_c", &"a".repeat(64)] {
+        assert!(validate_user_name(user).is_ok(), "{:?}", user);
+    }
+    for user in ["", ".alice", "al ice", "al/ice", "ä", &"a".repeat(65)] {
+        assert!(invalid(validate_user_name(user)), "{:?}", user);
+    }
+}
+
+#[test]
+fn passwords_fit_bcrypt() {
+    assert!(validate_password("12345678").is_ok());
+    assert!(validate_password(&"a".repeat(72)).is_ok());
+    assert!(validate_password("äöüäöüäö").is_ok());
+    assert!(invalid(validate_password("1234567")));
+    assert!(invalid(validate_password(&"a".repeat(73))));
+    assert!(invalid(validate_password(&"ä".repeat(37))));
+}
+
+#[test]
+fn extensions_are_letters_and_digits() {
+    for extension in ["mp4", "JPG", "7z", &"a".repeat(16)] {
+        assert!(validate_extension(extension).is_ok(), "{:?}", extension);
+    }
+    for extension in ["", "tar.gz", "m p4", "../x", &"a".repeat(17)] {
+        assert!(invalid(validate_extension(extension)), "{:?}", extension);
+    }
+
+    assert_eq!(usable_extension(Path::new("a/movie.mp4")), "mp4");
+    let fallback = usable_extension(Path::new("a/notes"));
+    assert!(validate_extension(fallback).is_ok());
+    assert_eq!(usable_extension(Path::new("a/b.we ird")), fallback);
+}
+
+#[test]
+fn paths_resolve_below_the_storage_root_only() {
+    let root = storage_root();
+    let root_str = root.to_str().unwrap();
+    fs::create_dir(root.join("pics")).unwrap();
+
+    assert_eq!(
+        resolve_under_root(root_str, &format!("{}/pics", root_str)).unwrap(),
+        root.join("pics")
+    );
+    // The part that doesn't exist yet is added back on
+    assert_eq!(
+        resolve_under_root(root_str, &format!("{}/pics/new/a.jpg", root_str)).unwrap(),
+        root.join("pics/new/a.jpg")
+    );
+
+    for path in [
+        root_str.to_string(),
+        format!("{}/pics/../..", root_str),
+        format!("{}/../other", root_str),
+        format!("{}-other/pics", root_str),
+        "/etc/passwd".to_string(),
+    ] {
+        assert!(invalid(resolve_under_root(root_str, &path)), "{}", path);
+    }
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks_out_of_the_storage_root_are_refused() {
+    let root = storage_root();
+    let outside = storage_root();
+    let root_str = root.to_str().unwrap();
+    std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+
+    assert!(invalid(resolve_under_root(
+        root_str,
+        &format!("{}/link", root_str)
+    )));
+    assert!(invalid(resolve_under_root(
+        root_str,
+        &format!("{}/link/new.jpg", root_str)
+    )));
+
+    fs::remove_dir_all(&root).unwrap();
+    fs::remove_dir_all(&outside).unwrap();
+}
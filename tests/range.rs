@@ -0,0 +1,75 @@
+use chrono::{TimeZone, Utc};
+use file_server::api::range::{http_date, if_range_matches, parse_range, ByteRange};
+
+#[test]
+fn single_ranges_are_served_partially() {
+    assert_eq!(parse_range("bytes=0-99", 1000), ByteRange::Partial(0, 99));
+    assert_eq!(
+        parse_range("bytes=100-", 1000),
+        ByteRange::Partial(100, 999)
+    );
+    assert_eq!(
+        parse_range("bytes=-100", 1000),
+        ByteRange::Partial(900, 999)
+    );
+    assert_eq!(parse_range(" bytes= 5 - 5 ", 10), ByteRange::Partial(5, 5));
+}
+
+#[test]
+fn ranges_are_clamped_to_the_resource() {
+    assert_eq!(parse_range("bytes=5-5000", 10), ByteRange::Partial(5, 9));
+    assert_eq!(parse_range("bytes=-5000", 10), ByteRange::Partial(0, 9));
+}
+
+#[test]
+fn invalid_and_multi_ranges_are_ignored() {
+    for header in [
+        "bytes=5-3",
+        "bytes=abc-",
+        "bytes=1-x",
+        "bytes=-x",
+        "bytes=5",
+        "items=0-1",
+        "bytes=0-1,4-5",
+    ] {
+        assert_eq!(parse_range(header, 10), ByteRange::Full, "{}", header);
+    }
+}
+
+#[test]
+fn ranges_past_the_end_are_unsatisfiable() {
+    assert_eq!(parse_range("bytes=10-", 10), ByteRange::Unsatisfiable);
+    assert_eq!(parse_range("bytes=10-20", 10), ByteRange::Unsatisfiable);
+    assert_eq!(parse_range("bytes=-0", 10), ByteRange::Unsatisfiable);
+    assert_eq!(parse_range("bytes=0-", 0), ByteRange::Unsatisfiable);
+}
+
+#[test]
+fn if_range_needs_the_current_etag_or_the_exact_date() {
+    let last_modified = Utc.ymd(2020, 6, 1).and_hms(12, 0, 0);
+    let etag = "\"abc\"";
+
+    assert!(if_range_matches(None, etag, last_modified));
+    assert!(if_range_matches(Some("\"abc\""), etag, last_modified));
+    assert!(!if_range_matches(Some("\"other\""), etag, last_modified));
+    assert!(!if_range_matches(Some("W/\"abc\""), etag, last_modified));
+
+    assert!(if_range_matches(
+        Some(&http_date(last_modified)),
+        etag,
+        last_modified
+    ));
+    let later = Utc.ymd(2020, 6, 2).and_hms(12, 0, 0);
+    let earlier = Utc.ymd(2020, 5, 31).and_hms(12, 0, 0);
+    assert!(!if_range_matches(
+        Some(&http_date(later)),
+        etag,
+        last_modified
+    ));
+    assert!(!if_range_matches(
+        Some(&http_date(earlier)),
+        etag,
+        last_modified
+    ));
+    assert!(!if_range_matches(Some("yesterday"), etag, last_modified));
+}
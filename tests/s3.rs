@@ -0,0 +1,433 @@
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use file_server::config::{Config, StorageBackend};
+use file_server::controller::error::ControllerError;
+use file_server::storage::s3::S3Storage;
+use file_server::storage::Storage;
+use file_server::util::get_uuid;
+use futures::stream::{self, StreamExt};
+use percent_encoding::percent_decode_str;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::env;
+use std::sync::{Arc, Mutex};
+use warp::http::{HeaderMap, Method, Response, StatusCode};
+use warp::hyper::Body;
+use warp::Filter;
+
+const BUCKET: &str = "file-server";
+const ACCESS_KEY: &str = "test-access";
+
+/**
+ * ____________________________________________________________________________________________
+ * Bucket, what the S3 stand-in holds
+ * ____________________________________________________________________________________________
+ * objects: Bytes and modification time of every key
+ * uploads: Parts of every multipart upload still going, by upload id
+ * completed: Number of parts of every finished multipart upload, by key
+ * ____________________________________________________________________________________________
+ */
+#[derive(Default)]
+struct Bucket {
+    objects: BTreeMap<String, (Vec<u8>, DateTime<Utc>)>,
+    uploads: HashMap<String, BTreeMap<u32, Vec<u8>>>,
+    completed: HashMap<String, usize>,
+}
+
+type SharedBucket = Arc<Mutex<Bucket>>;
+
+/**
+ * Serve the calls S3Storage makes to a path style bucket on a free local port, returning its
+ * endpoint. Requests must carry a signature by ACCESS_KEY and a payload hash matching their body
+ */
+fn serve(bucket: SharedBucket) -> String {
+    let routes = warp::any()
+        .and(warp::method())
+        .and(warp::path::full())
+        .and(warp::query::raw().or(warp::any().map(String::new)).unify())
+        .and(warp::header::headers_cloned())
+        .and(warp::body::bytes())
+        .and(warp::any().map(move || bucket.clone()))
+        .and_then(
+            |method: Method,
+             path: warp::path::FullPath,
+             query: String,
+             headers: HeaderMap,
+             body: Bytes,
+             bucket: SharedBucket| async move {
+                Ok::<_, Infallible>(handle(
+                    &bucket,
+                    method,
+                    path.as_str(),
+                    &query,
+                    &headers,
+                    body,
+                ))
+            },
+        );
+
+    let (address, server) = warp::serve(routes).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+
+    format!("http://{}", address)
+}
+
+fn handle(
+    bucket: &SharedBucket,
+    method: Method,
+    path: &str,
+    query: &str,
+    headers: &HeaderMap,
+    body: Bytes,
+) -> Response<Body> {
+    let signed = headers
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value.starts_with(&format!("AWS4-HMAC-SHA256 Credential={}/", ACCESS_KEY))
+        });
+    let payload_hash = headers
+        .get("x-amz-content-sha256")
+        .and_then(|value| value.to_str().ok());
+    if !signed || payload_hash != Some(&format!("{:x}", Sha256::digest(&body))) {
+        return error(StatusCode::FORBIDDEN, "SignatureDoesNotMatch");
+    }
+
+    let query: HashMap<String, String> = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(name), decode(value))
+        })
+        .collect();
+    let path = decode(path);
+    let key = match path.strip_prefix(&format!("/{}", BUCKET)) {
+        Some("") | Some("/") => None,
+        Some(rest) => Some(rest.trim_start_matches('/').to_string()),
+        None => return error(StatusCode::NOT_FOUND, "NoSuchBucket"),
+    };
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string())
+    };
+
+    let mut bucket = bucket.lock().unwrap();
+    let key = match key {
+        Some(key) => key,
+        None => return list(&bucket, &query),
+    };
+    let upload_id = query.get("uploadId").cloned();
+
+    match (method, upload_id) {
+        (Method::POST, None) if query.contains_key("uploads") => {
+            let upload_id = get_uuid();
+            bucket.uploads.insert(upload_id.clone(), BTreeMap::new());
+            xml(format!(
+                "<InitiateMultipartUploadResult><UploadId>{}</UploadId></InitiateMultipartUploadResult>",
+                upload_id
+            ))
+        }
+        (Method::PUT, Some(upload_id)) => {
+            let number: u32 = query["partNumber"].parse().unwrap();
+            let (part, copied) = match header("x-amz-copy-source") {
+                Some(source) => {
+                    match read_source(&bucket, &source, header("x-amz-copy-source-range")) {
+                        Some(part) => (part, true),
+                        None => return error(StatusCode::NOT_FOUND, "NoSuchKey"),
+                    }
+                }
+                None => (body.to_vec(), false),
+            };
+            let etag = format!("\"{:x}\"", Sha256::digest(&part));
+            match bucket.uploads.get_mut(&upload_id) {
+                Some(parts) => parts.insert(number, part),
+                None => return error(StatusCode::NOT_FOUND, "NoSuchUpload"),
+            };
+            match copied {
+                true => xml(format!(
+                    "<CopyPartResult><ETag>{}</ETag></CopyPartResult>",
+                    etag
+                )),
+                false => Response::builder()
+                    .header("etag", etag)
+                    .body(Body::empty())
+                    .unwrap(),
+            }
+        }
+        (Method::POST, Some(upload_id)) => {
+            let parts = match bucket.uploads.remove(&upload_id) {
+                Some(parts) => parts,
+                None => return error(StatusCode::NOT_FOUND, "NoSuchUpload"),
+            };
+            let completed = String::from_utf8_lossy(&body).matches("<Part>").count();
+            if completed != parts.len() {
+                return error(StatusCode::BAD_REQUEST, "InvalidPart");
+            }
+            bucket.completed.insert(key.clone(), parts.len());
+            bucket
+                .objects
+                .insert(key, (parts.into_values().flatten().collect(), Utc::now()));
+            xml("<CompleteMultipartUploadResult></CompleteMultipartUploadResult>".to_string())
+        }
+        (Method::DELETE, Some(upload_id)) => {
+            bucket.uploads.remove(&upload_id);
+            status(StatusCode::NO_CONTENT)
+        }
+        (Method::PUT, None) => {
+            let data = match header("x-amz-copy-source") {
+                Some(source) => match read_source(&bucket, &source, None) {
+                    Some(data) => data,
+                    None => return error(StatusCode::NOT_FOUND, "NoSuchKey"),
+                },
+                None => body.to_vec(),
+            };
+            bucket.objects.insert(key, (data, Utc::now()));
+            xml("<CopyObjectResult></CopyObjectResult>".to_string())
+        }
+        (Method::GET, None) | (Method::HEAD, None) => {
+            let (data, modified) = match bucket.objects.get(&key) {
+                Some(object) => object,
+                None => return error(StatusCode::NOT_FOUND, "NoSuchKey"),
+            };
+            // HEAD gets the same answer, hyper leaves the body out
+            let data = match header("range") {
+                Some(range) => match slice(data, &range) {
+                    Some(part) => part,
+                    None => return error(StatusCode::RANGE_NOT_SATISFIABLE, "InvalidRange"),
+                },
+                None => data.clone(),
+            };
+            Response::builder()
+                .status(match header("range") {
+                    Some(_) => StatusCode::PARTIAL_CONTENT,
+                    None => StatusCode::OK,
+                })
+                .header("content-length", data.len())
+                .header(
+                    "last-modified",
+                    modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string(),
+                )
+                .body(Body::from(data))
+                .unwrap()
+        }
+        (Method::DELETE, None) => {
+            bucket.objects.remove(&key);
+            status(StatusCode::NO_CONTENT)
+        }
+        _ => error(StatusCode::METHOD_NOT_ALLOWED, "MethodNotAllowed"),
+    }
+}
+/**
+ * ListObjectsV2 of the keys starting with the prefix in query, two at a time so continuation
+ * tokens get followed
+ */
+fn list(bucket: &Bucket, query: &HashMap<String, String>) -> Response<Body> {
+    let prefix = query.get("prefix").cloned().unwrap_or_default();
+    let after = query.get("continuation-token").cloned().unwrap_or_default();
+    let keys: Vec<&String> = bucket
+        .objects
+        .keys()
+        .filter(|key| key.starts_with(&prefix) && **key > after)
+        .collect();
+
+    let page = &keys[..keys.len().min(2)];
+    let contents: String = page
+        .iter()
+        .map(|key| format!("<Contents><Key>{}</Key></Contents>", key))
+        .collect();
+    let truncated = match keys.len() > page.len() {
+        true => format!(
+            "<IsTruncated>true</IsTruncated><NextContinuationToken>{}</NextContinuationToken>",
+            page[page.len() - 1]
+        ),
+        false => "<IsTruncated>false</IsTruncated>".to_string(),
+    };
+
+    xml(format!(
+        "<ListBucketResult>{}{}</ListBucketResult>",
+        contents, truncated
+    ))
+}
+/**
+ * The bytes of the object an x-amz-copy-source names, only those in range if given
+ */
+fn read_source(bucket: &Bucket, source: &str, range: Option<String>) -> Option<Vec<u8>> {
+    let key = decode(source)
+        .strip_prefix(&format!("/{}/", BUCKET))?
+        .to_string();
+    let (data, _) = bucket.objects.get(&key)?;
+
+    match range {
+        Some(range) => slice(data, &range),
+        None => Some(data.clone()),
+    }
+}
+/**
+ * The bytes of data in a bytes=start-end range
+ */
+fn slice(data: &[u8], range: &str) -> Option<Vec<u8>> {
+    let (start, end) = range.strip_prefix("bytes=")?.split_once('-')?;
+    let (start, end): (usize, usize) = (start.parse().ok()?, end.parse().ok()?);
+    if start > end || start >= data.len() {
+        return None;
+    }
+
+    Some(data[start..=end.min(data.len() - 1)].to_vec())
+}
+
+fn decode(value: &str) -> String {
+    percent_decode_str(value).decode_utf8_lossy().to_string()
+}
+
+fn xml(body: String) -> Response<Body> {
+    Response::builder()
+        .header("content-type", "application/xml")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
+
+fn error(status: StatusCode, code: &str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/xml")
+        .body(Body::from(format!("<Error><Code>{}</Code></Error>", code)))
+        .unwrap()
+}
+/**
+ * Every byte stored under key
+ */
+async fn read(storage: &S3Storage, key: &str) -> Vec<u8> {
+    let mut bytes = vec![];
+    let mut chunks = storage.get(key).await.expect("get");
+    while let Some(chunk) = chunks.next().await {
+        bytes.extend_from_slice(&chunk.expect("chunk"));
+    }
+
+    bytes
+}
+/**
+ * Every byte of key from start to end
+ */
+async fn read_range(storage: &S3Storage, key: &str, start: u64, end: u64) -> Vec<u8> {
+    let mut bytes = vec![];
+    let mut chunks = storage.get_range(key, start, end).await.expect("get range");
+    while let Some(chunk) = chunks.next().await {
+        bytes.extend_from_slice(&chunk.expect("chunk"));
+    }
+
+    bytes
+}
+/**
+ * Put, read, rename, list and delete below a fresh prefix, with one object large enough to go up in
+ * three parts. Returns the key of that object
+ */
+async fn exercise(storage: &S3Storage, part_size: usize) -> String {
+    let prefix = format!("test-{}", get_uuid());
+    let small = format!("{}/small.txt", prefix);
+    let moved = format!("{}/moved/small.txt", prefix);
+    let large = format!("{}/large.bin", prefix);
+
+    let mut data = stream::iter(vec![Ok(Bytes::from_static(b"hello world"))]);
+    assert_eq!(storage.put(&small, &mut data).await.unwrap(), 11);
+    assert_eq!(read(storage, &small).await, b"hello world");
+    assert_eq!(read_range(storage, &small, 6, 10).await, b"world");
+    assert_eq!(storage.stat(&small).await.unwrap().unwrap().size, 11);
+
+    // Streamed in chunks that don't line up with the parts
+    let bytes: Vec<u8> = (0..2 * part_size + 100).map(|i| (i % 251) as u8).collect();
+    let chunks: Vec<std::io::Result<Bytes>> = bytes
+        .chunks(part_size / 3 + 7)
+        .map(|chunk| Ok(Bytes::from(chunk.to_vec())))
+        .collect();
+    let mut data = stream::iter(chunks);
+    assert_eq!(
+        storage.put(&large, &mut data).await.unwrap(),
+        bytes.len() as u64
+    );
+    assert_eq!(read(storage, &large).await, bytes);
+    let (start, end) = (part_size - 5, part_size + 5);
+    assert_eq!(
+        read_range(storage, &large, start as u64, end as u64).await,
+        bytes[start..=end]
+    );
+
+    storage.rename(&small, &moved).await.unwrap();
+    assert!(!storage.exists(&small).await.unwrap());
+    assert_eq!(read(storage, &moved).await, b"hello world");
+
+    assert_eq!(
+        storage.list(&format!("{}/", prefix)).await.unwrap(),
+        vec![large.clone(), moved.clone()]
+    );
+
+    for key in [&large, &moved] {
+        storage.delete(key).await.unwrap();
+    }
+    storage.delete(&small).await.unwrap();
+    assert!(storage.list(&prefix).await.unwrap().is_empty());
+    assert!(storage.stat(&moved).await.unwrap().is_none());
+    assert!(matches!(
+        storage.get(&moved).await.err(),
+        Some(ControllerError::NotFound(_))
+    ));
+
+    large
+}
+
+#[tokio::test]
+async fn s3_storage_works_against_a_stand_in() {
+    let bucket = SharedBucket::default();
+    let config = Config {
+        storage_backend: StorageBackend::S3,
+        s3_endpoint: Some(serve(bucket.clone())),
+        s3_bucket: BUCKET.to_string(),
+        s3_access_key: ACCESS_KEY.to_string(),
+        s3_secret_key: "test-secret".to_string(),
+        s3_part_size: 1024,
+        ..Config::default()
+    };
+    let storage = S3Storage::new(&config).unwrap();
+
+    let large = exercise(&storage, 1024).await;
+    let bucket = bucket.lock().unwrap();
+    assert_eq!(bucket.completed.get(&large), Some(&3));
+    assert!(bucket.uploads.is_empty());
+    assert!(bucket.objects.is_empty());
+}
+
+/**
+ * Against a real S3 compatible server when FILE_SERVER_TEST_S3_ENDPOINT is set, e.g. a local MinIO,
+ * with the bucket and keys of FILE_SERVER_TEST_S3_BUCKET, _ACCESS_KEY and _SECRET_KEY
+ */
+#[tokio::test]
+async fn s3_storage_works_against_an_endpoint() {
+    let endpoint = match env::var("FILE_SERVER_TEST_S3_ENDPOINT") {
+        Ok(endpoint) => endpoint,
+        Err(_) => return,
+    };
+    let var = |name: &str, default: &str| env::var(name).unwrap_or_else(|_| default.to_string());
+    let part_size = 5 * 1024 * 1024;
+    let config = Config {
+        storage_backend: StorageBackend::S3,
+        s3_endpoint: Some(endpoint),
+        s3_bucket: var("FILE_SERVER_TEST_S3_BUCKET", BUCKET),
+        s3_access_key: var("FILE_SERVER_TEST_S3_ACCESS_KEY", "minioadmin"),
+        s3_secret_key: var("FILE_SERVER_TEST_S3_SECRET_KEY", "minioadmin"),
+        s3_part_size: part_size as u64,
+        ..Config::default()
+    };
+    let storage = S3Storage::new(&config).unwrap();
+
+    exercise(&storage, part_size).await;
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use common::{principal, test_config, user, write, ROOT};
+use file_server::constants::SCRUB_PAGE_SIZE;
+use file_server::controller::file_system::{create_folder, save_asset};
+use file_server::data_models::asset::Integrity;
+use file_server::scrub::scrub;
+use file_server::storage::memory::MemoryStorage;
+use file_server::storage::{asset_key, Storage};
+use file_server::store::MetaStore;
+
+async fn scrubs_flag_corrupt_and_missing_assets(store: impl MetaStore) {
+    let (storage, config) = (MemoryStorage::new(), test_config());
+    let alice_user = user(&store, &config, "alice").await;
+    let alice = principal(&store, &alice_user).await;
+    let folder = create_folder(&store, &storage, &config, &alice, "pics", None, None)
+        .await
+        .unwrap();
+    let folder_id = folder.id.clone().unwrap();
+
+    // More assets than fit on one page, so the scrub has to page through them
+    let mut ids = vec![];
+    for number in 0..SCRUB_PAGE_SIZE + 2 {
+        let data = format!("asset {}", number).into_bytes();
+        let tag = format!("asset {}", number);
+        let id = save_asset(
+            &store, &storage, &config, &alice, data, &tag, &folder_id, "txt",
+        )
+        .await
+        .unwrap();
+        ids.push(id);
+    }
+    let (corrupt, missing, unchecked) = (&ids[0], &ids[1], &ids[SCRUB_PAGE_SIZE + 1]);
+
+    let corrupt_asset = store.find_asset(corrupt).await.unwrap().unwrap();
+    write(
+        &storage,
+        &asset_key(ROOT, &corrupt_asset).unwrap(),
+        b"flipped",
+    )
+    .await;
+    let missing_asset = store.find_asset(missing).await.unwrap().unwrap();
+    storage
+        .delete(&asset_key(ROOT, &missing_asset).unwrap())
+        .await
+        .unwrap();
+    // Assets from before checksums were recorded get theirs now
+    store
+        .set_asset_checksum(unchecked, 0, "", None)
+        .await
+        .unwrap();
+
+    let report = scrub(&store, &storage, &config).await.unwrap();
+    assert_eq!(report.checked, ids.len() as u64);
+    assert_eq!(report.intact, ids.len() as u64 - 2);
+    assert_eq!(report.backfilled, 1);
+    assert!(report.failed.is_empty());
+    assert_eq!(report.corrupt.len(), 1);
+    assert_eq!(report.corrupt[0].id.as_ref(), Some(corrupt));
+    assert_eq!(report.missing.len(), 1);
+    assert_eq!(report.missing[0].id.as_ref(), Some(missing));
+
+    for (id, integrity) in [
+        (corrupt, Integrity::Corrupt),
+        (missing, Integrity::Missing),
+        (&ids[2], Integrity::Intact),
+        (unchecked, Integrity::Intact),
+    ] {
+        let asset = store.find_asset(id).await.unwrap().unwrap();
+        assert_eq!(asset.integrity, integrity);
+        assert!(asset.verified_at.is_some());
+    }
+    let backfilled = store.find_asset(unchecked).await.unwrap().unwrap();
+    assert_eq!(
+        backfilled.size,
+        format!("asset {}", SCRUB_PAGE_SIZE + 1).len() as i64
+    );
+    assert_eq!(backfilled.checksum.len(), 64);
+}
+
+async fn scrubbing_nothing_finds_nothing(store: impl MetaStore) {
+    let (storage, config) = (MemoryStorage::new(), test_config());
+
+    let report = scrub(&store, &storage, &config).await.unwrap();
+    assert_eq!(report.checked, 0);
+    assert_eq!(
+        report.summary(),
+        "Scrubbed 0 assets: 0 intact (0 checksums recorded), 0 corrupt, 0 missing, 0 failed"
+    );
+}
+
+on_every_store!(
+    scrubs_flag_corrupt_and_missing_assets,
+    scrubbing_nothing_finds_nothing
+);
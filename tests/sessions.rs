@@ -0,0 +1,153 @@
+mod common;
+
+use common::{principal, test_config, user};
+use file_server::config::Config;
+use file_server::controller::auth::login_user;
+use file_server::controller::error::ControllerError;
+use file_server::controller::file_system::{delete_user, set_user_password};
+use file_server::controller::session::{
+    list_sessions, logout_session, resolve_session, revoke_session,
+};
+use file_server::store::MetaStore;
+
+async fn logins_issue_sessions_that_resolve_to_their_user(store: impl MetaStore) {
+    let config = test_config();
+    let alice = user(&store, &config, "alice").await;
+
+    assert!(login_user(&store, &config, "alice", "wrong password")
+        .await
+        .unwrap()
+        .is_none());
+    assert!(login_user(&store, &config, "nobody", "correct horse")
+        .await
+        .unwrap()
+        .is_none());
+
+    let issued = login_user(&store, &config, "alice", "correct horse")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(issued.user.id, alice.id);
+    // Only a hash of the token is stored
+    assert_ne!(issued.session.token_hash, issued.token);
+
+    let resolved = resolve_session(&store, &issued.token).await.unwrap();
+    assert_eq!(resolved.unwrap().id, alice.id);
+    assert!(resolve_session(&store, "not a token")
+        .await
+        .unwrap()
+        .is_none());
+
+    logout_session(&store, &issued.token).await.unwrap();
+    assert!(resolve_session(&store, &issued.token)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+async fn expired_sessions_are_dead(store: impl MetaStore) {
+    let config = Config {
+        session_ttl_secs: 0,
+        ..test_config()
+    };
+    user(&store, &config, "alice").await;
+
+    let issued = login_user(&store, &config, "alice", "correct horse")
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(resolve_session(&store, &issued.token)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+async fn users_list_and_revoke_only_their_own_sessions(store: impl MetaStore) {
+    let config = test_config();
+    let alice = user(&store, &config, "alice").await;
+    let bob = user(&store, &config, "bob").await;
+    let alice_principal = principal(&store, &alice).await;
+    let bob_principal = principal(&store, &bob).await;
+
+    let first = login_user(&store, &config, "alice", "correct horse")
+        .await
+        .unwrap()
+        .unwrap();
+    let second = login_user(&store, &config, "alice", "correct horse")
+        .await
+        .unwrap()
+        .unwrap();
+    let first_id = first.session.id.clone().unwrap();
+    assert_eq!(
+        list_sessions(&store, &alice_principal).await.unwrap().len(),
+        2
+    );
+    assert!(list_sessions(&store, &bob_principal)
+        .await
+        .unwrap()
+        .is_empty());
+
+    assert!(matches!(
+        revoke_session(&store, &bob_principal, &first_id).await,
+        Err(ControllerError::NotFound(_))
+    ));
+    assert!(resolve_session(&store, &first.token)
+        .await
+        .unwrap()
+        .is_some());
+
+    revoke_session(&store, &alice_principal, &first_id)
+        .await
+        .unwrap();
+    assert!(resolve_session(&store, &first.token)
+        .await
+        .unwrap()
+        .is_none());
+    let live = list_sessions(&store, &alice_principal).await.unwrap();
+    assert_eq!(live.len(), 1);
+    assert_eq!(live[0].id, second.session.id);
+}
+
+async fn sessions_end_with_a_new_password_or_the_user(store: impl MetaStore) {
+    let config = test_config();
+    let alice = user(&store, &config, "alice").await;
+    let alice_id = alice.id.clone().unwrap();
+    let alice_principal = principal(&store, &alice).await;
+
+    let before = login_user(&store, &config, "alice", "correct horse")
+        .await
+        .unwrap()
+        .unwrap();
+    set_user_password(
+        &store,
+        &config,
+        &alice_principal,
+        &alice_id,
+        "battery staple",
+    )
+    .await
+    .unwrap();
+    assert!(resolve_session(&store, &before.token)
+        .await
+        .unwrap()
+        .is_none());
+
+    let after = login_user(&store, &config, "alice", "battery staple")
+        .await
+        .unwrap()
+        .unwrap();
+    delete_user(&store, &alice_principal, &alice_id)
+        .await
+        .unwrap();
+    assert!(resolve_session(&store, &after.token)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+on_every_store!(
+    logins_issue_sessions_that_resolve_to_their_user,
+    expired_sessions_are_dead,
+    users_list_and_revoke_only_their_own_sessions,
+    sessions_end_with_a_new_password_or_the_user
+);
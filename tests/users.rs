@@ -0,0 +1,59 @@
+mod common;
+
+use common::{principal, test_config, user};
+use file_server::controller::error::ControllerError;
+use file_server::controller::file_system::{
+    add_folder_admin, create_folder, delete_user, remove_folder_admin,
+};
+use file_server::storage::memory::MemoryStorage;
+use file_server::store::MetaStore;
+
+async fn sole_admins_hand_their_folders_on_before_deleting_themselves(store: impl MetaStore) {
+    let (storage, config) = (MemoryStorage::new(), test_config());
+    let alice = user(&store, &config, "alice").await;
+    let bob = user(&store, &config, "bob").await;
+    let (alice_id, bob_id) = (alice.id.clone().unwrap(), bob.id.clone().unwrap());
+    let alice_principal = principal(&store, &alice).await;
+    let bob_principal = principal(&store, &bob).await;
+
+    let folder = create_folder(
+        &store,
+        &storage,
+        &config,
+        &alice_principal,
+        "pics",
+        None,
+        None,
+    )
+    .await
+    .unwrap();
+    let folder_id = folder.id.clone().unwrap();
+
+    // Alice is the only admin of pics, and Bob can't make themselves one
+    assert!(matches!(
+        delete_user(&store, &alice_principal, &alice_id).await,
+        Err(ControllerError::Forbidden(_))
+    ));
+    assert!(matches!(
+        add_folder_admin(&store, &bob_principal, &folder_id, &bob_id).await,
+        Err(ControllerError::Forbidden(_))
+    ));
+
+    let bob_doc = add_folder_admin(&store, &alice_principal, &folder_id, &bob_id)
+        .await
+        .unwrap();
+    assert!(bob_doc.folder_admins.contains(&folder_id));
+
+    delete_user(&store, &alice_principal, &alice_id)
+        .await
+        .unwrap();
+    assert!(store.find_user(&alice_id).await.unwrap().is_none());
+
+    // Bob administrates pics alone now, and can give it up again
+    let bob_doc = remove_folder_admin(&store, &bob_principal, &folder_id, &bob_id)
+        .await
+        .unwrap();
+    assert!(!bob_doc.folder_admins.contains(&folder_id));
+}
+
+on_every_store!(sole_admins_hand_their_folders_on_before_deleting_themselves);